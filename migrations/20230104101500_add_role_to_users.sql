@@ -0,0 +1,10 @@
+-- Existing users could publish to everyone, so they keep full access.
+BEGIN;
+  ALTER TABLE users ADD COLUMN role TEXT NULL;
+  UPDATE users
+    SET role = 'admin'
+    WHERE role IS NULL;
+  ALTER TABLE users ALTER COLUMN role SET NOT NULL;
+  ALTER TABLE users ALTER COLUMN role SET DEFAULT 'viewer';
+  ALTER TABLE users ADD CONSTRAINT users_role_check CHECK (role IN ('admin', 'editor', 'viewer'));
+COMMIT;
@@ -0,0 +1,17 @@
+-- Invited users, and users forced to reset their password, have no usable
+-- password until they follow the link we email them.
+BEGIN;
+  ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
+  ALTER TABLE users ADD COLUMN disabled_at TIMESTAMP WITH TIME ZONE NULL;
+  ALTER TABLE users ALTER COLUMN password_hash DROP NOT NULL;
+
+  CREATE TABLE password_tokens(
+    token_hash TEXT NOT NULL,
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    purpose TEXT NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    PRIMARY KEY (token_hash)
+  );
+COMMIT;
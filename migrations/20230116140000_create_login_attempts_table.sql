@@ -0,0 +1,19 @@
+CREATE TABLE login_attempts(
+  id BIGSERIAL PRIMARY KEY,
+  username TEXT NOT NULL,
+  ip_address TEXT NULL,
+  succeeded BOOLEAN NOT NULL,
+  attempted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE INDEX login_attempts_username_idx ON login_attempts (username, attempted_at);
+CREATE INDEX login_attempts_ip_address_idx ON login_attempts (ip_address, attempted_at);
+
+-- One row per locked username or IP address, `locked_until` is pushed back
+-- every time a new lockout starts.
+CREATE TABLE login_lockouts(
+  scope TEXT NOT NULL,
+  key TEXT NOT NULL,
+  locked_until TIMESTAMP WITH TIME ZONE NOT NULL,
+  PRIMARY KEY (scope, key)
+);
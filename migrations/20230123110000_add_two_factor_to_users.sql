@@ -0,0 +1,13 @@
+-- A secret is stored as soon as enrollment starts, but only takes effect
+-- once a first code has been verified and `totp_enabled_at` is set.
+ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+ALTER TABLE users ADD COLUMN totp_enabled_at TIMESTAMPTZ NULL;
+-- The last accepted time step, so a code can't be replayed.
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
+
+CREATE TABLE totp_recovery_codes(
+    user_id uuid NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at TIMESTAMPTZ NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
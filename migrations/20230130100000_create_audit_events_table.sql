@@ -0,0 +1,17 @@
+CREATE TABLE audit_events(
+    id BIGSERIAL PRIMARY KEY,
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    action TEXT NOT NULL,
+    -- Who did it: the user's id when known, and the username they used at
+    -- the time, which also covers failed logins with unknown usernames.
+    actor_id uuid NULL REFERENCES users (id) ON DELETE SET NULL,
+    actor TEXT NULL,
+    -- What it was done to, e.g. a user id, a subscriber id or an issue title.
+    subject TEXT NULL,
+    ip_address TEXT NULL,
+    details JSONB NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX audit_events_occurred_at_idx ON audit_events (occurred_at DESC);
+CREATE INDEX audit_events_actor_idx ON audit_events (actor, occurred_at DESC);
+CREATE INDEX audit_events_action_idx ON audit_events (action, occurred_at DESC);
@@ -0,0 +1,14 @@
+-- Evidence of a subscriber's double opt-in: one row when they fill in the
+-- form, another when they follow the confirmation link.
+CREATE TABLE consent_records(
+    id BIGSERIAL PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    event TEXT NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    form_source TEXT NULL,
+    CONSTRAINT consent_records_event_check CHECK (event IN ('subscribed', 'confirmed'))
+);
+
+CREATE INDEX consent_records_subscriber_id_idx ON consent_records (subscriber_id, recorded_at);
@@ -0,0 +1,21 @@
+-- Every subscriber gets a secret token for the link to their preferences,
+-- which is included in every email we send them.
+ALTER TABLE subscriptions
+    ADD COLUMN preferences_token TEXT NOT NULL UNIQUE DEFAULT encode(gen_random_bytes(24), 'hex'),
+    ADD COLUMN frequency TEXT NOT NULL DEFAULT 'immediate',
+    ADD COLUMN paused_until TIMESTAMPTZ NULL,
+    ADD CONSTRAINT subscriptions_frequency_check CHECK (frequency IN ('immediate', 'weekly'));
+
+-- What issues can be about. Subscribers receive every topic unless they
+-- opt out of it.
+CREATE TABLE topics(
+    slug TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE subscriber_topic_opt_outs(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    topic_slug TEXT NOT NULL REFERENCES topics (slug) ON DELETE CASCADE,
+    PRIMARY KEY (subscriber_id, topic_slug)
+);
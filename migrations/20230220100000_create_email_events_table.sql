@@ -0,0 +1,20 @@
+-- Events reported by the email provider's webhook, e.g. deliveries, bounces
+-- and spam complaints.
+CREATE TABLE email_events(
+    id BIGSERIAL PRIMARY KEY,
+    -- Lets us ignore events the provider sends more than once.
+    provider_event_id TEXT NULL UNIQUE,
+    provider_message_id TEXT NULL,
+    subscriber_id uuid NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    email TEXT NOT NULL,
+    event TEXT NOT NULL,
+    occurred_at TIMESTAMPTZ NOT NULL,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    reason TEXT NULL,
+    payload JSONB NOT NULL,
+    CONSTRAINT email_events_event_check
+        CHECK (event IN ('delivered', 'bounce', 'dropped', 'spamreport', 'open', 'click'))
+);
+
+CREATE INDEX email_events_subscriber_id_idx ON email_events (subscriber_id, occurred_at);
+CREATE INDEX email_events_email_idx ON email_events (email);
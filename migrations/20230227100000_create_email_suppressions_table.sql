@@ -0,0 +1,15 @@
+-- Addresses, or whole domains, we must never email, whatever their
+-- subscription status.
+CREATE TABLE email_suppressions(
+    id BIGSERIAL PRIMARY KEY,
+    kind TEXT NOT NULL,
+    -- Lowercase address or domain
+    value TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    -- Where the entry comes from, e.g. "admin" or "legal request"
+    source TEXT NOT NULL,
+    created_by uuid NULL REFERENCES users (id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CONSTRAINT email_suppressions_kind_check CHECK (kind IN ('address', 'domain')),
+    UNIQUE (kind, value)
+);
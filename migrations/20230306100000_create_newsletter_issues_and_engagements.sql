@@ -0,0 +1,34 @@
+-- Every published issue is kept, so that opens and clicks can be reported
+-- against it.
+CREATE TABLE newsletter_issues(
+    id uuid PRIMARY KEY,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    topic_slug TEXT NULL REFERENCES topics (slug) ON DELETE SET NULL,
+    published_by uuid NULL REFERENCES users (id) ON DELETE SET NULL,
+    published_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- The links of an issue. Tracked links redirect to these URLs only, so they
+-- can't be used to send readers anywhere else.
+CREATE TABLE newsletter_issue_links(
+    id uuid PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    UNIQUE (newsletter_issue_id, url)
+);
+
+CREATE TABLE newsletter_engagements(
+    id BIGSERIAL PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    link_id uuid NULL REFERENCES newsletter_issue_links (id) ON DELETE CASCADE,
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CONSTRAINT newsletter_engagements_kind_check CHECK (kind IN ('open', 'click')),
+    CONSTRAINT newsletter_engagements_link_check CHECK ((kind = 'click') = (link_id IS NOT NULL))
+);
+
+CREATE INDEX newsletter_engagements_issue_idx ON newsletter_engagements (newsletter_issue_id, kind);
+CREATE INDEX newsletter_engagements_subscriber_idx ON newsletter_engagements (subscriber_id);
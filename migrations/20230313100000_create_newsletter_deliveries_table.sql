@@ -0,0 +1,18 @@
+-- What became of every email of a newsletter issue.
+CREATE TABLE newsletter_deliveries(
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    status TEXT NOT NULL DEFAULT 'queued',
+    failure_reason TEXT NULL,
+    -- The id the email provider gave the message, which its events refer to.
+    provider_message_id TEXT NULL,
+    queued_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    attempted_at TIMESTAMPTZ NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (newsletter_issue_id, subscriber_id),
+    CONSTRAINT newsletter_deliveries_status_check
+        CHECK (status IN ('queued', 'sent', 'suppressed', 'failed', 'bounced'))
+);
+
+CREATE INDEX newsletter_deliveries_provider_message_id_idx
+    ON newsletter_deliveries (provider_message_id);
@@ -0,0 +1,27 @@
+-- Issues are drafted, optionally approved by someone other than their
+-- author, and scheduled or published.
+ALTER TABLE newsletter_issues
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'draft',
+    ADD COLUMN created_by uuid NULL REFERENCES users (id) ON DELETE SET NULL,
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN approved_by uuid NULL REFERENCES users (id) ON DELETE SET NULL,
+    ADD COLUMN approved_at TIMESTAMPTZ NULL,
+    ADD COLUMN scheduled_for TIMESTAMPTZ NULL,
+    ADD CONSTRAINT newsletter_issues_status_check
+        CHECK (status IN ('draft', 'approved', 'scheduled', 'sending', 'sent'));
+
+-- Issues stored so far were published on the spot.
+UPDATE newsletter_issues
+SET status = 'sent',
+    created_by = published_by,
+    created_at = published_at,
+    updated_at = published_at;
+
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at DROP NOT NULL,
+    ALTER COLUMN published_at DROP DEFAULT;
+
+CREATE INDEX newsletter_issues_scheduled_for_idx
+    ON newsletter_issues (scheduled_for)
+    WHERE status = 'scheduled';
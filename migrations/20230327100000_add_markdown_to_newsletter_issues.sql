@@ -0,0 +1,3 @@
+-- Issues written in Markdown keep their source, so they can be edited again.
+-- Their HTML and text contents are rendered from it.
+ALTER TABLE newsletter_issues ADD COLUMN markdown_content TEXT NULL;
@@ -0,0 +1,14 @@
+-- Files uploaded for newsletter issues, sent along with them either as
+-- attachments or as inline images their HTML refers to with `cid:{id}`.
+CREATE TABLE newsletter_assets(
+    id uuid PRIMARY KEY,
+    filename TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    content BYTEA NOT NULL,
+    uploaded_by uuid NULL REFERENCES users (id) ON DELETE SET NULL,
+    uploaded_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- The assets sent with an issue as attachments. Inline images are found in
+-- its HTML instead.
+ALTER TABLE newsletter_issues ADD COLUMN attachment_ids uuid[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,8 @@
+-- Issues waiting to go out in the weekly digest of a subscriber who chose
+-- to receive one, rather than every issue as soon as it's published.
+CREATE TABLE digest_entries(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+    queued_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (subscriber_id, newsletter_issue_id)
+);
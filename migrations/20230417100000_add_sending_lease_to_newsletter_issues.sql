@@ -0,0 +1,9 @@
+-- The sender of an issue keeps renewing its lease for as long as it's
+-- sending it. Issues still `sending` once their lease ran out lost their
+-- sender, and are picked up again by the scheduler.
+ALTER TABLE newsletter_issues
+    ADD COLUMN sending_lease_until TIMESTAMPTZ NULL;
+
+CREATE INDEX newsletter_issues_sending_lease_until_idx
+    ON newsletter_issues (sending_lease_until)
+    WHERE status = 'sending';
@@ -0,0 +1,4 @@
+-- When a subscriber was last emailed a link to their data, for the request
+-- form not to be used to flood their inbox.
+ALTER TABLE subscriptions
+    ADD COLUMN data_link_sent_at TIMESTAMPTZ NULL;
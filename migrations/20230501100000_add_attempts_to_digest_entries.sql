@@ -0,0 +1,5 @@
+-- A digest is claimed by the instance sending it until its next attempt is
+-- due, and is tried again later and later after each failure.
+ALTER TABLE digest_entries
+    ADD COLUMN attempts SMALLINT NOT NULL DEFAULT 0,
+    ADD COLUMN next_attempt_at TIMESTAMPTZ NULL;
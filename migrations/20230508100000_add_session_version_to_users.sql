@@ -0,0 +1,5 @@
+-- Sessions hold the version of their user's sessions they were created at,
+-- and no longer log anyone in once it's bumped, as when an administrator
+-- forces a password reset.
+ALTER TABLE users
+    ADD COLUMN session_version INTEGER NOT NULL DEFAULT 0;
@@ -1,13 +1,40 @@
 use std::{io, net::TcpListener, ops::Deref};
 
 use crate::{
+    client_ip::TrustedProxies,
+    csrf::CsrfProtection,
     db::DB,
     email_client::EmailClient,
-    routes::{confirm, health_check, home, login, login_form, publish_newsletter, subscribe},
-    settings::{ApplicationSettings, Settings},
+    event_webhook::EventWebhookVerifier,
+    health::Readiness,
+    metrics::RequestMetrics,
+    newsletter_asset::MAX_ASSET_BYTES,
+    publishing::IssueSender,
+    routes::{
+        add_suppression, admin_dashboard, approve_issue, audit_log, audit_log_json, confirm,
+        confirm_two_factor_enrollment, create_issue, delivery_report, delivery_report_json,
+        disable_user, erase_own_subscriber_data, erase_subscriber, export_subscriber,
+        force_password_reset, forgot_password, forgot_password_form, health_check, health_live,
+        health_ready, home, invite_user, issue_engagement, list_issues, list_suppressions,
+        list_users, log_out, login, login_form, metrics, new_issue_form, preferences_form,
+        preview_newsletter, publish_issue, publish_newsletter, read_issue, read_issue_asset,
+        remove_suppression, request_subscriber_data, schedule_issue, send_test_newsletter,
+        sendgrid_events, set_password, set_password_form, show_issue, start_two_factor_enrollment,
+        subscribe, subscriber_consents, subscriber_data, subscriber_data_request_form,
+        subscriber_engagement, track_click, track_open, turn_off_two_factor, two_factor_form,
+        two_factor_settings, unschedule_issue, update_issue, update_preferences, upload_asset,
+        verify_two_factor, MetricsToken, MAX_EVENT_BATCH_BYTES,
+    },
+    settings::{ApplicationSettings, AuthenticationSettings, Env, NewsletterSettings, Settings},
+    shutdown::{drain, InFlightRequests, Shutdown},
+    signing::HmacSecret,
+    suppression::SuppressionList,
 };
-use actix_web::{dev::Server, web, App, HttpServer};
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, dev::Server, web, App, HttpServer};
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{postgres::PgPoolOptions, PgPool};
+use tokio::time::Instant;
 
 use tracing_actix_web::TracingLogger;
 
@@ -60,18 +87,28 @@ impl ApplicationBuilder {
                 .connect_lazy_with(db.connection_options())
         });
 
-        let email_client = email_client.unwrap_or_else(|| {
-            let email_client = &settings.email_client;
-            EmailClient::new(
-                email_client.base_url.clone(),
-                settings
-                    .email_client
-                    .sender_email()
-                    .expect("Invalid sender email address."),
-                email_client.authorization_token.clone(),
-                email_client.timeout(),
-            )
-        });
+        let email_client = email_client
+            .unwrap_or_else(|| {
+                let email_client = &settings.email_client;
+                EmailClient::new(
+                    email_client.base_url.clone(),
+                    settings
+                        .email_client
+                        .sender_email()
+                        .expect("Invalid sender email address."),
+                    email_client.authorization_token.clone(),
+                    email_client.timeout(),
+                )
+            })
+            .with_suppression_list(SuppressionList::new(db_pool.clone()));
+
+        let event_webhook_verifier = settings
+            .email_client
+            .event_webhook_verifier()
+            .expect("Invalid event webhook public key.");
+
+        let readiness = Readiness::new(settings.email_client.readiness_check);
+        let shutdown = Shutdown::new(readiness.clone(), settings.application.shutdown_timeout());
 
         let tcp_listener = tcp_listener.unwrap_or_else(|| {
             let Settings {
@@ -92,13 +129,22 @@ impl ApplicationBuilder {
             })
         });
 
-        let base_url = settings.application.base_url;
+        let secure_cookies = matches!(settings.application.env(), Env::Production);
 
         Application {
-            base_url,
+            base_url: settings.application.base_url,
+            hmac_secret: settings.application.hmac_secret,
+            metrics_token: settings.application.metrics_token,
+            secure_cookies,
+            authentication: settings.authentication,
             port: tcp_listener.local_addr().unwrap().port(),
             db_pool,
             email_client,
+            event_webhook_verifier,
+            newsletter: settings.newsletter,
+            readiness,
+            shutdown,
+            trusted_proxies: TrustedProxies::new(settings.application.trusted_proxies),
             tcp_listener,
         }
     }
@@ -116,10 +162,19 @@ impl Deref for ApplicationBaseUrl {
 
 pub struct Application {
     base_url: String,
+    hmac_secret: Secret<String>,
+    metrics_token: Option<Secret<String>>,
+    secure_cookies: bool,
+    authentication: AuthenticationSettings,
     port: u16,
     db_pool: PgPool,
     tcp_listener: TcpListener,
     email_client: EmailClient,
+    event_webhook_verifier: Option<EventWebhookVerifier>,
+    newsletter: NewsletterSettings,
+    readiness: Readiness,
+    shutdown: Shutdown,
+    trusted_proxies: TrustedProxies,
 }
 
 impl Application {
@@ -131,40 +186,303 @@ impl Application {
         self.port
     }
 
-    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.run()?.await
+    /// Whether the application reports being ready on `/health/ready`.
+    pub fn readiness(&self) -> Readiness {
+        self.readiness.clone()
+    }
+
+    /// Stops the application when triggered, as a signal would.
+    pub fn shutdown(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Serves requests, and sends scheduled issues as they come due, until
+    /// shutdown is triggered by Ctrl-C, `SIGTERM` or through `shutdown`.
+    ///
+    /// The server then stops accepting connections, and requests in flight
+    /// and background tasks get until the shutdown deadline to finish, before
+    /// the database pool is closed.
+    pub async fn run_until_stopped(self) -> Result<(), io::Error> {
+        let shutdown = self.shutdown();
+        let db_pool = self.db_pool.clone();
+
+        tokio::spawn(shutdown.clone().trigger_on_signal());
+
+        let scheduler = tokio::spawn(
+            self.issue_sender()
+                .run_scheduler_until_stopped(self.newsletter.clone()),
+        );
+        let server = self.run()?;
+        let server_handle = server.handle();
+        let mut server = tokio::spawn(server);
+
+        // The server only stops by itself when it fails
+        let stopped_server = tokio::select! {
+            outcome = &mut server => Some(outcome),
+            () = shutdown.triggered() => None,
+        };
+
+        shutdown.trigger();
+
+        let deadline = Instant::now() + shutdown.timeout();
+        let stop_server = async {
+            let outcome = match stopped_server {
+                Some(outcome) => outcome,
+                None => {
+                    // Stopping the server straight away can drop connections
+                    // with requests still in flight, so it first stops
+                    // accepting new ones until they're done, or the deadline
+                    server_handle.pause().await;
+                    let _ = tokio::time::timeout_at(deadline, shutdown.requests_finished()).await;
+                    server_handle.stop(true).await;
+                    server.await
+                }
+            };
+
+            // Requests can leave work behind them, such as emails to send
+            if tokio::time::timeout_at(deadline, shutdown.background_tasks_finished())
+                .await
+                .is_err()
+            {
+                tracing::warn!("Background tasks didn't finish before the shutdown deadline.");
+            }
+
+            outcome
+        };
+        let (outcome, _) = tokio::join!(stop_server, drain("scheduler", scheduler, deadline));
+
+        db_pool.close().await;
+
+        tracing::info!("Shut down.");
+
+        outcome.map_err(io::Error::other)?
+    }
+
+    fn issue_sender(&self) -> IssueSender {
+        IssueSender::new(
+            self.db_pool.clone(),
+            self.email_client.clone(),
+            self.base_url.clone(),
+            HmacSecret(self.hmac_secret.clone()),
+            self.shutdown(),
+        )
     }
 
     pub fn run(self) -> Result<Server, io::Error> {
+        let issue_sender = web::Data::new(self.issue_sender());
         let Self {
             base_url,
+            hmac_secret,
+            metrics_token,
+            secure_cookies,
+            authentication,
             tcp_listener,
             db_pool,
             email_client,
+            event_webhook_verifier,
+            readiness,
+            shutdown,
+            trusted_proxies,
             ..
         } = self;
 
         let base_url = web::Data::new(ApplicationBaseUrl(base_url));
         let db_pool = web::Data::new(db_pool);
         let email_client = web::Data::new(email_client);
+        let authentication = web::Data::new(authentication);
+        let event_webhook_verifier = web::Data::new(event_webhook_verifier);
+        let session_key = Key::from(hmac_secret.expose_secret().as_bytes());
+        let hmac_secret = web::Data::new(HmacSecret(hmac_secret));
+        let metrics_token = web::Data::new(metrics_token.map(MetricsToken));
+        let readiness = web::Data::new(readiness);
+        let shutdown_timeout = shutdown.timeout();
+        let shutdown = web::Data::new(shutdown);
+        let trusted_proxies = web::Data::new(trusted_proxies);
 
         let server = HttpServer::new(move || {
             App::new()
+                .wrap(
+                    CsrfProtection::default()
+                        .form_limit(MAX_ISSUE_FORM_BYTES)
+                        .exempt("/subscriptions")
+                        .exempt("/newsletters")
+                        .exempt("/newsletters/assets")
+                        .exempt("/newsletters/preview")
+                        .exempt("/newsletters/test")
+                        .exempt("/webhooks/sendgrid"),
+                )
+                .wrap(
+                    SessionMiddleware::builder(CookieSessionStore::default(), session_key.clone())
+                        .cookie_secure(secure_cookies)
+                        .build(),
+                )
+                .wrap(RequestMetrics)
                 .wrap(TracingLogger::default())
+                .wrap(InFlightRequests::new(shutdown.get_ref().clone()))
+                .route("/admin", web::get().to(admin_dashboard))
+                .route("/admin/audit", web::get().to(audit_log))
+                .route("/admin/audit.json", web::get().to(audit_log_json))
+                .route("/admin/logout", web::post().to(log_out))
+                .route("/admin/suppressions", web::get().to(list_suppressions))
+                .route("/admin/suppressions", web::post().to(add_suppression))
+                .route(
+                    "/admin/suppressions/{suppression_id}/delete",
+                    web::post().to(remove_suppression),
+                )
+                .route("/admin/two-factor", web::get().to(two_factor_settings))
+                .route(
+                    "/admin/two-factor/enroll",
+                    web::post().to(start_two_factor_enrollment),
+                )
+                .route(
+                    "/admin/two-factor/activate",
+                    web::post().to(confirm_two_factor_enrollment),
+                )
+                .route(
+                    "/admin/two-factor/disable",
+                    web::post().to(turn_off_two_factor),
+                )
+                .route("/admin/users", web::get().to(list_users))
+                .route("/admin/users", web::post().to(invite_user))
+                .route(
+                    "/admin/users/{user_id}/disable",
+                    web::post().to(disable_user),
+                )
+                .route(
+                    "/admin/users/{user_id}/password_reset",
+                    web::post().to(force_password_reset),
+                )
+                .route(
+                    "/admin/subscribers/export",
+                    web::get().to(export_subscriber),
+                )
+                .route("/admin/subscribers/erase", web::post().to(erase_subscriber))
+                .route(
+                    "/admin/subscribers/{subscriber_id}/consents",
+                    web::get().to(subscriber_consents),
+                )
+                .route(
+                    "/admin/subscribers/{subscriber_id}/engagement",
+                    web::get().to(subscriber_engagement),
+                )
+                .service(
+                    web::resource("/admin/newsletters")
+                        .app_data(issue_form_config())
+                        .route(web::get().to(list_issues))
+                        .route(web::post().to(create_issue)),
+                )
+                .route("/admin/newsletters/new", web::get().to(new_issue_form))
+                .service(
+                    web::resource("/admin/newsletters/{issue_id}")
+                        .app_data(issue_form_config())
+                        .route(web::get().to(show_issue))
+                        .route(web::post().to(update_issue)),
+                )
+                .route(
+                    "/admin/newsletters/{issue_id}/approve",
+                    web::post().to(approve_issue),
+                )
+                .route(
+                    "/admin/newsletters/{issue_id}/schedule",
+                    web::post().to(schedule_issue),
+                )
+                .route(
+                    "/admin/newsletters/{issue_id}/unschedule",
+                    web::post().to(unschedule_issue),
+                )
+                .route(
+                    "/admin/newsletters/{issue_id}/publish",
+                    web::post().to(publish_issue),
+                )
+                .route(
+                    "/admin/newsletters/{issue_id}/deliveries",
+                    web::get().to(delivery_report),
+                )
+                .route(
+                    "/admin/newsletters/{issue_id}/deliveries.json",
+                    web::get().to(delivery_report_json),
+                )
+                .route(
+                    "/admin/newsletters/{issue_id}/engagement",
+                    web::get().to(issue_engagement),
+                )
+                .route("/metrics", web::get().to(metrics))
                 .route("/newsletters", web::post().to(publish_newsletter))
+                .service(
+                    web::resource("/newsletters/assets")
+                        .app_data(web::PayloadConfig::new(MAX_ASSET_BYTES))
+                        .route(web::post().to(upload_asset)),
+                )
+                .route("/newsletters/preview", web::post().to(preview_newsletter))
+                .route("/newsletters/test", web::post().to(send_test_newsletter))
+                .route("/newsletters/{issue_id}", web::get().to(read_issue))
+                .route(
+                    "/newsletters/{issue_id}/assets/{asset_id}",
+                    web::get().to(read_issue_asset),
+                )
+                .route("/newsletters/{issue_id}/open", web::get().to(track_open))
+                .route("/newsletters/links/{link_id}", web::get().to(track_click))
                 .route("/health_check", web::get().to(health_check))
+                .route("/health/live", web::get().to(health_live))
+                .route("/health/ready", web::get().to(health_ready))
                 .route("/subscriptions", web::post().to(subscribe))
                 .route("/subscriptions/confirm", web::get().to(confirm))
+                .route("/subscriptions/data", web::get().to(subscriber_data))
+                .route(
+                    "/subscriptions/data/request",
+                    web::get().to(subscriber_data_request_form),
+                )
+                .route(
+                    "/subscriptions/data/request",
+                    web::post().to(request_subscriber_data),
+                )
+                .route(
+                    "/subscriptions/data/erase",
+                    web::post().to(erase_own_subscriber_data),
+                )
+                .route("/preferences", web::get().to(preferences_form))
+                .route("/preferences", web::post().to(update_preferences))
                 .route("/login", web::get().to(login_form))
                 .route("/login", web::post().to(login))
+                .route("/login/two-factor", web::get().to(two_factor_form))
+                .route("/login/two-factor", web::post().to(verify_two_factor))
+                .route("/password/forgot", web::get().to(forgot_password_form))
+                .route("/password/forgot", web::post().to(forgot_password))
+                .route("/password/set", web::get().to(set_password_form))
+                .route("/password/set", web::post().to(set_password))
+                .service(
+                    web::resource("/webhooks/sendgrid")
+                        .app_data(web::PayloadConfig::new(MAX_EVENT_BATCH_BYTES))
+                        .route(web::post().to(sendgrid_events)),
+                )
                 .route("/", web::get().to(home))
                 .app_data(base_url.clone())
                 .app_data(db_pool.clone())
                 .app_data(email_client.clone())
+                .app_data(authentication.clone())
+                .app_data(hmac_secret.clone())
+                .app_data(metrics_token.clone())
+                .app_data(event_webhook_verifier.clone())
+                .app_data(issue_sender.clone())
+                .app_data(readiness.clone())
+                .app_data(shutdown.clone())
+                .app_data(trusted_proxies.clone())
         })
         .listen(tcp_listener)?
+        // Stopping is up to `run_until_stopped`, as shutdown is coordinated
+        // with background tasks
+        .disable_signals()
+        .shutdown_timeout(shutdown_timeout.as_secs())
         .run();
 
         Ok(server)
     }
 }
+
+/// Issues are drafted with their Markdown, HTML and plain text contents in
+/// one form, and may be saved larger than they can be sent.
+const MAX_ISSUE_FORM_BYTES: usize = 1024 * 1024;
+
+fn issue_form_config() -> web::FormConfig {
+    web::FormConfig::default().limit(MAX_ISSUE_FORM_BYTES)
+}
@@ -0,0 +1,9 @@
+mod password;
+mod password_token;
+mod throttling;
+mod two_factor;
+
+pub use password::*;
+pub use password_token::*;
+pub use throttling::*;
+pub use two_factor::*;
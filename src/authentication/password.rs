@@ -0,0 +1,276 @@
+use anyhow::Context;
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
+    PasswordVerifier, Version,
+};
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::{settings::PasswordHashingSettings, telemetry::spawn_blocking_with_tracing};
+
+#[derive(thiserror::Error, Debug)]
+pub enum AuthError {
+    #[error("Invalid credentials.")]
+    InvalidCredentials(#[source] anyhow::Error),
+    #[error("Too many failed login attempts, try again later.")]
+    LockedOut { locked_until: DateTime<Utc> },
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+pub struct Credentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+/// Check `credentials` against the stored password hash.
+///
+/// When the password is right but its hash was computed with an older
+/// algorithm or weaker parameters than `settings`, it is re-hashed and
+/// stored, so raising the cost doesn't require users to reset passwords.
+#[tracing::instrument(name = "Validate credentials", skip(db_pool, settings, credentials))]
+pub async fn validate_credentials(
+    db_pool: &PgPool,
+    settings: &PasswordHashingSettings,
+    credentials: Credentials,
+) -> Result<Uuid, AuthError> {
+    let stored_credentials = get_stored_credentials(db_pool, &credentials.username).await?;
+    let hashing_settings = settings.clone();
+
+    let (user_id, stored_password_hash, upgraded_password_hash) =
+        spawn_blocking_with_tracing(move || {
+            let (user_id, stored_password_hash) = match stored_credentials {
+                Some(stored_credentials) => stored_credentials,
+                None => {
+                    // Hash the candidate anyway, so unknown usernames take as
+                    // long to reject as wrong passwords.
+                    compute_password_hash(&hashing_settings, credentials.password)?;
+
+                    return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                        "Unknown username."
+                    )));
+                }
+            };
+
+            verify_password_hash(stored_password_hash.clone(), credentials.password.clone())?;
+
+            let upgraded_password_hash =
+                if needs_rehash(stored_password_hash.expose_secret(), &hashing_settings) {
+                    Some(compute_password_hash(
+                        &hashing_settings,
+                        credentials.password,
+                    )?)
+                } else {
+                    None
+                };
+
+            Ok((user_id, stored_password_hash, upgraded_password_hash))
+        })
+        .await
+        .context("Failed to spawn blocking task.")??;
+
+    if let Some(upgraded_password_hash) = upgraded_password_hash {
+        // The password was right, failing to upgrade its hash shouldn't stop
+        // the user from logging in.
+        if let Err(error) = upgrade_password_hash(
+            db_pool,
+            user_id,
+            &stored_password_hash,
+            &upgraded_password_hash,
+        )
+        .await
+        {
+            tracing::warn!(
+                error.cause_chain = ?error,
+                "Failed to upgrade a password hash."
+            );
+        }
+    }
+
+    Ok(user_id)
+}
+
+#[tracing::instrument(name = "Get stored credentials", skip(db_pool, username))]
+async fn get_stored_credentials(
+    db_pool: &PgPool,
+    username: &str,
+) -> Result<Option<(Uuid, Secret<String>)>, anyhow::Error> {
+    // Disabled accounts, and accounts still waiting for a password to be set,
+    // are treated as unknown usernames.
+    let row: Option<_> = sqlx::query!(
+        r#"
+            SELECT id, password_hash
+            FROM users
+            WHERE username = $1 AND disabled_at IS NULL
+        "#,
+        username,
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to perform a query to validate auth credentials.")?
+    .and_then(|row| Some((row.id, Secret::new(row.password_hash?))));
+
+    Ok(row)
+}
+
+#[tracing::instrument(
+    name = "Verify password hash",
+    skip(expected_password_hash, password_candidate)
+)]
+pub fn verify_password_hash(
+    expected_password_hash: Secret<String>,
+    password_candidate: Secret<String>,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .context("Failed to parse hash in PHC string format.")?;
+
+    Argon2::default()
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .context("Invalid password.")
+        .map_err(AuthError::InvalidCredentials)
+}
+
+/// Whether `password_hash` was computed with another algorithm, version or
+/// parameters than the ones currently configured.
+fn needs_rehash(password_hash: &str, settings: &PasswordHashingSettings) -> bool {
+    let password_hash = match PasswordHash::new(password_hash) {
+        Ok(password_hash) => password_hash,
+        Err(_) => return true,
+    };
+
+    let params = match Params::try_from(&password_hash) {
+        Ok(params) => params,
+        Err(_) => return true,
+    };
+
+    password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+        || params.m_cost() != settings.memory_kib
+        || params.t_cost() != settings.iterations
+        || params.p_cost() != settings.parallelism
+}
+
+/// Only replaces the hash if it wasn't changed in the meantime, e.g. by a
+/// password reset.
+#[tracing::instrument(
+    name = "Upgrade password hash",
+    skip(db_pool, stored_password_hash, upgraded_password_hash)
+)]
+async fn upgrade_password_hash(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    stored_password_hash: &Secret<String>,
+    upgraded_password_hash: &Secret<String>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE users
+            SET password_hash = $1
+            WHERE id = $2 AND password_hash = $3
+        "#,
+        upgraded_password_hash.expose_secret(),
+        user_id,
+        stored_password_hash.expose_secret(),
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store upgraded password hash.")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Change password", skip(executor, settings, password))]
+pub async fn change_password<'e, E: PgExecutor<'e>>(
+    executor: E,
+    settings: &PasswordHashingSettings,
+    user_id: Uuid,
+    password: Secret<String>,
+) -> Result<(), anyhow::Error> {
+    let settings = settings.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(&settings, password))
+            .await?
+            .context("Failed to hash password.")?;
+
+    sqlx::query!(
+        r#"
+            UPDATE users
+            SET password_hash = $1
+            WHERE id = $2
+        "#,
+        password_hash.expose_secret(),
+        user_id
+    )
+    .execute(executor)
+    .await
+    .context("Failed to change user's password in the database.")?;
+
+    Ok(())
+}
+
+pub fn compute_password_hash(
+    settings: &PasswordHashingSettings,
+    password: Secret<String>,
+) -> Result<Secret<String>, anyhow::Error> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, settings.params()?)
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
+
+    Ok(Secret::new(password_hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::{password_hash::SaltString, Algorithm, Argon2, PasswordHasher, Version};
+    use secrecy::{ExposeSecret, Secret};
+
+    use super::{compute_password_hash, needs_rehash};
+    use crate::settings::PasswordHashingSettings;
+
+    fn settings(memory_kib: u32) -> PasswordHashingSettings {
+        PasswordHashingSettings {
+            memory_kib,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+
+    fn hash(settings: &PasswordHashingSettings) -> Secret<String> {
+        compute_password_hash(settings, Secret::new("password".into())).unwrap()
+    }
+
+    #[test]
+    fn hashes_with_current_parameters_are_kept() {
+        let settings = settings(4096);
+
+        assert!(!needs_rehash(hash(&settings).expose_secret(), &settings));
+    }
+
+    #[test]
+    fn hashes_with_outdated_parameters_need_a_rehash() {
+        let password_hash = hash(&settings(4096));
+
+        assert!(needs_rehash(password_hash.expose_secret(), &settings(8192)));
+    }
+
+    #[test]
+    fn hashes_with_other_algorithms_need_a_rehash() {
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        let argon2i_hash = Argon2::new(
+            Algorithm::Argon2i,
+            Version::V0x13,
+            settings(4096).params().unwrap(),
+        )
+        .hash_password(b"password", &salt)
+        .unwrap()
+        .to_string();
+
+        assert!(needs_rehash(&argon2i_hash, &settings(4096)));
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Why a user is being asked to choose a new password.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasswordTokenPurpose {
+    Invitation,
+    Reset,
+}
+
+impl PasswordTokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Invitation => "invitation",
+            Self::Reset => "reset",
+        }
+    }
+}
+
+/// Store a new single-use token allowing `user_id` to set their password.
+///
+/// Only a SHA-256 digest of the token is persisted, the plain token is
+/// returned so it can be emailed to the user. Tokens that were previously
+/// issued to the same user for the same purpose and never used are revoked.
+#[tracing::instrument(name = "Issue password token", skip(transaction))]
+pub async fn issue_password_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    purpose: PasswordTokenPurpose,
+    time_to_live: Duration,
+) -> Result<Secret<String>, anyhow::Error> {
+    let token = generate_password_token();
+
+    sqlx::query!(
+        r#"
+            DELETE FROM password_tokens
+            WHERE user_id = $1 AND purpose = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        purpose.as_str(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to revoke previous password tokens.")?;
+
+    sqlx::query!(
+        r#"
+            INSERT INTO password_tokens (token_hash, user_id, purpose, expires_at)
+            VALUES ($1, $2, $3, $4)
+        "#,
+        hash_password_token(token.expose_secret()),
+        user_id,
+        purpose.as_str(),
+        Utc::now() + time_to_live,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store password token.")?;
+
+    Ok(token)
+}
+
+/// Whether `user_id` was issued a token for `purpose`, still unused, in the
+/// last `cooldown`, so they aren't sent another one yet.
+///
+/// The user's row stays locked until the end of the transaction, for
+/// concurrent requests not to both find no recent token.
+#[tracing::instrument(name = "Check for a recent password token", skip(transaction))]
+pub async fn password_token_recently_issued(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    purpose: PasswordTokenPurpose,
+    cooldown: Duration,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM password_tokens t
+                WHERE t.user_id = u.id
+                    AND t.purpose = $2
+                    AND t.used_at IS NULL
+                    AND t.created_at > $3
+            ) AS "recently_issued!"
+            FROM users u
+            WHERE u.id = $1
+            FOR UPDATE OF u
+        "#,
+        user_id,
+        purpose.as_str(),
+        Utc::now() - cooldown,
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to check for a recent password token.")?;
+
+    Ok(row.recently_issued)
+}
+
+/// Look up the user a token was issued to, without using it up.
+#[tracing::instrument(name = "Get user_id from password token", skip(db_pool, token))]
+pub async fn get_user_id_from_password_token(
+    db_pool: &PgPool,
+    token: &Secret<String>,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT user_id
+            FROM password_tokens
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        "#,
+        hash_password_token(token.expose_secret()),
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve password token.")?;
+
+    Ok(row.map(|r| r.user_id))
+}
+
+/// Mark a token as used, returning the user it was issued to.
+///
+/// Returns `None` if the token is unknown, expired or was already used.
+#[tracing::instrument(name = "Consume password token", skip(transaction, token))]
+pub async fn consume_password_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &Secret<String>,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+            UPDATE password_tokens
+            SET used_at = now()
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+            RETURNING user_id
+        "#,
+        hash_password_token(token.expose_secret()),
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to consume password token.")?;
+
+    Ok(row.map(|r| r.user_id))
+}
+
+fn generate_password_token() -> Secret<String> {
+    let mut rng = thread_rng();
+
+    Secret::new(
+        std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(32)
+            .collect(),
+    )
+}
+
+fn hash_password_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
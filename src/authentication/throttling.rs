@@ -0,0 +1,260 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{is_two_factor_enabled, validate_credentials, AuthError, Credentials};
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    settings::{AuthenticationSettings, LoginThrottlingSettings},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LockoutScope {
+    Username,
+    IpAddress,
+}
+
+impl LockoutScope {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Username => "username",
+            Self::IpAddress => "ip_address",
+        }
+    }
+}
+
+/// Validate credentials, guarding against brute-force attacks.
+///
+/// Locked out usernames and IP addresses are rejected before the password is
+/// even hashed, and every recent failure delays the verification a bit more.
+#[tracing::instrument(name = "Authenticate", skip(db_pool, settings, credentials))]
+pub async fn authenticate(
+    db_pool: &PgPool,
+    settings: &AuthenticationSettings,
+    credentials: Credentials,
+    ip_address: Option<String>,
+) -> Result<Uuid, AuthError> {
+    let username = credentials.username.clone();
+    let ip_address = ip_address.as_deref();
+
+    ensure_not_locked_out(db_pool, &username, ip_address).await?;
+
+    let failures =
+        count_recent_failures(db_pool, &settings.throttling, &username, ip_address).await?;
+    let delay = settings
+        .throttling
+        .delay(failures.username.max(failures.ip_address));
+
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+
+    let outcome = validate_credentials(db_pool, &settings.password_hashing, credentials).await;
+
+    match &outcome {
+        // The attempt only counts as a success once the second factor is
+        // verified too, otherwise logging in with the password would reset
+        // the failures left by guessing codes.
+        Ok(user_id) if is_two_factor_enabled(db_pool, *user_id).await? => {}
+        Ok(_) => record_login_attempt(db_pool, &username, ip_address, true).await?,
+        Err(AuthError::InvalidCredentials(_)) => {
+            record_failed_login(db_pool, &settings.throttling, &username, ip_address).await?
+        }
+        Err(_) => {}
+    }
+
+    outcome
+}
+
+pub async fn ensure_not_locked_out(
+    db_pool: &PgPool,
+    username: &str,
+    ip_address: Option<&str>,
+) -> Result<(), AuthError> {
+    match get_active_lockout(db_pool, username, ip_address).await? {
+        Some(locked_until) => Err(AuthError::LockedOut { locked_until }),
+        None => Ok(()),
+    }
+}
+
+/// Count a failed attempt, locking out the username or IP address once
+/// they have failed too many times.
+#[tracing::instrument(name = "Record failed login", skip(db_pool, settings))]
+pub async fn record_failed_login(
+    db_pool: &PgPool,
+    settings: &LoginThrottlingSettings,
+    username: &str,
+    ip_address: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    record_login_attempt(db_pool, username, ip_address, false).await?;
+
+    let failures = count_recent_failures(db_pool, settings, username, ip_address).await?;
+
+    if failures.username >= settings.max_failures_per_username {
+        lock_out(db_pool, settings, LockoutScope::Username, username).await?;
+    }
+
+    if let Some(ip_address) = ip_address {
+        if failures.ip_address >= settings.max_failures_per_ip {
+            lock_out(db_pool, settings, LockoutScope::IpAddress, ip_address).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct RecentFailures {
+    username: i64,
+    ip_address: i64,
+}
+
+#[tracing::instrument(name = "Get active login lockout", skip(db_pool))]
+async fn get_active_lockout(
+    db_pool: &PgPool,
+    username: &str,
+    ip_address: Option<&str>,
+) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT MAX(locked_until) AS locked_until
+            FROM login_lockouts
+            WHERE locked_until > now()
+                AND ((scope = 'username' AND key = $1) OR (scope = 'ip_address' AND key = $2))
+        "#,
+        username,
+        ip_address,
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to retrieve login lockouts.")?;
+
+    Ok(row.locked_until)
+}
+
+/// Failures are counted over the sliding window, but only since the last
+/// lockout ended. For usernames, a successful login also resets the count.
+#[tracing::instrument(name = "Count recent login failures", skip(db_pool, settings))]
+async fn count_recent_failures(
+    db_pool: &PgPool,
+    settings: &LoginThrottlingSettings,
+    username: &str,
+    ip_address: Option<&str>,
+) -> Result<RecentFailures, anyhow::Error> {
+    let window_start = Utc::now() - settings.window();
+
+    let username_failures = sqlx::query!(
+        r#"
+            SELECT COUNT(*) AS "failures!"
+            FROM login_attempts
+            WHERE username = $1
+                AND NOT succeeded
+                AND attempted_at > GREATEST(
+                    $2,
+                    (SELECT MAX(attempted_at) FROM login_attempts WHERE username = $1 AND succeeded),
+                    (SELECT locked_until FROM login_lockouts WHERE scope = 'username' AND key = $1)
+                )
+        "#,
+        username,
+        window_start,
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to count login failures by username.")?
+    .failures;
+
+    let ip_address_failures = match ip_address {
+        Some(ip_address) => {
+            sqlx::query!(
+                r#"
+                    SELECT COUNT(*) AS "failures!"
+                    FROM login_attempts
+                    WHERE ip_address = $1
+                        AND NOT succeeded
+                        AND attempted_at > GREATEST(
+                            $2,
+                            (SELECT locked_until FROM login_lockouts WHERE scope = 'ip_address' AND key = $1)
+                        )
+                "#,
+                ip_address,
+                window_start,
+            )
+            .fetch_one(db_pool)
+            .await
+            .context("Failed to count login failures by IP address.")?
+            .failures
+        }
+        None => 0,
+    };
+
+    Ok(RecentFailures {
+        username: username_failures,
+        ip_address: ip_address_failures,
+    })
+}
+
+#[tracing::instrument(name = "Record login attempt", skip(db_pool))]
+pub async fn record_login_attempt(
+    db_pool: &PgPool,
+    username: &str,
+    ip_address: Option<&str>,
+    succeeded: bool,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO login_attempts (username, ip_address, succeeded)
+            VALUES ($1, $2, $3)
+        "#,
+        username,
+        ip_address,
+        succeeded,
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to record login attempt.")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Lock out login attempts", skip(db_pool, settings))]
+async fn lock_out(
+    db_pool: &PgPool,
+    settings: &LoginThrottlingSettings,
+    scope: LockoutScope,
+    key: &str,
+) -> Result<(), anyhow::Error> {
+    let locked_until = Utc::now() + settings.lockout();
+
+    sqlx::query!(
+        r#"
+            INSERT INTO login_lockouts (scope, key, locked_until)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (scope, key) DO UPDATE SET locked_until = EXCLUDED.locked_until
+        "#,
+        scope.as_str(),
+        key,
+        locked_until,
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store login lockout.")?;
+
+    AuditEvent::new(AuditAction::LoginLockedOut)
+        .subject(key)
+        .details(serde_json::json!({
+            "scope": scope.as_str(),
+            "locked_until": locked_until.to_rfc3339(),
+        }))
+        .record(db_pool)
+        .await?;
+
+    tracing::warn!(
+        lockout.scope = scope.as_str(),
+        lockout.key = key,
+        lockout.until = %locked_until,
+        "Too many failed logins, locking out further attempts."
+    );
+
+    Ok(())
+}
@@ -0,0 +1,485 @@
+use anyhow::Context;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use totp_rs::{Algorithm, Secret as TotpSecret, TOTP};
+use uuid::Uuid;
+
+use super::{ensure_not_locked_out, record_failed_login, record_login_attempt, AuthError};
+use crate::settings::LoginThrottlingSettings;
+
+const ISSUER: &str = "zero2prod";
+const DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+/// How many steps before and after the current one we accept, to make up for
+/// clock drift between our servers and the user's device.
+const ALLOWED_DRIFT_STEPS: u64 = 1;
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+/// Where a user stands with two-factor authentication.
+pub enum TwoFactorStatus {
+    Disabled,
+    /// A secret was generated but no code was verified against it yet, so it
+    /// isn't required to log in.
+    PendingActivation {
+        secret: Secret<String>,
+        otpauth_uri: String,
+    },
+    Enabled {
+        remaining_recovery_codes: i64,
+    },
+}
+
+struct TotpRecord {
+    username: String,
+    secret: Option<Secret<String>>,
+    is_enabled: bool,
+    last_used_step: Option<i64>,
+}
+
+#[tracing::instrument(name = "Get two-factor status", skip(db_pool))]
+pub async fn get_two_factor_status(
+    db_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<TwoFactorStatus, anyhow::Error> {
+    let record = get_totp_record(db_pool, user_id).await?;
+
+    if record.is_enabled {
+        let remaining_recovery_codes = sqlx::query!(
+            r#"
+                SELECT COUNT(*) AS "count!"
+                FROM totp_recovery_codes
+                WHERE user_id = $1 AND used_at IS NULL
+            "#,
+            user_id
+        )
+        .fetch_one(db_pool)
+        .await
+        .context("Failed to count remaining recovery codes.")?
+        .count;
+
+        return Ok(TwoFactorStatus::Enabled {
+            remaining_recovery_codes,
+        });
+    }
+
+    match record.secret {
+        Some(secret) => {
+            let otpauth_uri = build_totp(&secret, &record.username)?.get_url();
+
+            Ok(TwoFactorStatus::PendingActivation {
+                secret,
+                otpauth_uri,
+            })
+        }
+        None => Ok(TwoFactorStatus::Disabled),
+    }
+}
+
+#[tracing::instrument(name = "Check if two-factor is enabled", skip(db_pool))]
+pub async fn is_two_factor_enabled(db_pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    Ok(get_totp_record(db_pool, user_id).await?.is_enabled)
+}
+
+/// Generate a fresh secret for `user_id`, replacing any enrollment that was
+/// started but never activated. Users who already enabled two-factor
+/// authentication have to disable it first.
+#[tracing::instrument(name = "Begin two-factor enrollment", skip(db_pool))]
+pub async fn begin_two_factor_enrollment(
+    db_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let secret = TotpSecret::generate_secret().to_encoded().to_string();
+
+    sqlx::query!(
+        r#"
+            UPDATE users
+            SET totp_secret = $1, totp_last_used_step = NULL
+            WHERE id = $2 AND totp_enabled_at IS NULL
+        "#,
+        secret,
+        user_id
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store a new TOTP secret.")?;
+
+    Ok(())
+}
+
+/// Enable two-factor authentication once the user proved their authenticator
+/// app generates valid codes.
+///
+/// Returns the recovery codes to show the user, only their digests are
+/// stored. `None` means `code` didn't match the pending secret.
+#[tracing::instrument(name = "Activate two-factor", skip(db_pool, code))]
+pub async fn activate_two_factor(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<Option<Vec<Secret<String>>>, anyhow::Error> {
+    let record = get_totp_record(db_pool, user_id).await?;
+
+    let secret = match (&record.secret, record.is_enabled) {
+        (Some(secret), false) => secret,
+        _ => return Ok(None),
+    };
+
+    let step = match matching_step(&build_totp(secret, &record.username)?, code, unix_time()) {
+        Some(step) => step,
+        None => return Ok(None),
+    };
+
+    let recovery_codes: Vec<Secret<String>> = std::iter::repeat_with(generate_recovery_code)
+        .take(RECOVERY_CODE_COUNT)
+        .collect();
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    sqlx::query!(
+        r#"
+            UPDATE users
+            SET totp_enabled_at = now(), totp_last_used_step = $1
+            WHERE id = $2
+        "#,
+        step as i64,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to enable two-factor authentication.")?;
+
+    replace_recovery_codes(&mut transaction, user_id, &recovery_codes).await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to enable two-factor authentication.")?;
+
+    Ok(Some(recovery_codes))
+}
+
+#[tracing::instrument(name = "Disable two-factor", skip(db_pool))]
+pub async fn disable_two_factor(db_pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    sqlx::query!(
+        r#"
+            UPDATE users
+            SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_used_step = NULL
+            WHERE id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to disable two-factor authentication.")?;
+
+    replace_recovery_codes(&mut transaction, user_id, &[]).await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to disable two-factor authentication.")?;
+
+    Ok(())
+}
+
+/// Check a code from the user's authenticator app, or one of their unused
+/// recovery codes.
+///
+/// Failures count towards the same lockouts as wrong passwords, otherwise
+/// anyone holding a password could try every possible code. Codes that were
+/// already used don't: whoever sends one knew it, and clients making a few
+/// requests in a row run into it until the next code comes up.
+#[tracing::instrument(name = "Authenticate second factor", skip(db_pool, settings, code))]
+pub async fn authenticate_second_factor(
+    db_pool: &PgPool,
+    settings: &LoginThrottlingSettings,
+    user_id: Uuid,
+    code: &str,
+    ip_address: Option<&str>,
+) -> Result<(), AuthError> {
+    let record = get_totp_record(db_pool, user_id).await?;
+
+    ensure_not_locked_out(db_pool, &record.username, ip_address).await?;
+
+    let check = if looks_like_totp_code(code) {
+        use_totp_code(db_pool, user_id, &record, code).await?
+    } else {
+        use_recovery_code(db_pool, user_id, code).await?
+    };
+
+    match check {
+        CodeCheck::Valid => {
+            record_login_attempt(db_pool, &record.username, ip_address, true).await?;
+            Ok(())
+        }
+        CodeCheck::AlreadyUsed => Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+            "The two-factor code was already used."
+        ))),
+        CodeCheck::Invalid => {
+            record_failed_login(db_pool, settings, &record.username, ip_address).await?;
+            Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                "Invalid two-factor code."
+            )))
+        }
+    }
+}
+
+enum CodeCheck {
+    Valid,
+    Invalid,
+    /// A TOTP code for a time step that was already used.
+    AlreadyUsed,
+}
+
+#[tracing::instrument(name = "Get TOTP record", skip(db_pool))]
+async fn get_totp_record(db_pool: &PgPool, user_id: Uuid) -> Result<TotpRecord, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT username, totp_secret, totp_enabled_at, totp_last_used_step
+            FROM users
+            WHERE id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to retrieve two-factor settings.")?;
+
+    Ok(TotpRecord {
+        username: row.username,
+        is_enabled: row.totp_enabled_at.is_some() && row.totp_secret.is_some(),
+        secret: row.totp_secret.map(Secret::new),
+        last_used_step: row.totp_last_used_step,
+    })
+}
+
+/// A code is only accepted for a time step later than the last one used, so
+/// an intercepted code can't be replayed. That makes for one code every 30
+/// seconds at most.
+async fn use_totp_code(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    record: &TotpRecord,
+    code: &str,
+) -> Result<CodeCheck, anyhow::Error> {
+    let secret = match (&record.secret, record.is_enabled) {
+        (Some(secret), true) => secret,
+        _ => return Ok(CodeCheck::Invalid),
+    };
+
+    let step = match matching_step(&build_totp(secret, &record.username)?, code, unix_time()) {
+        Some(step) => step as i64,
+        None => return Ok(CodeCheck::Invalid),
+    };
+
+    if record.last_used_step.is_some_and(|last| step <= last) {
+        return Ok(CodeCheck::AlreadyUsed);
+    }
+
+    let result = sqlx::query!(
+        r#"
+            UPDATE users
+            SET totp_last_used_step = $1
+            WHERE id = $2 AND (totp_last_used_step IS NULL OR totp_last_used_step < $1)
+        "#,
+        step,
+        user_id
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to record the last used TOTP step.")?;
+
+    // Another request used a code of the same step in the meantime
+    Ok(if result.rows_affected() == 1 {
+        CodeCheck::Valid
+    } else {
+        CodeCheck::AlreadyUsed
+    })
+}
+
+async fn use_recovery_code(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<CodeCheck, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+            UPDATE totp_recovery_codes
+            SET used_at = now()
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code)
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to consume recovery code.")?;
+
+    Ok(if result.rows_affected() == 1 {
+        CodeCheck::Valid
+    } else {
+        CodeCheck::Invalid
+    })
+}
+
+async fn replace_recovery_codes(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: Uuid,
+    recovery_codes: &[Secret<String>],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete recovery codes.")?;
+
+    for code in recovery_codes {
+        sqlx::query!(
+            r#"
+                INSERT INTO totp_recovery_codes (user_id, code_hash)
+                VALUES ($1, $2)
+            "#,
+            user_id,
+            hash_recovery_code(code.expose_secret())
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to store recovery code.")?;
+    }
+
+    Ok(())
+}
+
+fn build_totp(secret: &Secret<String>, username: &str) -> Result<TOTP, anyhow::Error> {
+    let secret = TotpSecret::Encoded(secret.expose_secret().clone())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("Invalid TOTP secret: {:?}", e))?;
+
+    // Colons separate the issuer from the account name in otpauth URIs.
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        0,
+        STEP_SECONDS,
+        secret,
+        Some(ISSUER.to_string()),
+        username.replace(':', "_"),
+    )
+    .context("Failed to build TOTP generator.")
+}
+
+/// The time step `code` was generated for, if it falls within the allowed
+/// drift around `unix_time`.
+fn matching_step(totp: &TOTP, code: &str, unix_time: u64) -> Option<u64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    let current_step = unix_time / STEP_SECONDS;
+
+    (current_step.saturating_sub(ALLOWED_DRIFT_STEPS)..=current_step + ALLOWED_DRIFT_STEPS)
+        .find(|step| totp.check(&code, step * STEP_SECONDS))
+}
+
+fn looks_like_totp_code(code: &str) -> bool {
+    let digits: Vec<char> = code.chars().filter(|c| !c.is_whitespace()).collect();
+
+    digits.len() == DIGITS && digits.iter().all(char::is_ascii_digit)
+}
+
+fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("System time is before the UNIX epoch")
+        .as_secs()
+}
+
+/// Recovery codes are shown as two dash-separated groups for readability.
+fn generate_recovery_code() -> Secret<String> {
+    let mut rng = thread_rng();
+    let code: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .take(RECOVERY_CODE_LENGTH)
+        .collect();
+    let (head, tail) = code.split_at(RECOVERY_CODE_LENGTH / 2);
+
+    Secret::new(format!("{}-{}", head, tail))
+}
+
+/// Users may type recovery codes with or without the dash, in any case.
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_totp() -> TOTP {
+        let secret = Secret::new(TotpSecret::generate_secret().to_encoded().to_string());
+
+        build_totp(&secret, "ursula:le-guin").unwrap()
+    }
+
+    #[test]
+    fn codes_from_adjacent_steps_are_accepted() {
+        let totp = test_totp();
+        let now = 1_700_000_000;
+        let current_step = now / STEP_SECONDS;
+
+        for step in [current_step - 1, current_step, current_step + 1] {
+            let code = totp.generate(step * STEP_SECONDS);
+
+            assert_eq!(matching_step(&totp, &code, now), Some(step));
+        }
+    }
+
+    #[test]
+    fn codes_from_distant_steps_are_rejected() {
+        let totp = test_totp();
+        let now = 1_700_000_000;
+
+        let code = totp.generate(now - 3 * STEP_SECONDS);
+
+        assert_eq!(matching_step(&totp, &code, now), None);
+    }
+
+    #[test]
+    fn otpauth_uri_names_the_issuer_and_account() {
+        let uri = test_totp().get_url();
+
+        assert!(uri.starts_with("otpauth://totp/zero2prod:ursula_le-guin?"));
+    }
+
+    #[test]
+    fn recovery_codes_are_normalized_before_hashing() {
+        assert_eq!(
+            hash_recovery_code("abcde-12345"),
+            hash_recovery_code(" ABCDE12345 ")
+        );
+    }
+
+    #[test]
+    fn only_six_digits_look_like_a_totp_code() {
+        assert!(looks_like_totp_code("123456"));
+        assert!(looks_like_totp_code("123 456"));
+        assert!(!looks_like_totp_code("12345"));
+        assert!(!looks_like_totp_code("abcde-12345"));
+    }
+}
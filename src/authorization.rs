@@ -0,0 +1,244 @@
+use std::{future::Future, pin::Pin};
+
+use actix_web::{
+    dev::Payload,
+    http::header::{ContentType, LOCATION},
+    web, FromRequest, HttpRequest, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{domain::UserRole, error_chain_fmt, session_state::TypedSession};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    PublishNewsletter,
+    ManageUsers,
+    ViewReports,
+    ViewAuditLog,
+    ManageSubscribers,
+}
+
+impl Permission {
+    pub fn is_granted_to(&self, role: UserRole) -> bool {
+        match self {
+            Self::PublishNewsletter => matches!(role, UserRole::Admin | UserRole::Editor),
+            Self::ManageUsers => matches!(role, UserRole::Admin),
+            Self::ViewReports => true,
+            Self::ViewAuditLog => matches!(role, UserRole::Admin),
+            Self::ManageSubscribers => matches!(role, UserRole::Admin),
+        }
+    }
+}
+
+impl std::fmt::Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PublishNewsletter => write!(f, "publish newsletters"),
+            Self::ManageUsers => write!(f, "manage users"),
+            Self::ViewReports => write!(f, "view reports"),
+            Self::ViewAuditLog => write!(f, "view the audit log"),
+            Self::ManageSubscribers => write!(f, "manage subscribers"),
+        }
+    }
+}
+
+/// Every denial goes through this error, so that routes answer a missing
+/// permission with the same `403 Forbidden` response.
+#[derive(thiserror::Error)]
+pub enum AuthorizationError {
+    #[error("You are not allowed to {0}.")]
+    Forbidden(Permission),
+    #[error("You must be logged in.")]
+    Unauthenticated,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AuthorizationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for AuthorizationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::Unauthenticated => StatusCode::SEE_OTHER,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::Forbidden(_) => HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+            Self::Unauthenticated => HttpResponse::SeeOther()
+                .insert_header((LOCATION, "/login"))
+                .finish(),
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AuthorizedUser {
+    pub id: Uuid,
+    pub username: String,
+    pub role: UserRole,
+    session_version: i32,
+}
+
+impl AuthorizedUser {
+    pub fn require(&self, permission: Permission) -> Result<(), AuthorizationError> {
+        if permission.is_granted_to(self.role) {
+            Ok(())
+        } else {
+            Err(AuthorizationError::Forbidden(permission))
+        }
+    }
+}
+
+/// Routes behind a login form take an `AuthorizedUser` argument: the user
+/// is looked up from the session, and anonymous visitors are sent to the
+/// login page, as are those whose sessions were revoked since they logged
+/// in.
+impl FromRequest for AuthorizedUser {
+    type Error = AuthorizationError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let session = TypedSession::from_request(req, payload).into_inner();
+        let db_pool = req.app_data::<web::Data<PgPool>>().cloned();
+
+        Box::pin(async move {
+            let session =
+                session.map_err(|e| anyhow::anyhow!("Failed to read the session: {e}"))?;
+            let user_id = session
+                .get_user_id()
+                .context("Failed to read the user id from the session.")?
+                .ok_or(AuthorizationError::Unauthenticated)?;
+            // Sessions older than versions stand for the first one
+            let session_version = session
+                .get_session_version()
+                .context("Failed to read the session version from the session.")?
+                .unwrap_or_default();
+            let db_pool = db_pool.context("The database pool is not registered.")?;
+
+            let user = get_authorized_user(&db_pool, user_id).await?;
+
+            if user.session_version != session_version {
+                return Err(AuthorizationError::Unauthenticated);
+            }
+
+            Ok(user)
+        })
+    }
+}
+
+/// Guard for routes acting on behalf of an authenticated user.
+///
+/// Loads the user's role and fails with `AuthorizationError::Forbidden`
+/// unless it grants `permission`.
+#[tracing::instrument(name = "Authorize user", skip(db_pool))]
+pub async fn authorize(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    permission: Permission,
+) -> Result<AuthorizedUser, AuthorizationError> {
+    let user = get_authorized_user(db_pool, user_id).await?;
+
+    user.require(permission)?;
+
+    Ok(user)
+}
+
+/// Disabled or deleted users are no longer authenticated, even if their
+/// session is still alive.
+#[tracing::instrument(name = "Get user role", skip(db_pool))]
+pub async fn get_authorized_user(
+    db_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<AuthorizedUser, AuthorizationError> {
+    let row = sqlx::query!(
+        r#"
+            SELECT username, role, session_version
+            FROM users
+            WHERE id = $1 AND disabled_at IS NULL
+        "#,
+        user_id,
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve the role of a user.")?
+    .ok_or(AuthorizationError::Unauthenticated)?;
+
+    let role = UserRole::parse(row.role).map_err(anyhow::Error::msg)?;
+
+    Ok(AuthorizedUser {
+        id: user_id,
+        username: row.username,
+        role,
+        session_version: row.session_version,
+    })
+}
+
+/// Stored in the session on login, for it to be revoked along with the
+/// user's other sessions.
+#[tracing::instrument(name = "Get the session version of a user", skip(db_pool))]
+pub async fn get_session_version(db_pool: &PgPool, user_id: Uuid) -> Result<i32, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT session_version
+            FROM users
+            WHERE id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to retrieve the session version of a user.")?;
+
+    Ok(row.session_version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Permission;
+    use crate::domain::UserRole;
+
+    #[test]
+    fn admins_are_granted_every_permission() {
+        for permission in [
+            Permission::PublishNewsletter,
+            Permission::ManageUsers,
+            Permission::ViewReports,
+            Permission::ViewAuditLog,
+            Permission::ManageSubscribers,
+        ] {
+            assert!(permission.is_granted_to(UserRole::Admin));
+        }
+    }
+
+    #[test]
+    fn editors_can_publish_but_not_manage_users() {
+        assert!(Permission::PublishNewsletter.is_granted_to(UserRole::Editor));
+        assert!(Permission::ViewReports.is_granted_to(UserRole::Editor));
+        assert!(!Permission::ManageUsers.is_granted_to(UserRole::Editor));
+        assert!(!Permission::ViewAuditLog.is_granted_to(UserRole::Editor));
+        assert!(!Permission::ManageSubscribers.is_granted_to(UserRole::Editor));
+    }
+
+    #[test]
+    fn viewers_can_only_view_reports() {
+        assert!(Permission::ViewReports.is_granted_to(UserRole::Viewer));
+        assert!(!Permission::PublishNewsletter.is_granted_to(UserRole::Viewer));
+        assert!(!Permission::ManageUsers.is_granted_to(UserRole::Viewer));
+        assert!(!Permission::ViewAuditLog.is_granted_to(UserRole::Viewer));
+        assert!(!Permission::ManageSubscribers.is_granted_to(UserRole::Viewer));
+    }
+}
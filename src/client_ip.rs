@@ -0,0 +1,91 @@
+use std::net::IpAddr;
+
+use actix_web::{web, HttpRequest};
+use ipnet::IpNet;
+
+/// The load balancers and reverse proxies requests come through.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    pub fn new(networks: Vec<IpNet>) -> Self {
+        Self(networks)
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|network| network.contains(ip))
+    }
+}
+
+/// The address a request comes from, as logins are throttled and audited by
+/// it. `X-Forwarded-For` is only believed as far as it was written by
+/// trusted proxies, since clients can send whatever they like in it: the
+/// client is the last address before the trusted proxies.
+pub fn client_ip(request: &HttpRequest) -> Option<String> {
+    let peer = request.peer_addr()?.ip();
+    let Some(trusted_proxies) = request.app_data::<web::Data<TrustedProxies>>() else {
+        return Some(peer.to_string());
+    };
+
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer.to_string());
+    }
+
+    let mut client = peer;
+
+    for forwarded in request
+        .headers()
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+    {
+        match forwarded.trim().parse::<IpAddr>() {
+            Ok(ip) => {
+                client = ip;
+
+                if !trusted_proxies.contains(&ip) {
+                    break;
+                }
+            }
+            // Whatever comes before can't be told apart from made up
+            Err(_) => break,
+        }
+    }
+
+    Some(client.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test::TestRequest, web};
+
+    use super::{client_ip, TrustedProxies};
+
+    fn request_through(peer: &str, forwarded_for: &str) -> TestRequest {
+        TestRequest::default()
+            .peer_addr(format!("{peer}:4321").parse().unwrap())
+            .insert_header(("X-Forwarded-For", forwarded_for))
+            .app_data(web::Data::new(TrustedProxies::new(vec!["10.0.0.0/8"
+                .parse()
+                .unwrap()])))
+    }
+
+    #[test]
+    fn forwarded_addresses_from_untrusted_peers_are_ignored() {
+        let request = request_through("203.0.113.7", "198.51.100.1").to_http_request();
+
+        assert_eq!(client_ip(&request).as_deref(), Some("203.0.113.7"));
+    }
+
+    #[test]
+    fn the_client_is_the_last_address_before_trusted_proxies() {
+        // The first address was made up by the client
+        let request =
+            request_through("10.0.0.2", "198.51.100.1, 203.0.113.7, 10.0.0.1").to_http_request();
+
+        assert_eq!(client_ip(&request).as_deref(), Some("203.0.113.7"));
+    }
+}
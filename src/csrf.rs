@@ -0,0 +1,248 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+use actix_http::h1;
+use actix_session::{Session, SessionExt};
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::PayloadError,
+    http::{header::ContentType, Method},
+    web, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+};
+use futures_util::StreamExt;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+use crate::views;
+
+/// The name of the hidden form field carrying the token.
+pub const CSRF_FIELD_NAME: &str = "csrf_token";
+/// Clients that don't post forms can send the token in this header instead.
+pub const CSRF_HEADER_NAME: &str = "X-CSRF-Token";
+
+const CSRF_SESSION_KEY: &str = "csrf_token";
+const CSRF_TOKEN_LENGTH: usize = 32;
+const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+/// The same as `web::PayloadConfig`'s default.
+const DEFAULT_FORM_LIMIT: usize = 256 * 1024;
+
+/// The anti-forgery token of the current session, to embed in HTML forms
+/// with `views::csrf::input`. Extracting it issues one if the session has
+/// none yet, so only pages rendering a form set the session cookie.
+#[derive(Clone, Debug)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromRequest for CsrfToken {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<CsrfToken, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(session_token(&req.get_session()))
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CsrfFormField {
+    csrf_token: Option<String>,
+}
+
+/// Issues a random token per session and rejects state-changing requests
+/// that don't send it back, either as the `csrf_token` form field or in the
+/// `X-CSRF-Token` header.
+///
+/// Must be wrapped by the session middleware. Endpoints which don't rely on
+/// the session cookie, like those using 'Basic' auth or public sign-up
+/// forms, can be exempted by path.
+///
+/// Forms are read before routing, so the limit of their size must be at
+/// least that of the largest form of the application.
+#[derive(Clone, Debug)]
+pub struct CsrfProtection {
+    exempt_paths: Vec<String>,
+    form_limit: usize,
+}
+
+impl CsrfProtection {
+    pub fn exempt(mut self, path: &str) -> Self {
+        self.exempt_paths.push(path.to_string());
+        self
+    }
+
+    pub fn form_limit(mut self, limit: usize) -> Self {
+        self.form_limit = limit;
+        self
+    }
+}
+
+impl Default for CsrfProtection {
+    fn default() -> Self {
+        Self {
+            exempt_paths: Vec::new(),
+            form_limit: DEFAULT_FORM_LIMIT,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for CsrfProtection
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = CsrfMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfMiddleware {
+            service: Rc::new(service),
+            exempt_paths: Rc::new(self.exempt_paths.clone()),
+            form_limit: self.form_limit,
+        }))
+    }
+}
+
+pub struct CsrfMiddleware<S> {
+    service: Rc<S>,
+    exempt_paths: Rc<Vec<String>>,
+    form_limit: usize,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let is_exempt = self.exempt_paths.iter().any(|path| path == req.path());
+        let form_limit = self.form_limit;
+
+        Box::pin(async move {
+            if is_safe_method(req.method()) || is_exempt {
+                return service.call(req).await.map(|res| res.map_into_left_body());
+            }
+
+            // A session without a token never rendered a form, so nothing
+            // it submits can be genuine.
+            let expected_token = req.get_session().get::<String>(CSRF_SESSION_KEY)?;
+            let submitted_token = submitted_token(&mut req, form_limit).await?;
+
+            if !submitted_token
+                .zip(expected_token)
+                .is_some_and(|(submitted, expected)| {
+                    constant_time_eq(submitted.as_bytes(), expected.as_bytes())
+                })
+            {
+                tracing::warn!(
+                    http.method = %req.method(),
+                    http.target = req.path(),
+                    "Rejected a request with a missing or invalid CSRF token."
+                );
+
+                let response = HttpResponse::Forbidden()
+                    .content_type(ContentType::html())
+                    .body(views::csrf::forbidden().into_string());
+
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+
+            service.call(req).await.map(|res| res.map_into_left_body())
+        })
+    }
+}
+
+fn is_safe_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Read the token from the session, issuing one if there's none yet.
+fn session_token(session: &Session) -> Result<CsrfToken, actix_web::Error> {
+    if let Some(token) = session.get::<String>(CSRF_SESSION_KEY)? {
+        return Ok(CsrfToken(token));
+    }
+
+    let mut rng = thread_rng();
+    let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(CSRF_TOKEN_LENGTH)
+        .collect();
+
+    session.insert(CSRF_SESSION_KEY, &token)?;
+
+    Ok(CsrfToken(token))
+}
+
+/// The header wins over the form field. Reading the form consumes the
+/// payload, so it's put back for the handler to extract.
+async fn submitted_token(
+    req: &mut ServiceRequest,
+    form_limit: usize,
+) -> Result<Option<String>, actix_web::Error> {
+    if let Some(value) = req.headers().get(CSRF_HEADER_NAME) {
+        return Ok(value.to_str().ok().map(String::from));
+    }
+
+    if req.content_type() != FORM_CONTENT_TYPE {
+        return Ok(None);
+    }
+
+    let mut body = web::BytesMut::new();
+    let mut stream = req.take_payload();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > form_limit {
+            return Err(PayloadError::Overflow.into());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let body = body.freeze();
+    let token = std::str::from_utf8(&body)
+        .ok()
+        .and_then(|body| web::Query::<CsrfFormField>::from_query(body).ok())
+        .and_then(|form| form.into_inner().csrf_token);
+
+    let (_, mut payload) = h1::Payload::create(true);
+    payload.unread_data(body);
+    req.set_payload(payload.into());
+
+    Ok(token)
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::constant_time_eq;
+
+    #[test]
+    fn equal_tokens_match() {
+        assert!(constant_time_eq(b"token", b"token"));
+    }
+
+    #[test]
+    fn different_tokens_do_not_match() {
+        assert!(!constant_time_eq(b"token", b"tokem"));
+        assert!(!constant_time_eq(b"token", b"token-but-longer"));
+        assert!(!constant_time_eq(b"", b"token"));
+    }
+}
@@ -0,0 +1,322 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{DeliveryStatus, SubscriberEmail},
+    email_client::{EmailDelivery, SendEmailError},
+};
+
+/// How many failed deliveries a report lists one by one.
+const LISTED_FAILURES: i64 = 100;
+
+/// Every recipient of an issue starts out `queued`, before any email is
+/// sent.
+#[tracing::instrument(name = "Queue the deliveries of a newsletter issue", skip_all)]
+pub async fn queue_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_ids: &[Uuid],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO newsletter_deliveries (newsletter_issue_id, subscriber_id)
+            SELECT $1, subscriber_id
+            FROM UNNEST($2::uuid[]) AS subscriber_id
+        "#,
+        issue_id,
+        subscriber_ids
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to queue the deliveries of a newsletter issue.")?;
+
+    Ok(())
+}
+
+/// A subscriber still waiting for their email of an issue.
+pub struct QueuedDelivery {
+    pub subscriber_id: Uuid,
+    pub email: SubscriberEmail,
+    pub preferences_token: String,
+}
+
+/// The recipients of an issue who haven't been sent their email yet.
+#[tracing::instrument(
+    name = "Get the queued deliveries of a newsletter issue",
+    skip(db_pool)
+)]
+pub async fn queued_deliveries(
+    db_pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Vec<Result<QueuedDelivery, anyhow::Error>>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT d.subscriber_id, s.email, s.preferences_token
+            FROM newsletter_deliveries d
+            JOIN subscriptions s ON s.id = d.subscriber_id
+            WHERE d.newsletter_issue_id = $1 AND d.status = 'queued'
+            ORDER BY d.queued_at, d.subscriber_id
+        "#,
+        issue_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve the queued deliveries of a newsletter issue.")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            Ok(QueuedDelivery {
+                subscriber_id: r.subscriber_id,
+                email: SubscriberEmail::parse(r.email).map_err(anyhow::Error::msg)?,
+                preferences_token: r.preferences_token,
+            })
+        })
+        .collect())
+}
+
+/// Stores what became of the email of an issue to one subscriber.
+#[tracing::instrument(name = "Record a newsletter delivery", skip(db_pool, outcome))]
+pub async fn record_delivery(
+    db_pool: &PgPool,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    outcome: &Result<EmailDelivery, SendEmailError>,
+) -> Result<DeliveryStatus, anyhow::Error> {
+    let (status, message_id, failure_reason) = match outcome {
+        Ok(EmailDelivery::Sent { message_id }) => (DeliveryStatus::Sent, message_id.clone(), None),
+        Ok(EmailDelivery::Suppressed) => (
+            DeliveryStatus::Suppressed,
+            None,
+            Some("The recipient is on the suppression list.".to_string()),
+        ),
+        Err(error) => (DeliveryStatus::Failed, None, Some(failure_reason(error))),
+    };
+
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_deliveries
+            SET status = $3,
+                provider_message_id = $4,
+                failure_reason = $5,
+                attempted_at = now(),
+                updated_at = now()
+            WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+        "#,
+        issue_id,
+        subscriber_id,
+        status.as_str(),
+        message_id,
+        failure_reason
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to record a newsletter delivery.")?;
+
+    Ok(status)
+}
+
+/// The error and its causes, on one line.
+fn failure_reason(error: &SendEmailError) -> String {
+    let mut reason = error.to_string();
+    let mut source = std::error::Error::source(error);
+
+    while let Some(cause) = source {
+        reason.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+
+    reason
+}
+
+/// Marks a sent email as undelivered, as reported by the email provider.
+///
+/// SendGrid refers to a message in its events by the id it returned when
+/// the email was sent, followed by a suffix of its own, e.g. `abc.filter01`.
+#[tracing::instrument(name = "Mark a newsletter delivery as undelivered", skip(transaction))]
+pub async fn mark_undelivered(
+    transaction: &mut Transaction<'_, Postgres>,
+    provider_message_id: &str,
+    status: DeliveryStatus,
+    reason: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let message_ids: Vec<String> = provider_message_id
+        .match_indices('.')
+        .map(|(i, _)| provider_message_id[..i].to_string())
+        .chain(std::iter::once(provider_message_id.to_string()))
+        .collect();
+
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_deliveries
+            SET status = $2, failure_reason = $3, updated_at = now()
+            WHERE provider_message_id = ANY($1) AND status = 'sent'
+        "#,
+        &message_ids,
+        status.as_str(),
+        reason
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to mark a newsletter delivery as undelivered.")?;
+
+    Ok(())
+}
+
+/// How many deliveries of an issue are in each status.
+pub struct DeliveryCounts {
+    pub recipients: i64,
+    pub queued: i64,
+    pub sent: i64,
+    pub suppressed: i64,
+    pub failed: i64,
+}
+
+/// Emails that bounced since are counted as sent, as they were.
+#[tracing::instrument(name = "Count the deliveries of a newsletter issue", skip(db_pool))]
+pub async fn count_deliveries(
+    db_pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<DeliveryCounts, anyhow::Error> {
+    sqlx::query_as!(
+        DeliveryCounts,
+        r#"
+            SELECT
+                COUNT(*) AS "recipients!",
+                COUNT(*) FILTER (WHERE status = 'queued') AS "queued!",
+                COUNT(*) FILTER (WHERE status IN ('sent', 'bounced')) AS "sent!",
+                COUNT(*) FILTER (WHERE status = 'suppressed') AS "suppressed!",
+                COUNT(*) FILTER (WHERE status = 'failed') AS "failed!"
+            FROM newsletter_deliveries
+            WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to count the deliveries of a newsletter issue.")
+}
+
+/// How the sending of an issue went.
+#[derive(serde::Serialize)]
+pub struct DeliveryReport {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    pub recipients: i64,
+    pub queued: i64,
+    pub sent: i64,
+    pub suppressed: i64,
+    pub failed: i64,
+    pub bounced: i64,
+    pub first_attempt_at: Option<DateTime<Utc>>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    /// From publication to the last attempt.
+    pub duration_seconds: Option<i64>,
+    pub failure_reasons: Vec<FailureReason>,
+    pub failures: Vec<FailedDelivery>,
+}
+
+#[derive(serde::Serialize)]
+pub struct FailureReason {
+    pub status: String,
+    pub reason: Option<String>,
+    pub count: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct FailedDelivery {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub status: String,
+    pub reason: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get the delivery report of a newsletter issue", skip(db_pool))]
+pub async fn get_delivery_report(
+    db_pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<DeliveryReport>, anyhow::Error> {
+    let summary = sqlx::query!(
+        r#"
+            SELECT
+                i.title,
+                i.published_at AS "published_at!",
+                COUNT(d.subscriber_id) AS "recipients!",
+                COUNT(*) FILTER (WHERE d.status = 'queued') AS "queued!",
+                COUNT(*) FILTER (WHERE d.status = 'sent') AS "sent!",
+                COUNT(*) FILTER (WHERE d.status = 'suppressed') AS "suppressed!",
+                COUNT(*) FILTER (WHERE d.status = 'failed') AS "failed!",
+                COUNT(*) FILTER (WHERE d.status = 'bounced') AS "bounced!",
+                MIN(d.attempted_at) AS first_attempt_at,
+                MAX(d.attempted_at) AS last_attempt_at
+            FROM newsletter_issues i
+            LEFT JOIN newsletter_deliveries d ON d.newsletter_issue_id = i.id
+            WHERE i.id = $1 AND i.published_at IS NOT NULL
+            GROUP BY i.id
+        "#,
+        issue_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve the deliveries of a newsletter issue.")?;
+
+    let summary = match summary {
+        Some(summary) => summary,
+        None => return Ok(None),
+    };
+
+    let failure_reasons = sqlx::query_as!(
+        FailureReason,
+        r#"
+            SELECT status, failure_reason AS reason, COUNT(*) AS "count!"
+            FROM newsletter_deliveries
+            WHERE newsletter_issue_id = $1 AND status IN ('failed', 'bounced')
+            GROUP BY status, failure_reason
+            ORDER BY 3 DESC, status, failure_reason
+        "#,
+        issue_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve the failure reasons of a newsletter issue.")?;
+
+    let failures = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+            SELECT d.subscriber_id, s.email, d.status, d.failure_reason AS reason, d.updated_at
+            FROM newsletter_deliveries d
+            JOIN subscriptions s ON s.id = d.subscriber_id
+            WHERE d.newsletter_issue_id = $1 AND d.status IN ('failed', 'bounced')
+            ORDER BY d.updated_at, s.email
+            LIMIT $2
+        "#,
+        issue_id,
+        LISTED_FAILURES
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve the failed deliveries of a newsletter issue.")?;
+
+    Ok(Some(DeliveryReport {
+        issue_id,
+        title: summary.title,
+        published_at: summary.published_at,
+        recipients: summary.recipients,
+        queued: summary.queued,
+        sent: summary.sent,
+        suppressed: summary.suppressed,
+        failed: summary.failed,
+        bounced: summary.bounced,
+        first_attempt_at: summary.first_attempt_at,
+        last_attempt_at: summary.last_attempt_at,
+        duration_seconds: summary
+            .last_attempt_at
+            .map(|last_attempt_at| (last_attempt_at - summary.published_at).num_seconds()),
+        failure_reasons,
+        failures,
+    }))
+}
@@ -0,0 +1,389 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use maud::{html, PreEscaped};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{domain::SubscriberEmail, email_html::EmailHtml};
+
+/// A subscriber's digest that's due, with the issues it gathers in the
+/// order they were published.
+pub struct DueDigest {
+    pub subscriber_id: Uuid,
+    pub email: SubscriberEmail,
+    pub preferences_token: String,
+    pub issues: Vec<DigestIssue>,
+    /// How many times it failed to send before.
+    pub attempts: i16,
+}
+
+impl DueDigest {
+    fn issue_ids(&self) -> Vec<Uuid> {
+        self.issues.iter().map(|issue| issue.id).collect()
+    }
+}
+
+pub struct DigestIssue {
+    pub id: Uuid,
+    pub title: String,
+    pub html: String,
+    pub text: String,
+    pub attachments: Vec<Uuid>,
+}
+
+/// Holds an issue back for the next digest of each of `subscriber_ids`.
+#[tracing::instrument(
+    name = "Queue an issue for weekly digests",
+    skip(transaction, subscriber_ids)
+)]
+pub async fn queue_for_digests(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_ids: &[Uuid],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO digest_entries (subscriber_id, newsletter_issue_id)
+            SELECT subscriber_id, $1
+            FROM UNNEST($2::uuid[]) AS subscriber_id
+        "#,
+        issue_id,
+        subscriber_ids
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to queue a newsletter issue for weekly digests.")?;
+
+    Ok(())
+}
+
+/// How many subscribers are waiting for an issue in their next digest.
+#[tracing::instrument(name = "Count the queued digest entries of an issue", skip(db_pool))]
+pub async fn count_queued_for_digests(
+    db_pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<i64, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT COUNT(*) AS "count!"
+            FROM digest_entries
+            WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to count the digest entries of a newsletter issue.")?;
+
+    Ok(row.count)
+}
+
+/// How long a digest is left to the instance sending it, should it stop
+/// before telling how it went.
+const DIGEST_LEASE: Duration = Duration::from_secs(10 * 60);
+/// How long until a digest that failed to send is tried again, doubling
+/// with each attempt.
+const DIGEST_RETRY_DELAY: Duration = Duration::from_secs(15 * 60);
+/// Digests that failed to send this many times are given up on.
+const MAX_DIGEST_ATTEMPTS: i16 = 5;
+
+/// Subscribers with issues queued before `due_before`, unless they're
+/// being sent or waiting to be tried again. Those who paused delivery get
+/// their digest once they resume it, and those who are no longer confirmed
+/// don't get one.
+#[tracing::instrument(name = "Find due weekly digests", skip(db_pool))]
+pub async fn find_due_digests(
+    db_pool: &PgPool,
+    due_before: DateTime<Utc>,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT DISTINCT s.id
+            FROM subscriptions s
+            JOIN digest_entries d ON d.subscriber_id = s.id
+            WHERE d.queued_at < $1
+                AND (d.next_attempt_at IS NULL OR d.next_attempt_at <= now())
+                AND s.status = 'confirmed'
+                AND (s.paused_until IS NULL OR s.paused_until <= now())
+        "#,
+        due_before
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to look for due weekly digests.")?;
+
+    Ok(rows.into_iter().map(|r| r.id).collect())
+}
+
+/// Reads a subscriber's due digest and keeps other instances of the
+/// application from sending it for a while, without holding a lock while
+/// it's sent. There's nothing to send when another instance claimed it
+/// first, or already sent it.
+#[tracing::instrument(name = "Claim a due weekly digest", skip(db_pool))]
+pub async fn claim_due_digest(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+    due_before: DateTime<Utc>,
+) -> Result<Option<DueDigest>, anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let claimed = sqlx::query!(
+        r#"
+            UPDATE digest_entries
+            SET next_attempt_at = now() + make_interval(secs => $3)
+            WHERE subscriber_id = $1
+                AND queued_at < $2
+                AND (next_attempt_at IS NULL OR next_attempt_at <= now())
+            RETURNING newsletter_issue_id, attempts
+        "#,
+        subscriber_id,
+        due_before,
+        DIGEST_LEASE.as_secs_f64()
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to claim a weekly digest.")?;
+
+    if claimed.is_empty() {
+        return Ok(None);
+    }
+
+    let issue_ids: Vec<Uuid> = claimed.iter().map(|r| r.newsletter_issue_id).collect();
+    let attempts = claimed.iter().map(|r| r.attempts).max().unwrap_or_default();
+
+    let subscriber = sqlx::query!(
+        r#"
+            SELECT email, preferences_token
+            FROM subscriptions
+            WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to retrieve a subscriber for their weekly digest.")?;
+
+    let issues: Vec<DigestIssue> = sqlx::query!(
+        r#"
+            SELECT id, title, html_content, text_content, attachment_ids
+            FROM newsletter_issues
+            WHERE id = ANY($1)
+            ORDER BY published_at, id
+        "#,
+        &issue_ids
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to retrieve the issues of a weekly digest.")?
+    .into_iter()
+    .map(|r| DigestIssue {
+        id: r.id,
+        title: r.title,
+        html: r.html_content,
+        text: r.text_content,
+        attachments: r.attachment_ids,
+    })
+    .collect();
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to claim a weekly digest.")?;
+
+    Ok(Some(DueDigest {
+        subscriber_id,
+        email: SubscriberEmail::parse(subscriber.email).map_err(anyhow::Error::msg)?,
+        preferences_token: subscriber.preferences_token,
+        issues,
+        attempts,
+    }))
+}
+
+/// Takes the issues of a digest out of the queue, once it was sent.
+#[tracing::instrument(name = "Clear a weekly digest", skip(db_pool, digest))]
+pub async fn clear_digest(db_pool: &PgPool, digest: &DueDigest) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            DELETE FROM digest_entries
+            WHERE subscriber_id = $1 AND newsletter_issue_id = ANY($2)
+        "#,
+        digest.subscriber_id,
+        &digest.issue_ids()
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to clear a weekly digest.")?;
+
+    Ok(())
+}
+
+/// Puts a digest that failed to send off until its next attempt, or gives
+/// up on it after `MAX_DIGEST_ATTEMPTS`. Returns whether it was given up on.
+#[tracing::instrument(name = "Record a failed weekly digest", skip(db_pool, digest))]
+pub async fn record_digest_failure(
+    db_pool: &PgPool,
+    digest: &DueDigest,
+) -> Result<bool, anyhow::Error> {
+    if digest.attempts + 1 >= MAX_DIGEST_ATTEMPTS {
+        clear_digest(db_pool, digest).await?;
+
+        return Ok(true);
+    }
+
+    let retry_delay = DIGEST_RETRY_DELAY * 2u32.pow(digest.attempts as u32);
+
+    sqlx::query!(
+        r#"
+            UPDATE digest_entries
+            SET attempts = attempts + 1,
+                next_attempt_at = now() + make_interval(secs => $3)
+            WHERE subscriber_id = $1 AND newsletter_issue_id = ANY($2)
+        "#,
+        digest.subscriber_id,
+        &digest.issue_ids(),
+        retry_delay.as_secs_f64()
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to record a failed weekly digest.")?;
+
+    Ok(false)
+}
+
+fn digest_subject(issues: &[DigestIssue]) -> String {
+    match issues {
+        [issue] => format!("Your weekly digest: {}", issue.title),
+        _ => format!("Your weekly digest: {} new issues", issues.len()),
+    }
+}
+
+/// The subject, HTML and text contents of a digest: every issue in full,
+/// one after the other.
+pub fn compose_digest(issues: &[DigestIssue]) -> (String, String, String) {
+    let html_content = html! {
+        @for (i, issue) in issues.iter().enumerate() {
+            @if i > 0 {
+                hr;
+            }
+            h1 { (issue.title) }
+            (PreEscaped(EmailHtml::prepare(&issue.html).html()))
+        }
+    }
+    .into_string();
+
+    let text_content = issues
+        .iter()
+        .map(|issue| {
+            format!(
+                "{}\n{}\n\n{}",
+                issue.title,
+                "=".repeat(issue.title.chars().count()),
+                issue.text.trim_end()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n---\n\n");
+
+    (
+        digest_subject(issues),
+        html_content,
+        format!("{text_content}\n"),
+    )
+}
+
+/// A digest too large for a single email, with the title of every issue
+/// linking to where it can be read instead.
+pub fn compose_digest_of_links(issues: &[DigestIssue], base_url: &str) -> (String, String, String) {
+    let issue_url = |issue: &DigestIssue| format!("{base_url}/newsletters/{}", issue.id);
+
+    let html_content = html! {
+        p { "This week's issues don't fit in a single email. Read them here:" }
+        ul {
+            @for issue in issues {
+                li { a href=(issue_url(issue)) { (issue.title) } }
+            }
+        }
+    }
+    .into_string();
+
+    let text_content = issues
+        .iter()
+        .map(|issue| format!("- {}: {}\n", issue.title, issue_url(issue)))
+        .collect::<String>();
+
+    (
+        digest_subject(issues),
+        html_content,
+        format!(
+            "This week's issues don't fit in a single email. Read them here:\n\n{text_content}"
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{compose_digest, compose_digest_of_links, DigestIssue};
+
+    fn issue(title: &str, html: &str, text: &str) -> DigestIssue {
+        DigestIssue {
+            id: Uuid::new_v4(),
+            title: title.into(),
+            html: html.into(),
+            text: text.into(),
+            attachments: vec![],
+        }
+    }
+
+    #[test]
+    fn digests_gather_every_issue_in_full() {
+        let (subject, html, text) = compose_digest(&[
+            issue("First", "<p>One</p>", "One\n"),
+            issue("Fish & chips", "<p>Two</p>", "Two"),
+        ]);
+
+        assert_eq!(subject, "Your weekly digest: 2 new issues");
+        assert_eq!(
+            html,
+            "<h1>First</h1><p>One</p><hr><h1>Fish &amp; chips</h1><p>Two</p>"
+        );
+        assert_eq!(
+            text,
+            "First\n=====\n\nOne\n\n---\n\nFish & chips\n============\n\nTwo\n"
+        );
+    }
+
+    #[test]
+    fn digests_of_one_issue_are_named_after_it() {
+        let (subject, ..) = compose_digest(&[issue("First", "<p>One</p>", "One")]);
+
+        assert_eq!(subject, "Your weekly digest: First");
+    }
+
+    #[test]
+    fn digests_of_links_point_to_every_issue() {
+        let first = issue("First", "<p>One</p>", "One");
+        let second = issue("Fish & chips", "<p>Two</p>", "Two");
+        let (first_id, second_id) = (first.id, second.id);
+
+        let (subject, html, text) =
+            compose_digest_of_links(&[first, second], "https://example.com");
+
+        assert_eq!(subject, "Your weekly digest: 2 new issues");
+        assert!(html.contains(&format!(
+            r#"<a href="https://example.com/newsletters/{first_id}">First</a>"#
+        )));
+        assert!(html.contains("Fish &amp; chips"));
+        assert!(!html.contains("One"));
+        assert!(text.ends_with(&format!(
+            "- First: https://example.com/newsletters/{first_id}\n\
+            - Fish & chips: https://example.com/newsletters/{second_id}\n"
+        )));
+    }
+}
@@ -0,0 +1,58 @@
+/// A step of a subscriber's double opt-in, recorded as proof of consent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsentEvent {
+    Subscribed,
+    Confirmed,
+}
+
+impl ConsentEvent {
+    pub fn parse(s: String) -> Result<Self, String> {
+        match s.as_str() {
+            "subscribed" => Ok(Self::Subscribed),
+            "confirmed" => Ok(Self::Confirmed),
+            _ => Err(format!("{s} is not a valid consent event.")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Subscribed => "subscribed",
+            Self::Confirmed => "confirmed",
+        }
+    }
+}
+
+impl std::fmt::Display for ConsentEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl AsRef<str> for ConsentEvent {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConsentEvent;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn known_events_are_parsed_successfully() {
+        assert_ok_eq!(
+            ConsentEvent::parse("subscribed".into()),
+            ConsentEvent::Subscribed
+        );
+        assert_ok_eq!(
+            ConsentEvent::parse("confirmed".into()),
+            ConsentEvent::Confirmed
+        );
+    }
+
+    #[test]
+    fn unknown_events_are_rejected() {
+        assert_err!(ConsentEvent::parse("withdrawn".into()));
+    }
+}
@@ -0,0 +1,70 @@
+/// How often a subscriber wants to hear from us.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryFrequency {
+    /// Every issue, as soon as it's published.
+    Immediate,
+    /// A digest of the week's issues.
+    Weekly,
+}
+
+impl DeliveryFrequency {
+    pub const ALL: [DeliveryFrequency; 2] = [Self::Immediate, Self::Weekly];
+
+    pub fn parse(s: String) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "immediate" => Ok(Self::Immediate),
+            "weekly" => Ok(Self::Weekly),
+            _ => Err(format!("{s} is not a valid delivery frequency.")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Immediate => "immediate",
+            Self::Weekly => "weekly",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Immediate => "Every issue, as soon as it's out",
+            Self::Weekly => "A weekly digest",
+        }
+    }
+}
+
+impl std::fmt::Display for DeliveryFrequency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl AsRef<str> for DeliveryFrequency {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryFrequency;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn known_frequencies_are_parsed_successfully() {
+        assert_ok_eq!(
+            DeliveryFrequency::parse("immediate".into()),
+            DeliveryFrequency::Immediate
+        );
+        assert_ok_eq!(
+            DeliveryFrequency::parse("weekly".into()),
+            DeliveryFrequency::Weekly
+        );
+    }
+
+    #[test]
+    fn unknown_frequencies_are_rejected() {
+        assert_err!(DeliveryFrequency::parse("hourly".into()));
+        assert_err!(DeliveryFrequency::parse("".into()));
+    }
+}
@@ -0,0 +1,68 @@
+/// Where the email of a newsletter issue to one subscriber stands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    Queued,
+    Sent,
+    /// The subscriber is on the suppression list, so nothing was sent.
+    Suppressed,
+    Failed,
+    /// The email provider reported a bounce after the email was sent.
+    Bounced,
+}
+
+impl DeliveryStatus {
+    pub const ALL: [DeliveryStatus; 5] = [
+        Self::Queued,
+        Self::Sent,
+        Self::Suppressed,
+        Self::Failed,
+        Self::Bounced,
+    ];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| format!("{s} is not a delivery status."))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Sent => "sent",
+            Self::Suppressed => "suppressed",
+            Self::Failed => "failed",
+            Self::Bounced => "bounced",
+        }
+    }
+}
+
+impl std::fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl AsRef<str> for DeliveryStatus {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryStatus;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn statuses_are_parsed_back() {
+        for status in DeliveryStatus::ALL {
+            assert_ok_eq!(DeliveryStatus::parse(status.as_str()), status);
+        }
+    }
+
+    #[test]
+    fn unknown_statuses_are_rejected() {
+        assert_err!(DeliveryStatus::parse("delivered"));
+    }
+}
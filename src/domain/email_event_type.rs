@@ -0,0 +1,73 @@
+/// The events reported by SendGrid's event webhook that we keep track of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmailEventType {
+    Delivered,
+    Bounce,
+    Dropped,
+    SpamReport,
+    Open,
+    Click,
+}
+
+impl EmailEventType {
+    /// Other events, like `processed` or `deferred`, are of no use to us and
+    /// are rejected.
+    pub fn parse(s: String) -> Result<Self, String> {
+        match s.as_str() {
+            "delivered" => Ok(Self::Delivered),
+            "bounce" => Ok(Self::Bounce),
+            "dropped" => Ok(Self::Dropped),
+            "spamreport" => Ok(Self::SpamReport),
+            "open" => Ok(Self::Open),
+            "click" => Ok(Self::Click),
+            _ => Err(format!("{s} is not a tracked email event.")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Delivered => "delivered",
+            Self::Bounce => "bounce",
+            Self::Dropped => "dropped",
+            Self::SpamReport => "spamreport",
+            Self::Open => "open",
+            Self::Click => "click",
+        }
+    }
+}
+
+impl std::fmt::Display for EmailEventType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl AsRef<str> for EmailEventType {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmailEventType;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn tracked_events_are_parsed_successfully() {
+        assert_ok_eq!(
+            EmailEventType::parse("bounce".into()),
+            EmailEventType::Bounce
+        );
+        assert_ok_eq!(
+            EmailEventType::parse("spamreport".into()),
+            EmailEventType::SpamReport
+        );
+    }
+
+    #[test]
+    fn untracked_events_are_rejected() {
+        assert_err!(EmailEventType::parse("processed".into()));
+        assert_err!(EmailEventType::parse("deferred".into()));
+    }
+}
@@ -0,0 +1,29 @@
+/// How a subscriber engaged with a newsletter issue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EngagementKind {
+    /// The tracking pixel was loaded.
+    Open,
+    /// A tracked link was followed.
+    Click,
+}
+
+impl EngagementKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Click => "click",
+        }
+    }
+}
+
+impl std::fmt::Display for EngagementKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl AsRef<str> for EngagementKind {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
@@ -0,0 +1,136 @@
+/// Where a newsletter issue is in its workflow. An issue is drafted, can be
+/// approved by someone other than its author, is either scheduled or
+/// published right away, and is sent to every subscriber.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IssueStatus {
+    Draft,
+    Approved,
+    Scheduled,
+    Sending,
+    Sent,
+}
+
+impl IssueStatus {
+    pub const ALL: [IssueStatus; 5] = [
+        Self::Draft,
+        Self::Approved,
+        Self::Scheduled,
+        Self::Sending,
+        Self::Sent,
+    ];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| format!("{s} is not an issue status."))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Draft => "draft",
+            Self::Approved => "approved",
+            Self::Scheduled => "scheduled",
+            Self::Sending => "sending",
+            Self::Sent => "sent",
+        }
+    }
+
+    /// Approval is optional, so drafts can be scheduled or sent as they
+    /// are. Editing an approved issue takes it back to a draft, and so
+    /// does cancelling the schedule of an issue that wasn't approved.
+    pub fn can_become(&self, next: IssueStatus) -> bool {
+        use IssueStatus::*;
+
+        matches!(
+            (self, next),
+            (Draft, Approved)
+                | (Approved, Draft)
+                | (Draft | Approved, Scheduled)
+                | (Scheduled, Draft | Approved)
+                | (Draft | Approved | Scheduled, Sending)
+                | (Sending, Sent)
+        )
+    }
+
+    pub fn transition_to(&self, next: IssueStatus) -> Result<IssueStatus, String> {
+        if self.can_become(next) {
+            Ok(next)
+        } else {
+            Err(format!("A {self} issue can't become {next}."))
+        }
+    }
+
+    /// Only the content of issues that haven't been scheduled or sent can
+    /// change.
+    pub fn is_editable(&self) -> bool {
+        matches!(self, Self::Draft | Self::Approved)
+    }
+}
+
+impl std::fmt::Display for IssueStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl AsRef<str> for IssueStatus {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IssueStatus::{self, *};
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn statuses_are_parsed_back() {
+        for status in IssueStatus::ALL {
+            assert_ok_eq!(IssueStatus::parse(status.as_str()), status);
+        }
+    }
+
+    #[test]
+    fn issues_go_from_draft_to_sent() {
+        assert_ok_eq!(Draft.transition_to(Approved), Approved);
+        assert_ok_eq!(Approved.transition_to(Scheduled), Scheduled);
+        assert_ok_eq!(Scheduled.transition_to(Sending), Sending);
+        assert_ok_eq!(Sending.transition_to(Sent), Sent);
+    }
+
+    #[test]
+    fn approval_is_optional() {
+        assert_ok_eq!(Draft.transition_to(Scheduled), Scheduled);
+        assert_ok_eq!(Draft.transition_to(Sending), Sending);
+    }
+
+    #[test]
+    fn sent_issues_are_final() {
+        for status in IssueStatus::ALL {
+            assert_err!(Sent.transition_to(status));
+        }
+    }
+
+    #[test]
+    fn issues_being_sent_can_only_become_sent() {
+        assert_err!(Sending.transition_to(Draft));
+        assert_err!(Sending.transition_to(Scheduled));
+        assert_ok_eq!(Sending.transition_to(Sent), Sent);
+    }
+
+    #[test]
+    fn issues_are_sent_before_being_marked_sent() {
+        assert_err!(Draft.transition_to(Sent));
+        assert_err!(Scheduled.transition_to(Sent));
+    }
+
+    #[test]
+    fn scheduled_issues_are_not_editable() {
+        assert!(Draft.is_editable());
+        assert!(Approved.is_editable());
+        assert!(!Scheduled.is_editable());
+        assert!(!Sent.is_editable());
+    }
+}
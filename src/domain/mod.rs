@@ -1,7 +1,25 @@
+mod consent_event;
+mod delivery_frequency;
+mod delivery_status;
+mod email_event_type;
+mod engagement_kind;
+mod issue_status;
 mod new_subscriber;
+mod newsletter_content;
 mod subscriber_email;
 mod subscriber_name;
+mod suppression_target;
+mod user_role;
 
+pub use consent_event::ConsentEvent;
+pub use delivery_frequency::DeliveryFrequency;
+pub use delivery_status::DeliveryStatus;
+pub use email_event_type::EmailEventType;
+pub use engagement_kind::EngagementKind;
+pub use issue_status::IssueStatus;
 pub use new_subscriber::NewSubscriber;
+pub use newsletter_content::NewsletterContent;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use suppression_target::SuppressionTarget;
+pub use user_role::UserRole;
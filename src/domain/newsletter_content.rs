@@ -0,0 +1,326 @@
+use std::sync::OnceLock;
+
+use pulldown_cmark::{html, Event, HeadingLevel, Options, Parser, Tag};
+use regex::Regex;
+
+/// The HTML body of an issue, with the plain text alternative sent
+/// alongside it. Neither can run scripts in the reader's email client.
+#[derive(Clone, Debug)]
+pub struct NewsletterContent {
+    html: String,
+    text: String,
+}
+
+impl NewsletterContent {
+    /// Content written by hand is sanitized the same way rendered Markdown
+    /// is, once it's clear it isn't trying to run scripts outright.
+    pub fn parse(html: String, text: String) -> Result<Self, String> {
+        reject_unsafe_html(&html)?;
+
+        Ok(Self {
+            html: sanitizer().clean(&html).to_string(),
+            text,
+        })
+    }
+
+    /// Renders Markdown into sanitized HTML, and into plain text that
+    /// keeps its structure and the address of every link. Images can refer
+    /// to uploaded assets as `cid:{id}`.
+    pub fn from_markdown(markdown: &str) -> Result<Self, String> {
+        if markdown.trim().is_empty() {
+            return Err("Newsletter content can't be empty.".into());
+        }
+
+        // Markdown can embed raw HTML, which is held to the same rules
+        reject_unsafe_html(&embedded_html(markdown))?;
+
+        let mut html = String::new();
+
+        html::push_html(&mut html, Parser::new_ext(markdown, options()));
+
+        Ok(Self {
+            html: sanitizer().clean(&html).to_string(),
+            text: plain_text(markdown),
+        })
+    }
+
+    pub fn html(&self) -> &str {
+        &self.html
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+fn options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH
+}
+
+/// What's left after sanitizing is all that's sent. Styles are kept, for
+/// `<style>` blocks to be inlined when the issue is sent, as are media
+/// elements, for authors to be warned that email clients won't show them.
+/// Links aren't given a `rel`, which means nothing in an email.
+fn sanitizer() -> ammonia::Builder<'static> {
+    let mut sanitizer = ammonia::Builder::default();
+
+    sanitizer
+        .add_tags(&["audio", "style", "video"])
+        .rm_clean_content_tags(&["style"])
+        .add_tag_attributes("audio", &["src"])
+        .add_tag_attributes("video", &["src"])
+        .add_url_schemes(&["cid"])
+        .add_generic_attributes(&["style"])
+        .link_rel(None);
+
+    sanitizer
+}
+
+/// The raw HTML of a Markdown document. Code blocks and inline code are
+/// left out, as they're shown rather than run, and so is everything else
+/// the parser doesn't take for HTML.
+fn embedded_html(markdown: &str) -> String {
+    Parser::new_ext(markdown, options())
+        .filter_map(|event| match event {
+            Event::Html(html) => Some(html.into_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Turns away content that plainly tries to run scripts, to say why rather
+/// than quietly sanitizing it away.
+fn reject_unsafe_html(html: &str) -> Result<(), String> {
+    static SCRIPT: OnceLock<Regex> = OnceLock::new();
+    static EVENT_HANDLER: OnceLock<Regex> = OnceLock::new();
+
+    let script = SCRIPT.get_or_init(|| {
+        Regex::new(r#"(?i)<\s*script\b|\b(?:href|src|action)\s*=\s*["']?\s*javascript:"#).unwrap()
+    });
+    let event_handler =
+        EVENT_HANDLER.get_or_init(|| Regex::new(r#"(?i)<[a-z][^>]*\son[a-z]+\s*="#).unwrap());
+
+    if script.is_match(html) {
+        return Err("Newsletter content can't contain scripts.".into());
+    }
+
+    if event_handler.is_match(html) {
+        return Err("Newsletter content can't contain event handlers such as onclick.".into());
+    }
+
+    Ok(())
+}
+
+/// Markdown is mostly readable as it is, so the text keeps list markers,
+/// sets headings apart, and spells out the links. Raw HTML is left out.
+fn plain_text(markdown: &str) -> String {
+    let mut text = String::new();
+    // The next number of each ordered list being written, None for bullets
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut links: Vec<String> = Vec::new();
+    let mut link_text_start = 0;
+
+    for event in Parser::new_ext(markdown, options()) {
+        match event {
+            Event::Start(Tag::List(first_number)) => lists.push(first_number),
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                let indent = "  ".repeat(lists.len().saturating_sub(1));
+
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        text.push_str(&format!("{indent}{number}. "));
+                        *number += 1;
+                    }
+                    _ => text.push_str(&format!("{indent}- ")),
+                }
+            }
+            Event::End(Tag::Item) if !text.ends_with('\n') => text.push('\n'),
+            Event::Start(Tag::Link(_, url, _) | Tag::Image(_, url, _)) => {
+                links.push(url.to_string());
+                link_text_start = text.len();
+            }
+            Event::End(Tag::Link(..) | Tag::Image(..)) => {
+                if let Some(url) = links.pop() {
+                    if text[link_text_start..] != url {
+                        text.push_str(&format!(" ({url})"));
+                    }
+                }
+            }
+            Event::End(Tag::Heading(level, ..)) => {
+                if level == HeadingLevel::H1 {
+                    let width = text.lines().last().map_or(0, |l| l.chars().count());
+                    text.push('\n');
+                    text.push_str(&"=".repeat(width));
+                }
+                text.push_str("\n\n");
+            }
+            Event::End(Tag::Paragraph) => {
+                text.push_str(if lists.is_empty() { "\n\n" } else { "\n" });
+            }
+            Event::End(Tag::CodeBlock(_) | Tag::BlockQuote | Tag::Table(_)) => text.push('\n'),
+            Event::End(Tag::TableCell) => text.push('\t'),
+            Event::End(Tag::TableHead | Tag::TableRow) => {
+                text.truncate(text.trim_end_matches('\t').len());
+                text.push('\n');
+            }
+            Event::Text(content) | Event::Code(content) => text.push_str(&content),
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            Event::Rule => text.push_str("---\n\n"),
+            Event::TaskListMarker(done) => text.push_str(if done { "[x] " } else { "[ ] " }),
+            _ => {}
+        }
+    }
+
+    format!("{}\n", text.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NewsletterContent;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn markdown_is_rendered_to_html_and_text() {
+        let content = NewsletterContent::from_markdown(
+            "# News\n\nRead [the article](https://example.com/1).\n\n* one\n* two\n",
+        )
+        .unwrap();
+
+        assert!(content.html().contains("<h1>News</h1>"));
+        assert!(content.html().contains(r#"href="https://example.com/1""#));
+        assert_eq!(
+            content.text(),
+            "News\n====\n\nRead the article (https://example.com/1).\n\n- one\n- two\n"
+        );
+    }
+
+    #[test]
+    fn ordered_lists_keep_their_numbers_in_text() {
+        let content = NewsletterContent::from_markdown("3. three\n4. four\n").unwrap();
+
+        assert_eq!(content.text(), "3. three\n4. four\n");
+    }
+
+    #[test]
+    fn bare_links_are_not_repeated_in_text() {
+        let content = NewsletterContent::from_markdown("<https://example.com>").unwrap();
+
+        assert_eq!(content.text(), "https://example.com\n");
+    }
+
+    #[test]
+    fn images_can_refer_to_assets() {
+        let content =
+            NewsletterContent::from_markdown("![Chart](cid:0186c4f2-6f5a-7c1e-9d3b-2a4e5f6a7b8c)")
+                .unwrap();
+
+        assert!(content
+            .html()
+            .contains(r#"src="cid:0186c4f2-6f5a-7c1e-9d3b-2a4e5f6a7b8c""#));
+    }
+
+    #[test]
+    fn raw_html_in_markdown_is_sanitized() {
+        let content =
+            NewsletterContent::from_markdown("Hi <iframe src=\"https://example.com\"></iframe>")
+                .unwrap();
+
+        assert!(!content.html().contains("iframe"));
+    }
+
+    #[test]
+    fn scripts_are_rejected() {
+        for html in [
+            "<p>Hi</p><script>alert(1)</script>",
+            "<SCRIPT src=\"https://example.com/x.js\"></SCRIPT>",
+            "<a href=\"javascript:alert(1)\">Hi</a>",
+        ] {
+            assert_err!(NewsletterContent::parse(html.into(), "Hi".into()));
+            assert_err!(NewsletterContent::from_markdown(html));
+        }
+    }
+
+    #[test]
+    fn html_shown_as_code_in_markdown_is_accepted() {
+        for markdown in [
+            "Load it with `<script src=\"https://example.com/x.js\">`.",
+            "```html\n<script>alert(1)</script>\n<p onclick=\"go()\">Hi</p>\n```",
+            "    <img src=\"x.png\" onerror=\"alert(1)\">",
+        ] {
+            let content = assert_ok!(NewsletterContent::from_markdown(markdown));
+
+            assert!(content.html().contains("&lt;"), "{}", content.html());
+        }
+    }
+
+    #[test]
+    fn prose_comparing_values_is_accepted() {
+        assert_ok!(NewsletterContent::parse(
+            "<p>If a < b set online = true.</p>".into(),
+            "Hi".into()
+        ));
+        let content = assert_ok!(NewsletterContent::from_markdown(
+            "If a < b set online = true."
+        ));
+
+        assert_eq!(content.html(), "<p>If a &lt; b set online = true.</p>\n");
+    }
+
+    #[test]
+    fn event_handlers_are_rejected() {
+        for html in [
+            "<img src=\"x.png\" onerror=\"alert(1)\">",
+            "<p ONCLICK='alert(1)'>Hi</p>",
+        ] {
+            assert_err!(NewsletterContent::parse(html.into(), "Hi".into()));
+            assert_err!(NewsletterContent::from_markdown(html));
+        }
+    }
+
+    #[test]
+    fn scripts_that_slip_past_the_checks_are_sanitized_away() {
+        for (html, unsafe_part) in [
+            ("<svg/onload=alert(1)>", "onload"),
+            ("<a href=\"jav&#x61;script:alert(1)\">Hi</a>", "alert"),
+            (
+                "<button formaction=\"javascript:alert(1)\">Hi</button>",
+                "javascript",
+            ),
+            (
+                "<object data=\"https://example.com/x.swf\"></object>",
+                "object",
+            ),
+            (
+                "<iframe srcdoc=\"&lt;script&gt;alert(1)&lt;/script&gt;\"></iframe>",
+                "iframe",
+            ),
+        ] {
+            let content = assert_ok!(NewsletterContent::parse(html.into(), "Hi".into()));
+
+            assert!(
+                !content.html().to_lowercase().contains(unsafe_part),
+                "{html} became {}",
+                content.html()
+            );
+        }
+    }
+
+    #[test]
+    fn safe_html_is_kept_as_is() {
+        let html = r#"<p style="color: red">Read about <a href="https://example.com/online">online events</a></p>"#;
+        let content = assert_ok!(NewsletterContent::parse(html.into(), "Hi".into()));
+
+        assert_eq!(content.html(), html);
+    }
+
+    #[test]
+    fn empty_markdown_is_rejected() {
+        assert_err!(NewsletterContent::from_markdown(" \n"));
+    }
+}
@@ -0,0 +1,87 @@
+use validator::validate_email;
+
+/// What a suppression list entry blocks: a single address, or every address
+/// at a domain. Both are stored lowercase.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SuppressionTarget {
+    Address(String),
+    Domain(String),
+}
+
+impl SuppressionTarget {
+    /// Anything with an `@` is an address, anything else a domain.
+    pub fn parse(s: String) -> Result<Self, String> {
+        let target = s.trim().to_lowercase();
+
+        if target.contains('@') {
+            if validate_email(&target) {
+                return Ok(Self::Address(target));
+            }
+        } else if is_valid_domain(&target) {
+            return Ok(Self::Domain(target));
+        }
+
+        Err(format!("{s} is neither an email address nor a domain."))
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Address(_) => "address",
+            Self::Domain(_) => "domain",
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        match self {
+            Self::Address(value) | Self::Domain(value) => value,
+        }
+    }
+}
+
+fn is_valid_domain(s: &str) -> bool {
+    let labels: Vec<_> = s.split('.').collect();
+
+    labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SuppressionTarget;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn addresses_are_parsed_lowercase() {
+        assert_ok_eq!(
+            SuppressionTarget::parse("Ursula@Example.com".into()),
+            SuppressionTarget::Address("ursula@example.com".into())
+        );
+    }
+
+    #[test]
+    fn domains_are_parsed_lowercase() {
+        assert_ok_eq!(
+            SuppressionTarget::parse(" Mail.Example.com ".into()),
+            SuppressionTarget::Domain("mail.example.com".into())
+        );
+    }
+
+    #[test]
+    fn invalid_addresses_are_rejected() {
+        assert_err!(SuppressionTarget::parse("ursula@".into()));
+    }
+
+    #[test]
+    fn invalid_domains_are_rejected() {
+        assert_err!(SuppressionTarget::parse("localhost".into()));
+        assert_err!(SuppressionTarget::parse("exa mple.com".into()));
+        assert_err!(SuppressionTarget::parse("-example.com".into()));
+        assert_err!(SuppressionTarget::parse("".into()));
+    }
+}
@@ -0,0 +1,65 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UserRole {
+    Admin,
+    Editor,
+    Viewer,
+}
+
+impl UserRole {
+    pub fn parse(s: String) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "admin" => Ok(Self::Admin),
+            "editor" => Ok(Self::Editor),
+            "viewer" => Ok(Self::Viewer),
+            _ => Err(format!("{s} is not a valid user role.")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::Editor => "editor",
+            Self::Viewer => "viewer",
+        }
+    }
+}
+
+impl std::fmt::Display for UserRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl AsRef<str> for UserRole {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UserRole;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn known_roles_are_parsed_successfully() {
+        assert_ok_eq!(UserRole::parse("admin".into()), UserRole::Admin);
+        assert_ok_eq!(UserRole::parse("editor".into()), UserRole::Editor);
+        assert_ok_eq!(UserRole::parse("viewer".into()), UserRole::Viewer);
+    }
+
+    #[test]
+    fn roles_are_parsed_case_insensitively() {
+        assert_ok_eq!(UserRole::parse("Admin".into()), UserRole::Admin);
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        assert_err!(UserRole::parse("owner".into()));
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(UserRole::parse("".into()));
+    }
+}
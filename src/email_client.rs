@@ -3,16 +3,88 @@ use std::time::Duration;
 use reqwest::Client;
 use secrecy::{ExposeSecret, Secret};
 
-use crate::domain::SubscriberEmail;
+use crate::{
+    domain::SubscriberEmail, error_chain_fmt, suppression::SuppressionList,
+    telemetry::trace_context_headers,
+};
 
 use self::send_grid::{Content, MIMEType, Personalization, Recipient, SendEmailRequestBody};
 
+/// The header SendGrid returns the id of an accepted message in.
+const MESSAGE_ID_HEADER: &str = "X-Message-Id";
+
 #[derive(Clone, Debug)]
 pub struct EmailClient {
     http_client: Client,
     base_url: String,
     sender: SubscriberEmail,
     authorization_token: Secret<String>,
+    suppression_list: Option<SuppressionList>,
+}
+
+/// What became of an email we were asked to send.
+#[derive(Debug, PartialEq, Eq)]
+pub enum EmailDelivery {
+    /// Accepted by the email provider, which told us the id it gave the
+    /// message, if it did.
+    Sent { message_id: Option<String> },
+    /// The recipient is on the suppression list, so nothing was sent.
+    Suppressed,
+}
+
+/// A file sent along with an email.
+#[derive(Clone, Debug)]
+pub struct Attachment {
+    filename: String,
+    content_type: String,
+    /// Encoded once, as the same file is usually sent to many recipients.
+    base64_content: String,
+    disposition: Disposition,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Disposition {
+    Attachment,
+    /// Shown where the HTML content refers to it, as in
+    /// `<img src="cid:{content_id}">`.
+    Inline {
+        content_id: String,
+    },
+}
+
+impl Attachment {
+    pub fn new(
+        filename: String,
+        content_type: String,
+        content: &[u8],
+        disposition: Disposition,
+    ) -> Self {
+        Self {
+            filename,
+            content_type,
+            base64_content: base64::encode(content),
+            disposition,
+        }
+    }
+
+    /// How much of the message the attachment takes up.
+    pub fn encoded_len(&self) -> usize {
+        self.base64_content.len()
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum SendEmailError {
+    #[error("Failed to check the suppression list.")]
+    SuppressionCheck(#[source] sqlx::Error),
+    #[error("Failed to send an email.")]
+    Request(#[from] reqwest::Error),
+}
+
+impl std::fmt::Debug for SendEmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
 }
 
 impl EmailClient {
@@ -29,9 +101,32 @@ impl EmailClient {
             base_url,
             sender,
             authorization_token,
+            suppression_list: None,
         }
     }
 
+    /// Once set, recipients on the suppression list are skipped.
+    pub fn with_suppression_list(mut self, suppression_list: SuppressionList) -> Self {
+        self.suppression_list = Some(suppression_list);
+        self
+    }
+
+    /// Checks that SendGrid can be reached and accepts our API key, without
+    /// sending anything.
+    pub async fn check_health(&self) -> Result<(), reqwest::Error> {
+        self.http_client
+            .get(format!("{}/scopes", &self.base_url))
+            .header(
+                "Authorization",
+                format!("Bearer {}", self.authorization_token.expose_secret()),
+            )
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
     // curl --request POST \
     // --url https://api.sendgrid.com/v3/mail/send \
     // --header "Authorization: Bearer $SENDGRID_API_KEY" \
@@ -43,7 +138,36 @@ impl EmailClient {
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<EmailDelivery, SendEmailError> {
+        self.send_email_with_attachments(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+
+    pub async fn send_email_with_attachments(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        attachments: &[Attachment],
+    ) -> Result<EmailDelivery, SendEmailError> {
+        if let Some(suppression_list) = &self.suppression_list {
+            let suppression = suppression_list
+                .find(recipient)
+                .await
+                .map_err(SendEmailError::SuppressionCheck)?;
+
+            if let Some(suppression) = suppression {
+                tracing::info!(
+                    suppression.id,
+                    suppression.reason,
+                    "Skipped an email to a suppressed recipient."
+                );
+
+                return Ok(EmailDelivery::Suppressed);
+            }
+        }
+
         let url = format!("{}/mail/send", &self.base_url);
 
         let from_recipient = Recipient {
@@ -78,11 +202,28 @@ impl EmailClient {
             personalizations: &vec![Personalization {
                 to: vec![to_recipient],
             }],
+            attachments: attachments
+                .iter()
+                .map(|attachment| send_grid::Attachment {
+                    content: &attachment.base64_content,
+                    type_: &attachment.content_type,
+                    filename: &attachment.filename,
+                    disposition: match attachment.disposition {
+                        Disposition::Attachment => "attachment",
+                        Disposition::Inline { .. } => "inline",
+                    },
+                    content_id: match &attachment.disposition {
+                        Disposition::Attachment => None,
+                        Disposition::Inline { content_id } => Some(content_id),
+                    },
+                })
+                .collect(),
         };
 
-        let _builder = self
+        let response = self
             .http_client
             .post(&url)
+            .headers(trace_context_headers())
             .header(
                 "Authorization",
                 format!("Bearer {}", self.authorization_token.expose_secret()),
@@ -91,7 +232,13 @@ impl EmailClient {
             .send()
             .await?
             .error_for_status()?;
-        Ok(())
+        let message_id = response
+            .headers()
+            .get(MESSAGE_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+
+        Ok(EmailDelivery::Sent { message_id })
     }
 }
 
@@ -103,6 +250,8 @@ mod send_grid {
         pub subject: &'a str,
         pub content: &'a Vec<Content<'a>>,
         pub personalizations: &'a Vec<Personalization<'a>>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub attachments: Vec<Attachment<'a>>,
     }
 
     #[derive(serde::Serialize, Debug)]
@@ -130,11 +279,23 @@ mod send_grid {
     pub struct Personalization<'a> {
         pub to: Vec<Recipient<'a>>,
     }
+
+    #[derive(serde::Serialize, Debug)]
+    pub struct Attachment<'a> {
+        /// Base64 encoded.
+        pub content: &'a str,
+        #[serde(rename = "type")]
+        pub type_: &'a str,
+        pub filename: &'a str,
+        pub disposition: &'static str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub content_id: Option<&'a str>,
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use claims::{assert_err, assert_ok};
+    use claims::{assert_err, assert_ok, assert_ok_eq};
     use fake::{
         faker::{
             internet::en::SafeEmail,
@@ -151,7 +312,7 @@ mod tests {
 
     use crate::domain::SubscriberEmail;
 
-    use super::EmailClient;
+    use super::{Attachment, Disposition, EmailClient, EmailDelivery};
 
     fn subject() -> String {
         Sentence(1..2).fake()
@@ -181,7 +342,7 @@ mod tests {
             let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
 
             if let Ok(body) = result {
-                vec!["from", "personalizations", "subject", "content"]
+                ["from", "personalizations", "subject", "content"]
                     .iter()
                     .all(|x| body.get(x).is_some())
             } else {
@@ -212,6 +373,86 @@ mod tests {
         assert_ok!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_with_attachments_sends_them_base64_encoded() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let attachments = [
+            Attachment::new(
+                "report.pdf".into(),
+                "application/pdf".into(),
+                b"%PDF",
+                Disposition::Attachment,
+            ),
+            Attachment::new(
+                "chart.png".into(),
+                "image/png".into(),
+                b"PNG",
+                Disposition::Inline {
+                    content_id: "chart".into(),
+                },
+            ),
+        ];
+
+        let outcome = email_client
+            .send_email_with_attachments(&email(), &subject(), &content(), &content(), &attachments)
+            .await;
+
+        assert_ok!(outcome);
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+
+        assert_eq!(
+            body["attachments"],
+            serde_json::json!([
+                {
+                    "content": "JVBERg==",
+                    "type": "application/pdf",
+                    "filename": "report.pdf",
+                    "disposition": "attachment",
+                },
+                {
+                    "content": "UE5H",
+                    "type": "image/png",
+                    "filename": "chart.png",
+                    "disposition": "inline",
+                    "content_id": "chart",
+                },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_returns_the_id_of_the_sent_message() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(202).insert_header("X-Message-Id", "abc123"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok_eq!(
+            outcome,
+            EmailDelivery::Sent {
+                message_id: Some("abc123".into())
+            }
+        );
+    }
+
     #[tokio::test]
     async fn send_email_fails_if_the_server_returns_500() {
         let mock_server = MockServer::start().await;
@@ -247,4 +488,20 @@ mod tests {
 
         assert_err!(outcome);
     }
+
+    #[tokio::test]
+    async fn check_health_fails_if_the_api_key_is_rejected() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("Authorization"))
+            .and(path("/scopes"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_err!(email_client.check_health().await);
+    }
 }
@@ -0,0 +1,342 @@
+use std::sync::OnceLock;
+
+use kuchiki::{traits::*, ElementData, NodeDataRef, NodeRef, Selector, Selectors, Specificity};
+use regex::Regex;
+
+/// Gmail clips messages larger than this, hiding the rest of the issue, the
+/// tracking pixel and the preferences footer behind a link.
+pub const MAX_HTML_BYTES: usize = 102 * 1024;
+
+/// Elements email clients ignore, or refuse to display.
+const UNSUPPORTED_ELEMENTS: [&str; 12] = [
+    "audio", "button", "embed", "form", "iframe", "input", "link", "object", "script", "select",
+    "textarea", "video",
+];
+
+/// The HTML of an issue as email clients expect it: the rules of its
+/// `<style>` blocks are written into the `style` attribute of the elements
+/// they apply to, as most clients drop `<style>` blocks altogether.
+/// Whatever can't be kept that way is removed, and the authors are warned.
+pub struct EmailHtml {
+    html: String,
+    warnings: Vec<String>,
+}
+
+impl EmailHtml {
+    pub fn prepare(html: &str) -> Self {
+        let document = kuchiki::parse_html().one(html);
+        let mut warnings = Vec::new();
+
+        let stylesheet: String = document
+            .descendants()
+            .elements()
+            .filter(|element| &*element.name.local == "style")
+            .map(|element| element.text_contents())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let rules = parse_stylesheet(&stylesheet, &mut warnings);
+
+        for element in document.descendants().elements() {
+            inline_style(&element, &rules);
+        }
+
+        let mut removed = Vec::new();
+
+        for element in document.descendants().elements() {
+            let name = element.name.local.to_string();
+
+            if name == "style" {
+                removed.push(element.as_node().clone());
+            } else if UNSUPPORTED_ELEMENTS.contains(&name.as_str()) {
+                push_once(
+                    &mut warnings,
+                    format!(
+                        "<{name}> elements aren't supported by email clients and were removed."
+                    ),
+                );
+                removed.push(element.as_node().clone());
+            }
+        }
+
+        for node in removed {
+            node.detach();
+        }
+
+        let mut email_html = Self {
+            html: body_html(&document),
+            warnings,
+        };
+
+        if let Err(warning) = email_html.check_size() {
+            email_html.warnings.push(warning);
+        }
+
+        email_html
+    }
+
+    pub fn html(&self) -> &str {
+        &self.html
+    }
+
+    /// What was changed or removed for email clients, and whether the issue
+    /// is too large to be published.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Issues too large to be shown in full can be drafted, but not published.
+    pub fn check_size(&self) -> Result<(), String> {
+        check_html_size(&self.html)
+    }
+}
+
+/// Whether email clients show `html` in full.
+pub fn check_html_size(html: &str) -> Result<(), String> {
+    if html.len() > MAX_HTML_BYTES {
+        return Err(format!(
+            "The HTML content is {} KB, more than the {} KB email clients show in full.",
+            html.len().div_ceil(1024),
+            MAX_HTML_BYTES / 1024
+        ));
+    }
+
+    Ok(())
+}
+
+/// A style rule, with one selector.
+struct Rule {
+    selector: Selector,
+    specificity: Specificity,
+    declarations: Vec<(String, String)>,
+}
+
+/// Reads the rules that can be inlined. At-rules, such as `@media`, and
+/// selectors with pseudo-classes or pseudo-elements, such as `:hover`, only
+/// apply in a stylesheet.
+fn parse_stylesheet(stylesheet: &str, warnings: &mut Vec<String>) -> Vec<Rule> {
+    static COMMENT: OnceLock<Regex> = OnceLock::new();
+
+    let comment = COMMENT.get_or_init(|| Regex::new(r"(?s)/\*.*?\*/").unwrap());
+    let stylesheet = comment.replace_all(stylesheet, "");
+    let mut rest = stylesheet.trim_start();
+    let mut rules = Vec::new();
+
+    while !rest.is_empty() {
+        if let Some(at_rule) = rest.strip_prefix('@') {
+            let name: String = at_rule
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+                .collect();
+
+            push_once(
+                warnings,
+                format!("@{name} rules can't be inlined and were removed."),
+            );
+            rest = skip_at_rule(rest);
+        } else {
+            let Some((prelude, after)) = rest.split_once('{') else {
+                break;
+            };
+            let (block, after) = after.split_once('}').unwrap_or((after, ""));
+            let declarations = parse_declarations(block);
+
+            for selector in prelude.split(',').map(str::trim) {
+                if selector.contains(':') {
+                    push_once(
+                        warnings,
+                        format!(
+                            "The {selector} rule only applies in a stylesheet and was removed."
+                        ),
+                    );
+                    continue;
+                }
+
+                match Selectors::compile(selector) {
+                    Ok(selectors) => rules.extend(selectors.0.into_iter().map(|selector| Rule {
+                        specificity: selector.specificity(),
+                        selector,
+                        declarations: declarations.clone(),
+                    })),
+                    Err(()) => push_once(
+                        warnings,
+                        format!("The {selector} selector isn't supported and was removed."),
+                    ),
+                }
+            }
+
+            rest = after;
+        }
+
+        rest = rest.trim_start();
+    }
+
+    rules
+}
+
+/// Skips a statement such as `@import`, or a block such as `@media`, along
+/// with the blocks it's made of.
+fn skip_at_rule(at_rule: &str) -> &str {
+    let mut depth = 0;
+
+    for (i, c) in at_rule.char_indices() {
+        match c {
+            ';' if depth == 0 => return &at_rule[i + 1..],
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return &at_rule[i + 1..];
+                }
+            }
+            _ => {}
+        }
+    }
+
+    ""
+}
+
+fn parse_declarations(block: &str) -> Vec<(String, String)> {
+    block
+        .split(';')
+        .filter_map(|declaration| declaration.split_once(':'))
+        .map(|(property, value)| {
+            (
+                property.trim().to_ascii_lowercase(),
+                value.trim().to_string(),
+            )
+        })
+        .filter(|(property, value)| !property.is_empty() && !value.is_empty())
+        .collect()
+}
+
+/// Applies the matching rules in order of specificity, then of appearance,
+/// with the element's own `style` attribute winning over all of them.
+fn inline_style(element: &NodeDataRef<ElementData>, rules: &[Rule]) {
+    let mut matching: Vec<(usize, &Rule)> = rules
+        .iter()
+        .enumerate()
+        .filter(|(_, rule)| rule.selector.matches(element))
+        .collect();
+
+    if matching.is_empty() {
+        return;
+    }
+
+    matching.sort_by_key(|(order, rule)| (rule.specificity, *order));
+
+    let mut attributes = element.attributes.borrow_mut();
+    let own_style = attributes.get("style").map(parse_declarations);
+    let mut declarations: Vec<(String, String)> = Vec::new();
+
+    for (property, value) in matching
+        .into_iter()
+        .flat_map(|(_, rule)| rule.declarations.iter().cloned())
+        .chain(own_style.into_iter().flatten())
+    {
+        // Later declarations of a property win, and must come after any
+        // shorthand declared in between
+        declarations.retain(|(p, _)| *p != property);
+        declarations.push((property, value));
+    }
+
+    let style = declarations
+        .iter()
+        .map(|(property, value)| format!("{property}: {value}"))
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    attributes.insert("style", style);
+}
+
+/// Issues are sent as the contents of a body, whether or not they were
+/// written as a full document.
+fn body_html(document: &NodeRef) -> String {
+    let Ok(body) = document.select_first("body") else {
+        return document.to_string();
+    };
+
+    body.as_node()
+        .children()
+        .map(|child| child.to_string())
+        .collect()
+}
+
+fn push_once(warnings: &mut Vec<String>, warning: String) {
+    if !warnings.contains(&warning) {
+        warnings.push(warning);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EmailHtml, MAX_HTML_BYTES};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn style_blocks_are_inlined() {
+        let email_html = EmailHtml::prepare(
+            "<style>p { color: red; } .lead { font-size: 18px }</style>\
+            <p class=\"lead\">Hi</p><p>There</p>",
+        );
+
+        assert_eq!(
+            email_html.html(),
+            "<p class=\"lead\" style=\"color: red; font-size: 18px\">Hi</p>\
+            <p style=\"color: red\">There</p>"
+        );
+        assert!(email_html.warnings().is_empty());
+    }
+
+    #[test]
+    fn more_specific_rules_and_inline_styles_win() {
+        let email_html = EmailHtml::prepare(
+            "<style>#title { color: blue } h1 { color: red; margin: 0 }</style>\
+            <h1 id=\"title\" style=\"margin: 4px\">Hi</h1>",
+        );
+
+        assert_eq!(
+            email_html.html(),
+            "<h1 id=\"title\" style=\"color: blue; margin: 4px\">Hi</h1>"
+        );
+    }
+
+    #[test]
+    fn rules_that_only_apply_in_a_stylesheet_are_removed_with_a_warning() {
+        let email_html = EmailHtml::prepare(
+            "<style>\
+                @media (max-width: 600px) { p { color: red } }\
+                a:hover { color: green }\
+                p { color: black }\
+            </style>\
+            <p>Hi <a href=\"https://example.com\">there</a></p>",
+        );
+
+        assert_eq!(
+            email_html.html(),
+            "<p style=\"color: black\">Hi <a href=\"https://example.com\">there</a></p>"
+        );
+        assert_eq!(email_html.warnings().len(), 2);
+    }
+
+    #[test]
+    fn unsupported_elements_are_removed_with_a_warning() {
+        let email_html = EmailHtml::prepare(
+            "<p>Hi</p><iframe src=\"https://example.com\"></iframe>\
+            <form><input name=\"email\"><button>Go</button></form>",
+        );
+
+        assert_eq!(email_html.html(), "<p>Hi</p>");
+        assert_eq!(email_html.warnings().len(), 4);
+    }
+
+    #[test]
+    fn issues_larger_than_the_limit_are_too_large() {
+        let fits = EmailHtml::prepare(&format!("<p>{}</p>", "a".repeat(MAX_HTML_BYTES - 7)));
+        let too_large = EmailHtml::prepare(&format!("<p>{}</p>", "a".repeat(MAX_HTML_BYTES)));
+
+        assert_ok!(fits.check_size());
+        assert!(fits.warnings().is_empty());
+        assert_err!(too_large.check_size());
+        assert_eq!(too_large.warnings().len(), 1);
+    }
+}
@@ -0,0 +1,435 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use regex::{Captures, Regex};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{domain::EngagementKind, signing::HmacSecret};
+
+/// The query of a tracking pixel or a tracked link. It names the subscriber
+/// the email was sent to, and is signed so that opens and clicks can't be
+/// made up.
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
+pub struct TrackingQuery {
+    pub subscriber_id: Uuid,
+    pub signature: String,
+}
+
+impl TrackingQuery {
+    fn issue(hmac_secret: &HmacSecret, message: &str, subscriber_id: Uuid) -> Self {
+        Self {
+            subscriber_id,
+            signature: hmac_secret.sign(message),
+        }
+    }
+
+    fn query_string(&self) -> String {
+        format!(
+            "subscriber_id={}&signature={}",
+            self.subscriber_id, self.signature
+        )
+    }
+
+    pub fn verify_open(&self, hmac_secret: &HmacSecret, issue_id: Uuid) -> bool {
+        hmac_secret.verify(&open_message(issue_id, self.subscriber_id), &self.signature)
+    }
+
+    pub fn verify_click(&self, hmac_secret: &HmacSecret, link_id: Uuid) -> bool {
+        hmac_secret.verify(&click_message(link_id, self.subscriber_id), &self.signature)
+    }
+}
+
+fn open_message(issue_id: Uuid, subscriber_id: Uuid) -> String {
+    format!("newsletter-open:{issue_id}:{subscriber_id}")
+}
+
+fn click_message(link_id: Uuid, subscriber_id: Uuid) -> String {
+    format!("newsletter-click:{link_id}:{subscriber_id}")
+}
+
+/// The links of a published issue, each stored with an id that tracked
+/// links redirect through.
+pub struct IssueTracking {
+    issue_id: Uuid,
+    links: HashMap<String, Uuid>,
+}
+
+impl IssueTracking {
+    /// Gives every link found in the HTML content of an issue an id.
+    pub fn new(issue_id: Uuid, html: &str) -> Self {
+        let links = link_urls(html)
+            .into_iter()
+            .map(|url| (url, Uuid::now_v7()))
+            .collect();
+
+        Self { issue_id, links }
+    }
+
+    /// The links stored when the issue started being sent.
+    #[tracing::instrument(name = "Load the links of a newsletter issue", skip(db_pool))]
+    pub async fn load(db_pool: &PgPool, issue_id: Uuid) -> Result<Self, anyhow::Error> {
+        let links = sqlx::query!(
+            r#"
+                SELECT id, url
+                FROM newsletter_issue_links
+                WHERE newsletter_issue_id = $1
+            "#,
+            issue_id
+        )
+        .fetch_all(db_pool)
+        .await
+        .context("Failed to retrieve the links of a newsletter issue.")?
+        .into_iter()
+        .map(|r| (r.url, r.id))
+        .collect();
+
+        Ok(Self { issue_id, links })
+    }
+
+    /// Stores the links, for tracked links to redirect to.
+    #[tracing::instrument(name = "Store the links of a newsletter issue", skip_all)]
+    pub async fn save(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), anyhow::Error> {
+        for (url, link_id) in &self.links {
+            sqlx::query!(
+                r#"
+                    INSERT INTO newsletter_issue_links (id, newsletter_issue_id, url)
+                    VALUES ($1, $2, $3)
+                "#,
+                link_id,
+                self.issue_id,
+                url
+            )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to store a link of a newsletter issue.")?;
+        }
+
+        Ok(())
+    }
+
+    /// The HTML content of the issue as sent to one subscriber: its links
+    /// go through tracked links, and a tracking pixel is added at the end.
+    pub fn track(
+        &self,
+        html: &str,
+        base_url: &str,
+        hmac_secret: &HmacSecret,
+        subscriber_id: Uuid,
+    ) -> String {
+        let html = rewrite_links(html, |url| {
+            self.links.get(url).map(|link_id| {
+                let query = TrackingQuery::issue(
+                    hmac_secret,
+                    &click_message(*link_id, subscriber_id),
+                    subscriber_id,
+                );
+
+                format!(
+                    "{base_url}/newsletters/links/{link_id}?{}",
+                    query.query_string()
+                )
+            })
+        });
+        let query = TrackingQuery::issue(
+            hmac_secret,
+            &open_message(self.issue_id, subscriber_id),
+            subscriber_id,
+        );
+
+        format!(
+            "{html}<img src=\"{base_url}/newsletters/{}/open?{}\" \
+            width=\"1\" height=\"1\" alt=\"\" />",
+            self.issue_id,
+            query.query_string()
+        )
+    }
+}
+
+fn link_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+
+    PATTERN.get_or_init(|| {
+        Regex::new(r#"(?i)(<a\s[^>]*?\bhref\s*=\s*)(?:"([^"]*)"|'([^']*)')"#)
+            .expect("The link pattern is valid")
+    })
+}
+
+/// The web address of an anchor, if it has one. `mailto:` links and
+/// in-page anchors aren't tracked.
+fn link_url(captures: &Captures) -> Option<String> {
+    let href = captures.get(2).or_else(|| captures.get(3))?.as_str().trim();
+    let is_web_address = ["http://", "https://"].iter().any(|scheme| {
+        href.get(..scheme.len())
+            .is_some_and(|s| s.eq_ignore_ascii_case(scheme))
+    });
+
+    is_web_address.then(|| href.replace("&amp;", "&"))
+}
+
+/// The distinct web addresses linked to from `html`, in order.
+fn link_urls(html: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+
+    for url in link_pattern()
+        .captures_iter(html)
+        .filter_map(|c| link_url(&c))
+    {
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+
+    urls
+}
+
+/// Replaces the web address of every anchor in `html` for which `replace`
+/// returns a new one.
+fn rewrite_links(html: &str, mut replace: impl FnMut(&str) -> Option<String>) -> String {
+    link_pattern()
+        .replace_all(html, |captures: &Captures| {
+            match link_url(captures).and_then(|url| replace(&url)) {
+                Some(url) => format!("{}\"{url}\"", &captures[1]),
+                None => captures[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// Engagement of subscribers who have since been erased isn't recorded.
+#[tracing::instrument(name = "Record a newsletter open", skip(db_pool))]
+pub async fn record_open(
+    db_pool: &PgPool,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO newsletter_engagements (newsletter_issue_id, subscriber_id, kind)
+            SELECT i.id, s.id, $3::text
+            FROM newsletter_issues i, subscriptions s
+            WHERE i.id = $1 AND s.id = $2
+        "#,
+        issue_id,
+        subscriber_id,
+        EngagementKind::Open.as_str()
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to record a newsletter open.")?;
+
+    Ok(())
+}
+
+/// Where the link leads, or `None` if there is no such link.
+#[tracing::instrument(name = "Record a newsletter click", skip(db_pool))]
+pub async fn record_click(
+    db_pool: &PgPool,
+    link_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, anyhow::Error> {
+    let link = sqlx::query!(
+        r#"SELECT newsletter_issue_id, url FROM newsletter_issue_links WHERE id = $1"#,
+        link_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve a newsletter link.")?;
+
+    let link = match link {
+        Some(link) => link,
+        None => return Ok(None),
+    };
+
+    sqlx::query!(
+        r#"
+            INSERT INTO newsletter_engagements
+                (newsletter_issue_id, subscriber_id, kind, link_id)
+            SELECT $1::uuid, s.id, $3::text, $4::uuid
+            FROM subscriptions s
+            WHERE s.id = $2
+        "#,
+        link.newsletter_issue_id,
+        subscriber_id,
+        EngagementKind::Click.as_str(),
+        link_id
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to record a newsletter click.")?;
+
+    Ok(Some(link.url))
+}
+
+/// How readers engaged with an issue. Unique counts are of subscribers.
+#[derive(serde::Serialize)]
+pub struct IssueEngagement {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    pub opens: i64,
+    pub unique_opens: i64,
+    pub clicks: i64,
+    pub unique_clicks: i64,
+    pub links: Vec<LinkEngagement>,
+}
+
+#[derive(serde::Serialize)]
+pub struct LinkEngagement {
+    pub url: String,
+    pub clicks: i64,
+    pub unique_clicks: i64,
+}
+
+#[tracing::instrument(name = "Get the engagement of a newsletter issue", skip(db_pool))]
+pub async fn get_issue_engagement(
+    db_pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<IssueEngagement>, anyhow::Error> {
+    let issue = sqlx::query!(
+        r#"
+            SELECT
+                i.title,
+                i.published_at AS "published_at!",
+                COUNT(e.id) FILTER (WHERE e.kind = 'open') AS "opens!",
+                COUNT(DISTINCT e.subscriber_id) FILTER (WHERE e.kind = 'open') AS "unique_opens!",
+                COUNT(e.id) FILTER (WHERE e.kind = 'click') AS "clicks!",
+                COUNT(DISTINCT e.subscriber_id) FILTER (WHERE e.kind = 'click') AS "unique_clicks!"
+            FROM newsletter_issues i
+            LEFT JOIN newsletter_engagements e ON e.newsletter_issue_id = i.id
+            WHERE i.id = $1 AND i.published_at IS NOT NULL
+            GROUP BY i.id
+        "#,
+        issue_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve the engagement of a newsletter issue.")?;
+
+    let issue = match issue {
+        Some(issue) => issue,
+        None => return Ok(None),
+    };
+
+    let links = sqlx::query_as!(
+        LinkEngagement,
+        r#"
+            SELECT
+                l.url,
+                COUNT(e.id) AS "clicks!",
+                COUNT(DISTINCT e.subscriber_id) AS "unique_clicks!"
+            FROM newsletter_issue_links l
+            LEFT JOIN newsletter_engagements e ON e.link_id = l.id
+            WHERE l.newsletter_issue_id = $1
+            GROUP BY l.id
+            ORDER BY 2 DESC, l.url
+        "#,
+        issue_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve the clicks on the links of a newsletter issue.")?;
+
+    Ok(Some(IssueEngagement {
+        issue_id,
+        title: issue.title,
+        published_at: issue.published_at,
+        opens: issue.opens,
+        unique_opens: issue.unique_opens,
+        clicks: issue.clicks,
+        unique_clicks: issue.unique_clicks,
+        links,
+    }))
+}
+
+/// How a subscriber engaged with one issue.
+#[derive(serde::Serialize)]
+pub struct SubscriberIssueEngagement {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    pub opens: i64,
+    pub clicks: i64,
+    pub last_engaged_at: DateTime<Utc>,
+}
+
+/// The issues a subscriber opened or clicked through, latest first.
+#[tracing::instrument(name = "Get the engagement of a subscriber", skip(db_pool))]
+pub async fn get_subscriber_engagement(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<SubscriberIssueEngagement>, anyhow::Error> {
+    sqlx::query_as!(
+        SubscriberIssueEngagement,
+        r#"
+            SELECT
+                i.id AS issue_id,
+                i.title,
+                i.published_at AS "published_at!",
+                COUNT(*) FILTER (WHERE e.kind = 'open') AS "opens!",
+                COUNT(*) FILTER (WHERE e.kind = 'click') AS "clicks!",
+                MAX(e.occurred_at) AS "last_engaged_at!"
+            FROM newsletter_engagements e
+            JOIN newsletter_issues i ON i.id = e.newsletter_issue_id
+            WHERE e.subscriber_id = $1
+            GROUP BY i.id
+            ORDER BY i.published_at DESC
+        "#,
+        subscriber_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve the engagement of a subscriber.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{link_urls, rewrite_links};
+
+    #[test]
+    fn web_links_are_found_once_in_order() {
+        let html = r#"
+            <p><a href="https://example.com/b">B</a> <a class="x" href='http://example.com/a'>A</a></p>
+            <p><a href="https://example.com/b">B, again</a></p>
+        "#;
+
+        assert_eq!(
+            link_urls(html),
+            vec!["https://example.com/b", "http://example.com/a"]
+        );
+    }
+
+    #[test]
+    fn mail_and_in_page_links_are_left_alone() {
+        let html = r##"<a href="mailto:ursula@example.com">Mail</a><a href="#top">Top</a>"##;
+
+        assert!(link_urls(html).is_empty());
+        assert_eq!(rewrite_links(html, |_| Some("replaced".into())), html);
+    }
+
+    #[test]
+    fn escaped_ampersands_are_decoded() {
+        let html = r#"<a href="https://example.com/?a=1&amp;b=2">Link</a>"#;
+
+        assert_eq!(link_urls(html), vec!["https://example.com/?a=1&b=2"]);
+    }
+
+    #[test]
+    fn only_replaced_links_are_rewritten() {
+        let html = r#"<a href="https://example.com/a">A</a><a href='https://example.com/b'>B</a>"#;
+
+        let rewritten = rewrite_links(html, |url| {
+            url.ends_with("/a")
+                .then(|| "https://tracked.example.com/1".to_string())
+        });
+
+        assert_eq!(
+            rewritten,
+            r#"<a href="https://tracked.example.com/1">A</a><a href='https://example.com/b'>B</a>"#
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use p256::{
+    ecdsa::{signature::Verifier, Signature, VerifyingKey},
+    pkcs8::DecodePublicKey,
+};
+
+/// The header carrying the base64 encoded ECDSA signature of an event batch.
+pub const SIGNATURE_HEADER: &str = "X-Twilio-Email-Event-Webhook-Signature";
+/// The header carrying the timestamp that was signed along with the batch.
+pub const TIMESTAMP_HEADER: &str = "X-Twilio-Email-Event-Webhook-Timestamp";
+
+/// Checks that event batches posted to our webhook were signed by SendGrid.
+#[derive(Clone, Debug)]
+pub struct EventWebhookVerifier(VerifyingKey);
+
+impl EventWebhookVerifier {
+    /// Parses the public key as shown in SendGrid's settings: base64 encoded
+    /// DER.
+    pub fn from_public_key(public_key: &str) -> Result<Self, String> {
+        let der = base64::decode(public_key.trim())
+            .map_err(|e| format!("The event webhook public key is not base64: {e}"))?;
+        let key = VerifyingKey::from_public_key_der(&der)
+            .map_err(|e| format!("The event webhook public key is invalid: {e}"))?;
+
+        Ok(Self(key))
+    }
+
+    /// SendGrid signs the timestamp followed by the raw request body.
+    pub fn verify(&self, timestamp: &str, body: &[u8], signature: &str) -> bool {
+        let signature = match base64::decode(signature)
+            .ok()
+            .and_then(|der| Signature::from_der(&der).ok())
+        {
+            Some(signature) => signature,
+            None => return false,
+        };
+
+        let mut payload = timestamp.as_bytes().to_vec();
+        payload.extend_from_slice(body);
+
+        self.0.verify(&payload, &signature).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::{
+        ecdsa::{signature::Signer, Signature, SigningKey},
+        pkcs8::EncodePublicKey,
+    };
+    use rand::Rng;
+
+    use super::EventWebhookVerifier;
+
+    fn signing_key() -> SigningKey {
+        loop {
+            let bytes: [u8; 32] = rand::thread_rng().gen();
+
+            if let Ok(key) = SigningKey::from_slice(&bytes) {
+                return key;
+            }
+        }
+    }
+
+    fn verifier(key: &SigningKey) -> EventWebhookVerifier {
+        let der = key.verifying_key().to_public_key_der().unwrap();
+
+        EventWebhookVerifier::from_public_key(&base64::encode(der.as_bytes())).unwrap()
+    }
+
+    fn sign(key: &SigningKey, timestamp: &str, body: &[u8]) -> String {
+        let mut payload = timestamp.as_bytes().to_vec();
+        payload.extend_from_slice(body);
+        let signature: Signature = key.sign(&payload);
+
+        base64::encode(signature.to_der().as_bytes())
+    }
+
+    #[test]
+    fn signed_batches_are_accepted() {
+        let key = signing_key();
+        let signature = sign(&key, "1600000000", b"[]");
+
+        assert!(verifier(&key).verify("1600000000", b"[]", &signature));
+    }
+
+    #[test]
+    fn tampered_batches_are_rejected() {
+        let key = signing_key();
+        let signature = sign(&key, "1600000000", b"[]");
+
+        assert!(!verifier(&key).verify("1600000001", b"[]", &signature));
+        assert!(!verifier(&key).verify("1600000000", b"[{}]", &signature));
+        assert!(!verifier(&key).verify("1600000000", b"[]", "not-a-signature"));
+    }
+
+    #[test]
+    fn batches_signed_by_another_key_are_rejected() {
+        let signature = sign(&signing_key(), "1600000000", b"[]");
+
+        assert!(!verifier(&signing_key()).verify("1600000000", b"[]", &signature));
+    }
+
+    #[test]
+    fn invalid_public_keys_are_rejected() {
+        assert!(EventWebhookVerifier::from_public_key("not base64!").is_err());
+        assert!(EventWebhookVerifier::from_public_key(&base64::encode(b"not a key")).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use sqlx::PgPool;
+use tokio::{sync::Mutex, time::Instant};
+
+use crate::email_client::EmailClient;
+
+/// Dependencies slower than this to answer are reported as down.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the email provider's answer is reused for. Load balancers probe
+/// every few seconds, and each check is a request counted against our API
+/// quota.
+const EMAIL_PROVIDER_CHECK_TTL: Duration = Duration::from_secs(30);
+
+/// Whether the application should be sent requests. Shared by every worker,
+/// it stops being ready as soon as it starts shutting down, for load
+/// balancers to send requests elsewhere while in-flight ones finish.
+#[derive(Clone, Debug, Default)]
+pub struct Readiness {
+    shutting_down: Arc<AtomicBool>,
+    check_email_provider: bool,
+    last_email_provider_check: Arc<Mutex<Option<(Instant, DependencyCheck)>>>,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct ReadinessReport {
+    pub status: ReadinessStatus,
+    pub checks: BTreeMap<&'static str, DependencyCheck>,
+}
+
+#[derive(serde::Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadinessStatus {
+    Ready,
+    NotReady,
+    ShuttingDown,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct DependencyCheck {
+    pub status: DependencyStatus,
+    pub latency_ms: u64,
+    /// Either "unreachable" or "timed out". The endpoint is public, so what
+    /// went wrong is only logged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<&'static str>,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyStatus {
+    Up,
+    Down,
+}
+
+impl Readiness {
+    /// Probing the email provider is optional, since every check is then a
+    /// request to its API.
+    pub fn new(check_email_provider: bool) -> Self {
+        Self {
+            shutting_down: Arc::default(),
+            check_email_provider,
+            last_email_provider_check: Arc::default(),
+        }
+    }
+
+    pub fn mark_shutting_down(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Checks every dependency at once. Nothing is checked once shutting
+    /// down, since the answer is the same either way.
+    #[tracing::instrument(name = "Check readiness", skip(self, db_pool, email_client))]
+    pub async fn check(&self, db_pool: &PgPool, email_client: &EmailClient) -> ReadinessReport {
+        if self.is_shutting_down() {
+            return ReadinessReport {
+                status: ReadinessStatus::ShuttingDown,
+                checks: BTreeMap::new(),
+            };
+        }
+
+        let database = check_dependency(async {
+            sqlx::query("SELECT 1").execute(db_pool).await?;
+            Ok::<_, sqlx::Error>(())
+        });
+        let email_provider = async {
+            if self.check_email_provider {
+                Some(
+                    self.cached_email_provider_check(email_client.check_health())
+                        .await,
+                )
+            } else {
+                None
+            }
+        };
+        let (database, email_provider) = tokio::join!(database, email_provider);
+        let mut checks = BTreeMap::from([("database", database)]);
+
+        if let Some(email_provider) = email_provider {
+            checks.insert("email_provider", email_provider);
+        }
+
+        let status = if checks
+            .values()
+            .all(|check| check.status == DependencyStatus::Up)
+        {
+            ReadinessStatus::Ready
+        } else {
+            ReadinessStatus::NotReady
+        };
+
+        ReadinessReport { status, checks }
+    }
+
+    /// The lock is held during the check, for concurrent probes to wait for
+    /// its answer rather than each make a request of their own.
+    async fn cached_email_provider_check<F, E>(&self, check: F) -> DependencyCheck
+    where
+        F: Future<Output = Result<(), E>>,
+        E: std::fmt::Display,
+    {
+        let mut last_check = self.last_email_provider_check.lock().await;
+
+        if let Some((checked_at, check)) = last_check.as_ref() {
+            if checked_at.elapsed() < EMAIL_PROVIDER_CHECK_TTL {
+                return check.clone();
+            }
+        }
+
+        let check = check_dependency(check).await;
+
+        *last_check = Some((Instant::now(), check.clone()));
+
+        check
+    }
+}
+
+async fn check_dependency<F, E>(check: F) -> DependencyCheck
+where
+    F: Future<Output = Result<(), E>>,
+    E: std::fmt::Display,
+{
+    let started_at = Instant::now();
+    let outcome = tokio::time::timeout(CHECK_TIMEOUT, check).await;
+    let latency_ms = started_at.elapsed().as_millis() as u64;
+    let error = match outcome {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => {
+            tracing::warn!(error = %e, "A dependency failed its readiness check.");
+            Some("unreachable")
+        }
+        Err(_) => {
+            tracing::warn!(
+                "A dependency took more than {} seconds to answer its readiness check.",
+                CHECK_TIMEOUT.as_secs()
+            );
+            Some("timed out")
+        }
+    };
+
+    DependencyCheck {
+        status: match error {
+            None => DependencyStatus::Up,
+            Some(_) => DependencyStatus::Down,
+        },
+        latency_ms,
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        check_dependency, DependencyStatus, Readiness, CHECK_TIMEOUT, EMAIL_PROVIDER_CHECK_TTL,
+    };
+
+    #[tokio::test]
+    async fn dependencies_that_answer_are_up() {
+        let check = check_dependency(async { Ok::<_, String>(()) }).await;
+
+        assert_eq!(check.status, DependencyStatus::Up);
+        assert_eq!(check.error, None);
+    }
+
+    #[tokio::test]
+    async fn dependencies_that_fail_are_down_without_saying_why() {
+        let check = check_dependency(async { Err("Connection refused".to_string()) }).await;
+
+        assert_eq!(check.status, DependencyStatus::Down);
+        assert_eq!(check.error, Some("unreachable"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dependencies_that_take_too_long_are_down() {
+        let check = check_dependency(async {
+            tokio::time::sleep(CHECK_TIMEOUT + Duration::from_secs(1)).await;
+            Ok::<_, String>(())
+        })
+        .await;
+
+        assert_eq!(check.status, DependencyStatus::Down);
+        assert_eq!(check.error, Some("timed out"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_email_provider_is_checked_again_once_its_answer_is_stale() {
+        let readiness = Readiness::new(true);
+        let check =
+            |outcome: Result<(), String>| readiness.cached_email_provider_check(async { outcome });
+
+        assert_eq!(check(Ok(())).await.status, DependencyStatus::Up);
+        assert_eq!(
+            check(Err("Unauthorized".into())).await.status,
+            DependencyStatus::Up
+        );
+
+        tokio::time::advance(EMAIL_PROVIDER_CHECK_TTL).await;
+
+        assert_eq!(
+            check(Err("Unauthorized".into())).await.status,
+            DependencyStatus::Down
+        );
+    }
+}
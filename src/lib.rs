@@ -1,11 +1,30 @@
 pub mod application;
+pub mod audit;
 pub mod authentication;
+pub mod authorization;
+pub mod client_ip;
+pub mod csrf;
 pub mod db;
+pub mod delivery;
+pub mod digest;
 pub mod domain;
 pub mod email_client;
+pub mod email_html;
+pub mod engagement;
+pub mod event_webhook;
+pub mod health;
+pub mod metrics;
+pub mod newsletter_asset;
+pub mod newsletter_issue;
+pub mod publishing;
 pub mod routes;
+pub mod session_state;
 pub mod settings;
+pub mod shutdown;
+pub mod signing;
 pub mod stuff;
+pub mod subscriber_data;
+pub mod suppression;
 pub mod telemetry;
 pub mod views;
 
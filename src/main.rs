@@ -1,18 +1,26 @@
 use zero2prod::{
     application::Application,
     settings::Settings,
-    telemetry::{get_subscriber, init_subscriber},
+    telemetry::{get_subscriber, init_subscriber, shutdown_tracer_provider},
 };
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
+    let settings = Settings::load().expect("Failed to read configuration");
+    let subscriber = get_subscriber(
+        "zero2prod".into(),
+        "info".into(),
+        std::io::stdout,
+        &settings.telemetry,
+    );
     init_subscriber(subscriber);
 
-    Application::builder_from_settings(Settings::load().expect("Failed to read configuration"))
+    let outcome = Application::builder_from_settings(settings)
         .build()
         .run_until_stopped()
-        .await?;
+        .await;
 
-    Ok(())
+    shutdown_tracer_provider().await;
+
+    outcome
 }
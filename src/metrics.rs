@@ -0,0 +1,321 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    sync::OnceLock,
+    time::Instant,
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::Method,
+};
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use sqlx::PgPool;
+
+use crate::email_client::{EmailDelivery, SendEmailError};
+
+/// What an email was sent for.
+#[derive(Clone, Copy, Debug)]
+pub enum EmailKind {
+    Confirmation,
+    Digest,
+    Invitation,
+    Newsletter,
+    PasswordReset,
+    SubscriberData,
+    Test,
+}
+
+impl EmailKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailKind::Confirmation => "confirmation",
+            EmailKind::Digest => "digest",
+            EmailKind::Invitation => "invitation",
+            EmailKind::Newsletter => "newsletter",
+            EmailKind::PasswordReset => "password_reset",
+            EmailKind::SubscriberData => "subscriber_data",
+            EmailKind::Test => "test",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SubscriptionEvent {
+    Created,
+    Confirmed,
+}
+
+impl SubscriptionEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionEvent::Created => "created",
+            SubscriptionEvent::Confirmed => "confirmed",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LoginOutcome {
+    Succeeded,
+    Failed,
+}
+
+impl LoginOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginOutcome::Succeeded => "succeeded",
+            LoginOutcome::Failed => "failed",
+        }
+    }
+}
+
+/// Every metric of the application, in a registry of its own so that
+/// nothing else in the process ends up on `/metrics`.
+struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    db_pool_connections: IntGaugeVec,
+    emails: IntCounterVec,
+    subscriptions: IntCounterVec,
+    logins: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled."),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to handle HTTP requests.",
+            ),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new(
+                "db_pool_connections",
+                "Connections open in the database pool.",
+            ),
+            &["state"],
+        )
+        .unwrap();
+        let emails = IntCounterVec::new(
+            Opts::new("emails_total", "Emails handed to the email provider."),
+            &["kind", "outcome"],
+        )
+        .unwrap();
+        let subscriptions = IntCounterVec::new(
+            Opts::new("subscriptions_total", "Subscriptions created or confirmed."),
+            &["event"],
+        )
+        .unwrap();
+        let logins = IntCounterVec::new(
+            Opts::new("logins_total", "Login attempts, by outcome."),
+            &["outcome"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry
+            .register(Box::new(http_request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_pool_connections.clone()))
+            .unwrap();
+        registry.register(Box::new(emails.clone())).unwrap();
+        registry.register(Box::new(subscriptions.clone())).unwrap();
+        registry.register(Box::new(logins.clone())).unwrap();
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            db_pool_connections,
+            emails,
+            subscriptions,
+            logins,
+        }
+    }
+}
+
+fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+    METRICS.get_or_init(Metrics::new)
+}
+
+/// Counts an attempt to send an email. Recipients on the suppression list
+/// are counted apart, since nothing was sent to them.
+pub fn record_email(kind: EmailKind, outcome: &Result<EmailDelivery, SendEmailError>) {
+    let outcome = match outcome {
+        Ok(EmailDelivery::Sent { .. }) => "sent",
+        Ok(EmailDelivery::Suppressed) => "suppressed",
+        Err(_) => "failed",
+    };
+
+    metrics()
+        .emails
+        .with_label_values(&[kind.as_str(), outcome])
+        .inc();
+}
+
+pub fn record_subscription(event: SubscriptionEvent) {
+    metrics()
+        .subscriptions
+        .with_label_values(&[event.as_str()])
+        .inc();
+}
+
+pub fn record_login(outcome: LoginOutcome) {
+    metrics()
+        .logins
+        .with_label_values(&[outcome.as_str()])
+        .inc();
+}
+
+/// Every metric in the Prometheus text format, with the usage of `db_pool`
+/// as it is right now.
+pub fn render(db_pool: &PgPool) -> Result<String, prometheus::Error> {
+    let metrics = metrics();
+    let open = db_pool.size() as i64;
+    let idle = db_pool.num_idle() as i64;
+
+    metrics
+        .db_pool_connections
+        .with_label_values(&["idle"])
+        .set(idle);
+    metrics
+        .db_pool_connections
+        .with_label_values(&["in_use"])
+        .set(open - idle);
+
+    TextEncoder::new().encode_to_string(&metrics.registry.gather())
+}
+
+/// Standard methods by name, and any other as `other`, since clients can
+/// make up as many as they like.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::CONNECT => "CONNECT",
+        Method::DELETE => "DELETE",
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::OPTIONS => "OPTIONS",
+        Method::PATCH => "PATCH",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::TRACE => "TRACE",
+        _ => "other",
+    }
+}
+
+/// Counts and times every request, by the route that handled it rather
+/// than its path, so that ids in paths don't each get a series of their own.
+#[derive(Clone, Debug, Default)]
+pub struct RequestMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RequestMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let method = method_label(req.method());
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_string());
+        let started_at = Instant::now();
+
+        Box::pin(async move {
+            let outcome = service.call(req).await;
+            let status = match &outcome {
+                Ok(res) => res.status(),
+                Err(e) => e.as_response_error().status_code(),
+            };
+            let labels = [method, route.as_str(), status.as_str()];
+            let metrics = metrics();
+
+            metrics.http_requests.with_label_values(&labels).inc();
+            metrics
+                .http_request_duration
+                .with_label_values(&labels)
+                .observe(started_at.elapsed().as_secs_f64());
+
+            outcome
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::Method;
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::{method_label, record_email, record_login, render, EmailKind, LoginOutcome};
+    use crate::email_client::EmailDelivery;
+
+    #[tokio::test]
+    async fn metrics_are_rendered_in_the_prometheus_text_format() {
+        let db_pool = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/newsletter")
+            .unwrap();
+
+        record_email(EmailKind::Digest, &Ok(EmailDelivery::Suppressed));
+        record_login(LoginOutcome::Failed);
+
+        let text = render(&db_pool).unwrap();
+
+        assert!(text.contains("# TYPE emails_total counter"));
+        assert!(text.contains(r#"emails_total{kind="digest",outcome="suppressed"}"#));
+        assert!(text.contains(r#"logins_total{outcome="failed"}"#));
+        assert!(text.contains(r#"db_pool_connections{state="in_use"} 0"#));
+    }
+
+    #[test]
+    fn made_up_methods_share_a_label() {
+        assert_eq!(method_label(&Method::GET), "GET");
+        assert_eq!(method_label(&Method::from_bytes(b"BREW").unwrap()), "other");
+        assert_eq!(method_label(&Method::from_bytes(b"get").unwrap()), "other");
+    }
+}
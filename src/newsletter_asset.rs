@@ -0,0 +1,385 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use anyhow::Context;
+use regex::{Captures, Regex};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::email_client::{Attachment, Disposition};
+
+/// SendGrid accepts up to 30 MB per message, attachments included, which
+/// leaves room for a handful of assets of this size.
+pub const MAX_ASSET_BYTES: usize = 2 * 1024 * 1024;
+
+/// How many assets an issue can have, inline and attached together.
+pub const MAX_ASSETS: usize = 10;
+
+/// Assets are sent base64-encoded, a third larger than they are. This leaves
+/// the rest of SendGrid's 30 MB for the content of the issue.
+const MAX_ENCODED_ASSETS_BYTES: usize = 25 * 1024 * 1024;
+
+/// Images most email clients can show inline, and documents worth attaching.
+const ALLOWED_CONTENT_TYPES: [&str; 5] = [
+    "image/gif",
+    "image/jpeg",
+    "image/png",
+    "application/pdf",
+    "text/calendar",
+];
+
+/// A file uploaded to be sent with newsletter issues.
+#[derive(Debug)]
+pub struct NewAsset {
+    pub filename: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+impl NewAsset {
+    pub fn parse(filename: &str, content_type: &str, content: Vec<u8>) -> Result<Self, String> {
+        let filename = filename.trim();
+        // Parameters, such as a charset, don't matter to email clients
+        let content_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+
+        if filename.is_empty() || filename.contains(['/', '\\']) {
+            return Err("Assets need a file name, without a path.".into());
+        }
+
+        if !ALLOWED_CONTENT_TYPES.contains(&content_type.as_str()) {
+            return Err(format!(
+                "Assets can only be one of {}.",
+                ALLOWED_CONTENT_TYPES.join(", ")
+            ));
+        }
+
+        if content.is_empty() {
+            return Err("Assets can't be empty.".into());
+        }
+
+        if content.len() > MAX_ASSET_BYTES {
+            return Err(format!(
+                "Assets can't be larger than {} MB.",
+                MAX_ASSET_BYTES / 1024 / 1024
+            ));
+        }
+
+        Ok(Self {
+            filename: filename.to_string(),
+            content_type,
+            content,
+        })
+    }
+}
+
+#[tracing::instrument(name = "Store a newsletter asset", skip(db_pool, asset))]
+pub async fn insert_asset(
+    db_pool: &PgPool,
+    asset: &NewAsset,
+    uploaded_by: Uuid,
+) -> Result<Uuid, anyhow::Error> {
+    let asset_id = Uuid::now_v7();
+
+    sqlx::query!(
+        r#"
+            INSERT INTO newsletter_assets (id, filename, content_type, content, uploaded_by)
+            VALUES ($1, $2, $3, $4, $5)
+        "#,
+        asset_id,
+        asset.filename,
+        asset.content_type,
+        asset.content,
+        uploaded_by
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store a newsletter asset.")?;
+
+    Ok(asset_id)
+}
+
+/// An asset as it was uploaded, to be served on its own.
+pub struct StoredAsset {
+    pub filename: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+#[tracing::instrument(name = "Get a newsletter asset", skip(db_pool))]
+pub async fn get_asset(
+    db_pool: &PgPool,
+    asset_id: Uuid,
+) -> Result<Option<StoredAsset>, anyhow::Error> {
+    sqlx::query_as!(
+        StoredAsset,
+        r#"
+            SELECT filename, content_type, content
+            FROM newsletter_assets
+            WHERE id = $1
+        "#,
+        asset_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve a newsletter asset.")
+}
+
+/// An asset linked to by name.
+pub struct AssetName {
+    pub id: Uuid,
+    pub filename: String,
+}
+
+/// The names of `asset_ids`, in the same order. Those never uploaded are
+/// left out.
+#[tracing::instrument(name = "Get the names of newsletter assets", skip(db_pool))]
+pub async fn get_asset_names(
+    db_pool: &PgPool,
+    asset_ids: &[Uuid],
+) -> Result<Vec<AssetName>, anyhow::Error> {
+    sqlx::query_as!(
+        AssetName,
+        r#"
+            SELECT id, filename
+            FROM newsletter_assets
+            WHERE id = ANY($1)
+            ORDER BY array_position($1, id)
+        "#,
+        asset_ids
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve the names of newsletter assets.")
+}
+
+/// Matches `cid:{id}`, capturing the id.
+fn content_id() -> &'static Regex {
+    static CONTENT_ID: OnceLock<Regex> = OnceLock::new();
+
+    CONTENT_ID.get_or_init(|| {
+        Regex::new(r"(?i)\bcid:([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})")
+            .unwrap()
+    })
+}
+
+/// The assets an issue shows inline, referred to in its HTML as `cid:{id}`.
+pub fn inline_asset_ids(html: &str) -> Vec<Uuid> {
+    let mut asset_ids = Vec::new();
+
+    for captures in content_id().captures_iter(html) {
+        let asset_id: Uuid = captures[1].parse().expect("Matched a valid UUID");
+
+        if !asset_ids.contains(&asset_id) {
+            asset_ids.push(asset_id);
+        }
+    }
+
+    asset_ids
+}
+
+/// Points the inline assets of `html` to `{assets_url}/{id}`, for browsers,
+/// which don't know where to find `cid:{id}`.
+pub fn link_inline_assets(html: &str, assets_url: &str) -> String {
+    content_id()
+        .replace_all(html, |captures: &Captures| {
+            format!("{assets_url}/{}", captures[1].to_ascii_lowercase())
+        })
+        .into_owned()
+}
+
+/// Why the assets an issue refers to, inline or as attachments, can't be
+/// sent with it: there are too many of them, some were never uploaded, or
+/// they don't fit in a single email together.
+#[tracing::instrument(name = "Check newsletter assets", skip(db_pool, html))]
+pub async fn find_asset_problem(
+    db_pool: &PgPool,
+    html: &str,
+    attachment_ids: &[Uuid],
+) -> Result<Option<String>, anyhow::Error> {
+    let mut asset_ids = inline_asset_ids(html);
+
+    for asset_id in attachment_ids {
+        if !asset_ids.contains(asset_id) {
+            asset_ids.push(*asset_id);
+        }
+    }
+
+    if asset_ids.is_empty() {
+        return Ok(None);
+    }
+
+    if asset_ids.len() > MAX_ASSETS {
+        return Ok(Some(format!(
+            "Issues can't have more than {MAX_ASSETS} assets, inline or attached."
+        )));
+    }
+
+    let sizes: HashMap<Uuid, usize> = sqlx::query!(
+        r#"
+            SELECT id, octet_length(content) AS "size!"
+            FROM newsletter_assets
+            WHERE id = ANY($1)
+        "#,
+        &asset_ids
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to look up newsletter assets.")?
+    .into_iter()
+    .map(|r| (r.id, r.size as usize))
+    .collect();
+
+    if let Some(asset_id) = asset_ids.iter().find(|id| !sizes.contains_key(id)) {
+        return Ok(Some(format!("There is no asset {asset_id}.")));
+    }
+
+    let encoded_size: usize = sizes.into_values().map(encoded_len).sum();
+
+    if encoded_size > MAX_ENCODED_ASSETS_BYTES {
+        return Ok(Some(format!(
+            "The assets of an issue can't add up to more than {} MB once encoded, not {:.1} MB.",
+            MAX_ENCODED_ASSETS_BYTES / 1024 / 1024,
+            encoded_size as f64 / 1024.0 / 1024.0
+        )));
+    }
+
+    Ok(None)
+}
+
+/// Whether `attachments` can be sent in a single email, as the assets of
+/// any one issue can.
+pub fn attachments_fit(attachments: &[Attachment]) -> bool {
+    attachments.len() <= MAX_ASSETS
+        && attachments
+            .iter()
+            .map(Attachment::encoded_len)
+            .sum::<usize>()
+            <= MAX_ENCODED_ASSETS_BYTES
+}
+
+/// The size of `len` bytes once base64-encoded, padding included.
+fn encoded_len(len: usize) -> usize {
+    len.div_ceil(3) * 4
+}
+
+/// The files to send with an issue. Assets its HTML refers to are sent
+/// inline, even when they're also listed as attachments.
+#[tracing::instrument(name = "Load the attachments of an issue", skip(db_pool, html))]
+pub async fn load_attachments(
+    db_pool: &PgPool,
+    html: &str,
+    attachment_ids: &[Uuid],
+) -> Result<Vec<Attachment>, anyhow::Error> {
+    let inline_ids = inline_asset_ids(html);
+    let mut asset_ids = inline_ids.clone();
+
+    asset_ids.extend(attachment_ids.iter().filter(|id| !inline_ids.contains(id)));
+
+    if asset_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let rows = sqlx::query!(
+        r#"
+            SELECT id, filename, content_type, content
+            FROM newsletter_assets
+            WHERE id = ANY($1)
+        "#,
+        &asset_ids
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to load newsletter assets.")?;
+
+    let attachments = rows
+        .into_iter()
+        .map(|r| {
+            let disposition = if inline_ids.contains(&r.id) {
+                Disposition::Inline {
+                    content_id: r.id.to_string(),
+                }
+            } else {
+                Disposition::Attachment
+            };
+
+            Attachment::new(r.filename, r.content_type, &r.content, disposition)
+        })
+        .collect();
+
+    Ok(attachments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encoded_len, inline_asset_ids, link_inline_assets, NewAsset, MAX_ASSET_BYTES};
+    use claims::{assert_err, assert_ok};
+    use uuid::Uuid;
+
+    #[test]
+    fn images_and_documents_are_accepted() {
+        for content_type in [
+            "image/png",
+            "IMAGE/JPEG",
+            "application/pdf; name=report.pdf",
+        ] {
+            assert_ok!(NewAsset::parse("file", content_type, vec![1]));
+        }
+    }
+
+    #[test]
+    fn other_content_types_are_rejected() {
+        for content_type in ["text/html", "application/x-msdownload", ""] {
+            assert_err!(NewAsset::parse("file", content_type, vec![1]));
+        }
+    }
+
+    #[test]
+    fn empty_or_large_assets_are_rejected() {
+        assert_err!(NewAsset::parse("chart.png", "image/png", vec![]));
+        assert_err!(NewAsset::parse(
+            "chart.png",
+            "image/png",
+            vec![0; MAX_ASSET_BYTES + 1]
+        ));
+    }
+
+    #[test]
+    fn file_names_cannot_be_paths() {
+        for filename in ["", "../chart.png", "C:\\chart.png"] {
+            assert_err!(NewAsset::parse(filename, "image/png", vec![1]));
+        }
+    }
+
+    #[test]
+    fn inline_assets_are_found_once_each() {
+        let asset_id = Uuid::new_v4();
+        let html =
+            format!(r#"<img src="cid:{asset_id}"><img src='CID:{asset_id}'><img src="cid:logo">"#);
+
+        assert_eq!(inline_asset_ids(&html), vec![asset_id]);
+    }
+
+    #[test]
+    fn inline_assets_are_linked_for_browsers() {
+        let asset_id = Uuid::new_v4();
+        let html = format!(r#"<img src="cid:{asset_id}"><img src="cid:logo">"#);
+
+        assert_eq!(
+            link_inline_assets(&html, "https://example.com/assets"),
+            format!(r#"<img src="https://example.com/assets/{asset_id}"><img src="cid:logo">"#)
+        );
+    }
+
+    #[test]
+    fn encoded_assets_are_a_third_larger_with_padding() {
+        assert_eq!(encoded_len(0), 0);
+        assert_eq!(encoded_len(1), 4);
+        assert_eq!(encoded_len(3), 4);
+        assert_eq!(encoded_len(4), 8);
+        assert_eq!(encoded_len(3 * 1024), 4 * 1024);
+    }
+}
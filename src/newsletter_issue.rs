@@ -0,0 +1,476 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::IssueStatus;
+
+/// How long an issue stays with its sender without hearing from it. Issues
+/// still `sending` after that are picked up again by the scheduler.
+pub const SENDING_LEASE: Duration = Duration::from_secs(120);
+
+/// What an issue says, and who it's for.
+pub struct IssueContent {
+    pub title: String,
+    pub html: String,
+    pub text: String,
+    /// The source the HTML and text were rendered from, for issues written
+    /// in Markdown.
+    pub markdown: Option<String>,
+    /// Subscribers who opted out of the issue's topic don't receive it.
+    pub topic: Option<String>,
+    /// The assets sent as attachments, rather than shown inline.
+    pub attachments: Vec<Uuid>,
+}
+
+pub struct NewsletterIssue {
+    pub id: Uuid,
+    pub content: IssueContent,
+    pub status: IssueStatus,
+    pub created_by: Option<Uuid>,
+    pub author: Option<String>,
+    pub approved_by: Option<Uuid>,
+    pub approver: Option<String>,
+    pub published_by: Option<Uuid>,
+    pub scheduled_for: Option<DateTime<Utc>>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// An issue as listed among the others.
+pub struct IssueListing {
+    pub id: Uuid,
+    pub title: String,
+    pub status: String,
+    pub author: Option<String>,
+    pub scheduled_for: Option<DateTime<Utc>>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// New issues are drafts. Issues published on the spot go through the
+/// rest of the workflow right away.
+#[tracing::instrument(name = "Store a newsletter issue", skip(transaction, content))]
+pub async fn insert_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    content: &IssueContent,
+    created_by: Uuid,
+) -> Result<Uuid, anyhow::Error> {
+    let issue_id = Uuid::now_v7();
+
+    sqlx::query!(
+        r#"
+            INSERT INTO newsletter_issues
+                (id, title, text_content, html_content, markdown_content, topic_slug,
+                 attachment_ids, status, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#,
+        issue_id,
+        content.title,
+        content.text,
+        content.html,
+        content.markdown,
+        content.topic,
+        &content.attachments,
+        IssueStatus::Draft.as_str(),
+        created_by
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store a newsletter issue.")?;
+
+    Ok(issue_id)
+}
+
+#[tracing::instrument(name = "Get a newsletter issue", skip(db_pool))]
+pub async fn get_issue(
+    db_pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<NewsletterIssue>, anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    lock_issue(&mut transaction, issue_id).await
+}
+
+/// Reads an issue and keeps others from changing it until `transaction`
+/// ends, so its status is checked and changed as one.
+#[tracing::instrument(name = "Lock a newsletter issue", skip(transaction))]
+pub async fn lock_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<Option<NewsletterIssue>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT
+                i.id,
+                i.title,
+                i.text_content,
+                i.html_content,
+                i.markdown_content,
+                i.topic_slug,
+                i.attachment_ids,
+                i.status,
+                i.created_by,
+                author.username AS "author?",
+                i.approved_by,
+                approver.username AS "approver?",
+                i.published_by,
+                i.scheduled_for,
+                i.published_at,
+                i.updated_at
+            FROM newsletter_issues i
+            LEFT JOIN users author ON author.id = i.created_by
+            LEFT JOIN users approver ON approver.id = i.approved_by
+            WHERE i.id = $1
+            FOR UPDATE OF i
+        "#,
+        issue_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve a newsletter issue.")?;
+
+    row.map(|r| {
+        Ok(NewsletterIssue {
+            id: r.id,
+            content: IssueContent {
+                title: r.title,
+                html: r.html_content,
+                text: r.text_content,
+                markdown: r.markdown_content,
+                topic: r.topic_slug,
+                attachments: r.attachment_ids,
+            },
+            status: IssueStatus::parse(&r.status).map_err(anyhow::Error::msg)?,
+            created_by: r.created_by,
+            author: r.author,
+            approved_by: r.approved_by,
+            approver: r.approver,
+            published_by: r.published_by,
+            scheduled_for: r.scheduled_for,
+            published_at: r.published_at,
+            updated_at: r.updated_at,
+        })
+    })
+    .transpose()
+}
+
+/// An issue that was published, for anyone with its id to read. Drafts and
+/// issues waiting to be published aren't found.
+#[tracing::instrument(name = "Get a published newsletter issue", skip(db_pool))]
+pub async fn get_published_issue(
+    db_pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<IssueContent>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT
+                title,
+                text_content,
+                html_content,
+                markdown_content,
+                topic_slug,
+                attachment_ids
+            FROM newsletter_issues
+            WHERE id = $1 AND published_at IS NOT NULL
+        "#,
+        issue_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve a published newsletter issue.")?;
+
+    Ok(row.map(|r| IssueContent {
+        title: r.title,
+        html: r.html_content,
+        text: r.text_content,
+        markdown: r.markdown_content,
+        topic: r.topic_slug,
+        attachments: r.attachment_ids,
+    }))
+}
+
+#[tracing::instrument(name = "List newsletter issues", skip(db_pool))]
+pub async fn list_issues(db_pool: &PgPool) -> Result<Vec<IssueListing>, anyhow::Error> {
+    sqlx::query_as!(
+        IssueListing,
+        r#"
+            SELECT
+                i.id,
+                i.title,
+                i.status,
+                u.username AS "author?",
+                i.scheduled_for,
+                i.published_at,
+                i.updated_at
+            FROM newsletter_issues i
+            LEFT JOIN users u ON u.id = i.created_by
+            ORDER BY i.updated_at DESC
+        "#
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to list newsletter issues.")
+}
+
+/// Changing an approved issue withdraws its approval.
+#[tracing::instrument(name = "Update a newsletter issue", skip(transaction, content))]
+pub async fn update_content(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    content: &IssueContent,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET title = $2,
+                text_content = $3,
+                html_content = $4,
+                markdown_content = $5,
+                topic_slug = $6,
+                attachment_ids = $7,
+                status = $8,
+                approved_by = NULL,
+                approved_at = NULL,
+                updated_at = now()
+            WHERE id = $1
+        "#,
+        issue_id,
+        content.title,
+        content.text,
+        content.html,
+        content.markdown,
+        content.topic,
+        &content.attachments,
+        IssueStatus::Draft.as_str()
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to update a newsletter issue.")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Approve a newsletter issue", skip(transaction))]
+pub async fn approve(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    approved_by: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = $2, approved_by = $3, approved_at = now(), updated_at = now()
+            WHERE id = $1
+        "#,
+        issue_id,
+        IssueStatus::Approved.as_str(),
+        approved_by
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to approve a newsletter issue.")?;
+
+    Ok(())
+}
+
+/// The user scheduling an issue is the one who publishes it.
+#[tracing::instrument(name = "Schedule a newsletter issue", skip(transaction))]
+pub async fn schedule(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    scheduled_for: DateTime<Utc>,
+    scheduled_by: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = $2, scheduled_for = $3, published_by = $4, updated_at = now()
+            WHERE id = $1
+        "#,
+        issue_id,
+        IssueStatus::Scheduled.as_str(),
+        scheduled_for,
+        scheduled_by
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to schedule a newsletter issue.")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Unschedule a newsletter issue", skip(transaction))]
+pub async fn unschedule(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    status: IssueStatus,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = $2, scheduled_for = NULL, published_by = NULL, updated_at = now()
+            WHERE id = $1
+        "#,
+        issue_id,
+        status.as_str()
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to unschedule a newsletter issue.")?;
+
+    Ok(())
+}
+
+/// Scheduled issues keep the user who scheduled them as their publisher.
+/// The issue is leased to whoever is about to send it.
+#[tracing::instrument(name = "Mark a newsletter issue as sending", skip(transaction))]
+pub async fn mark_sending(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    published_by: Option<Uuid>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = $2,
+                published_by = COALESCE($3, published_by),
+                published_at = now(),
+                sending_lease_until = now() + make_interval(secs => $4),
+                updated_at = now()
+            WHERE id = $1
+        "#,
+        issue_id,
+        IssueStatus::Sending.as_str(),
+        published_by,
+        SENDING_LEASE.as_secs_f64()
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to start sending a newsletter issue.")?;
+
+    Ok(())
+}
+
+/// Takes over an issue whose sender stopped sending it.
+#[tracing::instrument(name = "Take over sending a newsletter issue", skip(transaction))]
+pub async fn take_over_sending(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET sending_lease_until = now() + make_interval(secs => $2)
+            WHERE id = $1
+        "#,
+        issue_id,
+        SENDING_LEASE.as_secs_f64()
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to take over sending a newsletter issue.")?;
+
+    Ok(())
+}
+
+/// Tells other instances of the application the issue is still being sent.
+#[tracing::instrument(name = "Renew the sending lease of a newsletter issue", skip(db_pool))]
+pub async fn renew_sending_lease(db_pool: &PgPool, issue_id: Uuid) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET sending_lease_until = now() + make_interval(secs => $2)
+            WHERE id = $1
+        "#,
+        issue_id,
+        SENDING_LEASE.as_secs_f64()
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to renew the sending lease of a newsletter issue.")?;
+
+    Ok(())
+}
+
+/// Lets the scheduler resume the issue straight away, for an issue its
+/// sender stopped sending on purpose.
+#[tracing::instrument(
+    name = "Release the sending lease of a newsletter issue",
+    skip(db_pool)
+)]
+pub async fn release_sending_lease(db_pool: &PgPool, issue_id: Uuid) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET sending_lease_until = NULL
+            WHERE id = $1
+        "#,
+        issue_id
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to release the sending lease of a newsletter issue.")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Mark a newsletter issue as sent", skip(db_pool))]
+pub async fn mark_sent(db_pool: &PgPool, issue_id: Uuid) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = $2, sending_lease_until = NULL, updated_at = now()
+            WHERE id = $1
+        "#,
+        issue_id,
+        IssueStatus::Sent.as_str()
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to mark a newsletter issue as sent.")?;
+
+    Ok(())
+}
+
+/// An issue whose sender stopped sending it, or else the scheduled issue
+/// that has been due the longest. Issues another instance of the
+/// application is already sending are skipped.
+#[tracing::instrument(name = "Claim a due newsletter issue", skip(transaction))]
+pub async fn claim_due_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT id
+            FROM newsletter_issues
+            WHERE (status = $1 AND scheduled_for <= now())
+                OR (status = $2 AND (sending_lease_until IS NULL OR sending_lease_until < now()))
+            ORDER BY status = $2 DESC, scheduled_for
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        "#,
+        IssueStatus::Scheduled.as_str(),
+        IssueStatus::Sending.as_str()
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look for due newsletter issues.")?;
+
+    Ok(row.map(|r| r.id))
+}
+
+#[tracing::instrument(name = "Check topic exists", skip(db_pool))]
+pub async fn topic_exists(db_pool: &PgPool, topic: &str) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT slug FROM topics WHERE slug = $1"#, topic)
+        .fetch_optional(db_pool)
+        .await
+        .context("Failed to look up a topic.")?;
+
+    Ok(row.is_some())
+}
@@ -0,0 +1,552 @@
+use std::time::Instant;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    delivery::{count_deliveries, queue_deliveries, queued_deliveries, record_delivery},
+    digest::{
+        claim_due_digest, clear_digest, compose_digest, compose_digest_of_links,
+        count_queued_for_digests, find_due_digests, queue_for_digests, record_digest_failure,
+        DueDigest,
+    },
+    domain::{DeliveryFrequency, IssueStatus, SubscriberEmail},
+    email_client::{Attachment, EmailClient, EmailDelivery},
+    email_html::{check_html_size, EmailHtml},
+    engagement::IssueTracking,
+    metrics::{self, EmailKind},
+    newsletter_asset::{attachments_fit, load_attachments},
+    newsletter_issue::{
+        claim_due_issue, get_issue, lock_issue, mark_sending, mark_sent, release_sending_lease,
+        renew_sending_lease, take_over_sending, IssueContent, NewsletterIssue, SENDING_LEASE,
+    },
+    routes::preferences_footer,
+    settings::NewsletterSettings,
+    shutdown::Shutdown,
+    signing::HmacSecret,
+};
+
+/// Preferences tokens are 24 random bytes, hex-encoded.
+const PREFERENCES_TOKEN_LENGTH: usize = 48;
+
+/// What the publisher is told once every email of an issue was attempted.
+/// Deliveries are reported on in more detail at
+/// `/admin/newsletters/{issue_id}/deliveries`.
+#[derive(serde::Serialize)]
+pub struct PublishSummary {
+    pub issue_id: Uuid,
+    pub recipients: i64,
+    pub sent: i64,
+    pub suppressed: i64,
+    pub failed: i64,
+    /// Subscribers the issue wasn't sent to yet, as the application was
+    /// shutting down. Sending it to them resumes once it's back up.
+    pub queued: i64,
+    /// Subscribers who get the issue in their next weekly digest instead.
+    pub digested: i64,
+}
+
+/// A weekly digest, as sent to its subscriber.
+struct DigestEmail {
+    subject: String,
+    html_content: String,
+    text_content: String,
+    attachments: Vec<Attachment>,
+}
+
+/// Sends issues to their subscribers, whether they're published on the spot
+/// or were scheduled.
+#[derive(Clone)]
+pub struct IssueSender {
+    db_pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    hmac_secret: HmacSecret,
+    shutdown: Shutdown,
+}
+
+impl IssueSender {
+    pub fn new(
+        db_pool: PgPool,
+        email_client: EmailClient,
+        base_url: String,
+        hmac_secret: HmacSecret,
+        shutdown: Shutdown,
+    ) -> Self {
+        Self {
+            db_pool,
+            email_client,
+            base_url,
+            hmac_secret,
+            shutdown,
+        }
+    }
+
+    /// Issues too large for email clients to show in full can't be sent.
+    /// What counts is the HTML subscribers receive, with its tracked links,
+    /// tracking pixel and preferences footer: ids and tokens, unknown until
+    /// it's sent, are stood in for by ones of the same length.
+    pub fn check_size(&self, html: &str) -> Result<(), String> {
+        let email_html = EmailHtml::prepare(html);
+        let html_content = IssueTracking::new(Uuid::nil(), email_html.html()).track(
+            email_html.html(),
+            &self.base_url,
+            &self.hmac_secret,
+            Uuid::nil(),
+        );
+        let (html_content, _) = render_issue(
+            &html_content,
+            "",
+            &self.base_url,
+            &"0".repeat(PREFERENCES_TOKEN_LENGTH),
+        );
+
+        check_html_size(&html_content)
+    }
+
+    /// Moves an issue to `sending` within `transaction`. Every subscriber of
+    /// its topic is queued, for an email of their own or for their next
+    /// weekly digest, and the links of the issue are stored for tracking.
+    #[tracing::instrument(
+        name = "Start sending a newsletter issue",
+        skip(self, transaction, content)
+    )]
+    pub async fn start_sending(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        issue_id: Uuid,
+        content: &IssueContent,
+        published_by: Option<Uuid>,
+    ) -> Result<(), anyhow::Error> {
+        mark_sending(transaction, issue_id, published_by).await?;
+
+        let (subscribers, digest_subscribers): (Vec<ConfirmedSubscriber>, Vec<_>) =
+            get_confirmed_subscribers(&self.db_pool, content.topic.as_deref())
+                .await?
+                .into_iter()
+                .filter_map(|subscriber| match subscriber {
+                    Ok(subscriber) => Some(subscriber),
+                    Err(error) => {
+                        tracing::warn!(
+                            error.cause_chain = ?error,
+                            "Skipping a confirmed subscriber. \
+                            Their stored contact details are invalid",
+                        );
+                        None
+                    }
+                })
+                .partition(|subscriber| subscriber.frequency == DeliveryFrequency::Immediate);
+        let email_html = EmailHtml::prepare(&content.html);
+
+        IssueTracking::new(issue_id, email_html.html())
+            .save(transaction)
+            .await?;
+        let subscriber_ids: Vec<Uuid> = subscribers.iter().map(|s| s.id).collect();
+
+        queue_deliveries(transaction, issue_id, &subscriber_ids).await?;
+        let digest_subscriber_ids: Vec<Uuid> = digest_subscribers.iter().map(|s| s.id).collect();
+
+        queue_for_digests(transaction, issue_id, &digest_subscriber_ids).await?;
+
+        Ok(())
+    }
+
+    /// Sends an issue that's being sent to every subscriber still waiting for
+    /// it, then marks it as sent. A failure to send to one subscriber is
+    /// recorded as such, and doesn't stop the others from receiving it.
+    ///
+    /// The lease on the issue is renewed along the way. Should sending stop
+    /// halfway, the scheduler picks the issue up once the lease runs out, and
+    /// sends it to the subscribers who didn't receive it yet. Once shutdown
+    /// is triggered, sending stops before the next subscriber, and the
+    /// issue is left for the scheduler to resume right away.
+    #[tracing::instrument(name = "Send a newsletter issue", skip(self))]
+    pub async fn send(&self, issue_id: Uuid) -> Result<PublishSummary, anyhow::Error> {
+        let issue = get_issue(&self.db_pool, issue_id)
+            .await?
+            .with_context(|| format!("There is no newsletter issue {issue_id}."))?;
+        // Only issues that are being sent can become sent
+        issue
+            .status
+            .transition_to(IssueStatus::Sent)
+            .map_err(anyhow::Error::msg)?;
+        let content = &issue.content;
+        let email_html = EmailHtml::prepare(&content.html);
+        let attachments =
+            load_attachments(&self.db_pool, email_html.html(), &content.attachments).await?;
+        let tracking = IssueTracking::load(&self.db_pool, issue_id).await?;
+        let mut lease_renewed_at = Instant::now();
+
+        for delivery in queued_deliveries(&self.db_pool, issue_id).await? {
+            let delivery = match delivery {
+                Ok(delivery) => delivery,
+                Err(error) => {
+                    tracing::warn!(
+                        error.cause_chain = ?error,
+                        "Skipping a queued delivery. \
+                        The subscriber's stored contact details are invalid",
+                    );
+                    continue;
+                }
+            };
+
+            if self.shutdown.is_triggered() {
+                tracing::info!(
+                    %issue_id,
+                    "Stopped sending a newsletter issue to shut down. \
+                    The subscribers it wasn't sent to yet stay queued",
+                );
+                release_sending_lease(&self.db_pool, issue_id).await?;
+
+                return self.summarize(issue_id).await;
+            }
+
+            if lease_renewed_at.elapsed() > SENDING_LEASE / 4 {
+                renew_sending_lease(&self.db_pool, issue_id).await?;
+                lease_renewed_at = Instant::now();
+            }
+
+            let html_content = tracking.track(
+                email_html.html(),
+                &self.base_url,
+                &self.hmac_secret,
+                delivery.subscriber_id,
+            );
+            let (html_content, text_content) = render_issue(
+                &html_content,
+                &content.text,
+                &self.base_url,
+                &delivery.preferences_token,
+            );
+
+            let outcome = self
+                .email_client
+                .send_email_with_attachments(
+                    &delivery.email,
+                    &content.title,
+                    &html_content,
+                    &text_content,
+                    &attachments,
+                )
+                .await;
+
+            metrics::record_email(EmailKind::Newsletter, &outcome);
+
+            if let Err(error) = &outcome {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    "Failed to send newsletter issue to {}",
+                    delivery.email
+                );
+            }
+
+            record_delivery(&self.db_pool, issue_id, delivery.subscriber_id, &outcome).await?;
+        }
+
+        mark_sent(&self.db_pool, issue_id).await?;
+
+        self.summarize(issue_id).await
+    }
+
+    async fn summarize(&self, issue_id: Uuid) -> Result<PublishSummary, anyhow::Error> {
+        let deliveries = count_deliveries(&self.db_pool, issue_id).await?;
+
+        Ok(PublishSummary {
+            issue_id,
+            recipients: deliveries.recipients,
+            sent: deliveries.sent,
+            suppressed: deliveries.suppressed,
+            failed: deliveries.failed,
+            queued: deliveries.queued,
+            digested: count_queued_for_digests(&self.db_pool, issue_id).await?,
+        })
+    }
+
+    /// Sends every issue that's due, one after the other: those whose sender
+    /// stopped sending them first, then scheduled ones. An issue that fails
+    /// to send doesn't keep the others from being sent.
+    #[tracing::instrument(name = "Send due newsletter issues", skip(self))]
+    pub async fn send_due_issues(&self) -> Result<(), anyhow::Error> {
+        // An issue stopped to shut down could be claimed again right away
+        while !self.shutdown.is_triggered() {
+            let mut transaction = self
+                .db_pool
+                .begin()
+                .await
+                .context("Failed to acquire a Postgres connection from the pool")?;
+
+            let issue_id = match claim_due_issue(&mut transaction).await? {
+                Some(issue_id) => issue_id,
+                None => return Ok(()),
+            };
+            let issue = lock_issue(&mut transaction, issue_id)
+                .await?
+                .context("A claimed newsletter issue is gone.")?;
+
+            if issue.status == IssueStatus::Sending {
+                tracing::warn!(%issue_id, "Resuming a newsletter issue its sender stopped sending.");
+                take_over_sending(&mut transaction, issue_id).await?;
+            } else {
+                issue
+                    .status
+                    .transition_to(IssueStatus::Sending)
+                    .map_err(anyhow::Error::msg)?;
+                self.start_sending(&mut transaction, issue_id, &issue.content, None)
+                    .await?;
+            }
+
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to send a newsletter issue.")?;
+
+            // The issue is leased until it's sent, so it isn't claimed again
+            // before the next check
+            if let Err(error) = self.send_claimed_issue(&issue).await {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    error.message = %error,
+                    %issue_id,
+                    "Failed to send a due newsletter issue",
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn send_claimed_issue(&self, issue: &NewsletterIssue) -> Result<(), anyhow::Error> {
+        let summary = self.send(issue.id).await?;
+        let mut event = AuditEvent::new(AuditAction::NewsletterPublished)
+            .subject(&issue.content.title)
+            .details(serde_json::json!(summary));
+
+        if let Some(published_by) = issue.published_by {
+            event = event.actor_id(published_by);
+        }
+
+        event.record(&self.db_pool).await
+    }
+
+    /// Sends the weekly digests of the issues published before `due_before`.
+    /// A digest that fails to send stays queued, without keeping the others
+    /// from being sent, and is tried again later and later until it's given
+    /// up on. Those not sent yet once shutdown is triggered are sent with
+    /// the next due digests.
+    #[tracing::instrument(name = "Send due weekly digests", skip(self))]
+    pub async fn send_due_digests(&self, due_before: DateTime<Utc>) -> Result<(), anyhow::Error> {
+        for subscriber_id in find_due_digests(&self.db_pool, due_before).await? {
+            if self.shutdown.is_triggered() {
+                break;
+            }
+
+            if let Err(error) = self.send_digest(subscriber_id, due_before).await {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    error.message = %error,
+                    %subscriber_id,
+                    "Failed to send a weekly digest",
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn send_digest(
+        &self,
+        subscriber_id: Uuid,
+        due_before: DateTime<Utc>,
+    ) -> Result<(), anyhow::Error> {
+        let digest = match claim_due_digest(&self.db_pool, subscriber_id, due_before).await? {
+            Some(digest) => digest,
+            None => return Ok(()),
+        };
+        let email = self.compose_digest_email(&digest).await?;
+
+        let outcome = self
+            .email_client
+            .send_email_with_attachments(
+                &digest.email,
+                &email.subject,
+                &email.html_content,
+                &email.text_content,
+                &email.attachments,
+            )
+            .await;
+
+        metrics::record_email(EmailKind::Digest, &outcome);
+
+        match outcome {
+            Ok(EmailDelivery::Sent { .. } | EmailDelivery::Suppressed) => {
+                clear_digest(&self.db_pool, &digest).await?;
+            }
+            Err(error) => {
+                let given_up = record_digest_failure(&self.db_pool, &digest).await?;
+
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    attempts = digest.attempts + 1,
+                    given_up,
+                    "Failed to send a weekly digest to {}",
+                    digest.email
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A digest gathers its issues in full if they fit in a single email
+    /// together, as each of them had to on its own, and links to them
+    /// otherwise.
+    async fn compose_digest_email(&self, digest: &DueDigest) -> Result<DigestEmail, anyhow::Error> {
+        let (subject, html_content, text_content) = compose_digest(&digest.issues);
+        let attachment_ids: Vec<Uuid> = digest
+            .issues
+            .iter()
+            .flat_map(|issue| issue.attachments.iter().copied())
+            .collect();
+        let attachments = load_attachments(&self.db_pool, &html_content, &attachment_ids).await?;
+        let (html_content, text_content) = render_issue(
+            &html_content,
+            &text_content,
+            &self.base_url,
+            &digest.preferences_token,
+        );
+
+        if check_html_size(&html_content).is_ok() && attachments_fit(&attachments) {
+            return Ok(DigestEmail {
+                subject,
+                html_content,
+                text_content,
+                attachments,
+            });
+        }
+
+        tracing::info!(
+            subscriber_id = %digest.subscriber_id,
+            "Linking to the issues of a weekly digest too large to gather them in full",
+        );
+
+        let (subject, html_content, text_content) =
+            compose_digest_of_links(&digest.issues, &self.base_url);
+        let (html_content, text_content) = render_issue(
+            &html_content,
+            &text_content,
+            &self.base_url,
+            &digest.preferences_token,
+        );
+
+        Ok(DigestEmail {
+            subject,
+            html_content,
+            text_content,
+            attachments: Vec::new(),
+        })
+    }
+
+    /// Checks for due issues and weekly digests every scheduler interval,
+    /// until shutdown is triggered. Sending issues and digests then stops
+    /// before the next subscriber.
+    pub async fn run_scheduler_until_stopped(self, settings: NewsletterSettings) {
+        let shutdown = self.shutdown.clone();
+        let mut ticks = tokio::time::interval(settings.scheduler_interval());
+
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                biased;
+                () = shutdown.triggered() => return,
+                _ = ticks.tick() => {},
+            }
+
+            if let Err(error) = self.send_due_issues().await {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    error.message = %error,
+                    "Failed to send due newsletter issues",
+                );
+            }
+
+            if shutdown.is_triggered() {
+                return;
+            }
+
+            let due_before = settings.last_digest_time(Utc::now());
+
+            if let Err(error) = self.send_due_digests(due_before).await {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    error.message = %error,
+                    "Failed to send due weekly digests",
+                );
+            }
+        }
+    }
+}
+
+/// The HTML and text contents of an issue as sent to one recipient, ending
+/// with a link to their preferences.
+pub fn render_issue(
+    html_content: &str,
+    text_content: &str,
+    base_url: &str,
+    preferences_token: &str,
+) -> (String, String) {
+    let (html_footer, plain_footer) = preferences_footer(base_url, preferences_token);
+
+    (
+        format!("{html_content}{html_footer}"),
+        format!("{text_content}{plain_footer}"),
+    )
+}
+
+struct ConfirmedSubscriber {
+    id: Uuid,
+    frequency: DeliveryFrequency,
+}
+
+/// Subscribers who paused delivery, or opted out of `topic`, are left out.
+/// So are those whose address bounced or who reported us as spam, as they
+/// are no longer `confirmed`.
+#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
+async fn get_confirmed_subscribers(
+    pool: &PgPool,
+    topic: Option<&str>,
+) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT id, email, frequency
+            FROM subscriptions s
+            WHERE status = 'confirmed'
+                AND (paused_until IS NULL OR paused_until <= now())
+                AND NOT EXISTS (
+                    SELECT 1
+                    FROM subscriber_topic_opt_outs o
+                    WHERE o.subscriber_id = s.id AND o.topic_slug = $1
+                )
+        "#,
+        topic
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let confirmed_subscribers = rows
+        .into_iter()
+        .map(|r| {
+            SubscriberEmail::parse(r.email).map_err(anyhow::Error::msg)?;
+
+            Ok(ConfirmedSubscriber {
+                id: r.id,
+                frequency: DeliveryFrequency::parse(r.frequency).map_err(anyhow::Error::msg)?,
+            })
+        })
+        .collect();
+
+    Ok(confirmed_subscribers)
+}
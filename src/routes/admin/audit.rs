@@ -0,0 +1,149 @@
+use std::fmt::Debug;
+
+use actix_web::{http::header::ContentType, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use maud::Markup;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+
+use crate::{
+    audit::AuditAction,
+    authorization::{AuthorizationError, AuthorizedUser, Permission},
+    error_chain_fmt,
+    views::{
+        self,
+        admin::audit::{AuditEntry, AuditFilter, AuditLogPage},
+    },
+};
+
+const PAGE_SIZE: i64 = 50;
+/// The offset of any later page wouldn't fit in an `i64`.
+const MAX_PAGE: i64 = i64::MAX / PAGE_SIZE;
+
+#[derive(thiserror::Error)]
+pub enum AuditLogError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    AuthorizationError(#[from] AuthorizationError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for AuditLogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for AuditLogError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::AuthorizationError(e) => e.status_code(),
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::ValidationError(_) => HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+            Self::AuthorizationError(e) => e.error_response(),
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct QueryParams {
+    actor: Option<String>,
+    action: Option<String>,
+    page: Option<i64>,
+}
+
+impl QueryParams {
+    /// Empty filters, as submitted by the filter form, are ignored.
+    fn parse(self) -> Result<(AuditFilter, i64), AuditLogError> {
+        let actor = self.actor.filter(|actor| !actor.trim().is_empty());
+        let action = self
+            .action
+            .filter(|action| !action.is_empty())
+            .map(|action| AuditAction::parse(&action))
+            .transpose()
+            .map_err(AuditLogError::ValidationError)?;
+        let page = self.page.unwrap_or(1);
+
+        if !(1..=MAX_PAGE).contains(&page) {
+            return Err(AuditLogError::ValidationError(format!(
+                "Pages are numbered from 1 to {MAX_PAGE}."
+            )));
+        }
+
+        Ok((AuditFilter { actor, action }, page))
+    }
+}
+
+pub async fn audit_log(
+    user: AuthorizedUser,
+    query: web::Query<QueryParams>,
+    db_pool: web::Data<PgPool>,
+) -> Result<Markup, AuditLogError> {
+    user.require(Permission::ViewAuditLog)?;
+
+    let (filter, page) = query.into_inner().parse()?;
+    let audit_log_page = get_audit_log_page(&db_pool, &filter, page).await?;
+
+    Ok(views::admin::audit::get(&audit_log_page, &filter))
+}
+
+pub async fn audit_log_json(
+    user: AuthorizedUser,
+    query: web::Query<QueryParams>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AuditLogError> {
+    user.require(Permission::ViewAuditLog)?;
+
+    let (filter, page) = query.into_inner().parse()?;
+    let audit_log_page = get_audit_log_page(&db_pool, &filter, page).await?;
+
+    Ok(HttpResponse::Ok().json(audit_log_page))
+}
+
+/// Most recent events first. One more row than needed is fetched to know
+/// whether there is a next page.
+#[tracing::instrument(name = "Get audit log page", skip(db_pool))]
+async fn get_audit_log_page(
+    db_pool: &PgPool,
+    filter: &AuditFilter,
+    page: i64,
+) -> Result<AuditLogPage, anyhow::Error> {
+    let mut events = sqlx::query_as!(
+        AuditEntry,
+        r#"
+            SELECT id, occurred_at, action, actor_id, actor, subject, ip_address, details
+            FROM audit_events
+            WHERE ($1::TEXT IS NULL OR actor = $1)
+                AND ($2::TEXT IS NULL OR action = $2)
+            ORDER BY occurred_at DESC, id DESC
+            LIMIT $3 OFFSET $4
+        "#,
+        filter.actor,
+        filter.action.map(|action| action.as_str()),
+        PAGE_SIZE + 1,
+        (page - 1) * PAGE_SIZE,
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve audit events.")?;
+
+    let has_next_page = events.len() as i64 > PAGE_SIZE;
+    events.truncate(PAGE_SIZE as usize);
+
+    Ok(AuditLogPage {
+        events,
+        page,
+        next_page: (has_next_page && page < MAX_PAGE).then_some(page + 1),
+    })
+}
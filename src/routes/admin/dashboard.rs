@@ -0,0 +1,10 @@
+use maud::Markup;
+
+use crate::{authorization::AuthorizedUser, csrf::CsrfToken, views};
+
+pub async fn admin_dashboard(
+    user: AuthorizedUser,
+    csrf_token: CsrfToken,
+) -> actix_web::Result<Markup> {
+    Ok(views::admin::dashboard::get(&user, &csrf_token))
+}
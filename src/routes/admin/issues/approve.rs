@@ -0,0 +1,57 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{see_issue, transition, IssueManagementError};
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    authorization::{AuthorizedUser, Permission},
+    domain::IssueStatus,
+    newsletter_issue::{approve, lock_issue},
+};
+
+/// A second pair of eyes on a draft. Authors can't approve their own issues.
+#[tracing::instrument(name = "Approve a newsletter issue", skip(user, db_pool))]
+pub async fn approve_issue(
+    user: AuthorizedUser,
+    issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueManagementError> {
+    user.require(Permission::PublishNewsletter)?;
+
+    let issue_id = issue_id.into_inner();
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue = lock_issue(&mut transaction, issue_id)
+        .await?
+        .ok_or(IssueManagementError::NotFound)?;
+
+    transition(&issue, IssueStatus::Approved)?;
+
+    if issue.created_by == Some(user.id) {
+        return Err(IssueManagementError::ValidationError {
+            issue_id: Some(issue_id),
+            message: "Issues must be approved by someone other than their author.".into(),
+        });
+    }
+
+    approve(&mut transaction, issue_id, user.id).await?;
+
+    AuditEvent::new(AuditAction::NewsletterApproved)
+        .actor_id(user.id)
+        .subject(issue_id)
+        .details(serde_json::json!({ "title": issue.content.title }))
+        .record(&mut transaction)
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to approve a newsletter issue.")?;
+
+    Ok(see_issue(issue_id))
+}
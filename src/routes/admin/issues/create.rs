@@ -0,0 +1,42 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use super::{see_issue, IssueFormData, IssueManagementError};
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    authorization::{AuthorizedUser, Permission},
+    newsletter_issue::insert_issue,
+};
+
+/// Issues start out as drafts, to be edited until they're ready.
+#[tracing::instrument(name = "Draft a newsletter issue", skip(user, form, db_pool))]
+pub async fn create_issue(
+    user: AuthorizedUser,
+    form: web::Form<IssueFormData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueManagementError> {
+    user.require(Permission::PublishNewsletter)?;
+
+    let content = form.0.parse(&db_pool, None).await?;
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue_id = insert_issue(&mut transaction, &content, user.id).await?;
+
+    AuditEvent::new(AuditAction::NewsletterDrafted)
+        .actor_id(user.id)
+        .subject(issue_id)
+        .details(serde_json::json!({ "title": content.title }))
+        .record(&mut transaction)
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a newsletter issue.")?;
+
+    Ok(see_issue(issue_id))
+}
@@ -0,0 +1,62 @@
+use actix_web::web;
+use maud::Markup;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::IssueManagementError;
+use crate::{
+    authorization::{AuthorizedUser, Permission},
+    csrf::CsrfToken,
+    email_html::EmailHtml,
+    newsletter_issue::{self, get_issue},
+    views,
+};
+
+#[derive(serde::Deserialize, Debug)]
+pub struct QueryParams {
+    error: Option<String>,
+}
+
+pub async fn list_issues(
+    user: AuthorizedUser,
+    db_pool: web::Data<PgPool>,
+) -> Result<Markup, IssueManagementError> {
+    user.require(Permission::PublishNewsletter)?;
+
+    let issues = newsletter_issue::list_issues(&db_pool).await?;
+
+    Ok(views::admin::issues::list(&issues))
+}
+
+pub async fn new_issue_form(
+    user: AuthorizedUser,
+    query: web::Query<QueryParams>,
+    csrf_token: CsrfToken,
+) -> Result<Markup, IssueManagementError> {
+    user.require(Permission::PublishNewsletter)?;
+
+    Ok(views::admin::issues::new(&csrf_token, query.0.error))
+}
+
+pub async fn show_issue(
+    user: AuthorizedUser,
+    issue_id: web::Path<Uuid>,
+    query: web::Query<QueryParams>,
+    db_pool: web::Data<PgPool>,
+    csrf_token: CsrfToken,
+) -> Result<Markup, IssueManagementError> {
+    user.require(Permission::PublishNewsletter)?;
+
+    let issue = get_issue(&db_pool, issue_id.into_inner())
+        .await?
+        .ok_or(IssueManagementError::NotFound)?;
+    let email_html = EmailHtml::prepare(&issue.content.html);
+
+    Ok(views::admin::issues::show(
+        &issue,
+        email_html.warnings(),
+        &user,
+        &csrf_token,
+        query.0.error,
+    ))
+}
@@ -0,0 +1,198 @@
+mod approve;
+mod create;
+mod get;
+mod publish;
+mod schedule;
+mod update;
+
+use std::fmt::Debug;
+
+use actix_web::{
+    http::header::{ContentType, LOCATION},
+    HttpResponse, ResponseError,
+};
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub use approve::approve_issue;
+pub use create::create_issue;
+pub use get::{list_issues, new_issue_form, show_issue};
+pub use publish::publish_issue;
+pub use schedule::{schedule_issue, unschedule_issue};
+pub use update::update_issue;
+
+use crate::{
+    authorization::AuthorizationError,
+    domain::{IssueStatus, NewsletterContent},
+    error_chain_fmt,
+    newsletter_asset::find_asset_problem,
+    newsletter_issue::{topic_exists, IssueContent, NewsletterIssue},
+    publishing::IssueSender,
+};
+
+#[derive(thiserror::Error)]
+pub enum IssueManagementError {
+    /// Sends the user back to the issue, or to the new issue form when
+    /// there's no issue yet, with the error.
+    #[error("{message}")]
+    ValidationError {
+        issue_id: Option<Uuid>,
+        message: String,
+    },
+    #[error("There is no such newsletter issue.")]
+    NotFound,
+    #[error(transparent)]
+    AuthorizationError(#[from] AuthorizationError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for IssueManagementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for IssueManagementError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError { .. } => StatusCode::SEE_OTHER,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::AuthorizationError(e) => e.status_code(),
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::ValidationError { issue_id, .. } => {
+                let encoded_error = urlencoding::Encoded::new(self.to_string());
+                let location = match issue_id {
+                    Some(issue_id) => format!("/admin/newsletters/{issue_id}"),
+                    None => "/admin/newsletters/new".to_string(),
+                };
+
+                HttpResponse::SeeOther()
+                    .insert_header((LOCATION, format!("{location}?error={encoded_error}")))
+                    .finish()
+            }
+            Self::NotFound => HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+            Self::AuthorizationError(e) => e.error_response(),
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct IssueFormData {
+    title: String,
+    /// When given, the HTML and text contents are rendered from it.
+    #[serde(default)]
+    markdown_content: String,
+    #[serde(default)]
+    html_content: String,
+    #[serde(default)]
+    text_content: String,
+    /// Left empty for issues going to every subscriber.
+    topic: String,
+    /// The ids of uploaded assets to attach, separated by commas.
+    #[serde(default)]
+    attachments: String,
+}
+
+impl IssueFormData {
+    async fn parse(
+        self,
+        db_pool: &PgPool,
+        issue_id: Option<Uuid>,
+    ) -> Result<IssueContent, IssueManagementError> {
+        let validation_error =
+            |message: String| IssueManagementError::ValidationError { issue_id, message };
+        let title = self.title.trim();
+        let topic = self.topic.trim();
+
+        if title.is_empty() {
+            return Err(validation_error("Issues need a title.".into()));
+        }
+
+        if !topic.is_empty() && !topic_exists(db_pool, topic).await? {
+            return Err(validation_error(format!(
+                "There is no topic called {topic}."
+            )));
+        }
+
+        let (content, markdown) = if self.markdown_content.trim().is_empty() {
+            (
+                NewsletterContent::parse(self.html_content, self.text_content),
+                None,
+            )
+        } else {
+            (
+                NewsletterContent::from_markdown(&self.markdown_content),
+                Some(self.markdown_content),
+            )
+        };
+        let content = content.map_err(validation_error)?;
+
+        let attachments = self
+            .attachments
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                id.parse::<Uuid>()
+                    .map_err(|_| validation_error(format!("{id} is not a valid asset id.")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(problem) = find_asset_problem(db_pool, content.html(), &attachments).await? {
+            return Err(validation_error(problem));
+        }
+
+        Ok(IssueContent {
+            title: title.to_string(),
+            html: content.html().to_string(),
+            text: content.text().to_string(),
+            markdown,
+            topic: (!topic.is_empty()).then(|| topic.to_string()),
+            attachments,
+        })
+    }
+}
+
+/// Moves an issue along its workflow, or tells the user why it can't go
+/// there.
+fn transition(
+    issue: &NewsletterIssue,
+    next: IssueStatus,
+) -> Result<IssueStatus, IssueManagementError> {
+    issue
+        .status
+        .transition_to(next)
+        .map_err(|message| IssueManagementError::ValidationError {
+            issue_id: Some(issue.id),
+            message,
+        })
+}
+
+/// Issues too large for email clients to show in full can't be sent.
+fn check_size(
+    issue: &NewsletterIssue,
+    issue_sender: &IssueSender,
+) -> Result<(), IssueManagementError> {
+    issue_sender
+        .check_size(&issue.content.html)
+        .map_err(|message| IssueManagementError::ValidationError {
+            issue_id: Some(issue.id),
+            message,
+        })
+}
+
+fn see_issue(issue_id: Uuid) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, format!("/admin/newsletters/{issue_id}")))
+        .finish()
+}
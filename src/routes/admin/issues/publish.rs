@@ -0,0 +1,69 @@
+use actix_web::{http::header::LOCATION, web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{check_size, transition, IssueManagementError};
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    authorization::{AuthorizedUser, Permission},
+    client_ip::client_ip,
+    domain::IssueStatus,
+    newsletter_issue::lock_issue,
+    publishing::IssueSender,
+};
+
+/// Sends a drafted, approved or scheduled issue right away, then shows how
+/// its delivery went.
+#[tracing::instrument(
+    name = "Publish a drafted newsletter issue",
+    skip(user, db_pool, issue_sender, request)
+)]
+pub async fn publish_issue(
+    user: AuthorizedUser,
+    issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+    issue_sender: web::Data<IssueSender>,
+    request: HttpRequest,
+) -> Result<HttpResponse, IssueManagementError> {
+    user.require(Permission::PublishNewsletter)?;
+
+    let issue_id = issue_id.into_inner();
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue = lock_issue(&mut transaction, issue_id)
+        .await?
+        .ok_or(IssueManagementError::NotFound)?;
+
+    transition(&issue, IssueStatus::Sending)?;
+    check_size(&issue, &issue_sender)?;
+    issue_sender
+        .start_sending(&mut transaction, issue_id, &issue.content, Some(user.id))
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to publish a newsletter issue.")?;
+
+    let summary = issue_sender.send(issue_id).await?;
+    let ip_address = client_ip(&request);
+
+    AuditEvent::new(AuditAction::NewsletterPublished)
+        .actor_id(user.id)
+        .subject(&issue.content.title)
+        .ip_address(ip_address.as_deref())
+        .details(serde_json::json!(summary))
+        .record(db_pool.get_ref())
+        .await?;
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header((
+            LOCATION,
+            format!("/admin/newsletters/{issue_id}/deliveries"),
+        ))
+        .finish())
+}
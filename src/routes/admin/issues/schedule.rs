@@ -0,0 +1,143 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{check_size, see_issue, transition, IssueManagementError};
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    authorization::{AuthorizedUser, Permission},
+    domain::IssueStatus,
+    newsletter_issue::{lock_issue, schedule, unschedule},
+    publishing::IssueSender,
+};
+
+#[derive(serde::Deserialize)]
+pub struct ScheduleFormData {
+    /// In UTC, as sent by a `datetime-local` input, or in RFC 3339.
+    scheduled_for: String,
+}
+
+impl ScheduleFormData {
+    fn parse(&self) -> Result<DateTime<Utc>, String> {
+        let scheduled_for = self.scheduled_for.trim();
+
+        DateTime::parse_from_rfc3339(scheduled_for)
+            .map(|scheduled_for| scheduled_for.with_timezone(&Utc))
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(scheduled_for, "%Y-%m-%dT%H:%M")
+                    .map(|scheduled_for| DateTime::from_utc(scheduled_for, Utc))
+            })
+            .map_err(|_| format!("{scheduled_for} is not a valid date and time."))
+    }
+}
+
+/// The issue is sent by the scheduler once it's due, on behalf of the user
+/// who scheduled it.
+#[tracing::instrument(
+    name = "Schedule a newsletter issue",
+    skip(user, form, db_pool, issue_sender)
+)]
+pub async fn schedule_issue(
+    user: AuthorizedUser,
+    issue_id: web::Path<Uuid>,
+    form: web::Form<ScheduleFormData>,
+    db_pool: web::Data<PgPool>,
+    issue_sender: web::Data<IssueSender>,
+) -> Result<HttpResponse, IssueManagementError> {
+    user.require(Permission::PublishNewsletter)?;
+
+    let issue_id = issue_id.into_inner();
+    let validation_error = |message: String| IssueManagementError::ValidationError {
+        issue_id: Some(issue_id),
+        message,
+    };
+    let scheduled_for = form.parse().map_err(validation_error)?;
+
+    if scheduled_for <= Utc::now() {
+        return Err(validation_error(
+            "Issues can only be scheduled in the future.".into(),
+        ));
+    }
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue = lock_issue(&mut transaction, issue_id)
+        .await?
+        .ok_or(IssueManagementError::NotFound)?;
+
+    transition(&issue, IssueStatus::Scheduled)?;
+    check_size(&issue, &issue_sender)?;
+    schedule(&mut transaction, issue_id, scheduled_for, user.id).await?;
+
+    AuditEvent::new(AuditAction::NewsletterScheduled)
+        .actor_id(user.id)
+        .subject(issue_id)
+        .details(serde_json::json!({
+            "title": issue.content.title,
+            "scheduled_for": scheduled_for,
+        }))
+        .record(&mut transaction)
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to schedule a newsletter issue.")?;
+
+    Ok(see_issue(issue_id))
+}
+
+/// Takes a scheduled issue back to where it was before it was scheduled, so
+/// it can be edited again.
+#[tracing::instrument(name = "Unschedule a newsletter issue", skip(user, db_pool))]
+pub async fn unschedule_issue(
+    user: AuthorizedUser,
+    issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueManagementError> {
+    user.require(Permission::PublishNewsletter)?;
+
+    let issue_id = issue_id.into_inner();
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue = lock_issue(&mut transaction, issue_id)
+        .await?
+        .ok_or(IssueManagementError::NotFound)?;
+
+    if issue.status != IssueStatus::Scheduled {
+        return Err(IssueManagementError::ValidationError {
+            issue_id: Some(issue_id),
+            message: format!("A {} issue isn't scheduled.", issue.status),
+        });
+    }
+
+    let previous_status = if issue.approved_by.is_some() {
+        IssueStatus::Approved
+    } else {
+        IssueStatus::Draft
+    };
+    let status = transition(&issue, previous_status)?;
+
+    unschedule(&mut transaction, issue_id, status).await?;
+
+    AuditEvent::new(AuditAction::NewsletterUnscheduled)
+        .actor_id(user.id)
+        .subject(issue_id)
+        .details(serde_json::json!({ "title": issue.content.title }))
+        .record(&mut transaction)
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unschedule a newsletter issue.")?;
+
+    Ok(see_issue(issue_id))
+}
@@ -0,0 +1,65 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{see_issue, transition, IssueFormData, IssueManagementError};
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    authorization::{AuthorizedUser, Permission},
+    domain::IssueStatus,
+    newsletter_issue::{lock_issue, update_content},
+};
+
+/// Saves the changes to an issue that hasn't been scheduled or sent yet.
+/// An approved issue that's changed needs to be approved again.
+#[tracing::instrument(name = "Update a newsletter issue", skip(user, form, db_pool))]
+pub async fn update_issue(
+    user: AuthorizedUser,
+    issue_id: web::Path<Uuid>,
+    form: web::Form<IssueFormData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueManagementError> {
+    user.require(Permission::PublishNewsletter)?;
+
+    let issue_id = issue_id.into_inner();
+    let content = form.0.parse(&db_pool, Some(issue_id)).await?;
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue = lock_issue(&mut transaction, issue_id)
+        .await?
+        .ok_or(IssueManagementError::NotFound)?;
+
+    if !issue.status.is_editable() {
+        return Err(IssueManagementError::ValidationError {
+            issue_id: Some(issue_id),
+            message: format!("A {} issue can't be edited.", issue.status),
+        });
+    }
+
+    if issue.status != IssueStatus::Draft {
+        transition(&issue, IssueStatus::Draft)?;
+    }
+
+    update_content(&mut transaction, issue_id, &content).await?;
+
+    AuditEvent::new(AuditAction::NewsletterUpdated)
+        .actor_id(user.id)
+        .subject(issue_id)
+        .details(serde_json::json!({
+            "title": content.title,
+            "approval_withdrawn": issue.status == IssueStatus::Approved,
+        }))
+        .record(&mut transaction)
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update a newsletter issue.")?;
+
+    Ok(see_issue(issue_id))
+}
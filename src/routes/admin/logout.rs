@@ -0,0 +1,11 @@
+use actix_web::{http::header::LOCATION, HttpResponse};
+
+use crate::session_state::TypedSession;
+
+pub async fn log_out(session: TypedSession) -> HttpResponse {
+    session.log_out();
+
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/login"))
+        .finish()
+}
@@ -0,0 +1,27 @@
+mod audit;
+mod dashboard;
+mod issues;
+mod logout;
+mod newsletters;
+mod subscribers;
+mod suppressions;
+mod two_factor;
+mod users;
+
+pub use audit::{audit_log, audit_log_json, AuditLogError};
+pub use dashboard::admin_dashboard;
+pub use issues::{
+    approve_issue, create_issue, list_issues, new_issue_form, publish_issue, schedule_issue,
+    show_issue, unschedule_issue, update_issue, IssueManagementError,
+};
+pub use logout::log_out;
+pub use newsletters::{
+    delivery_report, delivery_report_json, issue_engagement, NewsletterReportError,
+};
+pub use subscribers::{
+    erase_subscriber, export_subscriber, subscriber_consents, subscriber_engagement,
+    SubscriberManagementError,
+};
+pub use suppressions::{add_suppression, list_suppressions, remove_suppression, SuppressionError};
+pub use two_factor::*;
+pub use users::*;
@@ -0,0 +1,97 @@
+use std::fmt::Debug;
+
+use actix_web::{http::header::ContentType, web, HttpResponse, ResponseError};
+use maud::Markup;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authorization::{AuthorizationError, AuthorizedUser, Permission},
+    delivery::get_delivery_report,
+    engagement::get_issue_engagement,
+    error_chain_fmt, views,
+};
+
+#[derive(thiserror::Error)]
+pub enum NewsletterReportError {
+    #[error("There is no such newsletter issue.")]
+    NotFound,
+    #[error(transparent)]
+    AuthorizationError(#[from] AuthorizationError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for NewsletterReportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for NewsletterReportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::AuthorizationError(e) => e.status_code(),
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::NotFound => HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+            Self::AuthorizationError(e) => e.error_response(),
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+/// Opens and clicks of an issue, overall and per link.
+#[tracing::instrument(name = "Get the engagement report of an issue", skip(user, db_pool))]
+pub async fn issue_engagement(
+    user: AuthorizedUser,
+    issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, NewsletterReportError> {
+    user.require(Permission::ViewReports)?;
+
+    let engagement = get_issue_engagement(&db_pool, issue_id.into_inner())
+        .await?
+        .ok_or(NewsletterReportError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(engagement))
+}
+
+/// How many emails of an issue went out, failed or bounced, and why.
+#[tracing::instrument(name = "Get the delivery report of an issue", skip(user, db_pool))]
+pub async fn delivery_report(
+    user: AuthorizedUser,
+    issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<Markup, NewsletterReportError> {
+    user.require(Permission::ViewReports)?;
+
+    let report = get_delivery_report(&db_pool, issue_id.into_inner())
+        .await?
+        .ok_or(NewsletterReportError::NotFound)?;
+
+    Ok(views::admin::newsletters::delivery_report(&report))
+}
+
+#[tracing::instrument(name = "Get the delivery report of an issue", skip(user, db_pool))]
+pub async fn delivery_report_json(
+    user: AuthorizedUser,
+    issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, NewsletterReportError> {
+    user.require(Permission::ViewReports)?;
+
+    let report = get_delivery_report(&db_pool, issue_id.into_inner())
+        .await?
+        .ok_or(NewsletterReportError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(report))
+}
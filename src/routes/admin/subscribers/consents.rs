@@ -0,0 +1,48 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::SubscriberManagementError;
+use crate::{
+    authorization::{AuthorizedUser, Permission},
+    subscriber_data::{get_consent_records, ConsentRecord},
+};
+
+#[derive(serde::Serialize)]
+struct ConsentHistory {
+    subscriber_id: Uuid,
+    email: String,
+    status: String,
+    consents: Vec<ConsentRecord>,
+}
+
+/// Proof of a subscriber's double opt-in, oldest record first.
+#[tracing::instrument(name = "Get the consent history of a subscriber", skip(user, db_pool))]
+pub async fn subscriber_consents(
+    user: AuthorizedUser,
+    subscriber_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberManagementError> {
+    user.require(Permission::ManageSubscribers)?;
+
+    let subscriber_id = subscriber_id.into_inner();
+
+    let subscriber = sqlx::query!(
+        r#"SELECT email, status FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(db_pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber.")?
+    .ok_or(SubscriberManagementError::NotFound)?;
+
+    let consents = get_consent_records(&db_pool, subscriber_id).await?;
+
+    Ok(HttpResponse::Ok().json(ConsentHistory {
+        subscriber_id,
+        email: subscriber.email,
+        status: subscriber.status,
+        consents,
+    }))
+}
@@ -0,0 +1,67 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use super::SubscriberManagementError;
+use crate::{
+    authorization::{AuthorizedUser, Permission},
+    domain::SubscriberEmail,
+    signing::HmacSecret,
+    subscriber_data::{erase_subscriber_data, export_subscriber_data, ErasureRequester},
+};
+
+#[derive(serde::Deserialize, Debug)]
+pub struct SubscriberLookup {
+    email: String,
+}
+
+impl SubscriberLookup {
+    fn email(self) -> Result<SubscriberEmail, SubscriberManagementError> {
+        SubscriberEmail::parse(self.email).map_err(SubscriberManagementError::ValidationError)
+    }
+}
+
+/// Everything we hold about the subscriber with the given email, to answer
+/// a data access request.
+#[tracing::instrument(name = "Export the data of a subscriber", skip(user, db_pool))]
+pub async fn export_subscriber(
+    user: AuthorizedUser,
+    query: web::Query<SubscriberLookup>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberManagementError> {
+    user.require(Permission::ManageSubscribers)?;
+
+    let email = query.into_inner().email()?;
+    let data = export_subscriber_data(&db_pool, &email)
+        .await?
+        .ok_or(SubscriberManagementError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(data))
+}
+
+#[tracing::instrument(
+    name = "Erase the data of a subscriber",
+    skip(user, db_pool, hmac_secret)
+)]
+pub async fn erase_subscriber(
+    user: AuthorizedUser,
+    form: web::Form<SubscriberLookup>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, SubscriberManagementError> {
+    user.require(Permission::ManageSubscribers)?;
+
+    let email = form.into_inner().email()?;
+    let erased = erase_subscriber_data(
+        &db_pool,
+        &hmac_secret,
+        &email,
+        ErasureRequester::Admin(user.id),
+    )
+    .await?;
+
+    if !erased {
+        return Err(SubscriberManagementError::NotFound);
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "erased": true })))
+}
@@ -0,0 +1,46 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::SubscriberManagementError;
+use crate::{
+    authorization::{AuthorizedUser, Permission},
+    engagement::{get_subscriber_engagement, SubscriberIssueEngagement},
+};
+
+#[derive(serde::Serialize)]
+struct SubscriberEngagement {
+    subscriber_id: Uuid,
+    email: String,
+    issues: Vec<SubscriberIssueEngagement>,
+}
+
+/// The issues a subscriber opened or clicked through, latest first.
+#[tracing::instrument(name = "Get the engagement of a subscriber", skip(user, db_pool))]
+pub async fn subscriber_engagement(
+    user: AuthorizedUser,
+    subscriber_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberManagementError> {
+    user.require(Permission::ManageSubscribers)?;
+
+    let subscriber_id = subscriber_id.into_inner();
+
+    let subscriber = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(db_pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber.")?
+    .ok_or(SubscriberManagementError::NotFound)?;
+
+    let issues = get_subscriber_engagement(&db_pool, subscriber_id).await?;
+
+    Ok(HttpResponse::Ok().json(SubscriberEngagement {
+        subscriber_id,
+        email: subscriber.email,
+        issues,
+    }))
+}
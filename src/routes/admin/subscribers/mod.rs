@@ -0,0 +1,53 @@
+mod consents;
+mod data;
+mod engagement;
+
+use std::fmt::Debug;
+
+use actix_web::{http::header::ContentType, HttpResponse, ResponseError};
+use reqwest::StatusCode;
+
+pub use consents::subscriber_consents;
+pub use data::{erase_subscriber, export_subscriber};
+pub use engagement::subscriber_engagement;
+
+use crate::{authorization::AuthorizationError, error_chain_fmt};
+
+#[derive(thiserror::Error)]
+pub enum SubscriberManagementError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no such subscriber.")]
+    NotFound,
+    #[error(transparent)]
+    AuthorizationError(#[from] AuthorizationError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for SubscriberManagementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscriberManagementError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::AuthorizationError(e) => e.status_code(),
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::ValidationError(_) | Self::NotFound => HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+            Self::AuthorizationError(e) => e.error_response(),
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
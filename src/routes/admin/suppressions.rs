@@ -0,0 +1,208 @@
+use std::fmt::Debug;
+
+use actix_web::{http::header::ContentType, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    authorization::{AuthorizationError, AuthorizedUser, Permission},
+    domain::SuppressionTarget,
+    error_chain_fmt,
+    suppression::Suppression,
+};
+
+const DEFAULT_SOURCE: &str = "admin";
+
+#[derive(thiserror::Error)]
+pub enum SuppressionError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0} is already suppressed.")]
+    AlreadySuppressed(String),
+    #[error("There is no such suppression.")]
+    NotFound,
+    #[error(transparent)]
+    AuthorizationError(#[from] AuthorizationError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for SuppressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SuppressionError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::AlreadySuppressed(_) => StatusCode::CONFLICT,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::AuthorizationError(e) => e.status_code(),
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::ValidationError(_) | Self::AlreadySuppressed(_) | Self::NotFound => {
+                HttpResponse::build(self.status_code())
+                    .content_type(ContentType::plaintext())
+                    .body(self.to_string())
+            }
+            Self::AuthorizationError(e) => e.error_response(),
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SuppressionListPage {
+    suppressions: Vec<Suppression>,
+}
+
+#[tracing::instrument(name = "List suppressions", skip(user, db_pool))]
+pub async fn list_suppressions(
+    user: AuthorizedUser,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SuppressionError> {
+    user.require(Permission::ManageSubscribers)?;
+
+    let suppressions = sqlx::query_as!(
+        Suppression,
+        r#"
+            SELECT id, kind, value, reason, source, created_by, created_at
+            FROM email_suppressions
+            ORDER BY created_at DESC, id DESC
+        "#
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .context("Failed to retrieve the suppression list.")?;
+
+    Ok(HttpResponse::Ok().json(SuppressionListPage { suppressions }))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct SuppressionFormData {
+    /// An email address, or a domain to suppress every address at.
+    target: String,
+    reason: String,
+    source: Option<String>,
+}
+
+#[tracing::instrument(name = "Add a suppression", skip(user, db_pool))]
+pub async fn add_suppression(
+    user: AuthorizedUser,
+    form: web::Form<SuppressionFormData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SuppressionError> {
+    user.require(Permission::ManageSubscribers)?;
+
+    let SuppressionFormData {
+        target,
+        reason,
+        source,
+    } = form.into_inner();
+
+    let target = SuppressionTarget::parse(target).map_err(SuppressionError::ValidationError)?;
+    let reason = reason.trim();
+    let source = source
+        .as_deref()
+        .map(str::trim)
+        .filter(|source| !source.is_empty())
+        .unwrap_or(DEFAULT_SOURCE);
+
+    if reason.is_empty() {
+        return Err(SuppressionError::ValidationError(
+            "Give a reason for the suppression.".into(),
+        ));
+    }
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let suppression = sqlx::query_as!(
+        Suppression,
+        r#"
+            INSERT INTO email_suppressions (kind, value, reason, source, created_by)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (kind, value) DO NOTHING
+            RETURNING id, kind, value, reason, source, created_by, created_at
+        "#,
+        target.kind(),
+        target.value(),
+        reason,
+        source,
+        user.id,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to add a suppression.")?
+    .ok_or_else(|| SuppressionError::AlreadySuppressed(target.value().to_string()))?;
+
+    AuditEvent::new(AuditAction::SuppressionAdded)
+        .actor_id(user.id)
+        .subject(&suppression.value)
+        .details(serde_json::json!({
+            "kind": suppression.kind,
+            "reason": suppression.reason,
+            "source": suppression.source,
+        }))
+        .record(&mut transaction)
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to add a suppression.")?;
+
+    Ok(HttpResponse::Created().json(suppression))
+}
+
+#[tracing::instrument(name = "Remove a suppression", skip(user, db_pool))]
+pub async fn remove_suppression(
+    user: AuthorizedUser,
+    suppression_id: web::Path<i64>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SuppressionError> {
+    user.require(Permission::ManageSubscribers)?;
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let suppression = sqlx::query_as!(
+        Suppression,
+        r#"
+            DELETE FROM email_suppressions
+            WHERE id = $1
+            RETURNING id, kind, value, reason, source, created_by, created_at
+        "#,
+        suppression_id.into_inner(),
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to remove a suppression.")?
+    .ok_or(SuppressionError::NotFound)?;
+
+    AuditEvent::new(AuditAction::SuppressionRemoved)
+        .actor_id(user.id)
+        .subject(&suppression.value)
+        .details(serde_json::json!({ "kind": suppression.kind }))
+        .record(&mut transaction)
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to remove a suppression.")?;
+
+    Ok(HttpResponse::Ok().json(suppression))
+}
@@ -0,0 +1,169 @@
+use std::fmt::Debug;
+
+use actix_web::{http::header::LOCATION, web, HttpRequest, HttpResponse, ResponseError};
+use maud::Markup;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    authentication::{
+        activate_two_factor, authenticate_second_factor, begin_two_factor_enrollment,
+        disable_two_factor, get_two_factor_status, AuthError,
+    },
+    authorization::{AuthorizationError, AuthorizedUser},
+    client_ip::client_ip,
+    csrf::CsrfToken,
+    error_chain_fmt,
+    settings::AuthenticationSettings,
+    views,
+};
+
+#[derive(thiserror::Error)]
+pub enum TwoFactorSettingsError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    AuthorizationError(#[from] AuthorizationError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for TwoFactorSettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for TwoFactorSettingsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::SEE_OTHER,
+            Self::AuthorizationError(e) => e.status_code(),
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::ValidationError(_) => {
+                let encoded_error = urlencoding::Encoded::new(self.to_string());
+
+                HttpResponse::SeeOther()
+                    .insert_header((
+                        LOCATION,
+                        format!("/admin/two-factor?error={}", encoded_error),
+                    ))
+                    .finish()
+            }
+            Self::AuthorizationError(e) => e.error_response(),
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct QueryParams {
+    error: Option<String>,
+}
+
+pub async fn two_factor_settings(
+    user: AuthorizedUser,
+    query: web::Query<QueryParams>,
+    db_pool: web::Data<PgPool>,
+    csrf_token: CsrfToken,
+) -> Result<Markup, TwoFactorSettingsError> {
+    let status = get_two_factor_status(&db_pool, user.id).await?;
+
+    Ok(views::admin::two_factor::get(
+        &status,
+        &csrf_token,
+        query.0.error,
+    ))
+}
+
+#[tracing::instrument(name = "Start two-factor enrollment", skip(user, db_pool), fields(user_id = %user.id))]
+pub async fn start_two_factor_enrollment(
+    user: AuthorizedUser,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, TwoFactorSettingsError> {
+    begin_two_factor_enrollment(&db_pool, user.id).await?;
+
+    Ok(see_two_factor_settings())
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+/// The recovery codes are only ever shown in the response to this request.
+#[tracing::instrument(name = "Confirm two-factor enrollment", skip(user, form, db_pool), fields(user_id = %user.id))]
+pub async fn confirm_two_factor_enrollment(
+    user: AuthorizedUser,
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<Markup, TwoFactorSettingsError> {
+    let recovery_codes = activate_two_factor(&db_pool, user.id, &form.code)
+        .await?
+        .ok_or_else(|| {
+            TwoFactorSettingsError::ValidationError(
+                "That code didn't match, check your device's clock and try again.".into(),
+            )
+        })?;
+
+    AuditEvent::new(AuditAction::TwoFactorEnabled)
+        .actor_id(user.id)
+        .subject(user.id)
+        .record(db_pool.get_ref())
+        .await?;
+
+    Ok(views::admin::two_factor::recovery_codes(&recovery_codes))
+}
+
+/// Turning two-factor authentication off requires a current code, so a
+/// hijacked session can't be used to weaken the account.
+#[tracing::instrument(name = "Turn off two-factor", skip(user, form, db_pool, settings, request), fields(user_id = %user.id))]
+pub async fn turn_off_two_factor(
+    user: AuthorizedUser,
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    settings: web::Data<AuthenticationSettings>,
+    request: HttpRequest,
+) -> Result<HttpResponse, TwoFactorSettingsError> {
+    let ip_address = client_ip(&request);
+
+    authenticate_second_factor(
+        &db_pool,
+        &settings.throttling,
+        user.id,
+        &form.code,
+        ip_address.as_deref(),
+    )
+    .await
+    .map_err(|e| match e {
+        AuthError::InvalidCredentials(_) => {
+            TwoFactorSettingsError::ValidationError("Invalid code.".into())
+        }
+        AuthError::LockedOut { .. } => TwoFactorSettingsError::ValidationError(
+            "Too many failed attempts, try again later.".into(),
+        ),
+        AuthError::UnexpectedError(e) => TwoFactorSettingsError::UnexpectedError(e),
+    })?;
+
+    disable_two_factor(&db_pool, user.id).await?;
+
+    AuditEvent::new(AuditAction::TwoFactorDisabled)
+        .actor_id(user.id)
+        .subject(user.id)
+        .record(db_pool.get_ref())
+        .await?;
+
+    Ok(see_two_factor_settings())
+}
+
+fn see_two_factor_settings() -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/two-factor"))
+        .finish()
+}
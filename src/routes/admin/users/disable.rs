@@ -0,0 +1,68 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{see_users, UserManagementError};
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    authorization::{AuthorizedUser, Permission},
+};
+
+#[tracing::instrument(name = "Disable a user", skip(user, db_pool))]
+pub async fn disable_user(
+    user: AuthorizedUser,
+    user_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UserManagementError> {
+    user.require(Permission::ManageUsers)?;
+
+    let user_id = user_id.into_inner();
+
+    if user_id == user.id {
+        return Err(UserManagementError::ValidationError(
+            "You can't disable your own account.".into(),
+        ));
+    }
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    sqlx::query!(
+        r#"
+            UPDATE users
+            SET disabled_at = now()
+            WHERE id = $1 AND disabled_at IS NULL
+        "#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to disable user.")?;
+
+    sqlx::query!(
+        r#"
+            DELETE FROM password_tokens
+            WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to revoke the password tokens of a disabled user.")?;
+
+    AuditEvent::new(AuditAction::UserDisabled)
+        .actor_id(user.id)
+        .subject(user_id)
+        .record(&mut transaction)
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to disable a user.")?;
+
+    Ok(see_users())
+}
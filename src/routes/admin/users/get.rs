@@ -0,0 +1,63 @@
+use actix_web::web;
+use anyhow::Context;
+use maud::Markup;
+use sqlx::PgPool;
+
+use super::UserManagementError;
+use crate::{
+    authorization::{AuthorizedUser, Permission},
+    csrf::CsrfToken,
+    domain::UserRole,
+    views::{self, admin::users::UserSummary},
+};
+
+#[derive(serde::Deserialize, Debug)]
+pub struct QueryParams {
+    error: Option<String>,
+}
+
+pub async fn list_users(
+    user: AuthorizedUser,
+    query: web::Query<QueryParams>,
+    db_pool: web::Data<PgPool>,
+    csrf_token: CsrfToken,
+) -> Result<Markup, UserManagementError> {
+    user.require(Permission::ManageUsers)?;
+
+    let users = get_users(&db_pool).await?;
+
+    Ok(views::admin::users::get(&users, &csrf_token, query.0.error))
+}
+
+#[tracing::instrument(name = "Get users", skip(db_pool))]
+async fn get_users(db_pool: &PgPool) -> Result<Vec<UserSummary>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT
+                id,
+                username,
+                email,
+                role,
+                disabled_at IS NOT NULL AS "is_disabled!",
+                password_hash IS NOT NULL AS "has_password!"
+            FROM users
+            ORDER BY created_at
+        "#
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve users.")?;
+
+    rows.into_iter()
+        .map(|r| {
+            Ok(UserSummary {
+                id: r.id,
+                username: r.username,
+                email: r.email,
+                role: UserRole::parse(r.role).map_err(anyhow::Error::msg)?,
+                is_disabled: r.is_disabled,
+                has_password: r.has_password,
+            })
+        })
+        .collect()
+}
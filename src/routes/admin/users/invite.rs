@@ -0,0 +1,110 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::{see_users, UserManagementError};
+use crate::{
+    application::ApplicationBaseUrl,
+    audit::{AuditAction, AuditEvent},
+    authentication::{issue_password_token, PasswordTokenPurpose},
+    authorization::{AuthorizedUser, Permission},
+    domain::{SubscriberEmail, UserRole},
+    email_client::EmailClient,
+    routes::send_password_token_email,
+    settings::AuthenticationSettings,
+};
+
+#[derive(serde::Deserialize)]
+pub struct InviteFormData {
+    email: String,
+    role: String,
+}
+
+#[tracing::instrument(
+    name = "Invite a new user",
+    skip(form, db_pool, email_client, base_url, settings),
+    fields(invitee_email = %form.email, invitee_role = %form.role)
+)]
+pub async fn invite_user(
+    user: AuthorizedUser,
+    form: web::Form<InviteFormData>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    settings: web::Data<AuthenticationSettings>,
+) -> Result<HttpResponse, UserManagementError> {
+    use UserManagementError::*;
+
+    user.require(Permission::ManageUsers)?;
+
+    let email = SubscriberEmail::parse(form.0.email).map_err(ValidationError)?;
+    let role = UserRole::parse(form.0.role).map_err(ValidationError)?;
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let invitee_id = insert_invited_user(&mut transaction, &email, role)
+        .await?
+        .ok_or_else(|| ValidationError(format!("A user with the email {email} already exists.")))?;
+
+    let token = issue_password_token(
+        &mut transaction,
+        invitee_id,
+        PasswordTokenPurpose::Invitation,
+        settings.invitation_ttl(),
+    )
+    .await?;
+
+    AuditEvent::new(AuditAction::UserInvited)
+        .actor_id(user.id)
+        .subject(invitee_id)
+        .details(serde_json::json!({ "email": email.as_ref(), "role": role.as_str() }))
+        .record(&mut transaction)
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store an invited user.")?;
+
+    send_password_token_email(
+        &email_client,
+        &email,
+        &base_url,
+        &token,
+        PasswordTokenPurpose::Invitation,
+    )
+    .await
+    .context("Failed to send an invitation email.")?;
+
+    Ok(see_users())
+}
+
+/// Invited users log in with their email address, and can't until they have
+/// followed the invitation link to choose a password.
+#[tracing::instrument(name = "Saving invited user", skip(transaction))]
+async fn insert_invited_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+    role: UserRole,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let record = sqlx::query!(
+        r#"
+            INSERT INTO users (id, username, email, role)
+            VALUES ($1, $2, $2, $3)
+            ON CONFLICT DO NOTHING
+            RETURNING id
+        "#,
+        Uuid::now_v7(),
+        email.as_ref(),
+        role.as_ref(),
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to insert invited user in the database.")?;
+
+    Ok(record.map(|r| r.id))
+}
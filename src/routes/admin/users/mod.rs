@@ -0,0 +1,64 @@
+mod disable;
+mod get;
+mod invite;
+mod password_reset;
+
+use std::fmt::Debug;
+
+use actix_web::{http::header::LOCATION, HttpResponse, ResponseError};
+use reqwest::StatusCode;
+
+pub use disable::disable_user;
+pub use get::list_users;
+pub use invite::invite_user;
+pub use password_reset::force_password_reset;
+
+use crate::{authorization::AuthorizationError, error_chain_fmt};
+
+#[derive(thiserror::Error)]
+pub enum UserManagementError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    AuthorizationError(#[from] AuthorizationError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for UserManagementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UserManagementError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::SEE_OTHER,
+            Self::AuthorizationError(e) => e.status_code(),
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::ValidationError(_) => see_users_with_error(&self.to_string()),
+            Self::AuthorizationError(e) => e.error_response(),
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+fn see_users() -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/users"))
+        .finish()
+}
+
+fn see_users_with_error(error: &str) -> HttpResponse {
+    let encoded_error = urlencoding::Encoded::new(error);
+
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, format!("/admin/users?error={}", encoded_error)))
+        .finish()
+}
@@ -0,0 +1,103 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{see_users, see_users_with_error, UserManagementError};
+use crate::{
+    application::ApplicationBaseUrl,
+    audit::{AuditAction, AuditEvent},
+    authentication::{issue_password_token, PasswordTokenPurpose},
+    authorization::{AuthorizedUser, Permission},
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    routes::send_password_token_email,
+    settings::AuthenticationSettings,
+};
+
+/// Clear the user's password, so it can't be used anymore, log them out
+/// everywhere, and email them a link to choose a new one. Should the email
+/// fail to send, the reset stands, and can be forced again for another
+/// email.
+#[tracing::instrument(
+    name = "Force a password reset",
+    skip(user, db_pool, email_client, base_url, settings)
+)]
+pub async fn force_password_reset(
+    user: AuthorizedUser,
+    user_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    settings: web::Data<AuthenticationSettings>,
+) -> Result<HttpResponse, UserManagementError> {
+    use UserManagementError::*;
+
+    user.require(Permission::ManageUsers)?;
+
+    let user_id = user_id.into_inner();
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let email = sqlx::query!(
+        r#"
+            UPDATE users
+            SET password_hash = NULL, session_version = session_version + 1
+            WHERE id = $1 AND disabled_at IS NULL AND email IS NOT NULL
+            RETURNING email
+        "#,
+        user_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to clear the password of a user.")?
+    .and_then(|r| r.email)
+    .ok_or_else(|| ValidationError("Only active users with an email can be reset.".into()))?;
+
+    let email = SubscriberEmail::parse(email).map_err(anyhow::Error::msg)?;
+
+    let token = issue_password_token(
+        &mut transaction,
+        user_id,
+        PasswordTokenPurpose::Reset,
+        settings.password_reset_ttl(),
+    )
+    .await?;
+
+    AuditEvent::new(AuditAction::UserPasswordResetForced)
+        .actor_id(user.id)
+        .subject(user_id)
+        .record(&mut transaction)
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to reset a password.")?;
+
+    if let Err(error) = send_password_token_email(
+        &email_client,
+        &email,
+        &base_url,
+        &token,
+        PasswordTokenPurpose::Reset,
+    )
+    .await
+    {
+        tracing::error!(
+            error.cause_chain = ?error,
+            "Failed to send a forced password reset email to {}",
+            email
+        );
+
+        return Ok(see_users_with_error(&format!(
+            "The password of {email} was reset, but the email with a link to choose a new one \
+            could not be sent. Force the reset again to send another."
+        )));
+    }
+
+    Ok(see_users())
+}
@@ -1,5 +1,32 @@
-use actix_web::{HttpRequest, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use sqlx::PgPool;
+
+use crate::{
+    email_client::EmailClient,
+    health::{Readiness, ReadinessStatus},
+};
 
 pub async fn health_check(_req: HttpRequest) -> impl Responder {
     HttpResponse::Ok()
 }
+
+/// The process is up and serving requests, whatever the state of what it
+/// depends on.
+pub async fn health_live() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "live" }))
+}
+
+/// Whether the application can do its work right now, with the status and
+/// latency of each of its dependencies.
+pub async fn health_ready(
+    readiness: web::Data<Readiness>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+) -> HttpResponse {
+    let report = readiness.check(&db_pool, &email_client).await;
+
+    match report.status {
+        ReadinessStatus::Ready => HttpResponse::Ok().json(report),
+        _ => HttpResponse::ServiceUnavailable().json(report),
+    }
+}
@@ -1,13 +1,16 @@
 use actix_web::web;
 use maud::Markup;
 
-use crate::views;
+use crate::{csrf::CsrfToken, views};
 
 #[derive(serde::Deserialize, Debug)]
 pub struct QueryParams {
     error: Option<String>,
 }
 
-pub async fn login_form(query: web::Query<QueryParams>) -> actix_web::Result<Markup> {
-    Ok(views::login::get(query.0.error))
+pub async fn login_form(
+    query: web::Query<QueryParams>,
+    csrf_token: CsrfToken,
+) -> actix_web::Result<Markup> {
+    Ok(views::login::get(&csrf_token, query.0.error))
 }
@@ -1,13 +1,19 @@
 use std::fmt::Debug;
 
-use actix_web::{http::header::LOCATION, web, HttpResponse, ResponseError};
+use actix_web::{http::header::LOCATION, web, HttpRequest, HttpResponse, ResponseError};
 use reqwest::StatusCode;
 use secrecy::Secret;
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials},
+    audit::{AuditAction, AuditEvent},
+    authentication::{authenticate, is_two_factor_enabled, AuthError, Credentials},
+    authorization::get_session_version,
+    client_ip::client_ip,
     error_chain_fmt,
+    metrics::{self, LoginOutcome},
+    session_state::TypedSession,
+    settings::AuthenticationSettings,
 };
 
 #[derive(serde::Deserialize)]
@@ -16,29 +22,83 @@ pub struct FormData {
     password: Secret<String>,
 }
 
-#[tracing::instrument(skip(form, db_pool), fields(username=tracing::field::Empty, user_id=tracing::field::Empty))]
+#[tracing::instrument(skip(form, db_pool, session, settings, request), fields(username=tracing::field::Empty, user_id=tracing::field::Empty))]
 pub async fn login(
     db_pool: web::Data<PgPool>,
     form: web::Form<FormData>,
+    session: TypedSession,
+    settings: web::Data<AuthenticationSettings>,
+    request: HttpRequest,
 ) -> Result<HttpResponse, LoginError> {
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
 
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    let username = credentials.username.clone();
 
-    let user_id = validate_credentials(&db_pool, credentials)
-        .await
-        .map_err(|e| match e {
-            AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
-            AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
-        })?;
+    tracing::Span::current().record("username", tracing::field::display(&username));
 
-    tracing::Span::current().record("user.id", &tracing::field::display(&user_id));
+    let ip_address = client_ip(&request);
+
+    let user_id = match authenticate(&db_pool, &settings, credentials, ip_address.clone()).await {
+        Ok(user_id) => user_id,
+        Err(e) => {
+            let reason = match e {
+                AuthError::InvalidCredentials(_) => "invalid_credentials",
+                AuthError::LockedOut { .. } => "locked_out",
+                AuthError::UnexpectedError(_) => return Err(LoginError::UnexpectedError(e.into())),
+            };
+
+            AuditEvent::new(AuditAction::LoginFailed)
+                .actor(username)
+                .ip_address(ip_address.as_deref())
+                .details(serde_json::json!({ "reason": reason }))
+                .record(db_pool.get_ref())
+                .await?;
+
+            metrics::record_login(LoginOutcome::Failed);
+
+            return Err(match e {
+                AuthError::LockedOut { .. } => LoginError::LockedOut(e.into()),
+                _ => LoginError::AuthError(e.into()),
+            });
+        }
+    };
+
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    session.renew();
+    // Read along with the password, for a reset forced before the second
+    // factor is entered to apply
+    session
+        .insert_session_version(get_session_version(&db_pool, user_id).await?)
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+
+    if is_two_factor_enabled(&db_pool, user_id).await? {
+        session
+            .insert_pending_two_factor_user_id(user_id)
+            .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+
+        return Ok(HttpResponse::SeeOther()
+            .insert_header((LOCATION, "/login/two-factor"))
+            .finish());
+    }
+
+    session
+        .insert_user_id(user_id)
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+
+    AuditEvent::new(AuditAction::LoginSucceeded)
+        .actor_id(user_id)
+        .ip_address(ip_address.as_deref())
+        .record(db_pool.get_ref())
+        .await?;
+
+    metrics::record_login(LoginOutcome::Succeeded);
 
     Ok(HttpResponse::SeeOther()
-        .insert_header((LOCATION, "/"))
+        .insert_header((LOCATION, "/admin"))
         .finish())
 }
 
@@ -46,6 +106,8 @@ pub async fn login(
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("Too many failed login attempts, try again later")]
+    LockedOut(#[source] anyhow::Error),
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -61,6 +123,7 @@ impl ResponseError for LoginError {
         match self {
             LoginError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             LoginError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            LoginError::LockedOut(_) => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 
@@ -0,0 +1,162 @@
+use std::fmt::Debug;
+
+use actix_web::{http::header::LOCATION, web, HttpRequest, HttpResponse, ResponseError};
+use maud::Markup;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    authentication::{authenticate_second_factor, AuthError},
+    client_ip::client_ip,
+    csrf::CsrfToken,
+    error_chain_fmt,
+    metrics::{self, LoginOutcome},
+    session_state::TypedSession,
+    settings::AuthenticationSettings,
+    views,
+};
+
+#[derive(serde::Deserialize, Debug)]
+pub struct QueryParams {
+    error: Option<String>,
+}
+
+pub async fn two_factor_form(
+    query: web::Query<QueryParams>,
+    session: TypedSession,
+    csrf_token: CsrfToken,
+) -> Result<Markup, TwoFactorLoginError> {
+    session
+        .get_pending_two_factor_user_id()
+        .map_err(|e| TwoFactorLoginError::UnexpectedError(e.into()))?
+        .ok_or(TwoFactorLoginError::NoPendingLogin)?;
+
+    Ok(views::login::two_factor(&csrf_token, query.0.error))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[tracing::instrument(
+    name = "Verify second factor",
+    skip(form, db_pool, session, settings, request),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn verify_two_factor(
+    db_pool: web::Data<PgPool>,
+    form: web::Form<FormData>,
+    session: TypedSession,
+    settings: web::Data<AuthenticationSettings>,
+    request: HttpRequest,
+) -> Result<HttpResponse, TwoFactorLoginError> {
+    let user_id = session
+        .get_pending_two_factor_user_id()
+        .map_err(|e| TwoFactorLoginError::UnexpectedError(e.into()))?
+        .ok_or(TwoFactorLoginError::NoPendingLogin)?;
+
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let ip_address = client_ip(&request);
+
+    let outcome = authenticate_second_factor(
+        &db_pool,
+        &settings.throttling,
+        user_id,
+        &form.code,
+        ip_address.as_deref(),
+    )
+    .await;
+
+    if let Err(e) = outcome {
+        let reason = match e {
+            AuthError::InvalidCredentials(_) => "invalid_second_factor",
+            AuthError::LockedOut { .. } => "locked_out",
+            AuthError::UnexpectedError(_) => {
+                return Err(TwoFactorLoginError::UnexpectedError(e.into()))
+            }
+        };
+
+        AuditEvent::new(AuditAction::LoginFailed)
+            .actor_id(user_id)
+            .ip_address(ip_address.as_deref())
+            .details(serde_json::json!({ "reason": reason }))
+            .record(db_pool.get_ref())
+            .await?;
+
+        metrics::record_login(LoginOutcome::Failed);
+
+        return Err(match e {
+            AuthError::LockedOut { .. } => {
+                // Once locked out, the password has to be provided again.
+                session.remove_pending_two_factor_user_id();
+                TwoFactorLoginError::LockedOut(e.into())
+            }
+            _ => TwoFactorLoginError::InvalidCode(e.into()),
+        });
+    }
+
+    session.renew();
+    session.remove_pending_two_factor_user_id();
+    session
+        .insert_user_id(user_id)
+        .map_err(|e| TwoFactorLoginError::UnexpectedError(e.into()))?;
+
+    AuditEvent::new(AuditAction::LoginSucceeded)
+        .actor_id(user_id)
+        .ip_address(ip_address.as_deref())
+        .details(serde_json::json!({ "second_factor": true }))
+        .record(db_pool.get_ref())
+        .await?;
+
+    metrics::record_login(LoginOutcome::Succeeded);
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin"))
+        .finish())
+}
+
+#[derive(thiserror::Error)]
+pub enum TwoFactorLoginError {
+    #[error("Invalid code")]
+    InvalidCode(#[source] anyhow::Error),
+    #[error("Too many failed login attempts, try again later")]
+    LockedOut(#[source] anyhow::Error),
+    #[error("Log in with your password first")]
+    NoPendingLogin,
+    #[error("Something went wrong")]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for TwoFactorLoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for TwoFactorLoginError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidCode(_) => StatusCode::UNAUTHORIZED,
+            Self::LockedOut(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::NoPendingLogin => StatusCode::SEE_OTHER,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        let encoded_error = urlencoding::Encoded::new(self.to_string());
+
+        let location = match self {
+            Self::InvalidCode(_) => format!("/login/two-factor?error={}", encoded_error),
+            Self::NoPendingLogin => "/login".to_string(),
+            _ => format!("/login?error={}", encoded_error),
+        };
+
+        HttpResponse::SeeOther()
+            .insert_header((LOCATION, location))
+            .finish()
+    }
+}
@@ -0,0 +1,49 @@
+use actix_web::{
+    error::ErrorInternalServerError,
+    http::header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    web, HttpRequest, HttpResponse,
+};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::csrf::constant_time_eq;
+
+/// The bearer token Prometheus scrapes `/metrics` with.
+pub struct MetricsToken(pub Secret<String>);
+
+impl MetricsToken {
+    fn authorizes(&self, request: &HttpRequest) -> bool {
+        request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| {
+                constant_time_eq(token.as_bytes(), self.0.expose_secret().as_bytes())
+            })
+    }
+}
+
+/// Everything Prometheus scrapes, in its text format. Scrapes are rejected
+/// until a metrics token is set.
+pub async fn metrics(
+    request: HttpRequest,
+    db_pool: web::Data<PgPool>,
+    token: web::Data<Option<MetricsToken>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !token
+        .as_ref()
+        .as_ref()
+        .is_some_and(|t| t.authorizes(&request))
+    {
+        return Ok(HttpResponse::Unauthorized()
+            .insert_header((WWW_AUTHENTICATE, "Bearer"))
+            .finish());
+    }
+
+    let body = crate::metrics::render(&db_pool).map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(body))
+}
@@ -1,13 +1,30 @@
+mod admin;
 mod health_check;
 mod home;
 mod login;
+mod metrics;
 mod newsletters;
+mod password;
+mod preferences;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_data;
+mod tracking;
+mod webhooks;
 
+pub use admin::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
+pub use metrics::*;
 pub use newsletters::*;
+pub use password::*;
+pub use preferences::{
+    placeholder_preferences_footer, preferences_footer, preferences_form, update_preferences,
+    PreferencesError,
+};
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_data::*;
+pub use tracking::*;
+pub use webhooks::*;
@@ -0,0 +1,96 @@
+use std::fmt::Debug;
+
+use actix_web::{
+    http::header::{ContentType, CONTENT_TYPE},
+    web, HttpResponse, ResponseError,
+};
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    email_html::EmailHtml,
+    error_chain_fmt,
+    newsletter_asset::{get_asset, get_asset_names, inline_asset_ids, link_inline_assets},
+    newsletter_issue::get_published_issue,
+    views,
+};
+
+#[derive(thiserror::Error)]
+pub enum ArchiveError {
+    #[error("There is no such newsletter issue.")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ArchiveError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::NotFound => HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+/// A published issue, for those its email doesn't reach in full, such as
+/// subscribers whose digest was too large to gather every issue.
+#[tracing::instrument(name = "Read a published newsletter issue", skip(db_pool))]
+pub async fn read_issue(
+    issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ArchiveError> {
+    let issue_id = issue_id.into_inner();
+    let issue = get_published_issue(&db_pool, issue_id)
+        .await?
+        .ok_or(ArchiveError::NotFound)?;
+
+    let assets_url = format!("/newsletters/{issue_id}/assets");
+    let html = link_inline_assets(EmailHtml::prepare(&issue.html).html(), &assets_url);
+    let attachments = get_asset_names(&db_pool, &issue.attachments).await?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        views::newsletter_issue::read(&issue.title, &html, &assets_url, &attachments).into_string(),
+    ))
+}
+
+/// The assets of a published issue, inline or attached. Others aren't
+/// served, even to those who know their id.
+#[tracing::instrument(name = "Get an asset of a published newsletter issue", skip(db_pool))]
+pub async fn read_issue_asset(
+    path: web::Path<(Uuid, Uuid)>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ArchiveError> {
+    let (issue_id, asset_id) = path.into_inner();
+    let issue = get_published_issue(&db_pool, issue_id)
+        .await?
+        .ok_or(ArchiveError::NotFound)?;
+
+    if !issue.attachments.contains(&asset_id) && !inline_asset_ids(&issue.html).contains(&asset_id)
+    {
+        return Err(ArchiveError::NotFound);
+    }
+
+    let asset = get_asset(&db_pool, asset_id)
+        .await?
+        .ok_or(ArchiveError::NotFound)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((CONTENT_TYPE, asset.content_type))
+        .body(asset.content))
+}
@@ -0,0 +1,67 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{authenticate_publisher, PublishError};
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    newsletter_asset::{insert_asset, NewAsset},
+    settings::AuthenticationSettings,
+};
+
+#[derive(serde::Deserialize, Debug)]
+pub struct AssetQuery {
+    filename: String,
+}
+
+#[derive(serde::Serialize)]
+struct UploadedAsset {
+    id: Uuid,
+    filename: String,
+    content_type: String,
+    size: usize,
+    /// What the HTML of an issue refers to, to show the asset inline.
+    content_id: String,
+}
+
+/// Stores a file sent as the body of the request, with its type as the
+/// `Content-Type`, to be sent with newsletter issues. Issues list the ids of
+/// their attachments, and refer to inline images as `cid:{id}`.
+#[tracing::instrument(
+    name = "Upload a newsletter asset",
+    skip(body, db_pool, settings, request)
+)]
+pub async fn upload_asset(
+    body: web::Bytes,
+    query: web::Query<AssetQuery>,
+    db_pool: web::Data<PgPool>,
+    settings: web::Data<AuthenticationSettings>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    let publisher = authenticate_publisher(&request, &db_pool, &settings).await?;
+
+    let asset = NewAsset::parse(&query.filename, request.content_type(), body.to_vec())
+        .map_err(PublishError::ValidationError)?;
+    let asset_id = insert_asset(&db_pool, &asset, publisher.user_id).await?;
+    let uploaded = UploadedAsset {
+        id: asset_id,
+        filename: asset.filename,
+        content_type: asset.content_type,
+        size: asset.content.len(),
+        content_id: format!("cid:{asset_id}"),
+    };
+
+    AuditEvent::new(AuditAction::NewsletterAssetUploaded)
+        .actor_id(publisher.user_id)
+        .subject(asset_id)
+        .ip_address(publisher.ip_address.as_deref())
+        .details(serde_json::json!({
+            "filename": uploaded.filename,
+            "content_type": uploaded.content_type,
+            "size": uploaded.size,
+        }))
+        .record(db_pool.get_ref())
+        .await?;
+
+    Ok(HttpResponse::Created().json(uploaded))
+}
@@ -0,0 +1,300 @@
+mod archive;
+mod assets;
+mod preview;
+
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    authentication::{
+        authenticate, authenticate_second_factor, is_two_factor_enabled, AuthError, Credentials,
+    },
+    authorization::{authorize, AuthorizationError, Permission},
+    client_ip::client_ip,
+    domain::NewsletterContent,
+    error_chain_fmt,
+    newsletter_asset::find_asset_problem,
+    newsletter_issue::{insert_issue, topic_exists, IssueContent},
+    publishing::IssueSender,
+    settings::AuthenticationSettings,
+};
+use actix_web::{
+    http::header::{ContentType, HeaderMap, HeaderValue},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use reqwest::{
+    header::{self},
+    StatusCode,
+};
+use secrecy::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub use archive::{read_issue, read_issue_asset};
+pub use assets::upload_asset;
+pub use preview::{preview_newsletter, send_test_newsletter};
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error("Too many failed login attempts.")]
+    LockedOut {
+        #[source]
+        source: anyhow::Error,
+        retry_after_seconds: i64,
+    },
+    #[error(transparent)]
+    AuthorizationError(#[from] AuthorizationError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PublishError {
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::UnexpectedError(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+            Self::ValidationError(_) => HttpResponse::build(StatusCode::BAD_REQUEST)
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+            Self::AuthorizationError(e) => e.error_response(),
+            Self::LockedOut {
+                retry_after_seconds,
+                ..
+            } => HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after_seconds.to_string()))
+                .finish(),
+            Self::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_str(r#"Basic realm="publish""#).unwrap();
+
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+
+                response
+            }
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    title: String,
+    content: Content,
+    /// Subscribers who opted out of the issue's topic don't receive it.
+    topic: Option<String>,
+    /// Uploaded assets to attach. Those the HTML refers to as `cid:{id}`
+    /// are sent inline without being listed.
+    #[serde(default)]
+    attachments: Vec<Uuid>,
+}
+
+/// Issues are written either in Markdown, or as HTML with a plain text
+/// alternative.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    Markdown { markdown: String },
+    Html { html: String, text: String },
+}
+
+pub async fn publish_newsletter(
+    body: web::Json<BodyData>,
+    db_pool: web::Data<PgPool>,
+    issue_sender: web::Data<IssueSender>,
+    settings: web::Data<AuthenticationSettings>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    let publisher = authenticate_publisher(&request, &db_pool, &settings).await?;
+
+    let content = body.parse(&db_pool).await?;
+
+    issue_sender
+        .check_size(&content.html)
+        .map_err(PublishError::ValidationError)?;
+
+    // Published on the spot, the issue goes straight from draft to sending
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue_id = insert_issue(&mut transaction, &content, publisher.user_id).await?;
+
+    issue_sender
+        .start_sending(
+            &mut transaction,
+            issue_id,
+            &content,
+            Some(publisher.user_id),
+        )
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a newsletter issue.")?;
+
+    let summary = issue_sender.send(issue_id).await?;
+
+    AuditEvent::new(AuditAction::NewsletterPublished)
+        .actor_id(publisher.user_id)
+        .subject(&body.title)
+        .ip_address(publisher.ip_address.as_deref())
+        .details(serde_json::json!(summary))
+        .record(db_pool.get_ref())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+impl BodyData {
+    async fn parse(&self, db_pool: &PgPool) -> Result<IssueContent, PublishError> {
+        if let Some(topic) = &self.topic {
+            if !topic_exists(db_pool, topic).await? {
+                return Err(PublishError::ValidationError(format!(
+                    "There is no topic called {topic}."
+                )));
+            }
+        }
+
+        let (content, markdown) = match &self.content {
+            Content::Markdown { markdown } => (
+                NewsletterContent::from_markdown(markdown),
+                Some(markdown.clone()),
+            ),
+            Content::Html { html, text } => {
+                (NewsletterContent::parse(html.clone(), text.clone()), None)
+            }
+        };
+        let content = content.map_err(PublishError::ValidationError)?;
+
+        if let Some(problem) =
+            find_asset_problem(db_pool, content.html(), &self.attachments).await?
+        {
+            return Err(PublishError::ValidationError(problem));
+        }
+
+        Ok(IssueContent {
+            title: self.title.clone(),
+            html: content.html().to_string(),
+            text: content.text().to_string(),
+            markdown,
+            topic: self.topic.clone(),
+            attachments: self.attachments.clone(),
+        })
+    }
+}
+
+/// A user allowed to publish, authenticated with 'Basic' credentials, and
+/// their second factor if they enabled it.
+struct Publisher {
+    user_id: Uuid,
+    ip_address: Option<String>,
+}
+
+async fn authenticate_publisher(
+    request: &HttpRequest,
+    db_pool: &PgPool,
+    settings: &AuthenticationSettings,
+) -> Result<Publisher, PublishError> {
+    let credentials = basic_authentication(request.headers()).map_err(PublishError::AuthError)?;
+
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+
+    let ip_address = client_ip(request);
+
+    let user_id = authenticate(db_pool, settings, credentials, ip_address.clone())
+        .await
+        .map_err(auth_error_to_publish_error)?;
+
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    if is_two_factor_enabled(db_pool, user_id).await? {
+        let code = two_factor_code(request.headers()).map_err(PublishError::AuthError)?;
+
+        authenticate_second_factor(
+            db_pool,
+            &settings.throttling,
+            user_id,
+            code,
+            ip_address.as_deref(),
+        )
+        .await
+        .map_err(auth_error_to_publish_error)?;
+    }
+
+    authorize(db_pool, user_id, Permission::PublishNewsletter).await?;
+
+    Ok(Publisher {
+        user_id,
+        ip_address,
+    })
+}
+
+fn auth_error_to_publish_error(e: AuthError) -> PublishError {
+    match e {
+        AuthError::InvalidCredentials(_) => PublishError::AuthError(e.into()),
+        AuthError::LockedOut { locked_until } => PublishError::LockedOut {
+            retry_after_seconds: (locked_until - chrono::Utc::now()).num_seconds().max(1),
+            source: e.into(),
+        },
+        AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
+    }
+}
+
+fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+
+    let decoded_bytes = base64::decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credentials string is not valid UTF8.")?;
+
+    let mut credentials = decoded_credentials.splitn(2, ':');
+
+    let username = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
+        .to_string();
+
+    let password = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A password must be provide in 'Basic' auth."))?
+        .to_string();
+
+    Ok(Credentials {
+        username,
+        password: Secret::new(password),
+    })
+}
+
+/// Users with two-factor authentication enabled pass their current code, or
+/// a recovery code, alongside their 'Basic' credentials.
+///
+/// A code can't be used twice, so a client making several requests, like
+/// uploading assets before publishing, has to wait for the next code between
+/// them. Reused codes are rejected without counting towards a lockout.
+fn two_factor_code(headers: &HeaderMap) -> Result<&str, anyhow::Error> {
+    headers
+        .get("X-TOTP-Code")
+        .context("The 'X-TOTP-Code' header was missing")?
+        .to_str()
+        .context("The 'X-TOTP-Code' header was not a valid UTF8 string.")
+}
@@ -0,0 +1,189 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{authenticate_publisher, BodyData, PublishError};
+use crate::{
+    application::ApplicationBaseUrl,
+    audit::{AuditAction, AuditEvent},
+    domain::{DeliveryStatus, SubscriberEmail},
+    email_client::{EmailClient, EmailDelivery},
+    email_html::EmailHtml,
+    engagement::IssueTracking,
+    metrics::{self, EmailKind},
+    newsletter_asset::load_attachments,
+    publishing::IssueSender,
+    routes::placeholder_preferences_footer,
+    settings::AuthenticationSettings,
+    signing::HmacSecret,
+};
+
+/// Test sends go to a handful of people, not to a mailing list.
+const MAX_TEST_RECIPIENTS: usize = 10;
+
+#[derive(serde::Serialize)]
+struct Preview {
+    subject: String,
+    html: String,
+    text: String,
+    /// What had to change for email clients, and whether the issue is too
+    /// large to be published.
+    warnings: Vec<String>,
+}
+
+/// The HTML and text contents of an issue with a footer like subscribers
+/// get. There are no preferences for it to link to, so it's a placeholder.
+fn render_sample(html_content: &str, text_content: &str, base_url: &str) -> (String, String) {
+    let (html_footer, plain_footer) = placeholder_preferences_footer(base_url);
+
+    (
+        format!("{html_content}{html_footer}"),
+        format!("{text_content}{plain_footer}"),
+    )
+}
+
+/// Renders an issue as a subscriber would receive it, tracked links and
+/// tracking pixel included, without storing or sending it. As the issue
+/// isn't stored, the links lead nowhere.
+#[tracing::instrument(
+    name = "Preview a newsletter issue",
+    skip(body, db_pool, issue_sender, base_url, settings, hmac_secret, request)
+)]
+pub async fn preview_newsletter(
+    body: web::Json<BodyData>,
+    db_pool: web::Data<PgPool>,
+    issue_sender: web::Data<IssueSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    settings: web::Data<AuthenticationSettings>,
+    hmac_secret: web::Data<HmacSecret>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    authenticate_publisher(&request, &db_pool, &settings).await?;
+
+    let content = body.parse(&db_pool).await?;
+    let email_html = EmailHtml::prepare(&content.html);
+
+    let html_content = IssueTracking::new(Uuid::nil(), email_html.html()).track(
+        email_html.html(),
+        &base_url,
+        &hmac_secret,
+        Uuid::nil(),
+    );
+    let (html, text) = render_sample(&html_content, &content.text, &base_url);
+
+    let mut warnings = email_html.warnings().to_vec();
+
+    // Tracking and the footer can push an issue over the limit at which it
+    // can't be published.
+    if let (Ok(()), Err(warning)) = (
+        email_html.check_size(),
+        issue_sender.check_size(&content.html),
+    ) {
+        warnings.push(warning);
+    }
+
+    Ok(HttpResponse::Ok().json(Preview {
+        subject: body.title.clone(),
+        html,
+        text,
+        warnings,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct TestSendBody {
+    #[serde(flatten)]
+    issue: BodyData,
+    recipients: Vec<String>,
+}
+
+impl TestSendBody {
+    fn recipients(&self) -> Result<Vec<SubscriberEmail>, PublishError> {
+        if self.recipients.is_empty() || self.recipients.len() > MAX_TEST_RECIPIENTS {
+            return Err(PublishError::ValidationError(format!(
+                "A test is sent to between 1 and {MAX_TEST_RECIPIENTS} recipients."
+            )));
+        }
+
+        self.recipients
+            .iter()
+            .map(|email| SubscriberEmail::parse(email.clone()))
+            .collect::<Result<_, _>>()
+            .map_err(PublishError::ValidationError)
+    }
+}
+
+#[derive(serde::Serialize)]
+struct TestSendResult {
+    email: String,
+    status: &'static str,
+}
+
+/// Sends an issue to the given addresses only, for its authors to check it
+/// in their own inbox. Nothing is published or stored, and the links
+/// aren't tracked so that they can be followed.
+#[tracing::instrument(
+    name = "Send a test of a newsletter issue",
+    skip(body, db_pool, email_client, base_url, settings, request)
+)]
+pub async fn send_test_newsletter(
+    body: web::Json<TestSendBody>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    settings: web::Data<AuthenticationSettings>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    let publisher = authenticate_publisher(&request, &db_pool, &settings).await?;
+
+    let content = body.issue.parse(&db_pool).await?;
+
+    let recipients = body.recipients()?;
+    let subject = format!("[Test] {}", body.issue.title);
+    let email_html = EmailHtml::prepare(&content.html);
+    let attachments = load_attachments(&db_pool, email_html.html(), &content.attachments).await?;
+    let (html_content, text_content) = render_sample(email_html.html(), &content.text, &base_url);
+    let mut results = Vec::with_capacity(recipients.len());
+
+    for recipient in recipients {
+        let outcome = email_client
+            .send_email_with_attachments(
+                &recipient,
+                &subject,
+                &html_content,
+                &text_content,
+                &attachments,
+            )
+            .await;
+
+        metrics::record_email(EmailKind::Test, &outcome);
+
+        let status = match outcome {
+            Ok(EmailDelivery::Sent { .. }) => DeliveryStatus::Sent,
+            Ok(EmailDelivery::Suppressed) => DeliveryStatus::Suppressed,
+            Err(error) => {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    "Failed to send a test newsletter issue to {}",
+                    recipient
+                );
+                DeliveryStatus::Failed
+            }
+        };
+
+        results.push(TestSendResult {
+            email: recipient.as_ref().to_string(),
+            status: status.as_str(),
+        });
+    }
+
+    AuditEvent::new(AuditAction::NewsletterTestSent)
+        .actor_id(publisher.user_id)
+        .subject(&body.issue.title)
+        .ip_address(publisher.ip_address.as_deref())
+        .details(serde_json::json!({ "recipients": results }))
+        .record(db_pool.get_ref())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "results": results })))
+}
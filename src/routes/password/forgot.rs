@@ -0,0 +1,167 @@
+use actix_web::{http::header::LOCATION, web, HttpResponse};
+use anyhow::Context;
+use maud::Markup;
+use sqlx::PgPool;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use super::send_password_token_email;
+use crate::{
+    application::ApplicationBaseUrl,
+    authentication::{issue_password_token, password_token_recently_issued, PasswordTokenPurpose},
+    csrf::CsrfToken,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    settings::AuthenticationSettings,
+    shutdown::Shutdown,
+    views,
+};
+
+#[derive(serde::Deserialize, Debug)]
+pub struct QueryParams {
+    sent: Option<bool>,
+}
+
+pub async fn forgot_password_form(
+    query: web::Query<QueryParams>,
+    csrf_token: CsrfToken,
+) -> actix_web::Result<Markup> {
+    Ok(views::password::forgot(
+        &csrf_token,
+        query.0.sent.unwrap_or_default(),
+    ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    username: String,
+}
+
+/// Always answers with the same redirect, whether or not `username` matches
+/// an account, so the form can't be used to enumerate users.
+///
+/// The lookup and the email delivery happen in a background task: waiting
+/// for the email provider would otherwise make known usernames noticeably
+/// slower to answer. No email is sent while an unused reset link emailed to
+/// the same user in the last few minutes is still around.
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip(form, db_pool, email_client, base_url, settings, shutdown),
+    fields(username = %form.username)
+)]
+pub async fn forgot_password(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    settings: web::Data<AuthenticationSettings>,
+    shutdown: web::Data<Shutdown>,
+) -> HttpResponse {
+    shutdown.spawn(
+        async move {
+            let outcome = send_password_reset(
+                &form.username,
+                &db_pool,
+                &email_client,
+                &base_url,
+                &settings,
+            )
+            .await;
+
+            if let Err(error) = outcome {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    "Failed to send a password reset email."
+                );
+            }
+        }
+        .instrument(tracing::Span::current()),
+    );
+
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/password/forgot?sent=true"))
+        .finish()
+}
+
+async fn send_password_reset(
+    username: &str,
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    settings: &AuthenticationSettings,
+) -> Result<(), anyhow::Error> {
+    let (user_id, email) = match get_resettable_user(db_pool, username).await? {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    if password_token_recently_issued(
+        &mut transaction,
+        user_id,
+        PasswordTokenPurpose::Reset,
+        settings.password_reset_cooldown(),
+    )
+    .await?
+    {
+        tracing::info!("A password reset email was sent recently, not sending another one.");
+        return Ok(());
+    }
+
+    let token = issue_password_token(
+        &mut transaction,
+        user_id,
+        PasswordTokenPurpose::Reset,
+        settings.password_reset_ttl(),
+    )
+    .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a password reset token.")?;
+
+    send_password_token_email(
+        email_client,
+        &email,
+        base_url,
+        &token,
+        PasswordTokenPurpose::Reset,
+    )
+    .await
+    .context("Failed to send a password reset email.")?;
+
+    Ok(())
+}
+
+/// Only active users we know an email address for can reset their password.
+#[tracing::instrument(name = "Get resettable user", skip(db_pool))]
+async fn get_resettable_user(
+    db_pool: &PgPool,
+    username: &str,
+) -> Result<Option<(Uuid, SubscriberEmail)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT id, email
+            FROM users
+            WHERE username = $1 AND disabled_at IS NULL
+        "#,
+        username,
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve a user to reset their password.")?;
+
+    row.and_then(|r| Some((r.id, r.email?)))
+        .map(|(id, email)| {
+            Ok((
+                id,
+                SubscriberEmail::parse(email).map_err(anyhow::Error::msg)?,
+            ))
+        })
+        .transpose()
+}
@@ -0,0 +1,31 @@
+use actix_web::web;
+use maud::Markup;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use super::SetPasswordError;
+use crate::{authentication::get_user_id_from_password_token, csrf::CsrfToken, views};
+
+#[derive(serde::Deserialize, Debug)]
+pub struct QueryParams {
+    token: Secret<String>,
+    error: Option<String>,
+}
+
+pub async fn set_password_form(
+    query: web::Query<QueryParams>,
+    db_pool: web::Data<PgPool>,
+    csrf_token: CsrfToken,
+) -> Result<Markup, SetPasswordError> {
+    let QueryParams { token, error } = query.into_inner();
+
+    get_user_id_from_password_token(&db_pool, &token)
+        .await?
+        .ok_or(SetPasswordError::InvalidToken)?;
+
+    Ok(views::password::set(
+        &csrf_token,
+        token.expose_secret(),
+        error,
+    ))
+}
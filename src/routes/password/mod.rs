@@ -0,0 +1,99 @@
+mod forgot;
+mod get;
+mod post;
+
+use std::fmt::Debug;
+
+use actix_web::{http::header::ContentType, HttpResponse, ResponseError};
+use reqwest::StatusCode;
+use secrecy::{ExposeSecret, Secret};
+
+pub use forgot::{forgot_password, forgot_password_form};
+pub use get::set_password_form;
+pub use post::set_password;
+
+use crate::{
+    authentication::PasswordTokenPurpose,
+    domain::SubscriberEmail,
+    email_client::{EmailClient, EmailDelivery, SendEmailError},
+    error_chain_fmt,
+    metrics::{self, EmailKind},
+    views,
+};
+
+#[derive(thiserror::Error)]
+pub enum SetPasswordError {
+    #[error("The password link is invalid or has expired.")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for SetPasswordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SetPasswordError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidToken => StatusCode::BAD_REQUEST,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::InvalidToken => HttpResponse::build(self.status_code())
+                .content_type(ContentType::html())
+                .body(views::password::invalid_token().into_string()),
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Send a password token email",
+    skip(email_client, recipient, base_url, token)
+)]
+pub async fn send_password_token_email(
+    email_client: &EmailClient,
+    recipient: &SubscriberEmail,
+    base_url: &str,
+    token: &Secret<String>,
+    purpose: PasswordTokenPurpose,
+) -> Result<EmailDelivery, SendEmailError> {
+    let set_password_link = format!("{base_url}/password/set?token={}", token.expose_secret());
+
+    let (kind, subject, introduction) = match purpose {
+        PasswordTokenPurpose::Invitation => (
+            EmailKind::Invitation,
+            "You have been invited!",
+            "You have been invited to help manage our newsletter.",
+        ),
+        PasswordTokenPurpose::Reset => (
+            EmailKind::PasswordReset,
+            "Reset your password",
+            "A password reset was requested for your account.",
+        ),
+    };
+
+    let html_body = &format!(
+        "{introduction}<br />\
+        Click <a href=\"{set_password_link}\">here</a> to choose your password.",
+    );
+
+    let plain_body = &format!(
+        "{introduction}\n\
+        Visit {set_password_link} to choose your password.",
+    );
+
+    let outcome = email_client
+        .send_email(recipient, subject, html_body, plain_body)
+        .await;
+
+    metrics::record_email(kind, &outcome);
+
+    outcome
+}
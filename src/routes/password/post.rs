@@ -0,0 +1,98 @@
+use actix_web::{http::header::LOCATION, web, HttpResponse};
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::SetPasswordError;
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    authentication::{change_password, consume_password_token},
+    settings::AuthenticationSettings,
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    token: Secret<String>,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(name = "Set a password from a token", skip(form, db_pool, settings))]
+pub async fn set_password(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    settings: web::Data<AuthenticationSettings>,
+) -> Result<HttpResponse, SetPasswordError> {
+    let FormData {
+        token,
+        new_password,
+        new_password_check,
+    } = form.into_inner();
+
+    if let Err(error) = validate_new_password(&new_password, &new_password_check) {
+        let encoded_error = urlencoding::Encoded::new(error);
+
+        return Ok(HttpResponse::SeeOther()
+            .insert_header((
+                LOCATION,
+                format!(
+                    "/password/set?token={}&error={}",
+                    urlencoding::Encoded::new(token.expose_secret()),
+                    encoded_error
+                ),
+            ))
+            .finish());
+    }
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let user_id = consume_password_token(&mut transaction, &token)
+        .await?
+        .ok_or(SetPasswordError::InvalidToken)?;
+
+    change_password(
+        &mut transaction,
+        &settings.password_hashing,
+        user_id,
+        new_password,
+    )
+    .await?;
+
+    AuditEvent::new(AuditAction::UserPasswordSet)
+        .actor_id(user_id)
+        .subject(user_id)
+        .record(&mut transaction)
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to set a password.")?;
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/login"))
+        .finish())
+}
+
+fn validate_new_password(
+    new_password: &Secret<String>,
+    new_password_check: &Secret<String>,
+) -> Result<(), &'static str> {
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        return Err("You entered two different passwords.");
+    }
+
+    let length = new_password.expose_secret().graphemes(true).count();
+
+    if length < 12 {
+        Err("The password must be at least 12 characters long.")
+    } else if length > 128 {
+        Err("The password must be at most 128 characters long.")
+    } else {
+        Ok(())
+    }
+}
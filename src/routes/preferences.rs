@@ -0,0 +1,406 @@
+use std::fmt::Debug;
+
+use actix_web::{
+    http::header::{ContentType, LOCATION},
+    web, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use maud::Markup;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    csrf::CsrfToken,
+    domain::{DeliveryFrequency, SubscriberName},
+    error_chain_fmt,
+    views::{
+        self,
+        preferences::{SubscriberPreferences, TopicPreference},
+    },
+};
+
+const MAX_PAUSE_WEEKS: i64 = 52;
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error("The preferences link is invalid.")]
+    InvalidLink,
+    #[error("{message}")]
+    ValidationError { token: String, message: String },
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidLink => StatusCode::BAD_REQUEST,
+            Self::ValidationError { .. } => StatusCode::SEE_OTHER,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::InvalidLink => HttpResponse::build(self.status_code())
+                .content_type(ContentType::html())
+                .body(views::preferences::invalid_link().into_string()),
+            Self::ValidationError { token, message } => HttpResponse::SeeOther()
+                .insert_header((
+                    LOCATION,
+                    format!(
+                        "/preferences?token={}&error={}",
+                        urlencoding::encode(token),
+                        urlencoding::encode(message)
+                    ),
+                ))
+                .finish(),
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+/// The link to a subscriber's preferences, as HTML and plain text, to end
+/// every email we send them with.
+pub fn preferences_footer(base_url: &str, preferences_token: &str) -> (String, String) {
+    footer_linking_to(&format!("{base_url}/preferences?token={preferences_token}"))
+}
+
+/// Stands in for the preferences footer in emails no subscriber receives,
+/// such as previews and test sends. It links to the home page, as there are
+/// no preferences to link to.
+pub fn placeholder_preferences_footer(base_url: &str) -> (String, String) {
+    footer_linking_to(&format!("{base_url}/"))
+}
+
+fn footer_linking_to(link: &str) -> (String, String) {
+    (
+        format!("<hr /><p><a href=\"{link}\">Manage your subscription preferences</a></p>"),
+        format!("\n\n--\nManage your subscription preferences: {link}"),
+    )
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct QueryParams {
+    token: String,
+    saved: Option<bool>,
+    error: Option<String>,
+}
+
+#[tracing::instrument(name = "Show subscriber preferences", skip(query, db_pool, csrf_token))]
+pub async fn preferences_form(
+    query: web::Query<QueryParams>,
+    db_pool: web::Data<PgPool>,
+    csrf_token: CsrfToken,
+) -> Result<Markup, PreferencesError> {
+    let QueryParams {
+        token,
+        saved,
+        error,
+    } = query.into_inner();
+
+    let (_, preferences) = get_preferences(&db_pool, &token)
+        .await?
+        .ok_or(PreferencesError::InvalidLink)?;
+
+    Ok(views::preferences::get(
+        &csrf_token,
+        &token,
+        &preferences,
+        saved.unwrap_or_default(),
+        error,
+    ))
+}
+
+/// How to change when delivery is paused.
+#[derive(Debug, PartialEq, Eq)]
+enum PauseChange {
+    Keep,
+    Resume,
+    PauseForWeeks(i64),
+}
+
+impl PauseChange {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "" => Ok(Self::Keep),
+            "0" => Ok(Self::Resume),
+            weeks => match weeks.parse() {
+                Ok(weeks) if (1..=MAX_PAUSE_WEEKS).contains(&weeks) => {
+                    Ok(Self::PauseForWeeks(weeks))
+                }
+                _ => Err(format!(
+                    "Delivery can be paused for 1 to {MAX_PAUSE_WEEKS} weeks."
+                )),
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PreferencesUpdate {
+    name: SubscriberName,
+    frequency: DeliveryFrequency,
+    topics: Vec<String>,
+    pause: PauseChange,
+}
+
+#[derive(Debug, Default)]
+struct PreferencesForm {
+    token: String,
+    name: String,
+    frequency: String,
+    topics: Vec<String>,
+    pause_weeks: String,
+}
+
+impl PreferencesForm {
+    /// Topic checkboxes are all named `topics`, so the form is read as a
+    /// list of fields rather than deserialized into a struct.
+    fn from_fields(fields: Vec<(String, String)>) -> Self {
+        let mut form = Self::default();
+
+        for (key, value) in fields {
+            match key.as_str() {
+                "token" => form.token = value,
+                "name" => form.name = value,
+                "frequency" => form.frequency = value,
+                "pause_weeks" => form.pause_weeks = value,
+                "topics" => form.topics.push(value),
+                _ => {}
+            }
+        }
+
+        form
+    }
+
+    fn parse(self) -> Result<PreferencesUpdate, String> {
+        Ok(PreferencesUpdate {
+            name: SubscriberName::parse(self.name)?,
+            frequency: DeliveryFrequency::parse(self.frequency)?,
+            topics: self.topics,
+            pause: PauseChange::parse(&self.pause_weeks)?,
+        })
+    }
+}
+
+#[tracing::instrument(name = "Update subscriber preferences", skip(form, db_pool))]
+pub async fn update_preferences(
+    form: web::Form<Vec<(String, String)>>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let mut form = PreferencesForm::from_fields(form.into_inner());
+    let token = std::mem::take(&mut form.token);
+
+    let (subscriber_id, _) = get_preferences(&db_pool, &token)
+        .await?
+        .ok_or(PreferencesError::InvalidLink)?;
+
+    let update = form
+        .parse()
+        .map_err(|message| PreferencesError::ValidationError {
+            token: token.clone(),
+            message,
+        })?;
+
+    save_preferences(&db_pool, subscriber_id, &update).await?;
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header((
+            LOCATION,
+            format!(
+                "/preferences?token={}&saved=true",
+                urlencoding::encode(&token)
+            ),
+        ))
+        .finish())
+}
+
+#[tracing::instrument(name = "Get subscriber preferences", skip(db_pool, token))]
+async fn get_preferences(
+    db_pool: &PgPool,
+    token: &str,
+) -> Result<Option<(Uuid, SubscriberPreferences)>, anyhow::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+            SELECT id, name, frequency, paused_until
+            FROM subscriptions
+            WHERE preferences_token = $1
+        "#,
+        token
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve subscriber preferences.")?;
+
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => return Ok(None),
+    };
+
+    let topics = sqlx::query_as!(
+        TopicPreference,
+        r#"
+            SELECT t.slug, t.name, o.topic_slug IS NULL AS "subscribed!"
+            FROM topics t
+            LEFT JOIN subscriber_topic_opt_outs o
+                ON o.topic_slug = t.slug AND o.subscriber_id = $1
+            ORDER BY t.name
+        "#,
+        subscriber.id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve the topics of a subscriber.")?;
+
+    let preferences = SubscriberPreferences {
+        name: subscriber.name,
+        frequency: DeliveryFrequency::parse(subscriber.frequency).map_err(anyhow::Error::msg)?,
+        paused_until: subscriber.paused_until,
+        topics,
+    };
+
+    Ok(Some((subscriber.id, preferences)))
+}
+
+#[tracing::instrument(name = "Save subscriber preferences", skip(db_pool))]
+async fn save_preferences(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+    update: &PreferencesUpdate,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let (keep_pause, paused_until) = match update.pause {
+        PauseChange::Keep => (true, None),
+        PauseChange::Resume => (false, None),
+        PauseChange::PauseForWeeks(weeks) => (false, Some(Utc::now() + Duration::weeks(weeks))),
+    };
+
+    sqlx::query!(
+        r#"
+            UPDATE subscriptions
+            SET name = $2,
+                frequency = $3,
+                paused_until = CASE WHEN $4 THEN paused_until ELSE $5 END
+            WHERE id = $1
+        "#,
+        subscriber_id,
+        update.name.as_ref(),
+        update.frequency.as_ref(),
+        keep_pause,
+        paused_until,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update subscriber preferences.")?;
+
+    sqlx::query!(
+        r#"DELETE FROM subscriber_topic_opt_outs WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to clear the topic opt-outs of a subscriber.")?;
+
+    sqlx::query!(
+        r#"
+            INSERT INTO subscriber_topic_opt_outs (subscriber_id, topic_slug)
+            SELECT $1, slug
+            FROM topics
+            WHERE slug <> ALL($2)
+        "#,
+        subscriber_id,
+        &update.topics[..],
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the topic opt-outs of a subscriber.")?;
+
+    AuditEvent::new(AuditAction::SubscriberPreferencesUpdated)
+        .subject(subscriber_id)
+        .record(&mut transaction)
+        .await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update subscriber preferences.")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PauseChange, PreferencesForm};
+
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn pauses_are_parsed() {
+        assert_eq!(PauseChange::parse(""), Ok(PauseChange::Keep));
+        assert_eq!(PauseChange::parse("0"), Ok(PauseChange::Resume));
+        assert_eq!(PauseChange::parse("4"), Ok(PauseChange::PauseForWeeks(4)));
+    }
+
+    #[test]
+    fn pauses_out_of_range_are_rejected() {
+        assert!(PauseChange::parse("-1").is_err());
+        assert!(PauseChange::parse("53").is_err());
+        assert!(PauseChange::parse("forever").is_err());
+    }
+
+    #[test]
+    fn every_checked_topic_is_kept() {
+        let form = PreferencesForm::from_fields(fields(&[
+            ("token", "abc"),
+            ("name", "Ursula"),
+            ("frequency", "weekly"),
+            ("topics", "rust"),
+            ("topics", "sql"),
+            ("pause_weeks", ""),
+        ]));
+
+        assert_eq!(form.token, "abc");
+        assert_eq!(form.parse().unwrap().topics, ["rust", "sql"]);
+    }
+
+    #[test]
+    fn invalid_names_are_rejected() {
+        let form = PreferencesForm::from_fields(fields(&[
+            ("token", "abc"),
+            ("name", "<script>"),
+            ("frequency", "weekly"),
+        ]));
+
+        assert!(form.parse().is_err());
+    }
+
+    #[test]
+    fn unknown_frequencies_are_rejected() {
+        let form = PreferencesForm::from_fields(fields(&[
+            ("token", "abc"),
+            ("name", "Ursula"),
+            ("frequency", "hourly"),
+        ]));
+
+        assert!(form.parse().is_err());
+    }
+}
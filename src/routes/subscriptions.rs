@@ -1,18 +1,22 @@
 use std::fmt::{self, Debug, Display};
 
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{http::header, web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
 use chrono::Utc;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use reqwest::StatusCode;
-use sqlx::{PgPool, Postgres, Transaction};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use super::preferences_footer;
 use crate::{
     application::ApplicationBaseUrl,
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
-    email_client::EmailClient,
+    audit::{AuditAction, AuditEvent},
+    client_ip::client_ip,
+    domain::{ConsentEvent, NewSubscriber, SubscriberEmail, SubscriberName},
+    email_client::{EmailClient, EmailDelivery, SendEmailError},
     error_chain_fmt,
+    metrics::{self, EmailKind, SubscriptionEvent},
 };
 
 pub struct StoreTokenError(sqlx::Error);
@@ -66,6 +70,8 @@ impl ResponseError for SubscribeError {
 pub struct SubscriptionFormData {
     email: String,
     name: String,
+    /// Which of our sign-up forms was used, e.g. "footer" or "blog-post".
+    source: Option<String>,
 }
 
 impl TryFrom<SubscriptionFormData> for NewSubscriber {
@@ -80,7 +86,7 @@ impl TryFrom<SubscriptionFormData> for NewSubscriber {
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, db_pool, email_client, base_url),
+    skip(form, db_pool, email_client, base_url, request),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
@@ -91,17 +97,20 @@ pub async fn subscribe(
     db_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    request: HttpRequest,
 ) -> Result<HttpResponse, SubscribeError> {
     use SubscribeError::*;
 
-    let new_subscriber = form.0.try_into().map_err(ValidationError)?;
+    let mut form = form.into_inner();
+    let consent = ConsentContext::from_request(&request, form.source.take());
+    let new_subscriber = form.try_into().map_err(ValidationError)?;
 
     let mut transaction = db_pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
 
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let (subscriber_id, preferences_token) = insert_subscriber(&mut transaction, &new_subscriber)
         .await
         .context("Failed to insert new subscriber in the database.")?;
 
@@ -111,28 +120,105 @@ pub async fn subscribe(
         .await
         .context("Failed to store the confirmation token for a new subscriber.")?;
 
+    record_consent(
+        &mut transaction,
+        subscriber_id,
+        ConsentEvent::Subscribed,
+        &consent,
+    )
+    .await?;
+
+    AuditEvent::new(AuditAction::SubscriberCreated)
+        .subject(subscriber_id)
+        .record(&mut transaction)
+        .await?;
+
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
 
-    send_confirmation_email(&email_client, new_subscriber, &base_url, subscription_token)
-        .await
-        .context("Failed to send a confirmation email.")?;
+    metrics::record_subscription(SubscriptionEvent::Created);
+
+    send_confirmation_email(
+        &email_client,
+        new_subscriber,
+        &base_url,
+        subscription_token,
+        &preferences_token,
+    )
+    .await
+    .context("Failed to send a confirmation email.")?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Where a consent was given from.
+#[derive(Debug)]
+pub struct ConsentContext {
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    form_source: Option<String>,
+}
+
+impl ConsentContext {
+    /// Forms which don't say where they are from are identified by the page
+    /// they were submitted from.
+    pub fn from_request(request: &HttpRequest, form_source: Option<String>) -> Self {
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+
+        Self {
+            ip_address: client_ip(request),
+            user_agent: header(header::USER_AGENT),
+            form_source: form_source
+                .filter(|source| !source.trim().is_empty())
+                .or_else(|| header(header::REFERER)),
+        }
+    }
+}
+
+#[tracing::instrument(name = "Record consent", skip(executor))]
+pub async fn record_consent<'e, E: PgExecutor<'e>>(
+    executor: E,
+    subscriber_id: Uuid,
+    event: ConsentEvent,
+    consent: &ConsentContext,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO consent_records (subscriber_id, event, ip_address, user_agent, form_source)
+            VALUES ($1, $2, $3, $4, $5)
+        "#,
+        subscriber_id,
+        event.as_ref(),
+        consent.ip_address,
+        consent.user_agent,
+        consent.form_source,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to record consent.")?;
+
+    Ok(())
+}
+
+/// Returns the new subscriber's id and preferences token.
 #[tracing::instrument(name = "Saving new subscriber", skip(transaction, new_subscriber))]
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
+) -> Result<(Uuid, String), sqlx::Error> {
     let record = sqlx::query!(
         r#"
             INSERT INTO subscriptions (email, name, subscribed_at, status)
             VALUES($1, $2, $3, 'pending_confirmation')
-            RETURNING id
+            RETURNING id, preferences_token
         "#,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
@@ -145,35 +231,43 @@ pub async fn insert_subscriber(
         e
     })?;
 
-    Ok(record.id)
+    Ok((record.id, record.preferences_token))
 }
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber)
+    skip(email_client, new_subscriber, preferences_token)
 )]
 pub async fn send_confirmation_email(
     email_client: &web::Data<EmailClient>,
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+    preferences_token: &str,
+) -> Result<EmailDelivery, SendEmailError> {
+    let (html_footer, plain_footer) = preferences_footer(base_url, preferences_token);
     let confirmation_link =
         format!("{base_url}/subscriptions/confirm?subscription_token={subscription_token}");
 
     let html_body = &format!(
         "Welcome to our newsletter!<br />\
-        Click <a href=\"{confirmation_link}\">here</a> to confirm your subscription.",
+        Click <a href=\"{confirmation_link}\">here</a> to confirm your subscription.\
+        {html_footer}",
     );
 
     let plain_body = &format!(
         "Welcome to our newsletter!<br />\
-        Visit {confirmation_link} to confirm your subscription.",
+        Visit {confirmation_link} to confirm your subscription.\
+        {plain_footer}",
     );
 
-    email_client
+    let outcome = email_client
         .send_email(&new_subscriber.email, "Welcome!", html_body, plain_body)
-        .await
+        .await;
+
+    metrics::record_email(EmailKind::Confirmation, &outcome);
+
+    outcome
 }
 
 #[tracing::instrument(
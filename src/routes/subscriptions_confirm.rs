@@ -1,14 +1,26 @@
-use actix_web::{web, HttpResponse};
-use sqlx::PgPool;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use super::{record_consent, ConsentContext};
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    domain::ConsentEvent,
+    metrics::{self, SubscriptionEvent},
+};
+
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
-pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
+#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool, request))]
+pub async fn confirm(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
     let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
         Ok(id) => id,
         Err(_) => return HttpResponse::InternalServerError().finish(),
@@ -17,7 +29,10 @@ pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>
     match id {
         None => HttpResponse::Unauthorized().finish(),
         Some(subscriber_id) => {
-            if confirm_subscriber(&pool, subscriber_id).await.is_err() {
+            let consent = ConsentContext::from_request(&request, None);
+
+            if let Err(error) = confirm_with_consent(&pool, subscriber_id, &consent).await {
+                tracing::error!(error.cause_chain = ?error, "Failed to confirm a subscriber.");
                 return HttpResponse::InternalServerError().finish();
             }
 
@@ -26,19 +41,72 @@ pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>
     }
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+/// Following the link again is harmless, but only the first confirmation is
+/// recorded as consent.
+async fn confirm_with_consent(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    consent: &ConsentContext,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let newly_confirmed = confirm_subscriber(&mut transaction, subscriber_id).await?;
+
+    if newly_confirmed {
+        record_consent(
+            &mut transaction,
+            subscriber_id,
+            ConsentEvent::Confirmed,
+            consent,
+        )
+        .await?;
+
+        AuditEvent::new(AuditAction::SubscriberConfirmed)
+            .subject(subscriber_id)
+            .record(&mut transaction)
+            .await?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+
+    if newly_confirmed {
+        metrics::record_subscription(SubscriptionEvent::Confirmed);
+    }
+
+    Ok(())
+}
+
+/// Returns whether the subscriber was still pending confirmation.
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, transaction)
+)]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+            UPDATE subscriptions
+            SET status = 'confirmed'
+            WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
         subscriber_id
     )
-    .execute(pool)
+    .execute(transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
-    Ok(())
+
+    Ok(result.rows_affected() == 1)
 }
 
 #[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
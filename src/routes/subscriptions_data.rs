@@ -0,0 +1,228 @@
+use std::fmt::Debug;
+
+use actix_web::{
+    http::header::{ContentType, LOCATION},
+    web, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use maud::Markup;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use tracing::Instrument;
+
+use super::preferences_footer;
+use crate::{
+    application::ApplicationBaseUrl,
+    csrf::CsrfToken,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    error_chain_fmt,
+    metrics::{self, EmailKind},
+    shutdown::Shutdown,
+    signing::HmacSecret,
+    subscriber_data::{
+        erase_subscriber_data, export_subscriber_data, ErasureRequester, SubscriberDataLink,
+    },
+    views,
+};
+
+const DATA_LINK_TTL_HOURS: i64 = 24;
+/// How long after a data link no other one is emailed to the same address.
+const DATA_LINK_COOLDOWN_MINUTES: i64 = 5;
+
+#[derive(thiserror::Error)]
+pub enum SubscriberDataError {
+    #[error("The link is invalid or has expired.")]
+    InvalidLink,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for SubscriberDataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscriberDataError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidLink => StatusCode::BAD_REQUEST,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::InvalidLink => HttpResponse::build(self.status_code())
+                .content_type(ContentType::html())
+                .body(views::subscriber_data::invalid_link().into_string()),
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct RequestQueryParams {
+    sent: Option<bool>,
+}
+
+pub async fn subscriber_data_request_form(
+    query: web::Query<RequestQueryParams>,
+    csrf_token: CsrfToken,
+) -> actix_web::Result<Markup> {
+    Ok(views::subscriber_data::request(
+        &csrf_token,
+        query.0.sent.unwrap_or_default(),
+    ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RequestFormData {
+    email: String,
+}
+
+/// Answers the same whether or not we know the address, so the form can't be
+/// used to find out who is subscribed, and sends the email in the background
+/// for the answer not to take longer when we do. An address is sent at most
+/// one link every few minutes, however many times it's asked for.
+#[tracing::instrument(
+    name = "Request subscriber data",
+    skip(form, db_pool, email_client, base_url, hmac_secret, shutdown)
+)]
+pub async fn request_subscriber_data(
+    form: web::Form<RequestFormData>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    shutdown: web::Data<Shutdown>,
+) -> HttpResponse {
+    shutdown.spawn(
+        async move {
+            let outcome = send_subscriber_data_link(
+                form.0.email,
+                &db_pool,
+                &email_client,
+                &base_url,
+                &hmac_secret,
+            )
+            .await;
+
+            if let Err(error) = outcome {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    "Failed to send a subscriber data link."
+                );
+            }
+        }
+        .instrument(tracing::Span::current()),
+    );
+
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/subscriptions/data/request?sent=true"))
+        .finish()
+}
+
+async fn send_subscriber_data_link(
+    email: String,
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &HmacSecret,
+) -> Result<(), anyhow::Error> {
+    let email = match SubscriberEmail::parse(email) {
+        Ok(email) => email,
+        Err(_) => return Ok(()),
+    };
+
+    // Claiming the link in the same statement as the lookup keeps concurrent
+    // requests from both sending one
+    let subscriber = sqlx::query!(
+        r#"
+            UPDATE subscriptions
+            SET data_link_sent_at = now()
+            WHERE email = $1
+                AND (data_link_sent_at IS NULL OR data_link_sent_at < $2)
+            RETURNING preferences_token
+        "#,
+        email.as_ref(),
+        Utc::now() - Duration::minutes(DATA_LINK_COOLDOWN_MINUTES),
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to look up a subscriber.")?;
+
+    let preferences_token = match subscriber {
+        Some(subscriber) => subscriber.preferences_token,
+        None => return Ok(()),
+    };
+    let (html_footer, plain_footer) = preferences_footer(base_url, &preferences_token);
+
+    let link = SubscriberDataLink::issue(hmac_secret, &email, Duration::hours(DATA_LINK_TTL_HOURS))
+        .url(base_url);
+
+    let html_body = &format!(
+        "You asked to see the data we hold about you.<br />\
+        Click <a href=\"{link}\">here</a> to see it, or to have it erased.\
+        {html_footer}",
+    );
+
+    let plain_body = &format!(
+        "You asked to see the data we hold about you.\n\
+        Visit {link} to see it, or to have it erased.\
+        {plain_footer}",
+    );
+
+    let outcome = email_client
+        .send_email(&email, "Your data", html_body, plain_body)
+        .await;
+
+    metrics::record_email(EmailKind::SubscriberData, &outcome);
+
+    outcome.context("Failed to send a subscriber data email.")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Show subscriber data",
+    skip(link, db_pool, hmac_secret, csrf_token)
+)]
+pub async fn subscriber_data(
+    link: web::Query<SubscriberDataLink>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    csrf_token: CsrfToken,
+) -> Result<Markup, SubscriberDataError> {
+    let email = link
+        .verify(&hmac_secret)
+        .ok_or(SubscriberDataError::InvalidLink)?;
+
+    let data = export_subscriber_data(&db_pool, &email).await?;
+
+    Ok(views::subscriber_data::show(
+        &csrf_token,
+        &link,
+        data.as_ref(),
+    ))
+}
+
+#[tracing::instrument(
+    name = "Erase subscriber data on request",
+    skip(link, db_pool, hmac_secret)
+)]
+pub async fn erase_own_subscriber_data(
+    link: web::Form<SubscriberDataLink>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<Markup, SubscriberDataError> {
+    let email = link
+        .verify(&hmac_secret)
+        .ok_or(SubscriberDataError::InvalidLink)?;
+
+    erase_subscriber_data(&db_pool, &hmac_secret, &email, ErasureRequester::Subscriber).await?;
+
+    Ok(views::subscriber_data::erased())
+}
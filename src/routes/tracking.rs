@@ -0,0 +1,110 @@
+use std::fmt::Debug;
+
+use actix_web::{
+    http::header::{CacheControl, CacheDirective, ContentType, LOCATION},
+    web, HttpResponse, ResponseError,
+};
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    engagement::{record_click, record_open, TrackingQuery},
+    error_chain_fmt,
+    signing::HmacSecret,
+};
+
+/// A transparent, 1x1 GIF.
+const TRACKING_PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x01, 0x44, 0x00, 0x3b,
+];
+
+#[derive(thiserror::Error)]
+pub enum TrackingError {
+    #[error("The link is invalid.")]
+    InvalidLink,
+    #[error("There is no such link.")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for TrackingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for TrackingError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidLink => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::InvalidLink | Self::NotFound => HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+/// The tracking pixel is served whatever happens, so that mail clients
+/// don't show a broken image. Only opens with a valid signature are
+/// recorded.
+#[tracing::instrument(name = "Track a newsletter open", skip(query, db_pool, hmac_secret))]
+pub async fn track_open(
+    issue_id: web::Path<Uuid>,
+    query: web::Query<TrackingQuery>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let issue_id = issue_id.into_inner();
+
+    if query.verify_open(&hmac_secret, issue_id) {
+        if let Err(error) = record_open(&db_pool, issue_id, query.subscriber_id).await {
+            tracing::error!(error.cause_chain = ?error, "Failed to record a newsletter open");
+        }
+    } else {
+        tracing::warn!("Ignoring a newsletter open with an invalid signature");
+    }
+
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![
+            CacheDirective::NoStore,
+            CacheDirective::Private,
+        ]))
+        .body(TRACKING_PIXEL)
+}
+
+/// Sends the reader on to where the link leads. Tracked links only ever
+/// redirect to the links of a published issue.
+#[tracing::instrument(name = "Track a newsletter click", skip(query, db_pool, hmac_secret))]
+pub async fn track_click(
+    link_id: web::Path<Uuid>,
+    query: web::Query<TrackingQuery>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, TrackingError> {
+    let link_id = link_id.into_inner();
+
+    if !query.verify_click(&hmac_secret, link_id) {
+        return Err(TrackingError::InvalidLink);
+    }
+
+    let url = record_click(&db_pool, link_id, query.subscriber_id)
+        .await?
+        .ok_or(TrackingError::NotFound)?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .finish())
+}
@@ -0,0 +1,295 @@
+use std::{fmt::Debug, time::Duration};
+
+use actix_web::{
+    http::header::{ContentType, HeaderMap},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    delivery::mark_undelivered,
+    domain::{DeliveryStatus, EmailEventType},
+    error_chain_fmt,
+    event_webhook::{EventWebhookVerifier, SIGNATURE_HEADER, TIMESTAMP_HEADER},
+};
+
+/// SendGrid posts events in batches of up to a few thousand, well over the
+/// default limit of a request body.
+pub const MAX_EVENT_BATCH_BYTES: usize = 5 * 1024 * 1024;
+
+/// How far from now a batch can have been signed. SendGrid signs each
+/// attempt afresh, so a batch signed long ago is being replayed.
+const MAX_SIGNATURE_AGE: Duration = Duration::from_secs(5 * 60);
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("The request is not signed by the email provider.")]
+    InvalidSignature(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidSignature(_) => StatusCode::UNAUTHORIZED,
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::InvalidSignature(_) | Self::ValidationError(_) => {
+                HttpResponse::build(self.status_code())
+                    .content_type(ContentType::plaintext())
+                    .body(self.to_string())
+            }
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+/// An event as posted by SendGrid. Only the fields we act on are read, the
+/// whole event is stored as well.
+#[derive(serde::Deserialize, Debug)]
+struct SendGridEvent {
+    email: String,
+    timestamp: i64,
+    event: String,
+    sg_event_id: Option<String>,
+    sg_message_id: Option<String>,
+    reason: Option<String>,
+    /// `bounce` for hard bounces, `blocked` when the receiving server
+    /// refused the message for now.
+    #[serde(rename = "type")]
+    bounce_type: Option<String>,
+}
+
+/// Receives batches of events from SendGrid's signed event webhook.
+///
+/// Hard bounces and spam complaints take the subscriber out of the
+/// `confirmed` status, so they no longer receive newsletters. Bounced and
+/// dropped newsletter issues show up in their delivery report. Batches are
+/// retried by SendGrid until we answer with a success, and events we
+/// already recorded are skipped. Batches signed more than a few minutes
+/// ago are turned away as replays.
+#[tracing::instrument(
+    name = "Receive SendGrid events",
+    skip(request, body, db_pool, verifier)
+)]
+pub async fn sendgrid_events(
+    request: HttpRequest,
+    body: web::Bytes,
+    db_pool: web::Data<PgPool>,
+    verifier: web::Data<Option<EventWebhookVerifier>>,
+) -> Result<HttpResponse, WebhookError> {
+    verify_signature(verifier.as_ref().as_ref(), request.headers(), &body)
+        .map_err(WebhookError::InvalidSignature)?;
+
+    let events: Vec<serde_json::Value> = serde_json::from_slice(&body)
+        .map_err(|e| WebhookError::ValidationError(format!("Invalid event batch: {e}")))?;
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    for payload in events {
+        let event: SendGridEvent = serde_json::from_value(payload.clone())
+            .map_err(|e| WebhookError::ValidationError(format!("Invalid event: {e}")))?;
+
+        let event_type = match EmailEventType::parse(event.event.clone()) {
+            Ok(event_type) => event_type,
+            Err(_) => continue,
+        };
+
+        record_email_event(&mut transaction, &event, event_type, payload).await?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to record email events.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn verify_signature(
+    verifier: Option<&EventWebhookVerifier>,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(), anyhow::Error> {
+    let verifier = verifier.context("The event webhook public key is not configured.")?;
+    let header = |name| {
+        headers
+            .get(name)
+            .with_context(|| format!("The '{name}' header is missing."))?
+            .to_str()
+            .with_context(|| format!("The '{name}' header is not a valid string."))
+    };
+
+    let signature = header(SIGNATURE_HEADER)?;
+    let timestamp = header(TIMESTAMP_HEADER)?;
+
+    anyhow::ensure!(
+        verifier.verify(timestamp, body, signature),
+        "The signature does not match."
+    );
+
+    let signed_at: i64 = timestamp
+        .parse()
+        .context("The signed timestamp is not a number of seconds.")?;
+
+    anyhow::ensure!(
+        Utc::now().timestamp().abs_diff(signed_at) <= MAX_SIGNATURE_AGE.as_secs(),
+        "The batch was signed at {signed_at}, too long ago to be anything but a replay."
+    );
+
+    Ok(())
+}
+
+/// SendGrid gives every event an id, to tell those sent again apart.
+/// Should one come without it, the event itself stands in for it.
+fn provider_event_id(event: &SendGridEvent, payload: &serde_json::Value) -> String {
+    match &event.sg_event_id {
+        Some(event_id) => event_id.clone(),
+        // Object keys are sorted, so the same event hashes the same
+        None => format!("sha256:{:x}", Sha256::digest(payload.to_string())),
+    }
+}
+
+#[tracing::instrument(name = "Record an email event", skip(transaction, payload))]
+async fn record_email_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    event: &SendGridEvent,
+    event_type: EmailEventType,
+    payload: serde_json::Value,
+) -> Result<(), anyhow::Error> {
+    let occurred_at: DateTime<Utc> = Utc
+        .timestamp_opt(event.timestamp, 0)
+        .single()
+        .unwrap_or_else(Utc::now);
+
+    let recorded = sqlx::query!(
+        r#"
+            INSERT INTO email_events (
+                provider_event_id,
+                provider_message_id,
+                subscriber_id,
+                email,
+                event,
+                occurred_at,
+                reason,
+                payload
+            )
+            VALUES (
+                $1,
+                $2,
+                (SELECT id FROM subscriptions WHERE email = $3),
+                $3,
+                $4,
+                $5,
+                $6,
+                $7
+            )
+            ON CONFLICT (provider_event_id) DO NOTHING
+            RETURNING subscriber_id
+        "#,
+        provider_event_id(event, &payload),
+        event.sg_message_id,
+        event.email,
+        event_type.as_ref(),
+        occurred_at,
+        event.reason,
+        payload,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to record an email event.")?;
+
+    // Already recorded
+    let recorded = match recorded {
+        Some(recorded) => recorded,
+        None => return Ok(()),
+    };
+
+    if let Some(message_id) = &event.sg_message_id {
+        let status = match event_type {
+            EmailEventType::Bounce => Some(DeliveryStatus::Bounced),
+            EmailEventType::Dropped => Some(DeliveryStatus::Failed),
+            _ => None,
+        };
+
+        if let Some(status) = status {
+            mark_undelivered(transaction, message_id, status, event.reason.as_deref()).await?;
+        }
+    }
+
+    // About someone who isn't a subscriber
+    let subscriber_id = match recorded.subscriber_id {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(()),
+    };
+
+    match event_type {
+        EmailEventType::Bounce if event.bounce_type.as_deref() != Some("blocked") => {
+            mark_subscriber(transaction, subscriber_id, "bounced", event).await
+        }
+        EmailEventType::SpamReport => {
+            mark_subscriber(transaction, subscriber_id, "complained", event).await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A complaint trumps a bounce, but never the other way around.
+async fn mark_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    status: &str,
+    event: &SendGridEvent,
+) -> Result<(), anyhow::Error> {
+    let updated = sqlx::query!(
+        r#"
+            UPDATE subscriptions
+            SET status = $2
+            WHERE id = $1 AND status NOT IN ($2, 'complained')
+        "#,
+        subscriber_id,
+        status,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to update the status of a subscriber.")?;
+
+    if updated.rows_affected() == 0 {
+        return Ok(());
+    }
+
+    let action = if status == "complained" {
+        AuditAction::SubscriberComplained
+    } else {
+        AuditAction::SubscriberBounced
+    };
+
+    AuditEvent::new(action)
+        .subject(subscriber_id)
+        .details(serde_json::json!({ "reason": event.reason }))
+        .record(&mut *transaction)
+        .await
+}
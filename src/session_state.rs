@@ -0,0 +1,66 @@
+use std::future::{ready, Ready};
+
+use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use uuid::Uuid;
+
+/// A thin wrapper around `Session` exposing only the keys we rely on.
+pub struct TypedSession(Session);
+
+impl TypedSession {
+    const USER_ID_KEY: &'static str = "user_id";
+    const PENDING_TWO_FACTOR_USER_ID_KEY: &'static str = "pending_two_factor_user_id";
+    const SESSION_VERSION_KEY: &'static str = "session_version";
+
+    pub fn renew(&self) {
+        self.0.renew();
+    }
+
+    pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::USER_ID_KEY, user_id)
+    }
+
+    pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::USER_ID_KEY)
+    }
+
+    /// The version of the user's sessions when they entered their password.
+    /// The session is no longer valid once the user's is bumped.
+    pub fn insert_session_version(&self, version: i32) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_VERSION_KEY, version)
+    }
+
+    pub fn get_session_version(&self) -> Result<Option<i32>, SessionGetError> {
+        self.0.get(Self::SESSION_VERSION_KEY)
+    }
+
+    /// Remember a user who got their password right but still has to provide
+    /// their second factor. They aren't logged in until they do.
+    pub fn insert_pending_two_factor_user_id(
+        &self,
+        user_id: Uuid,
+    ) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_TWO_FACTOR_USER_ID_KEY, user_id)
+    }
+
+    pub fn get_pending_two_factor_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::PENDING_TWO_FACTOR_USER_ID_KEY)
+    }
+
+    pub fn remove_pending_two_factor_user_id(&self) {
+        self.0.remove(Self::PENDING_TWO_FACTOR_USER_ID_KEY);
+    }
+
+    pub fn log_out(self) {
+        self.0.purge()
+    }
+}
+
+impl FromRequest for TypedSession {
+    type Error = <Session as FromRequest>::Error;
+    type Future = Ready<Result<TypedSession, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(TypedSession(req.get_session())))
+    }
+}
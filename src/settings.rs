@@ -1,10 +1,14 @@
 use std::{env, time::Duration};
 
+use argon2::Params;
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, NaiveTime, Utc, Weekday};
+
 use config::{Config, ConfigError, Environment, File};
-use secrecy::Secret;
+use ipnet::IpNet;
+use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 
-use crate::domain::SubscriberEmail;
+use crate::{domain::SubscriberEmail, event_webhook::EventWebhookVerifier};
 
 #[derive(serde::Deserialize, Debug)]
 pub enum Env {
@@ -45,10 +49,17 @@ impl From<Result<String, env::VarError>> for Env {
 #[derive(serde::Deserialize, Debug)]
 pub struct Settings {
     pub application: ApplicationSettings,
+    pub authentication: AuthenticationSettings,
     pub database: DatabaseSettings,
     pub email_client: EmailClientSettings,
+    pub newsletter: NewsletterSettings,
+    #[serde(default)]
+    pub telemetry: TelemetrySettings,
 }
 
+/// What `actix_web::cookie::Key::from` needs to derive a signing key.
+const MIN_HMAC_SECRET_BYTES: usize = 64;
+
 #[derive(serde::Deserialize, Debug)]
 pub struct ApplicationSettings {
     env: String,
@@ -56,12 +67,115 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    /// How long requests in flight and background tasks get to finish,
+    /// once asked to stop.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_timeout_seconds: u64,
+    /// The networks of the load balancers and reverse proxies in front of
+    /// the application, whose `X-Forwarded-For` headers are believed.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpNet>,
+    /// The bearer token Prometheus scrapes `/metrics` with. Scrapes are
+    /// rejected until it's set.
+    pub metrics_token: Option<Secret<String>>,
 }
 
 impl ApplicationSettings {
+    fn validate(&self) -> Result<(), String> {
+        if self.hmac_secret.expose_secret().len() < MIN_HMAC_SECRET_BYTES {
+            return Err(format!(
+                "The HMAC secret must be at least {MIN_HMAC_SECRET_BYTES} bytes long."
+            ));
+        }
+
+        Ok(())
+    }
+
     pub fn env(&self) -> Env {
         self.env.as_str().into()
     }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_seconds)
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct AuthenticationSettings {
+    pub invitation_ttl_hours: i64,
+    pub password_reset_ttl_minutes: i64,
+    /// How long after a password reset email no other one is sent to the
+    /// same user, for the form not to be used to flood their inbox.
+    pub password_reset_cooldown_minutes: i64,
+    pub password_hashing: PasswordHashingSettings,
+    pub throttling: LoginThrottlingSettings,
+}
+
+impl AuthenticationSettings {
+    pub fn invitation_ttl(&self) -> ChronoDuration {
+        ChronoDuration::hours(self.invitation_ttl_hours)
+    }
+
+    pub fn password_reset_ttl(&self) -> ChronoDuration {
+        ChronoDuration::minutes(self.password_reset_ttl_minutes)
+    }
+
+    pub fn password_reset_cooldown(&self) -> ChronoDuration {
+        ChronoDuration::minutes(self.password_reset_cooldown_minutes)
+    }
+}
+
+/// Argon2id cost parameters for new password hashes. Existing hashes are
+/// upgraded to them the next time their owner logs in.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct PasswordHashingSettings {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl PasswordHashingSettings {
+    pub fn params(&self) -> Result<Params, argon2::Error> {
+        Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+    }
+}
+
+/// Failed logins are counted per username and per IP address over a sliding
+/// window. Every failure delays the next attempt a bit more, and reaching a
+/// threshold locks the username or IP address out for a while.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct LoginThrottlingSettings {
+    pub window_seconds: i64,
+    pub max_failures_per_username: i64,
+    pub max_failures_per_ip: i64,
+    pub lockout_seconds: i64,
+    pub delay_base_milliseconds: u64,
+    pub delay_max_milliseconds: u64,
+}
+
+impl LoginThrottlingSettings {
+    pub fn window(&self) -> ChronoDuration {
+        ChronoDuration::seconds(self.window_seconds)
+    }
+
+    pub fn lockout(&self) -> ChronoDuration {
+        ChronoDuration::seconds(self.lockout_seconds)
+    }
+
+    /// Exponential backoff based on the number of recent failures.
+    pub fn delay(&self, failures: i64) -> Duration {
+        if failures <= 0 {
+            return Duration::ZERO;
+        }
+
+        let exponent = (failures - 1).min(16) as u32;
+        let delay = self
+            .delay_base_milliseconds
+            .saturating_mul(2u64.pow(exponent));
+
+        Duration::from_millis(delay.min(self.delay_max_milliseconds))
+    }
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -70,6 +184,12 @@ pub struct EmailClientSettings {
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    /// SendGrid's public key for its signed event webhook. Events are
+    /// rejected until it's set.
+    pub webhook_public_key: Option<String>,
+    /// Whether `/health/ready` also checks that SendGrid accepts our API key.
+    #[serde(default)]
+    pub readiness_check: bool,
 }
 
 impl EmailClientSettings {
@@ -80,6 +200,65 @@ impl EmailClientSettings {
     pub fn timeout(&self) -> Duration {
         Duration::from_millis(self.timeout_milliseconds)
     }
+
+    pub fn event_webhook_verifier(&self) -> Result<Option<EventWebhookVerifier>, String> {
+        self.webhook_public_key
+            .as_deref()
+            .map(EventWebhookVerifier::from_public_key)
+            .transpose()
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct NewsletterSettings {
+    /// How often scheduled issues and weekly digests that are due get sent.
+    pub scheduler_interval_milliseconds: u64,
+    /// When weekly digests go out, in UTC.
+    pub digest_weekday: Weekday,
+    pub digest_hour: u32,
+}
+
+impl NewsletterSettings {
+    fn validate(&self) -> Result<(), String> {
+        if self.digest_hour > 23 {
+            return Err(format!(
+                "The digest hour must be between 0 and 23, not {}.",
+                self.digest_hour
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn scheduler_interval(&self) -> Duration {
+        Duration::from_millis(self.scheduler_interval_milliseconds)
+    }
+
+    /// The last time weekly digests were due, up to `now`. Issues published
+    /// since wait for the next digest.
+    pub fn last_digest_time(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let days_since_digest_day = (now.weekday().num_days_from_monday() + 7
+            - self.digest_weekday.num_days_from_monday())
+            % 7;
+        let digest_time = NaiveTime::from_hms_opt(self.digest_hour, 0, 0)
+            .expect("The digest hour must be between 0 and 23.");
+        let digest_date =
+            now.naive_utc().date() - ChronoDuration::days(days_since_digest_day.into());
+        let last_digest_time = DateTime::from_utc(digest_date.and_time(digest_time), Utc);
+
+        if last_digest_time > now {
+            last_digest_time - ChronoDuration::weeks(1)
+        } else {
+            last_digest_time
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct TelemetrySettings {
+    /// Where an OpenTelemetry collector accepts OTLP over HTTP, such as
+    /// `http://localhost:4318`. Spans aren't exported until it's set.
+    pub otlp_endpoint: Option<String>,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -94,14 +273,130 @@ impl Settings {
         let config_dir = base_path.join("config");
         let app_env: Env = env::var("APPLICATION_ENV").into();
 
-        Config::builder()
+        let settings: Self = Config::builder()
             .add_source(File::from(config_dir.join("base")).required(true))
             .add_source(File::from(config_dir.join(app_env.as_str())).required(true))
-            .add_source(Environment::default().separator("_"))
-            .set_override("application.env", app_env.as_str())
-            .unwrap()
-            .build()
-            .unwrap()
-            .try_deserialize()
+            // e.g. `APP_APPLICATION__HMAC_SECRET` sets `application.hmac_secret`
+            .add_source(
+                Environment::with_prefix("APP")
+                    .prefix_separator("_")
+                    .separator("__"),
+            )
+            .set_override("application.env", app_env.as_str())?
+            .build()?
+            .try_deserialize()?;
+
+        settings
+            .application
+            .validate()
+            .map_err(ConfigError::Message)?;
+        settings
+            .newsletter
+            .validate()
+            .map_err(ConfigError::Message)?;
+
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{DateTime, Utc, Weekday};
+
+    use secrecy::Secret;
+
+    use super::{ApplicationSettings, LoginThrottlingSettings, NewsletterSettings};
+
+    fn settings() -> LoginThrottlingSettings {
+        LoginThrottlingSettings {
+            window_seconds: 900,
+            max_failures_per_username: 5,
+            max_failures_per_ip: 20,
+            lockout_seconds: 900,
+            delay_base_milliseconds: 200,
+            delay_max_milliseconds: 3000,
+        }
+    }
+
+    #[test]
+    fn there_is_no_delay_without_recent_failures() {
+        assert_eq!(settings().delay(0), Duration::ZERO);
+    }
+
+    #[test]
+    fn the_delay_doubles_with_every_failure() {
+        let settings = settings();
+
+        assert_eq!(settings.delay(1), Duration::from_millis(200));
+        assert_eq!(settings.delay(2), Duration::from_millis(400));
+        assert_eq!(settings.delay(3), Duration::from_millis(800));
+    }
+
+    #[test]
+    fn the_delay_is_capped() {
+        assert_eq!(settings().delay(1000), Duration::from_millis(3000));
+    }
+
+    fn newsletter_settings() -> NewsletterSettings {
+        NewsletterSettings {
+            scheduler_interval_milliseconds: 30000,
+            digest_weekday: Weekday::Mon,
+            digest_hour: 8,
+        }
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn digests_are_due_on_the_last_digest_day() {
+        let settings = newsletter_settings();
+
+        // A Thursday, and a Monday after the digest went out
+        for now in ["2023-04-06T12:00:00Z", "2023-04-03T08:00:00Z"] {
+            assert_eq!(
+                settings.last_digest_time(at(now)),
+                at("2023-04-03T08:00:00Z")
+            );
+        }
+    }
+
+    #[test]
+    fn digests_are_due_the_week_before_until_the_digest_hour() {
+        assert_eq!(
+            newsletter_settings().last_digest_time(at("2023-04-03T07:59:59Z")),
+            at("2023-03-27T08:00:00Z")
+        );
+    }
+
+    #[test]
+    fn digest_hours_past_23_are_rejected() {
+        let settings = NewsletterSettings {
+            digest_hour: 24,
+            ..newsletter_settings()
+        };
+
+        assert!(settings.validate().is_err());
+        assert!(newsletter_settings().validate().is_ok());
+    }
+
+    #[test]
+    fn hmac_secrets_shorter_than_64_bytes_are_rejected() {
+        let with_secret = |length: usize| ApplicationSettings {
+            env: "local".into(),
+            port: 8000,
+            host: "127.0.0.1".into(),
+            base_url: "http://127.0.0.1".into(),
+            hmac_secret: Secret::new("a".repeat(length)),
+            shutdown_timeout_seconds: 30,
+            trusted_proxies: Vec::new(),
+            metrics_token: None,
+        };
+
+        assert!(with_secret(63).validate().is_err());
+        assert!(with_secret(64).validate().is_ok());
     }
 }
@@ -0,0 +1,288 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+    time::Duration,
+};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use tokio::{sync::watch, task::JoinHandle, time::Instant};
+
+use crate::health::Readiness;
+
+/// Coordinates stopping the application, shared by the server and every
+/// background task. Once triggered, the application reports not being ready
+/// and tasks stop taking new work, while they get until the deadline to
+/// finish what they're doing.
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    triggered: Arc<watch::Sender<bool>>,
+    in_flight: Arc<watch::Sender<usize>>,
+    background_tasks: Arc<watch::Sender<usize>>,
+    readiness: Readiness,
+    timeout: Duration,
+}
+
+impl Shutdown {
+    pub fn new(readiness: Readiness, timeout: Duration) -> Self {
+        let (triggered, _) = watch::channel(false);
+        let (in_flight, _) = watch::channel(0);
+        let (background_tasks, _) = watch::channel(0);
+
+        Self {
+            triggered: Arc::new(triggered),
+            in_flight: Arc::new(in_flight),
+            background_tasks: Arc::new(background_tasks),
+            readiness,
+            timeout,
+        }
+    }
+
+    /// How long in-flight work gets to finish, once triggered.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn trigger(&self) {
+        if !self.is_triggered() {
+            tracing::info!("Shutting down, waiting for in-flight work to finish.");
+        }
+
+        self.readiness.mark_shutting_down();
+        self.triggered.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.triggered.borrow()
+    }
+
+    /// Resolves once shutdown is triggered, straight away if it already was.
+    pub async fn triggered(&self) {
+        let mut triggered = self.triggered.subscribe();
+
+        while !*triggered.borrow_and_update() {
+            // The sender lives as long as `self`
+            let _ = triggered.changed().await;
+        }
+    }
+
+    /// Resolves once no request is being handled, straight away if none is.
+    pub async fn requests_finished(&self) {
+        none_left(&self.in_flight).await
+    }
+
+    /// Runs `task` in the background, for shutdown to wait for it before the
+    /// resources it relies on, such as the database pool, are closed.
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let guard = InFlightGuard::new(Arc::clone(&self.background_tasks));
+
+        tokio::spawn(async move {
+            let _guard = guard;
+
+            task.await
+        });
+    }
+
+    /// Resolves once every task run with `spawn` finished.
+    pub async fn background_tasks_finished(&self) {
+        none_left(&self.background_tasks).await
+    }
+
+    /// Triggers shutdown once the process is asked to stop, with Ctrl-C or
+    /// `SIGTERM`.
+    pub async fn trigger_on_signal(self) {
+        let ctrl_c = tokio::signal::ctrl_c();
+
+        #[cfg(unix)]
+        {
+            let mut terminate =
+                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                    .expect("Failed to listen for SIGTERM");
+
+            tokio::select! {
+                _ = ctrl_c => {},
+                _ = terminate.recv() => {},
+            }
+        }
+
+        #[cfg(not(unix))]
+        let _ = ctrl_c.await;
+
+        self.trigger();
+    }
+}
+
+async fn none_left(counter: &watch::Sender<usize>) {
+    let mut count = counter.subscribe();
+
+    while *count.borrow_and_update() > 0 {
+        // The sender lives as long as the coordinator
+        let _ = count.changed().await;
+    }
+}
+
+/// Waits for `task` until `deadline`, then aborts it. Returns whether it
+/// finished in time.
+pub async fn drain<T>(name: &str, mut task: JoinHandle<T>, deadline: Instant) -> bool {
+    if tokio::time::timeout_at(deadline, &mut task).await.is_ok() {
+        return true;
+    }
+
+    task.abort();
+
+    tracing::warn!(
+        task = name,
+        "A task didn't finish before the shutdown deadline and was aborted."
+    );
+
+    false
+}
+
+/// Counts the requests being handled, for shutdown to wait for them.
+#[derive(Clone, Debug)]
+pub struct InFlightRequests(Shutdown);
+
+impl InFlightRequests {
+    pub fn new(shutdown: Shutdown) -> Self {
+        Self(shutdown)
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for InFlightRequests
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = InFlightRequestsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(InFlightRequestsMiddleware {
+            service: Rc::new(service),
+            in_flight: Arc::clone(&self.0.in_flight),
+        }))
+    }
+}
+
+pub struct InFlightRequestsMiddleware<S> {
+    service: Rc<S>,
+    in_flight: Arc<watch::Sender<usize>>,
+}
+
+impl<S, B> Service<ServiceRequest> for InFlightRequestsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let guard = InFlightGuard::new(Arc::clone(&self.in_flight));
+
+        Box::pin(async move {
+            let _guard = guard;
+
+            service.call(req).await
+        })
+    }
+}
+
+/// Counts a request or a task until it's dropped, whether it finished or was
+/// cancelled.
+struct InFlightGuard(Arc<watch::Sender<usize>>);
+
+impl InFlightGuard {
+    fn new(in_flight: Arc<watch::Sender<usize>>) -> Self {
+        in_flight.send_modify(|n| *n += 1);
+
+        Self(in_flight)
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.send_modify(|n| *n -= 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{drain, InFlightGuard, Shutdown};
+    use crate::health::Readiness;
+
+    #[tokio::test]
+    async fn every_task_sharing_the_coordinator_is_told_to_stop() {
+        let readiness = Readiness::default();
+        let shutdown = Shutdown::new(readiness.clone(), Duration::from_secs(1));
+        let task = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.triggered().await }
+        });
+
+        assert!(!shutdown.is_triggered());
+
+        shutdown.trigger();
+
+        task.await.unwrap();
+        // Once triggered, it stays triggered
+        shutdown.triggered().await;
+        assert!(readiness.is_shutting_down());
+    }
+
+    #[tokio::test]
+    async fn requests_finished_waits_for_every_request_in_flight() {
+        let shutdown = Shutdown::new(Readiness::default(), Duration::from_secs(1));
+        let guard = InFlightGuard::new(shutdown.in_flight.clone());
+        let finished = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.requests_finished().await }
+        });
+
+        tokio::task::yield_now().await;
+        assert!(!finished.is_finished());
+
+        drop(guard);
+
+        finished.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn background_tasks_are_waited_for() {
+        let shutdown = Shutdown::new(Readiness::default(), Duration::from_secs(1));
+
+        shutdown.spawn(tokio::time::sleep(Duration::from_secs(5)));
+
+        let started_at = Instant::now();
+
+        shutdown.background_tasks_finished().await;
+
+        assert!(started_at.elapsed() >= Duration::from_secs(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn tasks_still_running_at_the_deadline_are_aborted() {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let quick = tokio::spawn(tokio::time::sleep(Duration::from_millis(500)));
+        let slow = tokio::spawn(tokio::time::sleep(Duration::from_secs(60)));
+
+        assert!(drain("quick", quick, deadline).await);
+        assert!(!drain("slow", slow, deadline).await);
+    }
+}
@@ -0,0 +1,69 @@
+use base64::{
+    alphabet::URL_SAFE,
+    engine::fast_portable::{FastPortable, NO_PAD},
+};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+const URL_SAFE_ENGINE: FastPortable = FastPortable::from(&URL_SAFE, NO_PAD);
+
+/// The application's secret key, used to sign the values we put in links so
+/// they can be trusted when they come back to us.
+#[derive(Clone)]
+pub struct HmacSecret(pub Secret<String>);
+
+impl HmacSecret {
+    /// A URL-safe signature of `message`.
+    pub fn sign(&self, message: &str) -> String {
+        base64::encode_engine(self.mac(message).finalize().into_bytes(), &URL_SAFE_ENGINE)
+    }
+
+    /// Checks `signature` in constant time.
+    pub fn verify(&self, message: &str, signature: &str) -> bool {
+        base64::decode_engine(signature, &URL_SAFE_ENGINE)
+            .is_ok_and(|signature| self.mac(message).verify_slice(&signature).is_ok())
+    }
+
+    fn mac(&self, message: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.0.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(message.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::HmacSecret;
+
+    fn secret(key: &str) -> HmacSecret {
+        HmacSecret(Secret::new(key.to_string()))
+    }
+
+    #[test]
+    fn signatures_are_verified() {
+        let secret = secret("secret");
+        let signature = secret.sign("message");
+
+        assert!(secret.verify("message", &signature));
+    }
+
+    #[test]
+    fn tampered_messages_are_rejected() {
+        let secret = secret("secret");
+        let signature = secret.sign("message");
+
+        assert!(!secret.verify("massage", &signature));
+        assert!(!secret.verify("message", "not-a-signature"));
+    }
+
+    #[test]
+    fn signatures_depend_on_the_key() {
+        let signature = secret("secret").sign("message");
+
+        assert!(!secret("another secret").verify("message", &signature));
+    }
+}
@@ -0,0 +1,406 @@
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditAction, AuditEvent},
+    domain::SubscriberEmail,
+    signing::HmacSecret,
+};
+
+/// The query of a link letting a subscriber see and erase their data
+/// without an account: it's signed, and only valid for a while.
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
+pub struct SubscriberDataLink {
+    pub email: String,
+    pub expires_at: i64,
+    pub signature: String,
+}
+
+impl SubscriberDataLink {
+    pub fn issue(hmac_secret: &HmacSecret, email: &SubscriberEmail, ttl: Duration) -> Self {
+        let email = email.as_ref().to_string();
+        let expires_at = (Utc::now() + ttl).timestamp();
+        let signature = hmac_secret.sign(&Self::message(&email, expires_at));
+
+        Self {
+            email,
+            expires_at,
+            signature,
+        }
+    }
+
+    pub fn url(&self, base_url: &str) -> String {
+        format!(
+            "{base_url}/subscriptions/data?email={}&expires_at={}&signature={}",
+            urlencoding::encode(&self.email),
+            self.expires_at,
+            self.signature
+        )
+    }
+
+    /// The email the link was issued for, unless it was tampered with or
+    /// has expired.
+    pub fn verify(&self, hmac_secret: &HmacSecret) -> Option<SubscriberEmail> {
+        let is_valid = self.expires_at > Utc::now().timestamp()
+            && hmac_secret.verify(
+                &Self::message(&self.email, self.expires_at),
+                &self.signature,
+            );
+
+        if is_valid {
+            SubscriberEmail::parse(self.email.clone()).ok()
+        } else {
+            None
+        }
+    }
+
+    fn message(email: &str, expires_at: i64) -> String {
+        format!("subscriber-data:{email}:{expires_at}")
+    }
+}
+
+/// Everything we hold about a subscriber, as handed over to them on request.
+#[derive(serde::Serialize)]
+pub struct SubscriberData {
+    pub subscriber: SubscriberRecord,
+    pub topic_opt_outs: Vec<String>,
+    pub subscription_tokens: Vec<SubscriptionTokenRecord>,
+    pub consents: Vec<ConsentRecord>,
+    pub email_events: Vec<EmailEventRecord>,
+    pub engagements: Vec<EngagementRecord>,
+    pub audit_events: Vec<SubscriberAuditRecord>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub frequency: String,
+    pub paused_until: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriptionTokenRecord {
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentRecord {
+    pub event: String,
+    pub recorded_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub form_source: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct EmailEventRecord {
+    pub event: String,
+    pub occurred_at: DateTime<Utc>,
+    pub reason: Option<String>,
+}
+
+/// An open or click of a newsletter issue we sent.
+#[derive(serde::Serialize)]
+pub struct EngagementRecord {
+    pub newsletter_issue_id: Uuid,
+    pub kind: String,
+    pub url: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberAuditRecord {
+    pub occurred_at: DateTime<Utc>,
+    pub action: String,
+    pub ip_address: Option<String>,
+    pub details: serde_json::Value,
+}
+
+/// Who asked for an erasure, for the audit trail.
+#[derive(Clone, Copy, Debug)]
+pub enum ErasureRequester {
+    Subscriber,
+    Admin(Uuid),
+}
+
+#[tracing::instrument(name = "Export subscriber data", skip(db_pool))]
+pub async fn export_subscriber_data(
+    db_pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<SubscriberData>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+            SELECT id, email, name, status, subscribed_at, frequency, paused_until
+            FROM subscriptions
+            WHERE email = $1
+        "#,
+        email.as_ref()
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve the subscriber.")?;
+
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => return Ok(None),
+    };
+
+    let topic_opt_outs = sqlx::query!(
+        r#"
+            SELECT topic_slug
+            FROM subscriber_topic_opt_outs
+            WHERE subscriber_id = $1
+            ORDER BY topic_slug
+        "#,
+        subscriber.id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve the topic opt-outs of a subscriber.")?
+    .into_iter()
+    .map(|r| r.topic_slug)
+    .collect();
+
+    let subscription_tokens = sqlx::query_as!(
+        SubscriptionTokenRecord,
+        r#"
+            SELECT token, created_at
+            FROM subscription_tokens
+            WHERE subscriber_id = $1
+            ORDER BY created_at
+        "#,
+        subscriber.id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve the subscription tokens of a subscriber.")?;
+
+    let consents = get_consent_records(db_pool, subscriber.id).await?;
+
+    let email_events = sqlx::query_as!(
+        EmailEventRecord,
+        r#"
+            SELECT event, occurred_at, reason
+            FROM email_events
+            WHERE subscriber_id = $1 OR email = $2
+            ORDER BY occurred_at, id
+        "#,
+        subscriber.id,
+        email.as_ref()
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve the email events of a subscriber.")?;
+
+    let engagements = sqlx::query_as!(
+        EngagementRecord,
+        r#"
+            SELECT e.newsletter_issue_id, e.kind, l.url AS "url?", e.occurred_at
+            FROM newsletter_engagements e
+            LEFT JOIN newsletter_issue_links l ON l.id = e.link_id
+            WHERE e.subscriber_id = $1
+            ORDER BY e.occurred_at, e.id
+        "#,
+        subscriber.id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve the newsletter engagement of a subscriber.")?;
+
+    let audit_events = sqlx::query_as!(
+        SubscriberAuditRecord,
+        r#"
+            SELECT occurred_at, action, ip_address, details
+            FROM audit_events
+            WHERE subject = $1
+            ORDER BY occurred_at, id
+        "#,
+        subscriber.id.to_string()
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve the audit events of a subscriber.")?;
+
+    Ok(Some(SubscriberData {
+        subscriber,
+        topic_opt_outs,
+        subscription_tokens,
+        consents,
+        email_events,
+        engagements,
+        audit_events,
+    }))
+}
+
+/// Oldest record first.
+#[tracing::instrument(name = "Get consent records", skip(db_pool))]
+pub async fn get_consent_records(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentRecord>, anyhow::Error> {
+    sqlx::query_as!(
+        ConsentRecord,
+        r#"
+            SELECT event, recorded_at, ip_address, user_agent, form_source
+            FROM consent_records
+            WHERE subscriber_id = $1
+            ORDER BY recorded_at, id
+        "#,
+        subscriber_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve the consent records of a subscriber.")
+}
+
+/// Deletes the subscriber along with their tokens, consent records and the
+/// events reported about their address.
+///
+/// The audit log keeps its history, but the events about the subscriber now
+/// point to a pseudonym instead: the same email always gets the same one, so
+/// a later request about it can still be traced without storing the address.
+///
+/// Returns whether there was a subscriber to erase.
+#[tracing::instrument(name = "Erase subscriber data", skip(db_pool, hmac_secret))]
+pub async fn erase_subscriber_data(
+    db_pool: &PgPool,
+    hmac_secret: &HmacSecret,
+    email: &SubscriberEmail,
+    requester: ErasureRequester,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let subscriber_id = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE email = $1 FOR UPDATE"#,
+        email.as_ref()
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the subscriber.")?
+    .map(|r| r.id);
+
+    let subscriber_id = match subscriber_id {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(false),
+    };
+
+    let pseudonym = pseudonymize(hmac_secret, email);
+
+    sqlx::query!(
+        r#"UPDATE audit_events SET subject = $1 WHERE subject = $2"#,
+        pseudonym,
+        subscriber_id.to_string()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to pseudonymize the audit events of a subscriber.")?;
+
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the subscription tokens of a subscriber.")?;
+
+    sqlx::query!(
+        r#"DELETE FROM email_events WHERE email = $1"#,
+        email.as_ref()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the email events of a subscriber.")?;
+
+    sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete a subscriber.")?;
+
+    let event = AuditEvent::new(AuditAction::SubscriberErased).subject(&pseudonym);
+    let event = match requester {
+        ErasureRequester::Subscriber => {
+            event.details(serde_json::json!({ "requested_by": "subscriber" }))
+        }
+        ErasureRequester::Admin(user_id) => event
+            .actor_id(user_id)
+            .details(serde_json::json!({ "requested_by": "admin" })),
+    };
+    event.record(&mut transaction).await?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")?;
+
+    Ok(true)
+}
+
+/// Keyed, so that the pseudonym of a known address can't be recomputed
+/// without the application's secret.
+pub fn pseudonymize(hmac_secret: &HmacSecret, email: &SubscriberEmail) -> String {
+    format!(
+        "erased:{}",
+        hmac_secret.sign(&format!("subscriber:{}", email.as_ref().to_lowercase()))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use secrecy::Secret;
+
+    use super::SubscriberDataLink;
+    use crate::{domain::SubscriberEmail, signing::HmacSecret};
+
+    fn hmac_secret() -> HmacSecret {
+        HmacSecret(Secret::new("secret".into()))
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse("ursula@example.com".into()).unwrap()
+    }
+
+    #[test]
+    fn a_data_link_is_valid_for_the_email_it_was_issued_for() {
+        let link = SubscriberDataLink::issue(&hmac_secret(), &email(), Duration::hours(1));
+
+        assert_eq!(
+            link.verify(&hmac_secret()).map(|email| email.to_string()),
+            Some("ursula@example.com".into())
+        );
+    }
+
+    #[test]
+    fn a_data_link_for_another_email_is_rejected() {
+        let mut link = SubscriberDataLink::issue(&hmac_secret(), &email(), Duration::hours(1));
+        link.email = "someone.else@example.com".into();
+
+        assert!(link.verify(&hmac_secret()).is_none());
+    }
+
+    #[test]
+    fn an_expired_data_link_is_rejected() {
+        let link = SubscriberDataLink::issue(&hmac_secret(), &email(), Duration::seconds(-1));
+
+        assert!(link.verify(&hmac_secret()).is_none());
+    }
+
+    #[test]
+    fn extending_a_data_link_is_rejected() {
+        let mut link = SubscriberDataLink::issue(&hmac_secret(), &email(), Duration::hours(1));
+        link.expires_at += 3600;
+
+        assert!(link.verify(&hmac_secret()).is_none());
+    }
+}
@@ -0,0 +1,50 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+
+/// An entry of the suppression list.
+#[derive(serde::Serialize, Debug)]
+pub struct Suppression {
+    pub id: i64,
+    pub kind: String,
+    pub value: String,
+    pub reason: String,
+    pub source: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Addresses and domains we must never email, checked by `EmailClient`
+/// before every send.
+#[derive(Clone, Debug)]
+pub struct SuppressionList {
+    db_pool: PgPool,
+}
+
+impl SuppressionList {
+    pub fn new(db_pool: PgPool) -> Self {
+        Self { db_pool }
+    }
+
+    /// The entry blocking `email`, if any. An entry for the address itself
+    /// is preferred over one for its domain.
+    #[tracing::instrument(name = "Check the suppression list", skip(self))]
+    pub async fn find(&self, email: &SubscriberEmail) -> Result<Option<Suppression>, sqlx::Error> {
+        sqlx::query_as!(
+            Suppression,
+            r#"
+                SELECT id, kind, value, reason, source, created_by, created_at
+                FROM email_suppressions
+                WHERE (kind = 'address' AND value = lower($1))
+                    OR (kind = 'domain' AND value = lower(split_part($1, '@', 2)))
+                ORDER BY kind
+                LIMIT 1
+            "#,
+            email.as_ref()
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+    }
+}
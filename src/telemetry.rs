@@ -1,11 +1,32 @@
+use opentelemetry::{
+    global,
+    runtime::Tokio,
+    sdk::{
+        propagation::TraceContextPropagator,
+        trace::{self, TracerProvider},
+        Resource,
+    },
+    trace::{TraceError, TracerProvider as _},
+    KeyValue,
+};
+use opentelemetry_http::HeaderInjector;
+use opentelemetry_otlp::{SpanExporterBuilder, WithExportConfig};
+use reqwest::header::HeaderMap;
 use tokio::task::JoinHandle;
 use tracing::{subscriber::set_global_default, Subscriber};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt, EnvFilter, Registry};
 
+use crate::settings::TelemetrySettings;
+
 /// Compose multiple layers into a `tracing`'s subscriber.
 ///
+/// Spans are also exported to an OpenTelemetry collector once
+/// `telemetry.otlp_endpoint` is set, which has to happen within a Tokio
+/// runtime since they're sent in batches from a background task.
+///
 /// ### Implementation Notes
 ///
 /// We are using `impl Subscriber` as return type to avoid having to
@@ -18,16 +39,30 @@ pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
+    telemetry: &TelemetrySettings,
 ) -> impl Subscriber + Send + Sync
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
 {
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
+    let otlp_layer = telemetry.otlp_endpoint.as_deref().map(|endpoint| {
+        let provider =
+            otlp_tracer_provider(&name, endpoint).expect("Failed to set up the OTLP span exporter");
+        let tracer = provider.tracer(name.clone());
+
+        global::set_tracer_provider(provider);
+
+        tracing_opentelemetry::layer().with_tracer(tracer)
+    });
     let formatting_layer = BunyanFormattingLayer::new(name, sink);
 
+    // Incoming requests join the trace of their `traceparent` header
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
     Registry::default()
         .with(env_filter)
+        .with(otlp_layer)
         .with(JsonStorageLayer)
         .with(formatting_layer)
 }
@@ -40,6 +75,47 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     set_global_default(subscriber).expect("Failed to set subscriber")
 }
 
+/// Exports the spans still waiting to be sent to the collector.
+pub async fn shutdown_tracer_provider() {
+    // Waits for the batch exporter, which runs on the Tokio runtime
+    tokio::task::spawn_blocking(global::shutdown_tracer_provider)
+        .await
+        .expect("Failed to shut down the tracer provider");
+}
+
+/// Exports spans in batches to the collector at `endpoint`, as coming from
+/// `service_name`.
+fn otlp_tracer_provider(service_name: &str, endpoint: &str) -> Result<TracerProvider, TraceError> {
+    let exporter = SpanExporterBuilder::from(
+        opentelemetry_otlp::new_exporter()
+            .http()
+            .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/'))),
+    )
+    .build_span_exporter()?;
+    let config = trace::config().with_resource(Resource::new([KeyValue::new(
+        "service.name",
+        service_name.to_string(),
+    )]));
+
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, Tokio)
+        .with_config(config)
+        .build())
+}
+
+/// The W3C `traceparent` of the current span, for outgoing requests to
+/// carry so that what they cause joins its trace.
+pub fn trace_context_headers() -> HeaderMap {
+    let context = tracing::Span::current().context();
+    let mut headers = HeaderMap::new();
+
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+
+    headers
+}
+
 pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
 where
     F: FnOnce() -> R + Send + 'static,
@@ -49,3 +125,64 @@ where
 
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::{
+        global,
+        sdk::{propagation::TraceContextPropagator, trace::TracerProvider},
+        trace::{TraceContextExt, TracerProvider as _},
+    };
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::{layer::SubscriberExt, Registry};
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{otlp_tracer_provider, trace_context_headers};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn spans_are_exported_to_the_collector() {
+        let collector = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v1/traces"))
+            .and(header("Content-Type", "application/x-protobuf"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&collector)
+            .await;
+
+        let provider = otlp_tracer_provider("test", &collector.uri()).unwrap();
+        let subscriber = Registry::default()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("Exported span").in_scope(|| {});
+        });
+
+        tokio::task::spawn_blocking(move || provider.force_flush())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn outgoing_requests_carry_the_trace_of_the_current_span() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let provider = TracerProvider::default();
+        let subscriber = Registry::default()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("Outgoing request");
+            let _entered = span.enter();
+            let trace_id = span.context().span().span_context().trace_id();
+            let headers = trace_context_headers();
+            let traceparent = headers["traceparent"].to_str().unwrap();
+
+            assert!(traceparent.starts_with(&format!("00-{trace_id}-")));
+        });
+    }
+}
@@ -0,0 +1,120 @@
+use chrono::{DateTime, Utc};
+use maud::{html, Markup};
+use uuid::Uuid;
+
+use crate::{audit::AuditAction, views::layout};
+
+#[derive(serde::Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub action: String,
+    pub actor_id: Option<Uuid>,
+    pub actor: Option<String>,
+    pub subject: Option<String>,
+    pub ip_address: Option<String>,
+    pub details: serde_json::Value,
+}
+
+#[derive(serde::Serialize)]
+pub struct AuditLogPage {
+    pub events: Vec<AuditEntry>,
+    pub page: i64,
+    pub next_page: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+}
+
+impl AuditFilter {
+    /// The query string for `page`, keeping the current filters.
+    fn page_query(&self, page: i64) -> String {
+        let mut query = format!("page={}", page);
+
+        if let Some(actor) = &self.actor {
+            query.push_str(&format!("&actor={}", urlencoding::encode(actor)));
+        }
+
+        if let Some(action) = self.action {
+            query.push_str(&format!("&action={}", action.as_str()));
+        }
+
+        query
+    }
+}
+
+pub fn get(audit_log_page: &AuditLogPage, filter: &AuditFilter) -> Markup {
+    layout(
+        "Audit log",
+        html! {
+            p { a href="/admin" { "<- Back" } }
+
+            (filter_form(filter))
+
+            table {
+                thead {
+                    tr {
+                        th { "When" }
+                        th { "Action" }
+                        th { "Actor" }
+                        th { "Subject" }
+                        th { "IP address" }
+                        th { "Details" }
+                    }
+                }
+                tbody {
+                    @for event in &audit_log_page.events {
+                        tr {
+                            td { (event.occurred_at.to_rfc3339()) }
+                            td { (event.action) }
+                            td { (event.actor.as_deref().unwrap_or("")) }
+                            td { (event.subject.as_deref().unwrap_or("")) }
+                            td { (event.ip_address.as_deref().unwrap_or("")) }
+                            td { code { (event.details.to_string()) } }
+                        }
+                    }
+                }
+            }
+
+            p {
+                @if audit_log_page.page > 1 {
+                    a href={ "/admin/audit?" (filter.page_query(audit_log_page.page - 1)) } { "<- Newer" }
+                    " "
+                }
+                @if let Some(next_page) = audit_log_page.next_page {
+                    a href={ "/admin/audit?" (filter.page_query(next_page)) } { "Older ->" }
+                }
+            }
+        },
+    )
+}
+
+fn filter_form(filter: &AuditFilter) -> Markup {
+    html! {
+        form action="/admin/audit" method="get" {
+            label {
+                "Actor "
+                input type="text" name="actor" value=(filter.actor.as_deref().unwrap_or(""));
+            }
+
+            " "
+
+            label {
+                "Action "
+                select name="action" {
+                    option value="" { "Any" }
+                    @for action in AuditAction::ALL {
+                        option value=(action.as_str()) selected[filter.action == Some(action)] { (action.as_str()) }
+                    }
+                }
+            }
+
+            " "
+
+            button type="submit" { "Filter" }
+        }
+    }
+}
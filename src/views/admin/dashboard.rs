@@ -0,0 +1,35 @@
+use maud::{html, Markup};
+
+use crate::{
+    authorization::{AuthorizedUser, Permission},
+    csrf::CsrfToken,
+    views::{csrf, layout},
+};
+
+pub fn get(user: &AuthorizedUser, csrf_token: &CsrfToken) -> Markup {
+    layout(
+        "Admin dashboard",
+        html! {
+            p { "Welcome " (user.username) "!" }
+
+            ol {
+                @if Permission::PublishNewsletter.is_granted_to(user.role) {
+                    li { a href="/admin/newsletters" { "Newsletter issues" } }
+                }
+                @if Permission::ManageUsers.is_granted_to(user.role) {
+                    li { a href="/admin/users" { "Manage users" } }
+                }
+                @if Permission::ViewAuditLog.is_granted_to(user.role) {
+                    li { a href="/admin/audit" { "Audit log" } }
+                }
+                li { a href="/admin/two-factor" { "Two-factor authentication" } }
+                li {
+                    form action="/admin/logout" method="post" {
+                        (csrf::input(csrf_token))
+                        button type="submit" { "Logout" }
+                    }
+                }
+            }
+        },
+    )
+}
@@ -0,0 +1,202 @@
+use maud::{html, Markup};
+
+use crate::{
+    authorization::AuthorizedUser,
+    csrf::CsrfToken,
+    domain::IssueStatus,
+    newsletter_issue::{IssueContent, IssueListing, NewsletterIssue},
+    views::{csrf, layout},
+};
+
+pub fn list(issues: &[IssueListing]) -> Markup {
+    layout(
+        "Newsletter issues",
+        html! {
+            p { a href="/admin" { "<- Back" } }
+            p { a href="/admin/newsletters/new" { "New issue" } }
+
+            table {
+                thead {
+                    tr {
+                        th { "Title" }
+                        th { "Status" }
+                        th { "Author" }
+                        th { "Scheduled for" }
+                        th { "Published at" }
+                        th { "Last updated" }
+                    }
+                }
+                tbody {
+                    @for issue in issues {
+                        tr {
+                            td { a href={ "/admin/newsletters/" (issue.id.to_string()) } { (issue.title) } }
+                            td { (issue.status) }
+                            td { (issue.author.as_deref().unwrap_or("")) }
+                            td { (issue.scheduled_for.map(|at| at.to_rfc3339()).unwrap_or_default()) }
+                            td { (issue.published_at.map(|at| at.to_rfc3339()).unwrap_or_default()) }
+                            td { (issue.updated_at.to_rfc3339()) }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
+pub fn new(csrf_token: &CsrfToken, error: Option<String>) -> Markup {
+    layout(
+        "New issue",
+        html! {
+            p { a href="/admin/newsletters" { "<- Back" } }
+
+            @if let Some(error) = error {
+               p { em style="color: red;" { (error) } }
+            }
+
+            (content_form("/admin/newsletters", None, csrf_token))
+        },
+    )
+}
+
+pub fn show(
+    issue: &NewsletterIssue,
+    warnings: &[String],
+    user: &AuthorizedUser,
+    csrf_token: &CsrfToken,
+    error: Option<String>,
+) -> Markup {
+    let action = |name: &str| format!("/admin/newsletters/{}/{name}", issue.id);
+
+    layout(
+        &issue.content.title,
+        html! {
+            p { a href="/admin/newsletters" { "<- Back" } }
+
+            @if let Some(error) = error {
+               p { em style="color: red;" { (error) } }
+            }
+
+            dl {
+                dt { "Status" } dd { (issue.status.as_str()) }
+                dt { "Author" } dd { (issue.author.as_deref().unwrap_or("Unknown")) }
+                @if let Some(approver) = &issue.approver {
+                    dt { "Approved by" } dd { (approver) }
+                }
+                @if let Some(scheduled_for) = issue.scheduled_for {
+                    dt { "Scheduled for" } dd { (scheduled_for.to_rfc3339()) }
+                }
+                @if let Some(published_at) = issue.published_at {
+                    dt { "Published at" } dd { (published_at.to_rfc3339()) }
+                }
+                dt { "Last updated" } dd { (issue.updated_at.to_rfc3339()) }
+            }
+
+            @if !warnings.is_empty() && issue.published_at.is_none() {
+                h2 { "Email client warnings" }
+                ul {
+                    @for warning in warnings {
+                        li { (warning) }
+                    }
+                }
+            }
+
+            @if issue.published_at.is_some() {
+                p {
+                    a href={ "/admin/newsletters/" (issue.id.to_string()) "/deliveries" } { "Deliveries" }
+                }
+            }
+
+            @if issue.status.is_editable() {
+                (content_form(&format!("/admin/newsletters/{}", issue.id), Some(&issue.content), csrf_token))
+            } @else {
+                h2 { "Content" }
+                p { "Topic: " (issue.content.topic.as_deref().unwrap_or("Everyone")) }
+                @if !issue.content.attachments.is_empty() {
+                    p { "Attachments: " (attachment_ids(&issue.content)) }
+                }
+                @if let Some(markdown) = &issue.content.markdown {
+                    pre { (markdown) }
+                }
+                pre { (issue.content.html) }
+                pre { (issue.content.text) }
+            }
+
+            @if issue.status.can_become(IssueStatus::Approved) && issue.created_by != Some(user.id) {
+                form action=(action("approve")) method="post" {
+                    (csrf::input(csrf_token))
+                    button type="submit" { "Approve" }
+                }
+            }
+            @if issue.status.can_become(IssueStatus::Scheduled) {
+                form action=(action("schedule")) method="post" {
+                    (csrf::input(csrf_token))
+                    label {
+                        "Send at (UTC)"
+                        input type="datetime-local" name="scheduled_for" required;
+                    }
+                    button type="submit" { "Schedule" }
+                }
+            }
+            @if issue.status == IssueStatus::Scheduled {
+                form action=(action("unschedule")) method="post" {
+                    (csrf::input(csrf_token))
+                    button type="submit" { "Unschedule" }
+                }
+            }
+            @if issue.status.can_become(IssueStatus::Sending) {
+                form action=(action("publish")) method="post" {
+                    (csrf::input(csrf_token))
+                    button type="submit" { "Publish now" }
+                }
+            }
+        },
+    )
+}
+
+fn content_form(action: &str, content: Option<&IssueContent>, csrf_token: &CsrfToken) -> Markup {
+    html! {
+        form action=(action) method="post" {
+            (csrf::input(csrf_token))
+            label {
+                "Title"
+                input type="text" name="title" required value=[content.map(|c| &c.title)];
+            }
+            label {
+                "Topic (leave empty to send to every subscriber)"
+                input type="text" name="topic" value=[content.and_then(|c| c.topic.as_deref())];
+            }
+            label {
+                "Attachments (ids of uploaded assets, separated by commas)"
+                input type="text" name="attachments" value=[content.map(attachment_ids)];
+            }
+            label {
+                "Markdown (the HTML and plain text contents are rendered from it, when given)"
+                textarea name="markdown_content" rows="20" cols="80" {
+                    (content.and_then(|c| c.markdown.as_deref()).unwrap_or_default())
+                }
+            }
+            label {
+                "HTML content"
+                textarea name="html_content" rows="20" cols="80" {
+                    (content.map(|c| c.html.as_str()).unwrap_or_default())
+                }
+            }
+            label {
+                "Plain text content"
+                textarea name="text_content" rows="20" cols="80" {
+                    (content.map(|c| c.text.as_str()).unwrap_or_default())
+                }
+            }
+            button type="submit" { "Save draft" }
+        }
+    }
+}
+
+fn attachment_ids(content: &IssueContent) -> String {
+    content
+        .attachments
+        .iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,6 @@
+pub mod audit;
+pub mod dashboard;
+pub mod issues;
+pub mod newsletters;
+pub mod two_factor;
+pub mod users;
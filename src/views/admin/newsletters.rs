@@ -0,0 +1,73 @@
+use maud::{html, Markup};
+
+use crate::{delivery::DeliveryReport, views::layout};
+
+pub fn delivery_report(report: &DeliveryReport) -> Markup {
+    layout(
+        &format!("Deliveries of {}", report.title),
+        html! {
+            p { a href="/admin" { "<- Back" } }
+
+            h2 { "Summary" }
+            dl {
+                dt { "Published at" } dd { (report.published_at.to_rfc3339()) }
+                dt { "Recipients" } dd { (report.recipients) }
+                dt { "Queued" } dd { (report.queued) }
+                dt { "Sent" } dd { (report.sent) }
+                dt { "Suppressed" } dd { (report.suppressed) }
+                dt { "Failed" } dd { (report.failed) }
+                dt { "Bounced" } dd { (report.bounced) }
+                @if let Some(last_attempt_at) = report.last_attempt_at {
+                    dt { "Last attempt at" } dd { (last_attempt_at.to_rfc3339()) }
+                }
+                @if let Some(duration_seconds) = report.duration_seconds {
+                    dt { "Sending took" } dd { (duration_seconds) " s" }
+                }
+            }
+
+            @if !report.failure_reasons.is_empty() {
+                h2 { "Failure reasons" }
+                table {
+                    thead {
+                        tr {
+                            th { "Status" }
+                            th { "Reason" }
+                            th { "Recipients" }
+                        }
+                    }
+                    tbody {
+                        @for failure_reason in &report.failure_reasons {
+                            tr {
+                                td { (failure_reason.status) }
+                                td { (failure_reason.reason.as_deref().unwrap_or("Unknown")) }
+                                td { (failure_reason.count) }
+                            }
+                        }
+                    }
+                }
+
+                h2 { "Failed deliveries" }
+                table {
+                    thead {
+                        tr {
+                            th { "Recipient" }
+                            th { "Status" }
+                            th { "Reason" }
+                            th { "When" }
+                        }
+                    }
+                    tbody {
+                        @for failure in &report.failures {
+                            tr {
+                                td { (failure.email) }
+                                td { (failure.status) }
+                                td { (failure.reason.as_deref().unwrap_or("Unknown")) }
+                                td { (failure.updated_at.to_rfc3339()) }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
@@ -0,0 +1,84 @@
+use maud::{html, Markup};
+use secrecy::{ExposeSecret, Secret};
+
+use crate::{
+    authentication::TwoFactorStatus,
+    csrf::CsrfToken,
+    views::{csrf, layout},
+};
+
+pub fn get(status: &TwoFactorStatus, csrf_token: &CsrfToken, errors: Option<String>) -> Markup {
+    layout(
+        "Two-factor authentication",
+        html! {
+            p { a href="/admin" { "<- Back" } }
+
+            @if let Some(errors) = errors {
+               p { em style="color: red;" { (errors) } }
+            }
+
+            @match status {
+                TwoFactorStatus::Disabled => {
+                    p { "Two-factor authentication is off." }
+
+                    form action="/admin/two-factor/enroll" method="post" {
+                        (csrf::input(csrf_token))
+                        button type="submit" { "Set up an authenticator app" }
+                    }
+                }
+                TwoFactorStatus::PendingActivation { secret, otpauth_uri } => {
+                    p { "Add this account to your authenticator app, then enter the code it shows to finish." }
+
+                    p { a href=(otpauth_uri) { "Open in authenticator app" } }
+                    p { "Or enter this key manually: " code { (secret.expose_secret()) } }
+
+                    (code_form("/admin/two-factor/activate", "Turn on", csrf_token))
+                }
+                TwoFactorStatus::Enabled { remaining_recovery_codes } => {
+                    p { "Two-factor authentication is on." }
+                    p { (remaining_recovery_codes) " unused recovery codes left." }
+
+                    (code_form("/admin/two-factor/disable", "Turn off", csrf_token))
+                }
+            }
+        },
+    )
+}
+
+pub fn recovery_codes(codes: &[Secret<String>]) -> Markup {
+    layout(
+        "Recovery codes",
+        html! {
+            p { "Two-factor authentication is on." }
+            p {
+                "Keep these recovery codes somewhere safe. Each can be used once to log in "
+                "if you lose access to your authenticator app. They won't be shown again."
+            }
+
+            ul {
+                @for code in codes {
+                    li { code { (code.expose_secret()) } }
+                }
+            }
+
+            p { a href="/admin" { "Back to the dashboard" } }
+        },
+    )
+}
+
+fn code_form(action: &str, label: &str, csrf_token: &CsrfToken) -> Markup {
+    html! {
+        form action=(action) method="post" {
+            (csrf::input(csrf_token))
+
+            div style="display: flex; flex-direction: column; align-items: flex-start; gap: 1rem;" {
+                label {
+                    "Code "
+                    input type="text" inputmode="numeric" autocomplete="one-time-code" placeholder="Enter the code from your app" name="code";
+                }
+
+                button type="submit" { (label) }
+            }
+        }
+    }
+}
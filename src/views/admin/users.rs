@@ -0,0 +1,108 @@
+use maud::{html, Markup};
+use uuid::Uuid;
+
+use crate::{
+    csrf::CsrfToken,
+    domain::UserRole,
+    views::{csrf, layout},
+};
+
+pub struct UserSummary {
+    pub id: Uuid,
+    pub username: String,
+    pub email: Option<String>,
+    pub role: UserRole,
+    pub is_disabled: bool,
+    pub has_password: bool,
+}
+
+pub fn get(users: &[UserSummary], csrf_token: &CsrfToken, errors: Option<String>) -> Markup {
+    layout(
+        "Users",
+        html! {
+            p { a href="/admin" { "<- Back" } }
+
+            @if let Some(errors) = errors {
+               p { em style="color: red;" { (errors) } }
+            }
+
+            table {
+                thead {
+                    tr {
+                        th { "Username" }
+                        th { "Email" }
+                        th { "Role" }
+                        th { "Status" }
+                        th {}
+                    }
+                }
+                tbody {
+                    @for user in users {
+                        (row(user, csrf_token))
+                    }
+                }
+            }
+
+            (invite_form(csrf_token))
+        },
+    )
+}
+
+fn row(user: &UserSummary, csrf_token: &CsrfToken) -> Markup {
+    let status = if user.is_disabled {
+        "disabled"
+    } else if !user.has_password {
+        "awaiting password"
+    } else {
+        "active"
+    };
+
+    html! {
+        tr {
+            td { (user.username) }
+            td { (user.email.as_deref().unwrap_or("")) }
+            td { (user.role.as_str()) }
+            td { (status) }
+            td {
+                @if !user.is_disabled {
+                    form action={ "/admin/users/" (user.id.to_string()) "/disable" } method="post" {
+                        (csrf::input(csrf_token))
+                        button type="submit" { "Disable" }
+                    }
+                    @if user.email.is_some() {
+                        form action={ "/admin/users/" (user.id.to_string()) "/password_reset" } method="post" {
+                            (csrf::input(csrf_token))
+                            button type="submit" { "Force password reset" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn invite_form(csrf_token: &CsrfToken) -> Markup {
+    html! {
+        form action="/admin/users" method="post" {
+            (csrf::input(csrf_token))
+
+            div style="display: flex; flex-direction: column; align-items: flex-start; gap: 1rem;" {
+                label {
+                    "Email "
+                    input type="email" placeholder="Enter email" name="email";
+                }
+
+                label {
+                    "Role "
+                    select name="role" {
+                        option value="viewer" { "Viewer" }
+                        option value="editor" { "Editor" }
+                        option value="admin" { "Admin" }
+                    }
+                }
+
+                button type="submit" { "Invite user" }
+            }
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use maud::{html, Markup};
+
+use super::layout;
+use crate::csrf::{CsrfToken, CSRF_FIELD_NAME};
+
+/// The hidden field every form posting back to us must include.
+pub fn input(csrf_token: &CsrfToken) -> Markup {
+    html! {
+        input type="hidden" name=(CSRF_FIELD_NAME) value=(csrf_token.as_str());
+    }
+}
+
+pub fn forbidden() -> Markup {
+    layout(
+        "Forbidden",
+        html! {
+            p { "This form has expired or didn't come from this site, so it wasn't submitted." }
+            p { "Go back, reload the page and try again." }
+        },
+    )
+}
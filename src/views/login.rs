@@ -1,14 +1,17 @@
 use maud::{html, Markup};
 
-use super::layout;
+use super::{csrf, layout};
+use crate::csrf::CsrfToken;
 
-pub fn get(errors: Option<String>) -> Markup {
-    layout("Login", form(errors))
+pub fn get(csrf_token: &CsrfToken, errors: Option<String>) -> Markup {
+    layout("Login", form(csrf_token, errors))
 }
 
-pub fn form(errors: Option<String>) -> Markup {
+pub fn form(csrf_token: &CsrfToken, errors: Option<String>) -> Markup {
     html! {
         form action="/login" method="post" {
+            (csrf::input(csrf_token))
+
             @if let Some(errors) = errors {
                p { em style="color: red;" { (errors) } }
             }
@@ -25,7 +28,35 @@ pub fn form(errors: Option<String>) -> Markup {
                 }
 
                 button type="submit" { "Login" }
+
+                a href="/password/forgot" { "Forgot your password?" }
             }
         }
     }
 }
+
+pub fn two_factor(csrf_token: &CsrfToken, errors: Option<String>) -> Markup {
+    layout(
+        "Two-factor authentication",
+        html! {
+            form action="/login/two-factor" method="post" {
+                (csrf::input(csrf_token))
+
+                @if let Some(errors) = errors {
+                   p { em style="color: red;" { (errors) } }
+                }
+
+                div style="display: flex; flex-direction: column; align-items: flex-start; gap: 1rem;" {
+                    label {
+                        "Code "
+                        input type="text" inputmode="numeric" autocomplete="one-time-code" placeholder="Enter the code from your app" name="code";
+                    }
+
+                    p { "Lost your device? Enter one of your recovery codes instead." }
+
+                    button type="submit" { "Verify" }
+                }
+            }
+        },
+    )
+}
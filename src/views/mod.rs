@@ -1,4 +1,10 @@
+pub mod admin;
+pub mod csrf;
 pub mod layout;
 pub mod login;
+pub mod newsletter_issue;
+pub mod password;
+pub mod preferences;
+pub mod subscriber_data;
 
 pub use layout::*;
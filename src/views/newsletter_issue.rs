@@ -0,0 +1,28 @@
+use maud::{html, Markup, PreEscaped};
+
+use super::layout;
+use crate::newsletter_asset::AssetName;
+
+/// `html` is the prepared HTML of the issue, as sent.
+pub fn read(title: &str, html: &str, assets_url: &str, attachments: &[AssetName]) -> Markup {
+    layout(
+        title,
+        html! {
+            h1 { (title) }
+            (PreEscaped(html))
+
+            @if !attachments.is_empty() {
+                h2 { "Attachments" }
+                ul {
+                    @for attachment in attachments {
+                        li {
+                            a href=(format!("{assets_url}/{}", attachment.id)) {
+                                (attachment.filename)
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
@@ -0,0 +1,76 @@
+use maud::{html, Markup};
+
+use super::{csrf, layout};
+use crate::csrf::CsrfToken;
+
+pub fn set(csrf_token: &CsrfToken, token: &str, errors: Option<String>) -> Markup {
+    layout("Set your password", set_form(csrf_token, token, errors))
+}
+
+pub fn set_form(csrf_token: &CsrfToken, token: &str, errors: Option<String>) -> Markup {
+    html! {
+        form action="/password/set" method="post" {
+            (csrf::input(csrf_token))
+
+            @if let Some(errors) = errors {
+               p { em style="color: red;" { (errors) } }
+            }
+
+            input type="hidden" name="token" value=(token);
+
+            div style="display: flex; flex-direction: column; align-items: flex-start; gap: 1rem;" {
+                label {
+                    "New password "
+                    input type="password" placeholder="Enter new password" name="new_password";
+                }
+
+                label {
+                    "Confirm new password "
+                    input type="password" placeholder="Type the new password again" name="new_password_check";
+                }
+
+                button type="submit" { "Set password" }
+            }
+        }
+    }
+}
+
+pub fn forgot(csrf_token: &CsrfToken, sent: bool) -> Markup {
+    layout(
+        "Forgot your password?",
+        html! {
+            @if sent {
+                p { "If an account matches, we've emailed a link to reset its password." }
+            }
+
+            (forgot_form(csrf_token))
+        },
+    )
+}
+
+pub fn forgot_form(csrf_token: &CsrfToken) -> Markup {
+    html! {
+        form action="/password/forgot" method="post" {
+            (csrf::input(csrf_token))
+
+            div style="display: flex; flex-direction: column; align-items: flex-start; gap: 1rem;" {
+                label {
+                    "Username "
+                    input type="text" placeholder="Enter Username" name="username";
+                }
+
+                button type="submit" { "Send me a reset link" }
+            }
+        }
+    }
+}
+
+pub fn invalid_token() -> Markup {
+    layout(
+        "Invalid link",
+        html! {
+            p { "This link is invalid or has expired." }
+            p { a href="/password/forgot" { "Request a new link" } }
+        },
+    )
+}
@@ -0,0 +1,113 @@
+use chrono::{DateTime, Utc};
+use maud::{html, Markup};
+
+use super::{csrf, layout};
+use crate::{csrf::CsrfToken, domain::DeliveryFrequency};
+
+#[derive(Debug)]
+pub struct SubscriberPreferences {
+    pub name: String,
+    pub frequency: DeliveryFrequency,
+    pub paused_until: Option<DateTime<Utc>>,
+    pub topics: Vec<TopicPreference>,
+}
+
+#[derive(Debug)]
+pub struct TopicPreference {
+    pub slug: String,
+    pub name: String,
+    pub subscribed: bool,
+}
+
+const PAUSE_OPTIONS: [(i64, &str); 3] = [(1, "1 week"), (4, "4 weeks"), (12, "12 weeks")];
+
+pub fn get(
+    csrf_token: &CsrfToken,
+    token: &str,
+    preferences: &SubscriberPreferences,
+    saved: bool,
+    errors: Option<String>,
+) -> Markup {
+    let paused_until = preferences
+        .paused_until
+        .filter(|paused_until| *paused_until > Utc::now());
+
+    layout(
+        "Your subscription preferences",
+        html! {
+            @if saved {
+                p { "Your preferences were saved." }
+            }
+
+            @if let Some(errors) = errors {
+               p { em style="color: red;" { (errors) } }
+            }
+
+            form action="/preferences" method="post" {
+                (csrf::input(csrf_token))
+                input type="hidden" name="token" value=(token);
+
+                div style="display: flex; flex-direction: column; align-items: flex-start; gap: 1rem;" {
+                    label {
+                        "Name "
+                        input type="text" name="name" value=(preferences.name);
+                    }
+
+                    @if !preferences.topics.is_empty() {
+                        fieldset {
+                            legend { "Topics" }
+
+                            @for topic in &preferences.topics {
+                                label {
+                                    input type="checkbox" name="topics" value=(topic.slug) checked[topic.subscribed];
+                                    " " (topic.name)
+                                }
+                                br;
+                            }
+                        }
+                    }
+
+                    fieldset {
+                        legend { "Frequency" }
+
+                        @for frequency in DeliveryFrequency::ALL {
+                            label {
+                                input type="radio" name="frequency" value=(frequency.as_str()) checked[frequency == preferences.frequency];
+                                " " (frequency.description())
+                            }
+                            br;
+                        }
+                    }
+
+                    label {
+                        @match paused_until {
+                            Some(paused_until) => "Delivery is paused until " (paused_until.format("%B %-d, %Y").to_string()) ". ",
+                            None => "Pause delivery ",
+                        }
+
+                        select name="pause_weeks" {
+                            option value="" selected { "Don't change" }
+                            @if paused_until.is_some() {
+                                option value="0" { "Resume now" }
+                            }
+                            @for (weeks, label) in PAUSE_OPTIONS {
+                                option value=(weeks) { "Pause for " (label) }
+                            }
+                        }
+                    }
+
+                    button type="submit" { "Save preferences" }
+                }
+            }
+        },
+    )
+}
+
+pub fn invalid_link() -> Markup {
+    layout(
+        "Invalid link",
+        html! {
+            p { "This link is invalid. Use the one at the bottom of our latest email." }
+        },
+    )
+}
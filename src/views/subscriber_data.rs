@@ -0,0 +1,101 @@
+use maud::{html, Markup};
+
+use super::{csrf, layout};
+use crate::{
+    csrf::CsrfToken,
+    subscriber_data::{SubscriberData, SubscriberDataLink},
+};
+
+pub fn request(csrf_token: &CsrfToken, sent: bool) -> Markup {
+    layout(
+        "Your data",
+        html! {
+            @if sent {
+                p { "If we hold data about this address, we've emailed it a link to see it." }
+            }
+
+            form action="/subscriptions/data/request" method="post" {
+                (csrf::input(csrf_token))
+
+                div style="display: flex; flex-direction: column; align-items: flex-start; gap: 1rem;" {
+                    label {
+                        "Email "
+                        input type="email" placeholder="Enter your email" name="email";
+                    }
+
+                    button type="submit" { "Send me a link to my data" }
+                }
+            }
+        },
+    )
+}
+
+pub fn show(
+    csrf_token: &CsrfToken,
+    link: &SubscriberDataLink,
+    data: Option<&SubscriberData>,
+) -> Markup {
+    layout(
+        "Your data",
+        html! {
+            @match data {
+                None => p { "We hold no data about " (link.email) "." },
+                Some(data) => {
+                    h2 { "Subscription" }
+                    dl {
+                        dt { "Email" } dd { (data.subscriber.email) }
+                        dt { "Name" } dd { (data.subscriber.name) }
+                        dt { "Status" } dd { (data.subscriber.status) }
+                        dt { "Subscribed at" } dd { (data.subscriber.subscribed_at.to_rfc3339()) }
+                    }
+
+                    h2 { "Consent" }
+                    ul {
+                        @for consent in &data.consents {
+                            li {
+                                (consent.event) " at " (consent.recorded_at.to_rfc3339())
+                                @if let Some(ip_address) = &consent.ip_address { " from " (ip_address) }
+                            }
+                        }
+                    }
+
+                    h2 { "History" }
+                    ul {
+                        @for event in &data.audit_events {
+                            li { (event.action) " at " (event.occurred_at.to_rfc3339()) }
+                        }
+                    }
+
+                    form action="/subscriptions/data/erase" method="post" {
+                        (csrf::input(csrf_token))
+                        input type="hidden" name="email" value=(link.email);
+                        input type="hidden" name="expires_at" value=(link.expires_at);
+                        input type="hidden" name="signature" value=(link.signature);
+
+                        p { "Erasing your data also ends your subscription. This can't be undone." }
+                        button type="submit" { "Erase my data" }
+                    }
+                }
+            }
+        },
+    )
+}
+
+pub fn erased() -> Markup {
+    layout(
+        "Your data was erased",
+        html! {
+            p { "Your data was erased and you won't receive our newsletter anymore." }
+        },
+    )
+}
+
+pub fn invalid_link() -> Markup {
+    layout(
+        "Invalid link",
+        html! {
+            p { "This link is invalid or has expired." }
+            p { a href="/subscriptions/data/request" { "Request a new link" } }
+        },
+    )
+}
@@ -0,0 +1,414 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::UserRole;
+
+use crate::{
+    test_app::{assert_is_redirect_to, ConfirmationLinks, TestApp},
+    test_user::TestUser,
+};
+
+const NEW_PASSWORD: &str = "a-long-enough-new-password";
+
+async fn invite_user(app: &TestApp, email: &str, role: &str) -> ConfirmationLinks {
+    let _mock_guard = Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Invite user")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let response = app
+        .post_form(
+            "/admin/users",
+            &serde_json::json!({ "email": email, "role": role }),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_links(email_request)
+}
+
+async fn set_password(app: &TestApp, link: &reqwest::Url) -> reqwest::Response {
+    let token = link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.to_string())
+        .unwrap();
+
+    app.post_form(
+        "/password/set",
+        &serde_json::json!({
+            "token": token,
+            "new_password": NEW_PASSWORD,
+            "new_password_check": NEW_PASSWORD,
+        }),
+    )
+    .await
+}
+
+/// Logs in with a client of its own, as from another browser.
+async fn log_in_elsewhere(app: &TestApp, username: &str, password: &str) -> reqwest::Client {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let login_page = client
+        .get(format!("{}/login", app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let csrf_token = login_page
+        .split(r#"name="csrf_token" value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap();
+
+    let response = client
+        .post(format!("{}/login", app.address))
+        .form(&serde_json::json!({
+            "username": username,
+            "password": password,
+            "csrf_token": csrf_token,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/admin");
+
+    client
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_users() {
+    let app = TestApp::spawn().await;
+
+    let response = app.get("/admin/users").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn only_admins_can_manage_users() {
+    let app = TestApp::spawn().await;
+    let editor = TestUser::generate_with_role(UserRole::Editor);
+    editor.insert(&app.db_pool).await;
+
+    app.login_as(&editor).await;
+
+    let response = app.get("/admin/users").await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let response = app
+        .post_form(
+            "/admin/users",
+            &serde_json::json!({ "email": "ursula@example.com", "role": "admin" }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn users_are_listed_for_admins() {
+    let app = TestApp::spawn().await;
+
+    app.login_as(&app.test_user).await;
+
+    let html_page = app.get_html("/admin/users").await;
+
+    assert!(html_page.contains(&app.test_user.username));
+}
+
+#[tokio::test]
+async fn invited_users_can_set_their_password_and_log_in() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let links = invite_user(&app, "ursula@example.com", "editor").await;
+
+    let response = reqwest::get(links.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = set_password(&app, &links.html).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "ursula@example.com",
+            "password": NEW_PASSWORD,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin");
+
+    let saved = sqlx::query!("SELECT role FROM users WHERE email = 'ursula@example.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.role, "editor");
+}
+
+#[tokio::test]
+async fn invalid_invitations_are_rejected() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let test_cases = vec![
+        (
+            serde_json::json!({ "email": "not-an-email", "role": "editor" }),
+            "invalid email",
+        ),
+        (
+            serde_json::json!({ "email": "ursula@example.com", "role": "owner" }),
+            "invalid role",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        let response = app.post_form("/admin/users", &invalid_body).await;
+
+        assert_eq!(
+            303,
+            response.status().as_u16(),
+            "The API did not redirect when the payload had an {error_message}"
+        );
+        assert!(response.headers()["Location"]
+            .to_str()
+            .unwrap()
+            .starts_with("/admin/users?error="));
+    }
+}
+
+#[tokio::test]
+async fn password_links_can_only_be_used_once() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let links = invite_user(&app, "ursula@example.com", "viewer").await;
+
+    set_password(&app, &links.html).await;
+
+    let response = set_password(&app, &links.html).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = reqwest::get(links.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn invitations_survive_a_password_reset_request() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let links = invite_user(&app, "ursula@example.com", "viewer").await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_form(
+        "/password/forgot",
+        &serde_json::json!({ "username": "ursula@example.com" }),
+    )
+    .await;
+    app.wait_for_emails(2).await;
+
+    let response = set_password(&app, &links.html).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn expired_password_links_are_rejected() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let links = invite_user(&app, "ursula@example.com", "viewer").await;
+
+    sqlx::query!("UPDATE password_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = set_password(&app, &links.html).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn mismatched_passwords_are_rejected() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let links = invite_user(&app, "ursula@example.com", "viewer").await;
+    let token = links
+        .html
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.to_string())
+        .unwrap();
+
+    let response = app
+        .post_form(
+            "/password/set",
+            &serde_json::json!({
+                "token": token,
+                "new_password": NEW_PASSWORD,
+                "new_password_check": "something-else-entirely",
+            }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 303);
+
+    let html_page = app
+        .get_html(response.headers()["Location"].to_str().unwrap())
+        .await;
+    assert!(html_page.contains("You entered two different passwords."));
+}
+
+#[tokio::test]
+async fn disabled_users_cannot_log_in() {
+    let app = TestApp::spawn().await;
+    let viewer = TestUser::generate_with_role(UserRole::Viewer);
+    viewer.insert(&app.db_pool).await;
+
+    app.login_as(&app.test_user).await;
+
+    let response = app
+        .post_form(&format!("/admin/users/{}/disable", viewer.id), &())
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &viewer.username,
+            "password": &viewer.password,
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login?error=Authentication%20failed");
+}
+
+#[tokio::test]
+async fn forcing_a_password_reset_invalidates_the_current_password() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let links = invite_user(&app, "ursula@example.com", "viewer").await;
+    set_password(&app, &links.html).await;
+
+    let user = sqlx::query!("SELECT id FROM users WHERE email = 'ursula@example.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_form(&format!("/admin/users/{}/password_reset", user.id), &())
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "ursula@example.com",
+            "password": NEW_PASSWORD,
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login?error=Authentication%20failed");
+}
+
+#[tokio::test]
+async fn forcing_a_password_reset_logs_the_user_out_everywhere() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let links = invite_user(&app, "ursula@example.com", "viewer").await;
+    set_password(&app, &links.html).await;
+
+    let user = sqlx::query!("SELECT id FROM users WHERE email = 'ursula@example.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let elsewhere = log_in_elsewhere(&app, "ursula@example.com", NEW_PASSWORD).await;
+    let dashboard = || async {
+        elsewhere
+            .get(format!("{}/admin", app.address))
+            .send()
+            .await
+            .unwrap()
+    };
+
+    assert_eq!(dashboard().await.status().as_u16(), 200);
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_form(&format!("/admin/users/{}/password_reset", user.id), &())
+        .await;
+
+    assert_is_redirect_to(&dashboard().await, "/login");
+    // The administrator's own session is untouched
+    assert_eq!(app.get("/admin").await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn forced_password_resets_whose_email_fails_say_so() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let links = invite_user(&app, "ursula@example.com", "viewer").await;
+    set_password(&app, &links.html).await;
+
+    let user = sqlx::query!("SELECT id FROM users WHERE email = 'ursula@example.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_form(&format!("/admin/users/{}/password_reset", user.id), &())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 303);
+
+    let location = response.headers()["Location"].to_str().unwrap();
+
+    assert!(location.starts_with("/admin/users?error="));
+    assert!(app
+        .get_html(location)
+        .await
+        .contains("was reset, but the email with a link to choose a new one could not be sent"));
+}
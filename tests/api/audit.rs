@@ -0,0 +1,203 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::UserRole;
+
+use crate::{test_app::TestApp, test_user::TestUser};
+
+async fn get_audit_events(app: &TestApp, query: &str) -> serde_json::Value {
+    let response = app.get(&format!("/admin/audit.json?{}", query)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    response.json().await.unwrap()
+}
+
+fn actions(audit_log_page: &serde_json::Value) -> Vec<&str> {
+    audit_log_page["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| event["action"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn logins_are_audited() {
+    let app = TestApp::spawn().await;
+
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password",
+    }))
+    .await;
+    app.login_as(&app.test_user).await;
+
+    let audit_log_page = get_audit_events(&app, "").await;
+    let events = audit_log_page["events"].as_array().unwrap();
+
+    assert_eq!(
+        actions(&audit_log_page),
+        ["login.succeeded", "login.failed"]
+    );
+    assert_eq!(events[0]["actor"], app.test_user.username.as_str());
+    assert_eq!(events[0]["actor_id"], app.test_user.id.to_string());
+    assert_eq!(events[1]["actor"], app.test_user.username.as_str());
+    assert_eq!(events[1]["details"]["reason"], "invalid_credentials");
+    assert_eq!(events[1]["ip_address"], "127.0.0.1");
+}
+
+#[tokio::test]
+async fn publishing_is_audited() {
+    let app = TestApp::spawn().await;
+
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await;
+
+    app.login_as(&app.test_user).await;
+    let audit_log_page = get_audit_events(&app, "action=newsletter.published").await;
+    let events = audit_log_page["events"].as_array().unwrap();
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["actor"], app.test_user.username.as_str());
+    assert_eq!(events[0]["subject"], "Newsletter title");
+    assert_eq!(events[0]["details"]["recipients"], 0);
+}
+
+#[tokio::test]
+async fn subscriber_changes_are_audited() {
+    let app = TestApp::spawn().await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html).await.unwrap();
+
+    app.login_as(&app.test_user).await;
+    let audit_log_page = get_audit_events(&app, "").await;
+
+    assert!(actions(&audit_log_page).contains(&"subscriber.created"));
+    assert!(actions(&audit_log_page).contains(&"subscriber.confirmed"));
+}
+
+#[tokio::test]
+async fn user_management_is_audited() {
+    let app = TestApp::spawn().await;
+    let editor = TestUser::generate_with_role(UserRole::Editor);
+    editor.insert(&app.db_pool).await;
+
+    app.login_as(&app.test_user).await;
+    app.post_form(&format!("/admin/users/{}/disable", editor.id), &())
+        .await;
+
+    let audit_log_page = get_audit_events(&app, "action=user.disabled").await;
+    let events = audit_log_page["events"].as_array().unwrap();
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["actor"], app.test_user.username.as_str());
+    assert_eq!(events[0]["subject"], editor.id.to_string());
+}
+
+#[tokio::test]
+async fn events_can_be_filtered_by_actor() {
+    let app = TestApp::spawn().await;
+    let editor = TestUser::generate_with_role(UserRole::Editor);
+    editor.insert(&app.db_pool).await;
+
+    app.login_as(&editor).await;
+    app.post_form("/admin/logout", &()).await;
+    app.login_as(&app.test_user).await;
+
+    let audit_log_page = get_audit_events(
+        &app,
+        &format!("actor={}", urlencoding::encode(&editor.username)),
+    )
+    .await;
+    let events = audit_log_page["events"].as_array().unwrap();
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["actor"], editor.username.as_str());
+}
+
+#[tokio::test]
+async fn events_are_paginated() {
+    let app = TestApp::spawn().await;
+
+    for i in 0..60 {
+        sqlx::query!(
+            "INSERT INTO audit_events (action, actor) VALUES ('login.failed', $1)",
+            format!("someone-{}", i)
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    app.login_as(&app.test_user).await;
+
+    let first_page = get_audit_events(&app, "action=login.failed").await;
+    assert_eq!(first_page["events"].as_array().unwrap().len(), 50);
+    assert_eq!(first_page["next_page"], 2);
+
+    let second_page = get_audit_events(&app, "action=login.failed&page=2").await;
+    assert_eq!(second_page["events"].as_array().unwrap().len(), 10);
+    assert!(second_page["next_page"].is_null());
+
+    let html_page = app.get_html("/admin/audit?action=login.failed").await;
+    assert!(html_page.contains("someone-59"));
+    assert!(html_page.contains("page=2&amp;action=login.failed"));
+}
+
+#[tokio::test]
+async fn unknown_actions_are_rejected() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let response = app.get("/admin/audit.json?action=nothing.happened").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn only_admins_can_see_the_audit_log() {
+    let app = TestApp::spawn().await;
+    let editor = TestUser::generate_with_role(UserRole::Editor);
+    editor.insert(&app.db_pool).await;
+
+    app.login_as(&editor).await;
+
+    assert_eq!(app.get("/admin/audit").await.status().as_u16(), 403);
+    assert_eq!(app.get("/admin/audit.json").await.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn out_of_range_pages_are_rejected() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    for page in ["0", "-1", &i64::MAX.to_string()] {
+        let response = app.get(&format!("/admin/audit.json?page={page}")).await;
+
+        assert_eq!(response.status().as_u16(), 400, "page={page}");
+    }
+
+    // The last page there can be
+    let response = app
+        .get(&format!("/admin/audit.json?page={}", i64::MAX / 50))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
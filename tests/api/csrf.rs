@@ -0,0 +1,105 @@
+use crate::test_app::{assert_is_redirect_to, TestApp};
+
+#[tokio::test]
+async fn forms_embed_the_session_csrf_token() {
+    let app = TestApp::spawn().await;
+
+    let token = app.csrf_token().await;
+    let html_page = app.get_html("/password/forgot").await;
+
+    assert!(html_page.contains(&format!(r#"name="csrf_token" value="{}""#, token)));
+}
+
+#[tokio::test]
+async fn forms_without_a_csrf_token_are_rejected() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .post_form_without_csrf_token(
+            "/login",
+            &serde_json::json!({
+                "username": &app.test_user.username,
+                "password": &app.test_user.password,
+            }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(response.text().await.unwrap().contains("reload the page"));
+}
+
+#[tokio::test]
+async fn forms_with_a_wrong_csrf_token_are_rejected() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let response = app
+        .post_form_without_csrf_token(
+            "/admin/logout",
+            &serde_json::json!({ "csrf_token": "forged-token" }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+
+    // Still logged in
+    let response = app.get("/admin").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_token_can_be_sent_in_a_header() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .header("X-CSRF-Token", app.csrf_token().await)
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn tokens_do_not_outlive_their_session() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let previous_token = app.csrf_token().await;
+    app.post_form("/admin/logout", &()).await;
+
+    let response = app
+        .post_form_without_csrf_token(
+            "/password/forgot",
+            &serde_json::json!({
+                "username": "someone",
+                "csrf_token": previous_token,
+            }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn only_pages_with_forms_set_the_session_cookie() {
+    let app = TestApp::spawn().await;
+    let client = reqwest::Client::new();
+
+    for (path, sets_cookie) in [("/health_check", false), ("/login", true)] {
+        let response = client
+            .get(format!("{}{}", &app.address, path))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.headers().contains_key("Set-Cookie"),
+            sets_cookie,
+            "GET {path}"
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{body_string_contains, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::UserRole;
+
+use crate::{test_app::TestApp, test_user::TestUser};
+
+async fn create_confirmed_subscriber(app: &TestApp, name: &str, email: &str) {
+    let _mock_guard = Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(format!(
+        "name={}&email={}",
+        urlencoding::encode(name),
+        urlencoding::encode(email)
+    ))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    reqwest::get(app.get_confirmation_links(&email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn publish_issue(app: &TestApp) -> serde_json::Value {
+    app.post_newsletters(json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap()
+    .json()
+    .await
+    .unwrap()
+}
+
+async fn delivery_report(app: &TestApp, issue_id: &serde_json::Value) -> serde_json::Value {
+    app.get(&format!(
+        "/admin/newsletters/{}/deliveries.json",
+        issue_id.as_str().unwrap()
+    ))
+    .await
+    .error_for_status()
+    .unwrap()
+    .json()
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn every_recipient_of_an_issue_has_a_delivery_status() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app, "le guin", "ursula@example.com").await;
+    create_confirmed_subscriber(&app, "butler", "octavia@example.com").await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let summary = publish_issue(&app).await;
+
+    assert_eq!(summary["recipients"], 2);
+    assert_eq!(summary["sent"], 2);
+    assert_eq!(summary["failed"], 0);
+
+    app.login_as(&app.test_user).await;
+
+    let report = delivery_report(&app, &summary["issue_id"]).await;
+
+    assert_eq!(report["title"], "Newsletter title");
+    assert_eq!(report["recipients"], 2);
+    assert_eq!(report["sent"], 2);
+    assert_eq!(report["queued"], 0);
+    assert!(report["last_attempt_at"].is_string());
+    assert!(report["duration_seconds"].is_number());
+    assert_eq!(report["failures"], json!([]));
+}
+
+#[tokio::test]
+async fn failed_sends_are_recorded_with_their_reason_and_dont_stop_the_issue() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app, "le guin", "ursula@example.com").await;
+    create_confirmed_subscriber(&app, "butler", "octavia@example.com").await;
+
+    Mock::given(path("/mail/send"))
+        .and(body_string_contains("ursula@example.com"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/mail/send"))
+        .and(body_string_contains("octavia@example.com"))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let summary = publish_issue(&app).await;
+
+    assert_eq!(summary["sent"], 1);
+    assert_eq!(summary["failed"], 1);
+
+    app.login_as(&app.test_user).await;
+
+    let report = delivery_report(&app, &summary["issue_id"]).await;
+    let failure = &report["failures"][0];
+
+    assert_eq!(report["sent"], 1);
+    assert_eq!(report["failed"], 1);
+    assert_eq!(failure["email"], "ursula@example.com");
+    assert_eq!(failure["status"], "failed");
+    assert!(failure["reason"].as_str().unwrap().contains("500"));
+    assert_eq!(report["failure_reasons"][0]["count"], 1);
+}
+
+#[tokio::test]
+async fn suppressed_recipients_are_reported_as_such() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app, "le guin", "ursula@example.com").await;
+    app.login_as(&app.test_user).await;
+    app.post_form(
+        "/admin/suppressions",
+        &json!({ "target": "ursula@example.com", "reason": "Spam trap" }),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let summary = publish_issue(&app).await;
+    let report = delivery_report(&app, &summary["issue_id"]).await;
+
+    assert_eq!(summary["suppressed"], 1);
+    assert_eq!(report["suppressed"], 1);
+    assert_eq!(report["sent"], 0);
+}
+
+#[tokio::test]
+async fn bounces_reported_by_the_email_provider_show_up_in_the_report() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app, "le guin", "ursula@example.com").await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(202).insert_header("X-Message-Id", "14c5d75ce93.dfd"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let summary = publish_issue(&app).await;
+
+    app.post_sendgrid_events(&json!([{
+        "email": "ursula@example.com",
+        "timestamp": 1675000000,
+        "event": "bounce",
+        "type": "bounce",
+        "reason": "550 5.1.1 The email account does not exist.",
+        "sg_event_id": "1",
+        "sg_message_id": "14c5d75ce93.dfd.filter0001.16648.5515E0B88.0",
+    }]))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    app.login_as(&app.test_user).await;
+
+    let report = delivery_report(&app, &summary["issue_id"]).await;
+
+    assert_eq!(report["sent"], 0);
+    assert_eq!(report["bounced"], 1);
+    assert_eq!(report["failures"][0]["status"], "bounced");
+    assert_eq!(
+        report["failures"][0]["reason"],
+        "550 5.1.1 The email account does not exist."
+    );
+}
+
+#[tokio::test]
+async fn the_delivery_report_page_summarizes_the_issue() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app, "le guin", "ursula@example.com").await;
+
+    Mock::given(path("/mail/send"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let summary = publish_issue(&app).await;
+    let viewer = TestUser::generate_with_role(UserRole::Viewer);
+    viewer.insert(&app.db_pool).await;
+    app.login_as(&viewer).await;
+
+    let html = app
+        .get_html(&format!(
+            "/admin/newsletters/{}/deliveries",
+            summary["issue_id"].as_str().unwrap()
+        ))
+        .await;
+
+    assert!(html.contains("Deliveries of Newsletter title"));
+    assert!(html.contains("Failure reasons"));
+    assert!(html.contains("ursula@example.com"));
+}
+
+#[tokio::test]
+async fn delivery_reports_of_unknown_issues_are_a_404() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let response = app
+        .get(&format!(
+            "/admin/newsletters/{}/deliveries.json",
+            Uuid::new_v4()
+        ))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,250 @@
+use std::time::Duration;
+
+use serde_json::json;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::test_app::TestApp;
+
+async fn create_weekly_subscriber(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query!("UPDATE subscriptions SET frequency = 'weekly'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+async fn publish(app: &TestApp, title: &str) -> serde_json::Value {
+    publish_with_html(app, title, &format!("<p>{title} as HTML</p>")).await
+}
+
+async fn publish_with_html(app: &TestApp, title: &str, html: &str) -> serde_json::Value {
+    let response = app
+        .post_newsletters(json!({
+            "title": title,
+            "content": {
+                "html": html,
+                "text": format!("{title} as plain text"),
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    response.json().await.unwrap()
+}
+
+/// Makes the queued issues a week old, for their digest to be due.
+async fn a_week_goes_by(app: &TestApp) {
+    sqlx::query!("UPDATE digest_entries SET queued_at = now() - interval '8 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+async fn queued_digest_entries(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM digest_entries"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn weekly_subscribers_get_issues_in_their_next_digest() {
+    let app = TestApp::spawn().await;
+    create_weekly_subscriber(&app).await;
+
+    let summary = publish(&app, "First issue").await;
+    publish(&app, "Second issue").await;
+
+    assert_eq!(summary["recipients"], 0);
+    assert_eq!(summary["digested"], 1);
+    assert_eq!(queued_digest_entries(&app).await, 2);
+    // Only the confirmation email was sent
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    a_week_goes_by(&app).await;
+
+    for _ in 0..50 {
+        if queued_digest_entries(&app).await == 0 {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    assert_eq!(queued_digest_entries(&app).await, 0);
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = email["content"][0]["value"].as_str().unwrap();
+    let text = email["content"][1]["value"].as_str().unwrap();
+
+    assert_eq!(email["subject"], "Your weekly digest: 2 new issues");
+    assert!(html.find("First issue as HTML").unwrap() < html.find("Second issue as HTML").unwrap());
+    assert!(text.contains("First issue as plain text"));
+    assert!(text.contains("/preferences?token="));
+}
+
+#[tokio::test]
+async fn issues_published_since_the_last_digest_wait_for_the_next_one() {
+    let app = TestApp::spawn().await;
+    create_weekly_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    publish(&app, "First issue").await;
+
+    // The scheduler checks for due digests a few times over
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    assert_eq!(queued_digest_entries(&app).await, 1);
+}
+
+#[tokio::test]
+async fn digests_too_large_for_one_email_link_to_their_issues() {
+    let app = TestApp::spawn().await;
+    create_weekly_subscriber(&app).await;
+
+    // Each issue fits in an email, but not both together
+    let body = "a".repeat(60 * 1024);
+    let first = publish_with_html(&app, "First issue", &format!("<p>{body}</p>")).await;
+    let second = publish_with_html(&app, "Second issue", &format!("<p>{body}</p>")).await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    a_week_goes_by(&app).await;
+
+    for _ in 0..50 {
+        if queued_digest_entries(&app).await == 0 {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = email["content"][0]["value"].as_str().unwrap();
+
+    assert_eq!(email["subject"], "Your weekly digest: 2 new issues");
+    assert!(!html.contains(&body));
+
+    for summary in [first, second] {
+        let issue_path = format!("/newsletters/{}", summary["issue_id"].as_str().unwrap());
+
+        assert!(html.contains(&format!("{issue_path}\"")));
+
+        let issue_page = reqwest::get(format!("{}{issue_path}", app.address))
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+
+        assert!(issue_page.contains(&body));
+    }
+}
+
+#[tokio::test]
+async fn digests_that_fail_to_send_are_tried_again_later() {
+    let app = TestApp::spawn().await;
+    create_weekly_subscriber(&app).await;
+    publish(&app, "First issue").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    a_week_goes_by(&app).await;
+
+    let attempts = || async {
+        sqlx::query!(
+            r#"
+                SELECT
+                    attempts,
+                    COALESCE(next_attempt_at > now() + interval '10 minutes', false)
+                        AS "backing_off!"
+                FROM digest_entries
+            "#
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+    };
+
+    for _ in 0..50 {
+        if attempts().await.attempts > 0 {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let requests = app.email_server.received_requests().await.unwrap().len();
+
+    // The scheduler checks for due digests a few times over
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let entry = attempts().await;
+
+    assert_eq!(entry.attempts, 1);
+    assert!(entry.backing_off);
+    assert_eq!(
+        app.email_server.received_requests().await.unwrap().len(),
+        requests
+    );
+}
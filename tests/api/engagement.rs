@@ -0,0 +1,288 @@
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::UserRole;
+
+use crate::{test_app::TestApp, test_user::TestUser};
+
+async fn create_confirmed_subscriber(app: &TestApp) -> Uuid {
+    let _mock_guard = Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+/// Publishes an issue linking to two pages, and returns its id with the
+/// email sent to the subscriber.
+async fn publish_issue(app: &TestApp) -> (String, wiremock::Request) {
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response: serde_json::Value = app
+        .post_newsletters(json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Read more at https://example.com/articles/1",
+                "html": "<p><a href=\"https://example.com/articles/1\">Read more</a> \
+                    or <a href=\"https://example.com/?a=1&amp;b=2\">browse</a>.</p>",
+            }
+        }))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    (
+        response["issue_id"].as_str().unwrap().to_string(),
+        email_request,
+    )
+}
+
+async fn issue_engagement(app: &TestApp, issue_id: &str) -> serde_json::Value {
+    app.get(&format!("/admin/newsletters/{issue_id}/engagement"))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn links_in_html_content_are_rewritten_into_tracked_links() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    let (issue_id, email_request) = publish_issue(&app).await;
+
+    let tracking = app.get_tracking_links(&email_request);
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = body["content"][0]["value"].as_str().unwrap();
+    let text = body["content"][1]["value"].as_str().unwrap();
+
+    assert_eq!(tracking.links.len(), 2);
+    assert!(!html.contains("https://example.com"));
+    assert!(html.contains("/preferences?token="));
+    assert!(text.contains("https://example.com/articles/1"));
+    assert_eq!(
+        tracking.pixel.path(),
+        format!("/newsletters/{issue_id}/open")
+    );
+}
+
+#[tokio::test]
+async fn tracked_links_redirect_to_the_original_link_and_count_clicks() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+    let (issue_id, email_request) = publish_issue(&app).await;
+    let tracking = app.get_tracking_links(&email_request);
+
+    for link in [&tracking.links[0], &tracking.links[0], &tracking.links[1]] {
+        app.api_client.get(link.clone()).send().await.unwrap();
+    }
+    let response = app
+        .api_client
+        .get(tracking.links[1].clone())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        "https://example.com/?a=1&b=2"
+    );
+
+    app.login_as(&app.test_user).await;
+
+    let engagement = issue_engagement(&app, &issue_id).await;
+
+    assert_eq!(engagement["title"], "Newsletter title");
+    assert_eq!(engagement["clicks"], 4);
+    assert_eq!(engagement["unique_clicks"], 1);
+    assert_eq!(engagement["opens"], 0);
+    assert_eq!(
+        engagement["links"],
+        json!([
+            { "url": "https://example.com/?a=1&b=2", "clicks": 2, "unique_clicks": 1 },
+            { "url": "https://example.com/articles/1", "clicks": 2, "unique_clicks": 1 },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn the_tracking_pixel_is_a_gif_and_counts_opens() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+    let (issue_id, email_request) = publish_issue(&app).await;
+    let tracking = app.get_tracking_links(&email_request);
+
+    app.api_client
+        .get(tracking.pixel.clone())
+        .send()
+        .await
+        .unwrap();
+    let response = app.api_client.get(tracking.pixel).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("Content-Type").unwrap(), "image/gif");
+    assert!(response.bytes().await.unwrap().starts_with(b"GIF89a"));
+
+    app.login_as(&app.test_user).await;
+
+    let engagement = issue_engagement(&app, &issue_id).await;
+
+    assert_eq!(engagement["opens"], 2);
+    assert_eq!(engagement["unique_opens"], 1);
+}
+
+#[tokio::test]
+async fn tampered_tracking_links_are_not_counted() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+    let (issue_id, email_request) = publish_issue(&app).await;
+    let tracking = app.get_tracking_links(&email_request);
+    let other_subscriber = Uuid::new_v4();
+
+    let tamper = |url: &reqwest::Url| {
+        let signature = url
+            .query_pairs()
+            .find(|(key, _)| key == "signature")
+            .unwrap()
+            .1
+            .into_owned();
+        let mut url = url.clone();
+
+        url.set_query(Some(&format!(
+            "subscriber_id={other_subscriber}&signature={signature}"
+        )));
+        url
+    };
+
+    let click = app
+        .api_client
+        .get(tamper(&tracking.links[0]))
+        .send()
+        .await
+        .unwrap();
+    let open = app
+        .api_client
+        .get(tamper(&tracking.pixel))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(click.status().as_u16(), 400);
+    assert!(click.headers().get("Location").is_none());
+    assert_eq!(open.status().as_u16(), 200);
+
+    app.login_as(&app.test_user).await;
+
+    let engagement = issue_engagement(&app, &issue_id).await;
+
+    assert_eq!(engagement["opens"], 0);
+    assert_eq!(engagement["clicks"], 0);
+}
+
+#[tokio::test]
+async fn engagement_is_reported_per_subscriber() {
+    let app = TestApp::spawn().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let (issue_id, email_request) = publish_issue(&app).await;
+    let tracking = app.get_tracking_links(&email_request);
+
+    app.api_client.get(tracking.pixel).send().await.unwrap();
+    app.api_client
+        .get(tracking.links[0].clone())
+        .send()
+        .await
+        .unwrap();
+
+    app.login_as(&app.test_user).await;
+
+    let response = app
+        .get(&format!("/admin/subscribers/{subscriber_id}/engagement"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let engagement: serde_json::Value = response.json().await.unwrap();
+    let issues = engagement["issues"].as_array().unwrap();
+
+    assert_eq!(engagement["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0]["issue_id"], issue_id);
+    assert_eq!(issues[0]["opens"], 1);
+    assert_eq!(issues[0]["clicks"], 1);
+}
+
+#[tokio::test]
+async fn viewers_can_see_issue_reports_but_not_subscriber_engagement() {
+    let app = TestApp::spawn().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let (issue_id, _) = publish_issue(&app).await;
+    let viewer = TestUser::generate_with_role(UserRole::Viewer);
+    viewer.insert(&app.db_pool).await;
+
+    app.login_as(&viewer).await;
+
+    let issue_report = app
+        .get(&format!("/admin/newsletters/{issue_id}/engagement"))
+        .await;
+    let subscriber_report = app
+        .get(&format!("/admin/subscribers/{subscriber_id}/engagement"))
+        .await;
+
+    assert_eq!(issue_report.status().as_u16(), 200);
+    assert_eq!(subscriber_report.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn reports_of_unknown_issues_are_a_404() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let response = app
+        .get(&format!("/admin/newsletters/{}/engagement", Uuid::new_v4()))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
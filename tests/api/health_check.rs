@@ -13,3 +13,65 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+#[tokio::test]
+async fn the_application_is_live_whatever_its_dependencies() {
+    let app = TestApp::spawn().await;
+    app.db_pool.close().await;
+
+    let response = app.get_health("live").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(body["status"], "live");
+}
+
+#[tokio::test]
+async fn readiness_reports_the_status_and_latency_of_each_dependency() {
+    let app = TestApp::spawn().await;
+
+    let response = app.get_health("ready").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert!(body["checks"]["database"]["latency_ms"].is_u64());
+    // Only checked when asked to
+    assert!(body["checks"].get("email_provider").is_none());
+}
+
+#[tokio::test]
+async fn the_application_is_not_ready_without_its_database() {
+    let app = TestApp::spawn().await;
+    app.db_pool.close().await;
+
+    let response = app.get_health("ready").await;
+
+    assert_eq!(response.status().as_u16(), 503);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(body["status"], "not_ready");
+    assert_eq!(body["checks"]["database"]["status"], "down");
+    assert_eq!(body["checks"]["database"]["error"], "unreachable");
+}
+
+#[tokio::test]
+async fn the_application_is_not_ready_once_shutting_down() {
+    let app = TestApp::spawn().await;
+    app.readiness.mark_shutting_down();
+
+    let response = app.get_health("ready").await;
+
+    assert_eq!(response.status().as_u16(), 503);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(body["status"], "shutting_down");
+    assert_eq!(app.get_health("live").await.status().as_u16(), 200);
+}
@@ -0,0 +1,185 @@
+use argon2::{password_hash::SaltString, Argon2, Params, PasswordHasher};
+
+use crate::test_app::{assert_is_redirect_to, TestApp};
+
+#[tokio::test]
+async fn an_error_message_is_shown_on_failure() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "random-username",
+            "password": "random-password",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login?error=Authentication%20failed");
+
+    let html_page = app.get_html("/login?error=Authentication%20failed").await;
+
+    assert!(html_page.contains("Authentication failed"));
+}
+
+#[tokio::test]
+async fn redirect_to_admin_dashboard_after_login_success() {
+    let app = TestApp::spawn().await;
+
+    app.login_as(&app.test_user).await;
+
+    let html_page = app.get_html("/admin").await;
+
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
+    let app = TestApp::spawn().await;
+
+    let response = app.get("/admin").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn logout_clears_session_state() {
+    let app = TestApp::spawn().await;
+
+    app.login_as(&app.test_user).await;
+
+    let response = app.post_form("/admin/logout", &()).await;
+
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.get("/admin").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn usernames_are_locked_out_after_too_many_failures() {
+    let app = TestApp::spawn().await;
+
+    for _ in 0..5 {
+        let response = app
+            .post_login(&serde_json::json!({
+                "username": &app.test_user.username,
+                "password": "wrong-password",
+            }))
+            .await;
+
+        assert_is_redirect_to(&response, "/login?error=Authentication%20failed");
+    }
+
+    // Even the right password is rejected while locked out
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+
+    assert_is_redirect_to(
+        &response,
+        "/login?error=Too%20many%20failed%20login%20attempts%2C%20try%20again%20later",
+    );
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().contains_key("Retry-After"));
+}
+
+#[tokio::test]
+async fn lockouts_expire() {
+    let app = TestApp::spawn().await;
+
+    for _ in 0..5 {
+        app.post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "wrong-password",
+        }))
+        .await;
+    }
+
+    sqlx::query!("UPDATE login_lockouts SET locked_until = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    app.login_as(&app.test_user).await;
+}
+
+#[tokio::test]
+async fn login_attempts_are_recorded() {
+    let app = TestApp::spawn().await;
+
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password",
+    }))
+    .await;
+    app.login_as(&app.test_user).await;
+
+    let attempts = sqlx::query!(
+        "SELECT succeeded, ip_address FROM login_attempts WHERE username = $1 ORDER BY id",
+        app.test_user.username
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(attempts.len(), 2);
+    assert!(!attempts[0].succeeded);
+    assert!(attempts[1].succeeded);
+    assert_eq!(attempts[1].ip_address.as_deref(), Some("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn outdated_password_hashes_are_upgraded_on_login() {
+    let app = TestApp::spawn().await;
+
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let outdated_hash = Argon2::new(
+        argon2::Algorithm::Argon2i,
+        argon2::Version::V0x13,
+        Params::new(4096, 1, 1, None).unwrap(),
+    )
+    .hash_password(app.test_user.password.as_bytes(), &salt)
+    .unwrap()
+    .to_string();
+
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE id = $2",
+        outdated_hash,
+        app.test_user.id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.login_as(&app.test_user).await;
+
+    let stored_hash = sqlx::query!(
+        "SELECT password_hash FROM users WHERE id = $1",
+        app.test_user.id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .password_hash
+    .unwrap();
+
+    assert!(stored_hash.starts_with("$argon2id$v=19$m=15000,t=2,p=1$"));
+
+    // The upgraded hash still matches the same password
+    app.post_form("/admin/logout", &()).await;
+    app.login_as(&app.test_user).await;
+}
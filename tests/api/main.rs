@@ -1,6 +1,25 @@
+mod admin_users;
+mod audit;
+mod csrf;
+mod deliveries;
+mod digests;
+mod engagement;
 mod health_check;
+mod login;
+mod metrics;
 mod newsletter;
+mod newsletter_assets;
+mod newsletter_issues;
+mod newsletter_preview;
+mod password_reset;
+mod preferences;
+mod shutdown;
+mod subscriber_consents;
+mod subscriber_data;
 mod subscriptions;
 mod subscriptions_confirm;
+mod suppressions;
 mod test_app;
 mod test_user;
+mod two_factor;
+mod webhooks;
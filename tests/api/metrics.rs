@@ -0,0 +1,149 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::test_app::{TestApp, METRICS_TOKEN};
+
+/// The value of a series, or zero when nothing was recorded for it yet.
+///
+/// Metrics are shared by every application spawned by the test suite, so
+/// tests can only rely on them growing.
+async fn scrape(app: &TestApp, series: &str) -> f64 {
+    let text = app.get_metrics().await.text().await.unwrap();
+
+    text.lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .map_or(0.0, |value| value.parse().unwrap())
+}
+
+#[tokio::test]
+async fn metrics_are_exposed_in_the_prometheus_text_format() {
+    let app = TestApp::spawn().await;
+
+    let response = app.get_metrics().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/plain; version=0.0.4"
+    );
+
+    let text = response.text().await.unwrap();
+
+    assert!(text.contains("# TYPE db_pool_connections gauge"));
+    assert!(text.contains(r#"db_pool_connections{state="idle"}"#));
+}
+
+#[tokio::test]
+async fn metrics_need_the_metrics_token() {
+    let app = TestApp::spawn().await;
+    let client = reqwest::Client::new();
+    let url = format!("{}/metrics", &app.address);
+
+    let test_cases = [
+        (client.get(&url), "no token"),
+        (client.get(&url).bearer_auth("wrong-token"), "a wrong token"),
+        (
+            client.get(&url).basic_auth(METRICS_TOKEN, None::<&str>),
+            "basic credentials",
+        ),
+    ];
+
+    for (request, description) in test_cases {
+        let response = request.send().await.unwrap();
+
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Metrics were served with {description}."
+        );
+        assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+    }
+}
+
+#[tokio::test]
+async fn requests_are_counted_by_route_rather_than_path() {
+    let app = TestApp::spawn().await;
+    let series =
+        r#"http_requests_total{method="GET",route="/admin/newsletters/{issue_id}",status="303"}"#;
+    let before = scrape(&app, series).await;
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/newsletters/{}",
+            &app.address,
+            uuid::Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(scrape(&app, series).await >= before + 1.0);
+    assert!(
+        scrape(
+            &app,
+            r#"http_request_duration_seconds_count{method="GET",route="/admin/newsletters/{issue_id}",status="303"}"#
+        )
+        .await
+            >= 1.0
+    );
+}
+
+#[tokio::test]
+async fn logins_are_counted_by_outcome() {
+    let app = TestApp::spawn().await;
+    let succeeded = r#"logins_total{outcome="succeeded"}"#;
+    let failed = r#"logins_total{outcome="failed"}"#;
+    let (succeeded_before, failed_before) =
+        (scrape(&app, succeeded).await, scrape(&app, failed).await);
+
+    app.post_login(&serde_json::json!({
+        "username": "random-username",
+        "password": "random-password",
+    }))
+    .await;
+    app.login_as(&app.test_user).await;
+
+    assert!(scrape(&app, succeeded).await >= succeeded_before + 1.0);
+    assert!(scrape(&app, failed).await >= failed_before + 1.0);
+}
+
+#[tokio::test]
+async fn subscriptions_and_their_confirmation_emails_are_counted() {
+    let app = TestApp::spawn().await;
+    let created = r#"subscriptions_total{event="created"}"#;
+    let confirmed = r#"subscriptions_total{event="confirmed"}"#;
+    let emails = r#"emails_total{kind="confirmation",outcome="sent"}"#;
+    let (created_before, confirmed_before, emails_before) = (
+        scrape(&app, created).await,
+        scrape(&app, confirmed).await,
+        scrape(&app, emails).await,
+    );
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert!(scrape(&app, created).await >= created_before + 1.0);
+    assert!(scrape(&app, confirmed).await >= confirmed_before + 1.0);
+    assert!(scrape(&app, emails).await >= emails_before + 1.0);
+}
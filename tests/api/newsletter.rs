@@ -5,7 +5,12 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::test_app::{ConfirmationLinks, TestApp};
+use zero2prod::domain::UserRole;
+
+use crate::{
+    test_app::{ConfirmationLinks, TestApp},
+    test_user::TestUser,
+};
 
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
@@ -58,6 +63,150 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     assert_eq!(response.status().as_u16(), 200);
 }
 
+#[tokio::test]
+async fn newsletters_written_in_markdown_are_sent_as_html_and_text() {
+    let app = TestApp::spawn().await;
+
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "markdown": "Newsletter body in **Markdown**, [read more](https://example.com/1)."
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = email["content"][0]["value"].as_str().unwrap();
+    let text = email["content"][1]["value"].as_str().unwrap();
+
+    assert!(html.contains("<strong>Markdown</strong>"));
+    assert!(text.starts_with("Newsletter body in Markdown, read more (https://example.com/1)."));
+}
+
+#[tokio::test]
+async fn newsletters_with_scripts_or_event_handlers_are_rejected() {
+    let app = TestApp::spawn().await;
+
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let test_cases = vec![
+        (
+            json!({ "html": "<p>Hi</p><script>alert(1)</script>", "text": "Hi" }),
+            "a script",
+        ),
+        (
+            json!({ "html": "<img src=\"x.png\" onerror=\"alert(1)\">", "text": "Hi" }),
+            "an event handler",
+        ),
+        (
+            json!({ "markdown": "Hi <a href=\"#\" onclick=\"alert(1)\">there</a>" }),
+            "an event handler in Markdown",
+        ),
+    ];
+
+    for (content, description) in test_cases {
+        let response = app
+            .post_newsletters(json!({ "title": "Newsletter title", "content": content }))
+            .await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the content had {description}."
+        );
+    }
+}
+
+#[tokio::test]
+async fn newsletters_are_sent_with_their_styles_inlined() {
+    let app = TestApp::spawn().await;
+
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(json!({
+            "title": "Newsletter title",
+            "content": {
+                "html": "<style>p { color: #333; }</style><p>Newsletter body as HTML</p>",
+                "text": "Newsletter body as plain text"
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = email["content"][0]["value"].as_str().unwrap();
+
+    assert!(html.starts_with(r#"<p style="color: #333">Newsletter body as HTML</p>"#));
+    assert!(!html.contains("<style>"));
+}
+
+#[tokio::test]
+async fn newsletters_too_large_for_email_clients_are_rejected() {
+    let app = TestApp::spawn().await;
+
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(json!({
+            "title": "Newsletter title",
+            "content": {
+                "html": format!("<p>{}</p>", "a".repeat(110 * 1024)),
+                "text": "Newsletter body as plain text"
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.text().await.unwrap().contains("KB"));
+}
+
 #[tokio::test]
 async fn newsletters_returns_400_for_invalid_data() {
     let app = TestApp::spawn().await;
@@ -132,7 +281,7 @@ async fn requests_missing_authorization_are_rejected() {
     let app = TestApp::spawn().await;
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
+        .post(format!("{}/newsletters", &app.address))
         .json(&json!({
             "title": "Newsletter title",
             "content": {
@@ -158,7 +307,7 @@ async fn non_existing_user_is_rejected() {
     let password = Uuid::now_v7().to_string();
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
+        .post(format!("{}/newsletters", &app.address))
         .basic_auth(username, Some(password))
         .json(&json!({
             "title": "Newsletter title",
@@ -187,7 +336,7 @@ async fn invalid_password_is_rejected() {
     assert_ne!(app.test_user.password, password);
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
+        .post(format!("{}/newsletters", &app.address))
         .basic_auth(username, Some(password))
         .json(&json!({
             "title": "Newsletter title",
@@ -206,3 +355,100 @@ async fn invalid_password_is_rejected() {
         response.headers()["WWW-Authenticate"]
     );
 }
+
+#[tokio::test]
+async fn viewers_are_forbidden_from_publishing() {
+    let app = TestApp::spawn().await;
+    let viewer = TestUser::generate_with_role(UserRole::Viewer);
+    viewer.insert(&app.db_pool).await;
+
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters_as(
+            &viewer,
+            json!({
+                "title": "Newsletter title",
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+        )
+        .await;
+
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn editors_are_allowed_to_publish() {
+    let app = TestApp::spawn().await;
+    let editor = TestUser::generate_with_role(UserRole::Editor);
+    editor.insert(&app.db_pool).await;
+
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters_as(
+            &editor,
+            json!({
+                "title": "Newsletter title",
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+        )
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn newsletters_too_large_once_their_links_are_tracked_are_rejected() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Just under the limit as written, but not with every link tracked
+    let links: String = (0..30)
+        .map(|i| format!("<a href=\"https://example.com/{i}\">{i}</a>"))
+        .collect();
+    let html = format!("<p>{}</p><p>{links}</p>", "a".repeat(100 * 1024));
+    assert!(html.len() < 102 * 1024);
+
+    let response = app
+        .post_newsletters(json!({
+            "title": "Newsletter title",
+            "content": {
+                "html": html,
+                "text": "Newsletter body as plain text",
+            },
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("more than the 102 KB email clients show in full"));
+}
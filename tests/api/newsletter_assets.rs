@@ -0,0 +1,337 @@
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::UserRole;
+
+use crate::{test_app::TestApp, test_user::TestUser};
+
+/// The smallest valid PNG, a single transparent pixel.
+const PNG: &[u8] = &[
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4,
+    0x89, 0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x00, 0x01, 0x00, 0x00,
+    0x05, 0x00, 0x01, 0x0d, 0x0a, 0x2d, 0xb4, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae,
+    0x42, 0x60, 0x82,
+];
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// Uploads an asset as the test user and returns its id.
+async fn upload(app: &TestApp, filename: &str, content_type: &str, content: &[u8]) -> String {
+    let response = app
+        .post_asset(&app.test_user, filename, content_type, content.to_vec())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let asset: serde_json::Value = response.json().await.unwrap();
+
+    asset["id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn images_and_documents_can_be_uploaded() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .post_asset(&app.test_user, "pixel.png", "image/png", PNG.to_vec())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let asset: serde_json::Value = response.json().await.unwrap();
+    let asset_id: Uuid = asset["id"].as_str().unwrap().parse().unwrap();
+
+    assert_eq!(asset["filename"], "pixel.png");
+    assert_eq!(asset["content_type"], "image/png");
+    assert_eq!(asset["size"], PNG.len());
+    assert_eq!(asset["content_id"], format!("cid:{asset_id}"));
+
+    let stored = sqlx::query!(
+        "SELECT filename, content FROM newsletter_assets WHERE id = $1",
+        asset_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(stored.filename, "pixel.png");
+    assert_eq!(stored.content, PNG);
+}
+
+#[tokio::test]
+async fn uploads_of_other_types_or_sizes_are_rejected() {
+    let app = TestApp::spawn().await;
+
+    let test_cases = [
+        ("page.html", "text/html", b"<p>Hi</p>".to_vec(), 400),
+        ("program.exe", "application/x-msdownload", vec![1], 400),
+        ("empty.png", "image/png", vec![], 400),
+        ("huge.png", "image/png", vec![0; 3 * 1024 * 1024], 413),
+    ];
+
+    for (filename, content_type, content, status) in test_cases {
+        let response = app
+            .post_asset(&app.test_user, filename, content_type, content)
+            .await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "The API did not reject {filename}."
+        );
+    }
+}
+
+#[tokio::test]
+async fn uploads_need_a_user_allowed_to_publish() {
+    let app = TestApp::spawn().await;
+    let viewer = TestUser::generate_with_role(UserRole::Viewer);
+    viewer.insert(&app.db_pool).await;
+
+    let anonymous = reqwest::Client::new()
+        .post(format!("{}/newsletters/assets", &app.address))
+        .query(&[("filename", "pixel.png")])
+        .header("Content-Type", "image/png")
+        .body(PNG.to_vec())
+        .send()
+        .await
+        .unwrap();
+    let forbidden = app
+        .post_asset(&viewer, "pixel.png", "image/png", PNG.to_vec())
+        .await;
+
+    assert_eq!(anonymous.status().as_u16(), 401);
+    assert_eq!(forbidden.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn newsletters_are_sent_with_their_attachments_and_inline_images() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    let image_id = upload(&app, "pixel.png", "image/png", PNG).await;
+    let document_id = upload(&app, "report.pdf", "application/pdf", b"%PDF-1.4").await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(json!({
+            "title": "Newsletter title",
+            "content": {
+                "markdown": format!("Our numbers:\n\n![Chart](cid:{image_id})"),
+            },
+            "attachments": [document_id],
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = email["content"][0]["value"].as_str().unwrap();
+    let attachments = email["attachments"].as_array().unwrap();
+    let attachment = |filename: &str| {
+        attachments
+            .iter()
+            .find(|a| a["filename"] == filename)
+            .unwrap_or_else(|| panic!("{filename} was not attached."))
+    };
+
+    assert!(html.contains(&format!("src=\"cid:{image_id}\"")));
+    assert_eq!(attachments.len(), 2);
+    assert_eq!(attachment("pixel.png")["disposition"], "inline");
+    assert_eq!(attachment("pixel.png")["content_id"], image_id);
+    assert_eq!(attachment("pixel.png")["content"], base64::encode(PNG));
+    assert_eq!(attachment("report.pdf")["disposition"], "attachment");
+    assert_eq!(attachment("report.pdf")["type"], "application/pdf");
+}
+
+#[tokio::test]
+async fn published_issues_can_be_read_online_with_their_assets() {
+    let app = TestApp::spawn().await;
+
+    let image_id = upload(&app, "pixel.png", "image/png", PNG).await;
+    let document_id = upload(&app, "report.pdf", "application/pdf", b"%PDF-1.4").await;
+    let unrelated_id = upload(&app, "other.png", "image/png", PNG).await;
+
+    let response = app
+        .post_newsletters(json!({
+            "title": "Newsletter title",
+            "content": {
+                "markdown": format!("Our numbers:\n\n![Chart](cid:{image_id})"),
+            },
+            "attachments": [document_id],
+        }))
+        .await;
+    let summary: serde_json::Value = response.json().await.unwrap();
+    let issue_url = format!(
+        "{}/newsletters/{}",
+        app.address,
+        summary["issue_id"].as_str().unwrap()
+    );
+
+    let html = reqwest::get(&issue_url)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let image = reqwest::get(format!("{issue_url}/assets/{image_id}"))
+        .await
+        .unwrap();
+    let unrelated = reqwest::get(format!("{issue_url}/assets/{unrelated_id}"))
+        .await
+        .unwrap();
+
+    assert!(html.contains("<h1>Newsletter title</h1>"));
+    assert!(html.contains(&format!(
+        "src=\"/newsletters/{}/assets/{image_id}\"",
+        summary["issue_id"].as_str().unwrap()
+    )));
+    assert!(html.contains("report.pdf"));
+    assert_eq!(image.status().as_u16(), 200);
+    assert_eq!(image.headers()["Content-Type"], "image/png");
+    assert_eq!(image.bytes().await.unwrap().as_ref(), PNG);
+    assert_eq!(unrelated.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn newsletters_referring_to_unknown_assets_are_rejected() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let unknown = Uuid::new_v4();
+    let test_cases = [
+        json!({
+            "title": "Newsletter title",
+            "content": {
+                "html": format!("<img src=\"cid:{unknown}\">"),
+                "text": "Newsletter body as plain text",
+            },
+        }),
+        json!({
+            "title": "Newsletter title",
+            "content": { "markdown": "Newsletter body" },
+            "attachments": [unknown],
+        }),
+    ];
+
+    for body in test_cases {
+        let response = app.post_newsletters(body).await;
+
+        assert_eq!(response.status().as_u16(), 400);
+        assert!(response
+            .text()
+            .await
+            .unwrap()
+            .contains(&format!("There is no asset {unknown}.")));
+    }
+}
+
+#[tokio::test]
+async fn newsletters_with_too_many_or_too_large_assets_are_rejected() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let mut pixels = Vec::new();
+    let mut reports = Vec::new();
+
+    for i in 0..11 {
+        pixels.push(upload(&app, &format!("pixel-{i}.png"), "image/png", PNG).await);
+    }
+
+    for i in 0..10 {
+        let content = vec![0; 2 * 1024 * 1024];
+        reports.push(
+            upload(
+                &app,
+                &format!("report-{i}.pdf"),
+                "application/pdf",
+                &content,
+            )
+            .await,
+        );
+    }
+
+    let inline_images: String = pixels[..6]
+        .iter()
+        .map(|id| format!("<img src=\"cid:{id}\">"))
+        .collect();
+    let test_cases = [
+        (
+            json!({
+                "title": "Newsletter title",
+                "content": {
+                    "html": inline_images,
+                    "text": "Newsletter body as plain text",
+                },
+                "attachments": pixels[6..],
+            }),
+            "more than 10 assets",
+        ),
+        (
+            json!({
+                "title": "Newsletter title",
+                "content": { "markdown": "Newsletter body" },
+                "attachments": reports,
+            }),
+            "more than 25 MB once encoded",
+        ),
+    ];
+
+    for (body, error_message) in test_cases {
+        let response = app.post_newsletters(body).await;
+
+        assert_eq!(response.status().as_u16(), 400);
+        assert!(response.text().await.unwrap().contains(error_message));
+    }
+}
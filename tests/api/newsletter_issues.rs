@@ -0,0 +1,603 @@
+use std::time::Duration;
+
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, body_string_contains, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::UserRole;
+
+use crate::{
+    test_app::{assert_is_redirect_to, TestApp},
+    test_user::TestUser,
+};
+
+fn draft() -> serde_json::Value {
+    json!({
+        "title": "Newsletter title",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "text_content": "Newsletter body as plain text",
+        "topic": "",
+    })
+}
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// Drafts an issue as the logged in user and returns its path.
+async fn create_draft(app: &TestApp, draft: &serde_json::Value) -> String {
+    let response = app.post_form("/admin/newsletters", draft).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+
+    response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+async fn issue_status(app: &TestApp, issue_path: &str) -> String {
+    let issue_id: Uuid = issue_path.rsplit('/').next().unwrap().parse().unwrap();
+
+    sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status
+}
+
+async fn editor(app: &TestApp) -> TestUser {
+    let editor = TestUser::generate_with_role(UserRole::Editor);
+    editor.insert(&app.db_pool).await;
+    editor
+}
+
+#[tokio::test]
+async fn drafts_are_saved_without_being_sent() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.login_as(&app.test_user).await;
+
+    let issue_path = create_draft(&app, &draft()).await;
+    let issue_page = app.get_html(&issue_path).await;
+    let issues_page = app.get_html("/admin/newsletters").await;
+
+    assert!(issue_page.contains("Newsletter body as HTML"));
+    assert!(issue_page.contains("draft"));
+    assert!(issues_page.contains("Newsletter title"));
+    assert_eq!(issue_status(&app, &issue_path).await, "draft");
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited_over_several_sessions() {
+    let app = TestApp::spawn().await;
+    let editor = editor(&app).await;
+
+    app.login_as(&app.test_user).await;
+    let issue_path = create_draft(&app, &draft()).await;
+
+    app.login_as(&editor).await;
+
+    let mut edited = draft();
+    edited["title"] = json!("Edited title");
+
+    let response = app.post_form(&issue_path, &edited).await;
+
+    assert_is_redirect_to(&response, &issue_path);
+    assert!(app.get_html(&issue_path).await.contains("Edited title"));
+    assert_eq!(issue_status(&app, &issue_path).await, "draft");
+}
+
+#[tokio::test]
+async fn drafts_need_a_title_and_a_known_topic() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    for (field, value) in [("title", ""), ("topic", "unknown")] {
+        let mut invalid = draft();
+        invalid[field] = json!(value);
+
+        let response = app.post_form("/admin/newsletters", &invalid).await;
+
+        assert_eq!(response.status().as_u16(), 303);
+        assert!(response
+            .headers()
+            .get("Location")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("/admin/newsletters/new?error="));
+    }
+
+    let stored_issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+
+    assert_eq!(stored_issues, 0);
+}
+
+#[tokio::test]
+async fn drafts_written_in_markdown_keep_their_source() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let mut markdown_draft = draft();
+    markdown_draft["markdown_content"] = json!("Newsletter body in **Markdown**");
+
+    let issue_path = create_draft(&app, &markdown_draft).await;
+    let issue_id: Uuid = issue_path.rsplit('/').next().unwrap().parse().unwrap();
+    let issue = sqlx::query!(
+        "SELECT html_content, text_content, markdown_content FROM newsletter_issues WHERE id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert!(issue.html_content.contains("<strong>Markdown</strong>"));
+    assert_eq!(issue.text_content, "Newsletter body in Markdown\n");
+    assert!(app
+        .get_html(&issue_path)
+        .await
+        .contains("Newsletter body in **Markdown**"));
+}
+
+#[tokio::test]
+async fn drafts_with_scripts_are_rejected() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+    let issue_path = create_draft(&app, &draft()).await;
+
+    let mut unsafe_draft = draft();
+    unsafe_draft["html_content"] = json!("<p>Hi</p><script>alert(1)</script>");
+
+    let response = app.post_form(&issue_path, &unsafe_draft).await;
+
+    assert!(response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with(&format!("{issue_path}?error=")));
+    assert!(!app.get_html(&issue_path).await.contains("alert(1)"));
+}
+
+#[tokio::test]
+async fn authors_cannot_approve_their_own_issues() {
+    let app = TestApp::spawn().await;
+    let editor = editor(&app).await;
+
+    app.login_as(&app.test_user).await;
+    let issue_path = create_draft(&app, &draft()).await;
+
+    let response = app
+        .post_form(&format!("{issue_path}/approve"), &json!({}))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(issue_status(&app, &issue_path).await, "draft");
+
+    app.login_as(&editor).await;
+
+    let response = app
+        .post_form(&format!("{issue_path}/approve"), &json!({}))
+        .await;
+
+    assert_is_redirect_to(&response, &issue_path);
+    assert_eq!(issue_status(&app, &issue_path).await, "approved");
+    assert!(app.get_html(&issue_path).await.contains(&editor.username));
+}
+
+#[tokio::test]
+async fn editing_an_approved_issue_withdraws_its_approval() {
+    let app = TestApp::spawn().await;
+    let editor = editor(&app).await;
+
+    app.login_as(&app.test_user).await;
+    let issue_path = create_draft(&app, &draft()).await;
+
+    app.login_as(&editor).await;
+    app.post_form(&format!("{issue_path}/approve"), &json!({}))
+        .await;
+    app.post_form(&issue_path, &draft()).await;
+
+    assert_eq!(issue_status(&app, &issue_path).await, "draft");
+}
+
+#[tokio::test]
+async fn publishing_a_draft_sends_it_to_confirmed_subscribers() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.login_as(&app.test_user).await;
+    let issue_path = create_draft(&app, &draft()).await;
+
+    let response = app
+        .post_form(&format!("{issue_path}/publish"), &json!({}))
+        .await;
+
+    assert_is_redirect_to(&response, &format!("{issue_path}/deliveries"));
+    assert_eq!(issue_status(&app, &issue_path).await, "sent");
+    assert!(app
+        .get_html(&format!("{issue_path}/deliveries"))
+        .await
+        .contains("Deliveries of Newsletter title"));
+}
+
+#[tokio::test]
+async fn sent_issues_can_be_neither_edited_nor_sent_again() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.login_as(&app.test_user).await;
+    let issue_path = create_draft(&app, &draft()).await;
+    app.post_form(&format!("{issue_path}/publish"), &json!({}))
+        .await;
+
+    let mut edited = draft();
+    edited["title"] = json!("Edited title");
+
+    let edit = app.post_form(&issue_path, &edited).await;
+    let publish = app
+        .post_form(&format!("{issue_path}/publish"), &json!({}))
+        .await;
+
+    for response in [edit, publish] {
+        assert_eq!(response.status().as_u16(), 303);
+        assert!(response
+            .headers()
+            .get("Location")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with(&format!("{issue_path}?error=")));
+    }
+    assert!(!app.get_html(&issue_path).await.contains("Edited title"));
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_sent_once_due() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.login_as(&app.test_user).await;
+    let issue_path = create_draft(&app, &draft()).await;
+
+    let response = app
+        .post_form(
+            &format!("{issue_path}/schedule"),
+            &json!({ "scheduled_for": "2100-01-01T09:00" }),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, &issue_path);
+    assert_eq!(issue_status(&app, &issue_path).await, "scheduled");
+
+    // Time flies
+    sqlx::query!("UPDATE newsletter_issues SET scheduled_for = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let mut status = String::new();
+
+    for _ in 0..50 {
+        status = issue_status(&app, &issue_path).await;
+
+        if status == "sent" {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    assert_eq!(status, "sent");
+
+    let audit_event =
+        sqlx::query!("SELECT actor FROM audit_events WHERE action = 'newsletter.published'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+
+    assert_eq!(audit_event.actor, Some(app.test_user.username.clone()));
+}
+
+#[tokio::test]
+async fn issues_left_sending_are_resumed_once_their_sender_is_gone() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+    let already_sent_to = sqlx::query!(
+        r#"
+            INSERT INTO subscriptions (email, name, subscribed_at, status)
+            VALUES ('ursula@example.com', 'ursula', now(), 'confirmed')
+            RETURNING id
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .id;
+
+    app.login_as(&app.test_user).await;
+    let issue_path = create_draft(&app, &draft()).await;
+    let issue_id: Uuid = issue_path.rsplit('/').next().unwrap().parse().unwrap();
+
+    // The sender stopped halfway, and its lease ran out
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = 'sending',
+                published_at = now(),
+                sending_lease_until = now() - interval '1 second'
+            WHERE id = $1
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+            INSERT INTO newsletter_deliveries (newsletter_issue_id, subscriber_id, status)
+            SELECT $1, id, CASE WHEN id = $2 THEN 'sent' ELSE 'queued' END
+            FROM subscriptions
+        "#,
+        issue_id,
+        already_sent_to
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .and(body_string_contains("ursula_le_guin@gmail.com"))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let mut status = String::new();
+
+    for _ in 0..50 {
+        status = issue_status(&app, &issue_path).await;
+
+        if status == "sent" {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    assert_eq!(status, "sent");
+
+    // Had the email been sent again, it'd have failed
+    let not_sent = sqlx::query!(
+        r#"
+            SELECT COUNT(*) AS "count!"
+            FROM newsletter_deliveries
+            WHERE newsletter_issue_id = $1 AND status <> 'sent'
+        "#,
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+
+    assert_eq!(not_sent, 0);
+}
+
+#[tokio::test]
+async fn issues_being_sent_are_left_to_their_sender() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+    let issue_path = create_draft(&app, &draft()).await;
+
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = 'sending',
+                published_at = now(),
+                sending_lease_until = now() + interval '1 minute'
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // The scheduler checks for due issues a few times over
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    assert_eq!(issue_status(&app, &issue_path).await, "sending");
+}
+
+#[tokio::test]
+async fn unscheduled_issues_can_be_edited_again() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+    let issue_path = create_draft(&app, &draft()).await;
+
+    app.post_form(
+        &format!("{issue_path}/schedule"),
+        &json!({ "scheduled_for": "2100-01-01T09:00:00Z" }),
+    )
+    .await;
+
+    let edit = app.post_form(&issue_path, &draft()).await;
+
+    assert!(edit
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with(&format!("{issue_path}?error=")));
+
+    app.post_form(&format!("{issue_path}/unschedule"), &json!({}))
+        .await;
+
+    assert_eq!(issue_status(&app, &issue_path).await, "draft");
+    assert_is_redirect_to(&app.post_form(&issue_path, &draft()).await, &issue_path);
+}
+
+#[tokio::test]
+async fn issues_cannot_be_scheduled_in_the_past() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+    let issue_path = create_draft(&app, &draft()).await;
+
+    for scheduled_for in ["2000-01-01T09:00", "tomorrow"] {
+        let response = app
+            .post_form(
+                &format!("{issue_path}/schedule"),
+                &json!({ "scheduled_for": scheduled_for }),
+            )
+            .await;
+
+        assert_eq!(response.status().as_u16(), 303);
+        assert_eq!(issue_status(&app, &issue_path).await, "draft");
+    }
+}
+
+#[tokio::test]
+async fn issues_too_large_for_email_clients_can_be_drafted_but_not_sent() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let mut large = draft();
+    large["html_content"] = json!(format!("<p>{}</p>", "a".repeat(110 * 1024)));
+
+    let issue_path = create_draft(&app, &large).await;
+
+    assert!(app
+        .get_html(&issue_path)
+        .await
+        .contains("more than the 102 KB email clients show in full"));
+
+    let scheduled = app
+        .post_form(
+            &format!("{issue_path}/schedule"),
+            &json!({ "scheduled_for": "2100-01-01T09:00" }),
+        )
+        .await;
+    let published = app
+        .post_form(&format!("{issue_path}/publish"), &json!({}))
+        .await;
+
+    for response in [scheduled, published] {
+        assert_eq!(response.status().as_u16(), 303);
+        assert_eq!(issue_status(&app, &issue_path).await, "draft");
+    }
+}
+
+#[tokio::test]
+async fn issues_larger_than_the_default_payload_limit_can_be_drafted() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let mut large = draft();
+    large["html_content"] = json!(format!("<p>{}</p>", "a".repeat(512 * 1024)));
+
+    let issue_path = create_draft(&app, &large).await;
+
+    assert_eq!(issue_status(&app, &issue_path).await, "draft");
+}
+
+#[tokio::test]
+async fn drafts_cannot_be_read_online() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+    let issue_path = create_draft(&app, &draft()).await;
+    let issue_id = issue_path.rsplit('/').next().unwrap();
+
+    let response = reqwest::get(format!("{}/newsletters/{issue_id}", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn viewers_cannot_draft_issues() {
+    let app = TestApp::spawn().await;
+    let viewer = TestUser::generate_with_role(UserRole::Viewer);
+    viewer.insert(&app.db_pool).await;
+
+    app.login_as(&viewer).await;
+
+    let listing = app.get("/admin/newsletters").await;
+    let creation = app.post_form("/admin/newsletters", &draft()).await;
+
+    assert_eq!(listing.status().as_u16(), 403);
+    assert_eq!(creation.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn unknown_issues_are_a_404() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let response = app
+        .get(&format!("/admin/newsletters/{}", Uuid::new_v4()))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,296 @@
+use serde_json::json;
+use wiremock::{
+    matchers::{any, body_string_contains, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::UserRole;
+
+use crate::{test_app::TestApp, test_user::TestUser};
+
+fn issue() -> serde_json::Value {
+    json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p><a href=\"https://example.com/articles/1\">Read more</a></p>",
+        }
+    })
+}
+
+fn test_send(recipients: &[&str]) -> serde_json::Value {
+    let mut body = issue();
+
+    body["recipients"] = json!(recipients);
+    body
+}
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn stored_issues(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn previews_render_the_issue_as_delivered_without_sending_it() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters_to("/newsletters/preview", &app.test_user, issue())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let preview: serde_json::Value = response.json().await.unwrap();
+    let html = preview["html"].as_str().unwrap();
+    let text = preview["text"].as_str().unwrap();
+
+    assert_eq!(preview["subject"], "Newsletter title");
+    assert!(!html.contains("https://example.com/articles/1"));
+    assert!(html.contains("/newsletters/links/"));
+    assert!(html.contains("/open?"));
+    assert!(html.contains("Manage your subscription preferences"));
+    assert!(text.starts_with("Newsletter body as plain text"));
+    assert!(text.contains("Manage your subscription preferences"));
+    assert!(!text.contains("/preferences?token="));
+    assert_eq!(stored_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn previews_warn_about_what_email_clients_dont_support() {
+    let app = TestApp::spawn().await;
+    let mut body = issue();
+
+    body["content"]["html"] = json!(
+        "<style>@media (max-width: 600px) { p { margin: 0 } } p { margin: 8px }</style>\
+        <p>Hi</p><video src=\"https://example.com/1.mp4\"></video>"
+    );
+
+    let response = app
+        .post_newsletters_to("/newsletters/preview", &app.test_user, body)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let preview: serde_json::Value = response.json().await.unwrap();
+    let html = preview["html"].as_str().unwrap();
+    let warnings = preview["warnings"].as_array().unwrap();
+
+    assert!(html.starts_with(r#"<p style="margin: 8px">Hi</p>"#));
+    assert!(!html.contains("<video"));
+    assert_eq!(warnings.len(), 2);
+}
+
+#[tokio::test]
+async fn previews_warn_when_tracking_makes_issues_too_large_to_publish() {
+    let app = TestApp::spawn().await;
+    let mut body = issue();
+
+    // Just short of the limit before the links are tracked and the footer added
+    body["content"]["html"] = json!(format!("<p>{}</p>", "a".repeat(102 * 1024 - 64)));
+
+    let response = app
+        .post_newsletters_to("/newsletters/preview", &app.test_user, body)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let preview: serde_json::Value = response.json().await.unwrap();
+    let warnings = preview["warnings"].as_array().unwrap();
+
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0]
+        .as_str()
+        .unwrap()
+        .contains("more than the 102 KB email clients show in full"));
+}
+
+#[tokio::test]
+async fn previews_and_tests_need_a_user_allowed_to_publish() {
+    let app = TestApp::spawn().await;
+    let viewer = TestUser::generate_with_role(UserRole::Viewer);
+    viewer.insert(&app.db_pool).await;
+
+    for path in ["/newsletters/preview", "/newsletters/test"] {
+        let anonymous = reqwest::Client::new()
+            .post(format!("{}{path}", &app.address))
+            .json(&test_send(&["ursula@example.com"]))
+            .send()
+            .await
+            .unwrap();
+        let forbidden = app
+            .post_newsletters_to(path, &viewer, test_send(&["ursula@example.com"]))
+            .await;
+
+        assert_eq!(anonymous.status().as_u16(), 401);
+        assert_eq!(forbidden.status().as_u16(), 403);
+    }
+}
+
+#[tokio::test]
+async fn previews_of_issues_for_unknown_topics_are_rejected() {
+    let app = TestApp::spawn().await;
+    let mut body = issue();
+
+    body["topic"] = json!("unknown");
+
+    let response = app
+        .post_newsletters_to("/newsletters/preview", &app.test_user, body)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn test_sends_only_go_to_the_given_addresses() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/mail/send"))
+        .and(body_string_contains("editor@example.com"))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters_to(
+            "/newsletters/test",
+            &app.test_user,
+            test_send(&["editor@example.com"]),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(
+        body["results"],
+        json!([{ "email": "editor@example.com", "status": "sent" }])
+    );
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+    assert_eq!(email["subject"], "[Test] Newsletter title");
+
+    let html = email["content"][0]["value"].as_str().unwrap();
+
+    // Links can be followed, and there are no preferences to link to
+    assert!(html.contains("https://example.com/articles/1"));
+    assert!(!html.contains("/newsletters/links/"));
+    assert!(html.contains("Manage your subscription preferences"));
+    assert!(!html.contains("/preferences?token="));
+    assert_eq!(stored_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn test_sends_report_the_outcome_per_address() {
+    let app = TestApp::spawn().await;
+
+    Mock::given(path("/mail/send"))
+        .and(body_string_contains("broken@example.com"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/mail/send"))
+        .and(body_string_contains("editor@example.com"))
+        .respond_with(ResponseTemplate::new(202))
+        .mount(&app.email_server)
+        .await;
+
+    let body: serde_json::Value = app
+        .post_newsletters_to(
+            "/newsletters/test",
+            &app.test_user,
+            test_send(&["editor@example.com", "broken@example.com"]),
+        )
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        body["results"],
+        json!([
+            { "email": "editor@example.com", "status": "sent" },
+            { "email": "broken@example.com", "status": "failed" },
+        ])
+    );
+
+    let audit_event =
+        sqlx::query!("SELECT subject FROM audit_events WHERE action = 'newsletter.test_sent'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+
+    assert_eq!(audit_event.subject.as_deref(), Some("Newsletter title"));
+}
+
+#[tokio::test]
+async fn test_sends_need_a_few_valid_addresses() {
+    let app = TestApp::spawn().await;
+    let too_many: Vec<String> = (0..11).map(|i| format!("editor{i}@example.com")).collect();
+    let too_many: Vec<&str> = too_many.iter().map(String::as_str).collect();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for (recipients, description) in [
+        (vec![], "no recipients"),
+        (vec!["not-an-email"], "an invalid address"),
+        (too_many, "too many recipients"),
+    ] {
+        let response = app
+            .post_newsletters_to("/newsletters/test", &app.test_user, test_send(&recipients))
+            .await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the payload had {description}."
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::test_app::{assert_is_redirect_to, TestApp};
+
+const NEW_PASSWORD: &str = "a-long-enough-new-password";
+
+async fn set_user_email(app: &TestApp, email: &str) {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE id = $2",
+        email,
+        app.test_user.id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn the_login_form_links_to_the_forgot_password_page() {
+    let app = TestApp::spawn().await;
+
+    let html_page = app.get_html("/login").await;
+
+    assert!(html_page.contains(r#"href="/password/forgot""#));
+}
+
+#[tokio::test]
+async fn known_and_unknown_usernames_get_the_same_response() {
+    let app = TestApp::spawn().await;
+    set_user_email(&app, "ursula@example.com").await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let known = app
+        .post_form(
+            "/password/forgot",
+            &serde_json::json!({ "username": &app.test_user.username }),
+        )
+        .await;
+    let unknown = app
+        .post_form(
+            "/password/forgot",
+            &serde_json::json!({ "username": "nobody-by-that-name" }),
+        )
+        .await;
+
+    assert_is_redirect_to(&known, "/password/forgot?sent=true");
+    assert_is_redirect_to(&unknown, "/password/forgot?sent=true");
+    assert_eq!(known.text().await.unwrap(), unknown.text().await.unwrap());
+}
+
+#[tokio::test]
+async fn no_email_is_sent_for_unknown_usernames() {
+    let app = TestApp::spawn().await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_form(
+        "/password/forgot",
+        &serde_json::json!({ "username": "nobody-by-that-name" }),
+    )
+    .await;
+
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+}
+
+#[tokio::test]
+async fn users_can_reset_their_password_from_the_emailed_link() {
+    let app = TestApp::spawn().await;
+    set_user_email(&app, "ursula@example.com").await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_form(
+        "/password/forgot",
+        &serde_json::json!({ "username": &app.test_user.username }),
+    )
+    .await;
+
+    let email_request = &app.wait_for_emails(1).await[0];
+    let links = app.get_links(email_request);
+    let token = links
+        .html
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.to_string())
+        .unwrap();
+
+    let response = app
+        .post_form(
+            "/password/set",
+            &serde_json::json!({
+                "token": token,
+                "new_password": NEW_PASSWORD,
+                "new_password_check": NEW_PASSWORD,
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": NEW_PASSWORD,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin");
+}
+
+#[tokio::test]
+async fn repeated_requests_send_a_single_email() {
+    let app = TestApp::spawn().await;
+    set_user_email(&app, "ursula@example.com").await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..3 {
+        let response = app
+            .post_form(
+                "/password/forgot",
+                &serde_json::json!({ "username": &app.test_user.username }),
+            )
+            .await;
+        assert_is_redirect_to(&response, "/password/forgot?sent=true");
+    }
+
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+}
@@ -0,0 +1,286 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::test_app::{assert_is_redirect_to, TestApp};
+
+/// Subscribe and confirm a subscriber, returning the link to their
+/// preferences from the confirmation email.
+async fn create_confirmed_subscriber(app: &TestApp) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    app.get_preferences_link(email_request)
+}
+
+async fn create_topics(app: &TestApp) {
+    sqlx::query!("INSERT INTO topics (slug, name) VALUES ('rust', 'Rust'), ('sql', 'SQL')")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+fn token(preferences_link: &reqwest::Url) -> String {
+    preferences_link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+async fn save_preferences(
+    app: &TestApp,
+    preferences_link: &reqwest::Url,
+    fields: &[(&str, &str)],
+) -> reqwest::Response {
+    let token = token(preferences_link);
+    let mut form = vec![("token", token.as_str())];
+    form.extend_from_slice(fields);
+
+    // Topics are repeated fields, which the JSON based `post_form` can't send
+    let csrf_token = app.csrf_token().await;
+    form.push(("csrf_token", &csrf_token));
+
+    app.post_form_without_csrf_token("/preferences", &form)
+        .await
+}
+
+fn newsletter(topic: Option<&str>) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "topic": topic,
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn newsletters_link_to_the_preferences_of_their_recipient() {
+    let app = TestApp::spawn().await;
+    let preferences_link = create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter(None))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+    assert_eq!(app.get_preferences_link(email_request), preferences_link);
+    assert!(body["content"][1]["value"]
+        .as_str()
+        .unwrap()
+        .contains(preferences_link.path()));
+}
+
+#[tokio::test]
+async fn the_preferences_page_shows_the_current_preferences() {
+    let app = TestApp::spawn().await;
+    create_topics(&app).await;
+    let preferences_link = create_confirmed_subscriber(&app).await;
+
+    let response = app.api_client.get(preferences_link).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let html_page = response.text().await.unwrap();
+
+    assert!(html_page.contains(r#"value="le guin""#));
+    assert!(html_page.contains(r#"value="immediate" checked"#));
+    assert!(html_page.contains(r#"value="rust" checked"#));
+    assert!(html_page.contains(r#"value="sql" checked"#));
+}
+
+#[tokio::test]
+async fn an_unknown_preferences_token_is_rejected() {
+    let app = TestApp::spawn().await;
+
+    let response = app.get("/preferences?token=not-a-token").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_preferences() {
+    let app = TestApp::spawn().await;
+    create_topics(&app).await;
+    let preferences_link = create_confirmed_subscriber(&app).await;
+
+    let response = save_preferences(
+        &app,
+        &preferences_link,
+        &[
+            ("name", "Ursula K. Le Guin"),
+            ("frequency", "weekly"),
+            ("topics", "sql"),
+            ("pause_weeks", "4"),
+        ],
+    )
+    .await;
+
+    assert_is_redirect_to(
+        &response,
+        &format!("/preferences?token={}&saved=true", token(&preferences_link)),
+    );
+
+    let saved = sqlx::query!(
+        r#"
+            SELECT name, frequency, paused_until > now() + interval '27 days' AS "paused!"
+            FROM subscriptions
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+    assert_eq!(saved.frequency, "weekly");
+    assert!(saved.paused);
+
+    let opt_outs = sqlx::query!("SELECT topic_slug FROM subscriber_topic_opt_outs")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(opt_outs.len(), 1);
+    assert_eq!(opt_outs[0].topic_slug, "rust");
+
+    let mut saved_link = preferences_link.clone();
+    saved_link.query_pairs_mut().append_pair("saved", "true");
+    let html_page = app
+        .api_client
+        .get(saved_link)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html_page.contains("Your preferences were saved."));
+    assert!(html_page.contains("Delivery is paused until"));
+}
+
+#[tokio::test]
+async fn invalid_names_are_rejected() {
+    let app = TestApp::spawn().await;
+    let preferences_link = create_confirmed_subscriber(&app).await;
+
+    let response = save_preferences(
+        &app,
+        &preferences_link,
+        &[("name", "<script>"), ("frequency", "weekly")],
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(response.headers()["Location"]
+        .to_str()
+        .unwrap()
+        .contains("&error="));
+
+    let saved = sqlx::query!("SELECT name, frequency FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.frequency, "immediate");
+}
+
+#[tokio::test]
+async fn paused_subscribers_do_not_receive_newsletters() {
+    let app = TestApp::spawn().await;
+    let preferences_link = create_confirmed_subscriber(&app).await;
+
+    save_preferences(
+        &app,
+        &preferences_link,
+        &[
+            ("name", "le guin"),
+            ("frequency", "immediate"),
+            ("pause_weeks", "1"),
+        ],
+    )
+    .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter(None)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribers_only_receive_the_topics_they_kept() {
+    let app = TestApp::spawn().await;
+    create_topics(&app).await;
+    let preferences_link = create_confirmed_subscriber(&app).await;
+
+    save_preferences(
+        &app,
+        &preferences_link,
+        &[
+            ("name", "le guin"),
+            ("frequency", "immediate"),
+            ("topics", "sql"),
+        ],
+    )
+    .await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    for topic in [Some("rust"), Some("sql"), None] {
+        app.post_newsletters(newsletter(topic))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn newsletters_about_an_unknown_topic_are_rejected() {
+    let app = TestApp::spawn().await;
+
+    let response = app.post_newsletters(newsletter(Some("cooking"))).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
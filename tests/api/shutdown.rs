@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::test_app::TestApp;
+
+#[tokio::test]
+async fn requests_in_flight_finish_before_the_database_pool_is_closed() {
+    let app = TestApp::spawn().await;
+
+    // A slow email provider keeps the subscription request in flight
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let in_flight = tokio::spawn(
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+            .send(),
+    );
+
+    // Wait for the confirmation email to be underway
+    for _ in 0..50 {
+        if !app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .is_empty()
+        {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    app.shutdown.trigger();
+
+    let response = in_flight.await.unwrap().unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.stopped.await.unwrap().unwrap();
+
+    assert!(app.db_pool.is_closed());
+    assert!(reqwest::get(format!("{}/health/live", &app.address))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn issues_being_sent_stop_before_the_next_subscriber() {
+    let app = TestApp::spawn().await;
+
+    for email in ["ursula@example.com", "le_guin@example.com"] {
+        sqlx::query!(
+            r#"
+                INSERT INTO subscriptions (email, name, subscribed_at, status)
+                VALUES ($1, 'ursula', now(), 'confirmed')
+            "#,
+            email
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let publish = app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "html": "<p>Newsletter body as HTML</p>",
+            "text": "Newsletter body as plain text",
+        }
+    }));
+    let shut_down_while_sending = async {
+        // Wait for the first email to be underway
+        for _ in 0..50 {
+            if !app
+                .email_server
+                .received_requests()
+                .await
+                .unwrap()
+                .is_empty()
+            {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        app.shutdown.trigger();
+    };
+    let (response, ()) = tokio::join!(publish, shut_down_while_sending);
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let summary: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(summary["sent"], 1);
+    assert_eq!(summary["queued"], 1);
+
+    app.stopped.await.unwrap().unwrap();
+
+    let pool = sqlx::PgPool::connect_with(app.db_pool.connect_options().clone())
+        .await
+        .unwrap();
+    let issue = sqlx::query!("SELECT status, sending_lease_until FROM newsletter_issues")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+    // Left for the scheduler to resume right away
+    assert_eq!(issue.status, "sending");
+    assert_eq!(issue.sending_lease_until, None);
+}
@@ -0,0 +1,149 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::UserRole;
+
+use crate::{test_app::TestApp, test_user::TestUser};
+
+async fn subscribe_from_a_browser(app: &TestApp) -> reqwest::Response {
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "Mozilla/5.0 (test)")
+        .header("Referer", "https://example.com/blog/post")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com&source=footer")
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn subscriber_id(app: &TestApp) -> uuid::Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+#[tokio::test]
+async fn subscribing_and_confirming_are_recorded_as_consent() {
+    let app = TestApp::spawn().await;
+
+    subscribe_from_a_browser(&app)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // Following the link twice only records one confirmation
+    for _ in 0..2 {
+        reqwest::Client::new()
+            .get(confirmation_links.html.clone())
+            .header("User-Agent", "Mail client")
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    let subscriber_id = subscriber_id(&app).await;
+
+    app.login_as(&app.test_user).await;
+    let response = app
+        .get(&format!("/admin/subscribers/{}/consents", subscriber_id))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let history: serde_json::Value = response.json().await.unwrap();
+    let consents = history["consents"].as_array().unwrap();
+
+    assert_eq!(history["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(history["status"], "confirmed");
+    assert_eq!(consents.len(), 2);
+    assert_eq!(consents[0]["event"], "subscribed");
+    assert_eq!(consents[0]["ip_address"], "127.0.0.1");
+    assert_eq!(consents[0]["user_agent"], "Mozilla/5.0 (test)");
+    assert_eq!(consents[0]["form_source"], "footer");
+    assert_eq!(consents[1]["event"], "confirmed");
+    assert_eq!(consents[1]["ip_address"], "127.0.0.1");
+    assert_eq!(consents[1]["user_agent"], "Mail client");
+    assert!(consents[0]["recorded_at"].is_string());
+}
+
+#[tokio::test]
+async fn the_referer_is_the_form_source_when_the_form_does_not_say() {
+    let app = TestApp::spawn().await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Referer", "https://example.com/blog/post")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let consent = sqlx::query!("SELECT event, form_source FROM consent_records")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(consent.event, "subscribed");
+    assert_eq!(
+        consent.form_source.as_deref(),
+        Some("https://example.com/blog/post")
+    );
+}
+
+#[tokio::test]
+async fn the_consent_history_of_an_unknown_subscriber_is_a_404() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let response = app
+        .get(&format!(
+            "/admin/subscribers/{}/consents",
+            uuid::Uuid::new_v4()
+        ))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn only_admins_can_see_consent_histories() {
+    let app = TestApp::spawn().await;
+    subscribe_from_a_browser(&app)
+        .await
+        .error_for_status()
+        .unwrap();
+    let subscriber_id = subscriber_id(&app).await;
+    let editor = TestUser::generate_with_role(UserRole::Editor);
+    editor.insert(&app.db_pool).await;
+
+    app.login_as(&editor).await;
+    let response = app
+        .get(&format!("/admin/subscribers/{}/consents", subscriber_id))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
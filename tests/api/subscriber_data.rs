@@ -0,0 +1,308 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::UserRole;
+
+use crate::{
+    test_app::{assert_is_redirect_to, TestApp},
+    test_user::TestUser,
+};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+/// Subscribe and confirm `EMAIL`, returning the subscriber's id.
+async fn create_confirmed_subscriber(app: &TestApp) -> uuid::Uuid {
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+/// Request a data link for `EMAIL` and return it.
+async fn request_data_link(app: &TestApp) -> reqwest::Url {
+    let sent_emails = app.email_server.received_requests().await.unwrap().len();
+
+    let response = app
+        .post_form(
+            "/subscriptions/data/request",
+            &serde_json::json!({ "email": EMAIL }),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, "/subscriptions/data/request?sent=true");
+
+    let email_requests = app.wait_for_emails(sent_emails + 1).await;
+
+    app.get_links(&email_requests[sent_emails]).html
+}
+
+async fn export(app: &TestApp, email: &str) -> reqwest::Response {
+    app.get(&format!(
+        "/admin/subscribers/export?email={}",
+        urlencoding::encode(email)
+    ))
+    .await
+}
+
+#[tokio::test]
+async fn a_subscriber_can_see_their_data_from_an_emailed_link() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    let link = request_data_link(&app).await;
+    let response = app.api_client.get(link).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let html_page = response.text().await.unwrap();
+
+    assert!(html_page.contains(EMAIL));
+    assert!(html_page.contains("le guin"));
+    assert!(html_page.contains("confirmed"));
+    assert!(html_page.contains("Erase my data"));
+}
+
+#[tokio::test]
+async fn no_data_link_is_sent_for_unknown_emails() {
+    let app = TestApp::spawn().await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_form(
+            "/subscriptions/data/request",
+            &serde_json::json!({ "email": "nobody@example.com" }),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, "/subscriptions/data/request?sent=true");
+
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+}
+
+#[tokio::test]
+async fn repeated_requests_send_a_single_data_link() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    request_data_link(&app).await;
+
+    let response = app
+        .post_form(
+            "/subscriptions/data/request",
+            &serde_json::json!({ "email": EMAIL }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/subscriptions/data/request?sent=true");
+
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    // The confirmation email, then a single data link
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(email_requests.len(), 2);
+}
+
+#[tokio::test]
+async fn a_tampered_data_link_is_rejected() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    let mut link = request_data_link(&app).await;
+    let query: Vec<(String, String)> = link
+        .query_pairs()
+        .map(|(key, value)| {
+            let value = match key.as_ref() {
+                "email" => "someone.else@example.com".to_string(),
+                _ => value.into_owned(),
+            };
+            (key.into_owned(), value)
+        })
+        .collect();
+    link.query_pairs_mut().clear().extend_pairs(query);
+
+    let response = app.api_client.get(link).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("This link is invalid or has expired."));
+}
+
+#[tokio::test]
+async fn a_subscriber_can_erase_their_data() {
+    let app = TestApp::spawn().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+
+    let link = request_data_link(&app).await;
+    let query: serde_json::Map<_, _> = link
+        .query_pairs()
+        .map(|(key, value)| (key.into_owned(), value.into_owned().into()))
+        .collect();
+
+    let response = app.post_form("/subscriptions/data/erase", &query).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let subscribers = sqlx::query!("SELECT COUNT(*) AS count FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let tokens = sqlx::query!("SELECT COUNT(*) AS count FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let consents = sqlx::query!("SELECT COUNT(*) AS count FROM consent_records")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(subscribers.count, Some(0));
+    assert_eq!(tokens.count, Some(0));
+    assert_eq!(consents.count, Some(0));
+
+    let subjects = sqlx::query!(
+        "SELECT action, subject FROM audit_events WHERE action LIKE 'subscriber.%' ORDER BY id"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(subjects.len(), 3);
+    assert_eq!(subjects[2].action, "subscriber.erased");
+
+    for event in &subjects {
+        let subject = event.subject.as_deref().unwrap();
+
+        assert!(subject.starts_with("erased:"));
+        assert_eq!(subject, subjects[0].subject.as_deref().unwrap());
+        assert!(!subject.contains(&subscriber_id.to_string()));
+        assert!(!subject.contains(EMAIL));
+    }
+}
+
+#[tokio::test]
+async fn an_admin_can_export_everything_held_about_an_email() {
+    let app = TestApp::spawn().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    app.login_as(&app.test_user).await;
+
+    let response = export(&app, EMAIL).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let data: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(data["subscriber"]["id"], subscriber_id.to_string());
+    assert_eq!(data["subscriber"]["email"], EMAIL);
+    assert_eq!(data["subscriber"]["name"], "le guin");
+    assert_eq!(data["subscriber"]["status"], "confirmed");
+    assert_eq!(data["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(data["consents"].as_array().unwrap().len(), 2);
+    assert_eq!(
+        data["audit_events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|event| event["action"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        ["subscriber.created", "subscriber.confirmed"]
+    );
+}
+
+#[tokio::test]
+async fn exporting_an_unknown_or_invalid_email_fails() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    assert_eq!(
+        export(&app, "nobody@example.com").await.status().as_u16(),
+        404
+    );
+    assert_eq!(export(&app, "not-an-email").await.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn an_admin_can_erase_a_subscriber() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_as(&app.test_user).await;
+
+    let response = app
+        .post_form(
+            "/admin/subscribers/erase",
+            &serde_json::json!({ "email": EMAIL }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(export(&app, EMAIL).await.status().as_u16(), 404);
+
+    let erasure = sqlx::query!(
+        "SELECT actor_id, details FROM audit_events WHERE action = 'subscriber.erased'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(erasure.actor_id, Some(app.test_user.id));
+    assert_eq!(erasure.details["requested_by"], "admin");
+
+    let response = app
+        .post_form(
+            "/admin/subscribers/erase",
+            &serde_json::json!({ "email": EMAIL }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn only_admins_can_export_and_erase_subscribers() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+    let editor = TestUser::generate_with_role(UserRole::Editor);
+    editor.insert(&app.db_pool).await;
+
+    app.login_as(&editor).await;
+
+    assert_eq!(export(&app, EMAIL).await.status().as_u16(), 403);
+
+    let response = app
+        .post_form(
+            "/admin/subscribers/erase",
+            &serde_json::json!({ "email": EMAIL }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
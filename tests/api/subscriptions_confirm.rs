@@ -9,7 +9,7 @@ use crate::test_app::TestApp;
 async fn confirmations_without_token_are_rejected_with_a_400() {
     let app = TestApp::spawn().await;
 
-    let response = reqwest::get(&format!("{}/subscriptions/confirm", app.address))
+    let response = reqwest::get(format!("{}/subscriptions/confirm", app.address))
         .await
         .unwrap();
 
@@ -0,0 +1,236 @@
+use serde_json::json;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::UserRole;
+
+use crate::{test_app::TestApp, test_user::TestUser};
+
+async fn suppress(app: &TestApp, target: &str) -> reqwest::Response {
+    app.post_form(
+        "/admin/suppressions",
+        &json!({ "target": target, "reason": "Asked us never to write again" }),
+    )
+    .await
+}
+
+async fn create_confirmed_subscriber(app: &TestApp, name: &str, email: &str) {
+    let _mock_guard = Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(format!(
+        "name={}&email={}",
+        urlencoding::encode(name),
+        urlencoding::encode(email)
+    ))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    reqwest::get(app.get_confirmation_links(&email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+fn newsletter() -> serde_json::Value {
+    json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_not_sent_to_suppressed_addresses() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let response = suppress(&app, "Ursula_Le_Guin@gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn newsletters_are_not_sent_to_suppressed_domains() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app, "le guin", "ursula@suppressed.example.com").await;
+    create_confirmed_subscriber(&app, "butler", "octavia@example.com").await;
+    app.login_as(&app.test_user).await;
+
+    suppress(&app, "suppressed.example.com")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+    assert_eq!(
+        body["personalizations"][0]["to"][0]["email"],
+        "octavia@example.com"
+    );
+}
+
+#[tokio::test]
+async fn removed_suppressions_no_longer_apply() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    app.login_as(&app.test_user).await;
+
+    let suppression: serde_json::Value = suppress(&app, "ursula_le_guin@gmail.com")
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    let response = app
+        .post_form(
+            &format!("/admin/suppressions/{}/delete", suppression["id"]),
+            &json!({}),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter())
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn suppressions_are_listed_with_their_reason_and_source() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    app.post_form(
+        "/admin/suppressions",
+        &json!({
+            "target": "example.com",
+            "reason": "Spam trap domain",
+            "source": "deliverability review",
+        }),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    suppress(&app, "ursula@example.org")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let response = app.get("/admin/suppressions").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let page: serde_json::Value = response.json().await.unwrap();
+    let suppressions = page["suppressions"].as_array().unwrap();
+
+    assert_eq!(suppressions.len(), 2);
+    assert_eq!(suppressions[0]["kind"], "address");
+    assert_eq!(suppressions[0]["value"], "ursula@example.org");
+    assert_eq!(suppressions[0]["source"], "admin");
+    assert_eq!(suppressions[0]["created_by"], app.test_user.id.to_string());
+    assert_eq!(suppressions[1]["kind"], "domain");
+    assert_eq!(suppressions[1]["reason"], "Spam trap domain");
+    assert_eq!(suppressions[1]["source"], "deliverability review");
+}
+
+#[tokio::test]
+async fn invalid_or_duplicate_suppressions_are_rejected() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    assert_eq!(suppress(&app, "not a domain").await.status().as_u16(), 400);
+    assert_eq!(
+        app.post_form(
+            "/admin/suppressions",
+            &json!({ "target": "example.com", "reason": " " }),
+        )
+        .await
+        .status()
+        .as_u16(),
+        400
+    );
+
+    assert_eq!(suppress(&app, "example.com").await.status().as_u16(), 201);
+    assert_eq!(suppress(&app, "Example.com").await.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn removing_an_unknown_suppression_is_a_404() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let response = app
+        .post_form("/admin/suppressions/42/delete", &json!({}))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn only_admins_can_manage_suppressions() {
+    let app = TestApp::spawn().await;
+    let editor = TestUser::generate_with_role(UserRole::Editor);
+    editor.insert(&app.db_pool).await;
+
+    app.login_as(&editor).await;
+
+    assert_eq!(app.get("/admin/suppressions").await.status().as_u16(), 403);
+    assert_eq!(suppress(&app, "example.com").await.status().as_u16(), 403);
+}
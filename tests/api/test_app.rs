@@ -1,29 +1,50 @@
 //! tests/health_check.rs
 use linkify::{LinkFinder, LinkKind};
 use once_cell::sync::Lazy;
-use secrecy::ExposeSecret;
+use p256::{
+    ecdsa::{signature::Signer, Signature, SigningKey},
+    pkcs8::EncodePublicKey,
+};
+use rand::Rng;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use std::net::TcpListener;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 use wiremock::MockServer;
 use zero2prod::{
     application::Application,
     db::DB,
-    settings::Settings,
+    health::Readiness,
+    settings::{Settings, TelemetrySettings},
+    shutdown::Shutdown,
     telemetry::{get_subscriber, init_subscriber},
 };
 
 use crate::test_user::TestUser;
 
+/// The token the test application expects scrapes of `/metrics` to carry.
+pub const METRICS_TOKEN: &str = "metrics-token";
+
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
     let subscriber_name = "test".to_string();
 
     if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::stdout,
+            &TelemetrySettings::default(),
+        );
         init_subscriber(subscriber);
     } else {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::sink,
+            &TelemetrySettings::default(),
+        );
         init_subscriber(subscriber);
     }
 });
@@ -34,12 +55,26 @@ pub struct ConfirmationLinks {
     pub text: reqwest::Url,
 }
 
+/// The tracked links of a newsletter issue, in order, and its tracking pixel.
+#[derive(Debug)]
+pub struct TrackingLinks {
+    pub links: Vec<reqwest::Url>,
+    pub pixel: reqwest::Url,
+}
+
 pub struct TestApp {
     pub test_user: TestUser,
     pub address: String,
     pub port: u16,
     pub db_pool: PgPool,
     pub email_server: MockServer,
+    pub api_client: reqwest::Client,
+    /// Signs events the way SendGrid does.
+    pub event_webhook_key: SigningKey,
+    pub readiness: Readiness,
+    pub shutdown: Shutdown,
+    /// Resolves once the application stopped, after shutdown.
+    pub stopped: JoinHandle<Result<(), std::io::Error>>,
 }
 
 impl TestApp {
@@ -51,11 +86,21 @@ impl TestApp {
         // Mock email server
         let email_server = MockServer::start().await;
 
+        let event_webhook_key = generate_signing_key();
+
         // Load settings and mutate with mock server URL
         let settings = {
             let mut settings = Settings::load().expect("Failed to read configuration");
+            let public_key = event_webhook_key
+                .verifying_key()
+                .to_public_key_der()
+                .unwrap();
 
             settings.email_client.base_url = email_server.uri();
+            settings.application.metrics_token = Some(Secret::new(METRICS_TOKEN.into()));
+            settings.email_client.webhook_public_key = Some(base64::encode(public_key.as_bytes()));
+            // Send scheduled issues soon after they're due
+            settings.newsletter.scheduler_interval_milliseconds = 100;
             settings
         };
 
@@ -79,8 +124,16 @@ impl TestApp {
             .build();
 
         let port = application.port();
+        let readiness = application.readiness();
+        let shutdown = application.shutdown();
+        let stopped = tokio::spawn(application.run_until_stopped());
 
-        let _ = tokio::spawn(application.run_until_stopped());
+        // Keep cookies around and let tests inspect redirects
+        let api_client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .cookie_store(true)
+            .build()
+            .unwrap();
 
         let app = Self {
             test_user: TestUser::generate(),
@@ -88,6 +141,11 @@ impl TestApp {
             port,
             db_pool,
             email_server,
+            api_client,
+            event_webhook_key,
+            readiness,
+            shutdown,
+            stopped,
         };
 
         app.test_user.insert(&app.db_pool).await;
@@ -97,7 +155,7 @@ impl TestApp {
 
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -105,31 +163,213 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    /// Post events to the webhook, signed by `event_webhook_key`.
+    pub async fn post_sendgrid_events(&self, events: &serde_json::Value) -> reqwest::Response {
+        let body = serde_json::to_vec(events).unwrap();
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+        let signature = self.sign_sendgrid_events(&timestamp, &body);
+
+        self.post_sendgrid_events_with_signature(body, &timestamp, &signature)
+            .await
+    }
+
+    pub fn sign_sendgrid_events(&self, timestamp: &str, body: &[u8]) -> String {
+        let mut payload = timestamp.as_bytes().to_vec();
+        payload.extend_from_slice(body);
+        let signature: Signature = self.event_webhook_key.sign(&payload);
+
+        base64::encode(signature.to_der().as_bytes())
+    }
+
+    pub async fn post_sendgrid_events_with_signature(
+        &self,
+        body: Vec<u8>,
+        timestamp: &str,
+        signature: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/webhooks/sendgrid", &self.address))
+            .header("Content-Type", "application/json")
+            .header("X-Twilio-Email-Event-Webhook-Timestamp", timestamp)
+            .header("X-Twilio-Email-Event-Webhook-Signature", signature)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_health_check(&self) -> reqwest::Response {
         reqwest::Client::new()
-            .get(&format!("{}/health_check", &self.address))
+            .get(format!("{}/health_check", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_health(&self, check: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/health/{check}", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_metrics(&self) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/metrics", &self.address))
+            .bearer_auth(METRICS_TOKEN)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
     pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        self.post_newsletters_as(&self.test_user, body).await
+    }
+
+    pub async fn post_newsletters_as(
+        &self,
+        user: &TestUser,
+        body: serde_json::Value,
+    ) -> reqwest::Response {
+        self.post_newsletters_to("/newsletters", user, body).await
+    }
+
+    /// Posts to one of the `/newsletters` routes, with 'Basic' credentials.
+    pub async fn post_newsletters_to(
+        &self,
+        path: &str,
+        user: &TestUser,
+        body: serde_json::Value,
+    ) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/newsletters", &self.address))
-            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .post(format!("{}{path}", &self.address))
+            .basic_auth(&user.username, Some(&user.password))
             .json(&body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
-    pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+    /// Uploads a newsletter asset as `user`, with 'Basic' credentials.
+    pub async fn post_asset(
+        &self,
+        user: &TestUser,
+        filename: &str,
+        content_type: &str,
+        content: Vec<u8>,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters/assets", &self.address))
+            .query(&[("filename", filename)])
+            .basic_auth(&user.username, Some(&user.password))
+            .header("Content-Type", content_type)
+            .body(content)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form("/login", body).await
+    }
+
+    /// Log in as `user`, keeping the session cookie in `api_client`.
+    pub async fn login_as(&self, user: &TestUser) {
+        let response = self
+            .post_login(&serde_json::json!({
+                "username": &user.username,
+                "password": &user.password,
+            }))
+            .await;
+
+        assert_is_redirect_to(&response, "/admin");
+    }
+
+    pub async fn get(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_html(&self, path: &str) -> String {
+        self.get(path).await.text().await.unwrap()
+    }
+
+    /// Post a form the way a browser would, including the session's CSRF
+    /// token.
+    pub async fn post_form<Body>(&self, path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let mut form = match serde_json::to_value(body).unwrap() {
+            serde_json::Value::Object(form) => form,
+            serde_json::Value::Null => serde_json::Map::new(),
+            _ => panic!("Forms must serialize to an object"),
+        };
+        form.insert("csrf_token".into(), self.csrf_token().await.into());
+
+        self.post_form_without_csrf_token(path, &form).await
+    }
+
+    pub async fn post_form_without_csrf_token<Body>(
+        &self,
+        path: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}{}", &self.address, path))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// The CSRF token of the current session, as embedded in its forms.
+    pub async fn csrf_token(&self) -> String {
+        let html_page = self.get_html("/login").await;
+
+        html_page
+            .split(r#"name="csrf_token" value=""#)
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .expect("No CSRF token in the login form")
+            .to_string()
+    }
+
+    /// Wait for emails sent from background tasks to reach the mock server.
+    pub async fn wait_for_emails(&self, count: usize) -> Vec<wiremock::Request> {
+        for _ in 0..50 {
+            let requests = self.email_server.received_requests().await.unwrap();
+
+            if requests.len() >= count {
+                return requests;
+            }
+
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+
+        panic!("Expected {count} email(s) to be sent.");
+    }
+
+    /// Extract the single link contained in both bodies of an email, besides
+    /// the link to the subscriber's preferences.
+    pub fn get_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
         let get_link = |haystack: &str| {
             let links: Vec<_> = LinkFinder::new()
                 .links(haystack)
                 .filter(|link| *link.kind() == LinkKind::Url)
+                .filter(|link| !link.as_str().contains("/preferences?"))
                 .collect();
 
             assert_eq!(links.len(), 1);
@@ -148,6 +388,68 @@ impl TestApp {
 
         ConfirmationLinks { html, text }
     }
+
+    pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links(email_request)
+    }
+
+    /// The link to the recipient's preferences, found in the HTML body.
+    pub fn get_preferences_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let html = body["content"][0]["value"].as_str().unwrap();
+
+        let raw_link = LinkFinder::new()
+            .links(html)
+            .find(|link| link.as_str().contains("/preferences?"))
+            .expect("No preferences link in the email")
+            .as_str()
+            .to_owned();
+        let mut preferences_link = reqwest::Url::parse(&raw_link).unwrap();
+
+        preferences_link.set_port(Some(self.port)).unwrap();
+        preferences_link
+    }
+
+    /// The tracked links and the tracking pixel found in the HTML body.
+    pub fn get_tracking_links(&self, email_request: &wiremock::Request) -> TrackingLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let html = body["content"][0]["value"].as_str().unwrap();
+
+        let mut links: Vec<_> = LinkFinder::new()
+            .links(html)
+            .filter(|link| *link.kind() == LinkKind::Url)
+            .map(|link| {
+                let mut url = reqwest::Url::parse(link.as_str()).unwrap();
+
+                url.set_port(Some(self.port)).unwrap();
+                url
+            })
+            .collect();
+        let pixel = links
+            .iter()
+            .position(|url| url.path().ends_with("/open"))
+            .map(|i| links.remove(i))
+            .expect("No tracking pixel in the email");
+
+        links.retain(|url| url.path().starts_with("/newsletters/links/"));
+
+        TrackingLinks { links, pixel }
+    }
+}
+
+fn generate_signing_key() -> SigningKey {
+    loop {
+        let bytes: [u8; 32] = rand::thread_rng().gen();
+
+        if let Ok(key) = SigningKey::from_slice(&bytes) {
+            return key;
+        }
+    }
+}
+
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
 }
 
 async fn configure_database(db: &DB) -> PgPool {
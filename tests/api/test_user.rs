@@ -1,19 +1,26 @@
 use argon2::{password_hash::SaltString, Argon2, Params, PasswordHasher};
 use sqlx::PgPool;
 use uuid::Uuid;
+use zero2prod::domain::UserRole;
 
 pub struct TestUser {
     pub id: Uuid,
     pub username: String,
     pub password: String,
+    pub role: UserRole,
 }
 
 impl TestUser {
     pub fn generate() -> Self {
+        Self::generate_with_role(UserRole::Admin)
+    }
+
+    pub fn generate_with_role(role: UserRole) -> Self {
         Self {
             id: Uuid::now_v7(),
             username: Uuid::now_v7().to_string(),
             password: Uuid::now_v7().to_string(),
+            role,
         }
     }
 
@@ -30,17 +37,18 @@ impl TestUser {
 
         sqlx::query!(
             r#"
-                INSERT INTO users (id, username, password_hash)
-                VALUES ($1, $2, $3)
+                INSERT INTO users (id, username, password_hash, role)
+                VALUES ($1, $2, $3, $4)
                 RETURNING username, password_hash
             "#,
             self.id,
             self.username,
             password_hash,
+            self.role.as_ref(),
         )
         .fetch_one(db_pool)
         .await
-        .map(|row| row.password_hash)
+        .map(|row| row.password_hash.unwrap())
         .expect("Failed to get or create test user.")
     }
 }
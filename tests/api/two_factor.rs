@@ -0,0 +1,331 @@
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::test_app::{assert_is_redirect_to, TestApp};
+
+async fn get_totp(app: &TestApp) -> TOTP {
+    let secret = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE id = $1",
+        app.test_user.id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .totp_secret
+    .expect("No TOTP secret was stored");
+
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        30,
+        Secret::Encoded(secret).to_bytes().unwrap(),
+        None,
+        "test".into(),
+    )
+    .unwrap()
+}
+
+/// The code for `steps` time steps away from now.
+fn code(totp: &TOTP, steps: i64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    totp.generate((now + steps * 30) as u64)
+}
+
+/// Turn on two-factor authentication for the logged in test user, returning
+/// their TOTP generator and recovery codes.
+async fn enable_two_factor(app: &TestApp) -> (TOTP, Vec<String>) {
+    let response = app.post_form("/admin/two-factor/enroll", &()).await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+
+    let totp = get_totp(app).await;
+
+    let response = app
+        .post_form(
+            "/admin/two-factor/activate",
+            &serde_json::json!({ "code": code(&totp, 0) }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let html_page = response.text().await.unwrap();
+    let recovery_codes = html_page
+        .split("<li><code>")
+        .skip(1)
+        .map(|item| item.split("</code>").next().unwrap().to_string())
+        .collect();
+
+    (totp, recovery_codes)
+}
+
+async fn log_in_with_password(app: &TestApp) {
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn enrollment_shows_an_otpauth_uri() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    app.post_form("/admin/two-factor/enroll", &()).await;
+
+    let html_page = app.get_html("/admin/two-factor").await;
+
+    assert!(html_page.contains("otpauth://totp/zero2prod:"));
+}
+
+#[tokio::test]
+async fn enrollment_is_not_activated_by_an_invalid_code() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    app.post_form("/admin/two-factor/enroll", &()).await;
+    let totp = get_totp(&app).await;
+
+    let response = app
+        .post_form(
+            "/admin/two-factor/activate",
+            &serde_json::json!({ "code": code(&totp, -5) }),
+        )
+        .await;
+
+    assert!(response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("/admin/two-factor?error="));
+
+    // Logging in still only takes a password
+    app.post_form("/admin/logout", &()).await;
+    app.login_as(&app.test_user).await;
+}
+
+#[tokio::test]
+async fn recovery_codes_are_stored_hashed() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+
+    let (_, recovery_codes) = enable_two_factor(&app).await;
+
+    assert_eq!(recovery_codes.len(), 10);
+
+    let stored_hashes: Vec<String> = sqlx::query!(
+        "SELECT code_hash FROM totp_recovery_codes WHERE user_id = $1",
+        app.test_user.id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.code_hash)
+    .collect();
+
+    assert_eq!(stored_hashes.len(), 10);
+    for code in &recovery_codes {
+        assert!(!stored_hashes.contains(code));
+    }
+}
+
+#[tokio::test]
+async fn a_code_is_required_to_log_in_once_enabled() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+    let (totp, _) = enable_two_factor(&app).await;
+    app.post_form("/admin/logout", &()).await;
+
+    log_in_with_password(&app).await;
+
+    // The password alone doesn't grant access
+    let response = app.get("/admin").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_form(
+            "/login/two-factor",
+            &serde_json::json!({ "code": code(&totp, 1) }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin");
+
+    let html_page = app.get_html("/admin").await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn codes_cannot_be_replayed() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+    let (totp, _) = enable_two_factor(&app).await;
+    app.post_form("/admin/logout", &()).await;
+
+    // The code that was used to activate two-factor authentication
+    let used_step = sqlx::query!(
+        "SELECT totp_last_used_step FROM users WHERE id = $1",
+        app.test_user.id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .totp_last_used_step
+    .unwrap();
+    let used_code = totp.generate(used_step as u64 * 30);
+
+    log_in_with_password(&app).await;
+
+    let response = app
+        .post_form(
+            "/login/two-factor",
+            &serde_json::json!({ "code": used_code }),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, "/login/two-factor?error=Invalid%20code");
+}
+
+#[tokio::test]
+async fn recovery_codes_can_only_be_used_once() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+    let (_, recovery_codes) = enable_two_factor(&app).await;
+    app.post_form("/admin/logout", &()).await;
+
+    log_in_with_password(&app).await;
+    let response = app
+        .post_form(
+            "/login/two-factor",
+            &serde_json::json!({ "code": &recovery_codes[0] }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin");
+
+    app.post_form("/admin/logout", &()).await;
+
+    log_in_with_password(&app).await;
+    let response = app
+        .post_form(
+            "/login/two-factor",
+            &serde_json::json!({ "code": &recovery_codes[0] }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/login/two-factor?error=Invalid%20code");
+}
+
+#[tokio::test]
+async fn failed_codes_lead_to_a_lockout() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+    enable_two_factor(&app).await;
+    app.post_form("/admin/logout", &()).await;
+
+    log_in_with_password(&app).await;
+
+    for _ in 0..5 {
+        app.post_form(
+            "/login/two-factor",
+            &serde_json::json!({ "code": "000000" }),
+        )
+        .await;
+    }
+
+    let response = app
+        .post_form(
+            "/login/two-factor",
+            &serde_json::json!({ "code": "000000" }),
+        )
+        .await;
+
+    assert_is_redirect_to(
+        &response,
+        "/login?error=Too%20many%20failed%20login%20attempts%2C%20try%20again%20later",
+    );
+}
+
+#[tokio::test]
+async fn two_factor_can_be_turned_off_with_a_valid_code() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+    let (totp, _) = enable_two_factor(&app).await;
+
+    let response = app
+        .post_form(
+            "/admin/two-factor/disable",
+            &serde_json::json!({ "code": code(&totp, 1) }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+
+    app.post_form("/admin/logout", &()).await;
+    app.login_as(&app.test_user).await;
+}
+
+#[tokio::test]
+async fn publishing_requires_a_code_header_once_enabled() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+    let (totp, _) = enable_two_factor(&app).await;
+
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+
+    let response = app.post_newsletters(body.clone()).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .header("X-TOTP-Code", code(&totp, 1))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn reusing_a_code_to_publish_does_not_lead_to_a_lockout() {
+    let app = TestApp::spawn().await;
+    app.login_as(&app.test_user).await;
+    let (totp, _) = enable_two_factor(&app).await;
+    app.post_form("/admin/logout", &()).await;
+
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let code = code(&totp, 1);
+    let publish = || {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", &app.address))
+            .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+            .header("X-TOTP-Code", &code)
+            .json(&body)
+            .send()
+    };
+
+    assert_eq!(publish().await.unwrap().status().as_u16(), 200);
+    for _ in 0..6 {
+        assert_eq!(publish().await.unwrap().status().as_u16(), 401);
+    }
+
+    log_in_with_password(&app).await;
+}
@@ -0,0 +1,266 @@
+use serde_json::json;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::test_app::TestApp;
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+fn event(event: &str, event_id: &str) -> serde_json::Value {
+    json!({
+        "email": EMAIL,
+        "timestamp": 1675000000,
+        "event": event,
+        "sg_event_id": event_id,
+        "sg_message_id": "message-id",
+    })
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+async fn recorded_events(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT event FROM email_events ORDER BY id")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.event)
+        .collect()
+}
+
+#[tokio::test]
+async fn tracked_events_are_recorded() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app
+        .post_sendgrid_events(&json!([
+            event("processed", "1"),
+            event("delivered", "2"),
+            event("open", "3"),
+            event("click", "4"),
+        ]))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(recorded_events(&app).await, ["delivered", "open", "click"]);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn events_sent_twice_are_recorded_once() {
+    let app = TestApp::spawn().await;
+
+    for _ in 0..2 {
+        app.post_sendgrid_events(&json!([event("delivered", "1")]))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+
+    assert_eq!(recorded_events(&app).await, ["delivered"]);
+}
+
+#[tokio::test]
+async fn events_without_an_id_sent_twice_are_recorded_once() {
+    let app = TestApp::spawn().await;
+    let mut delivered = event("delivered", "");
+    delivered.as_object_mut().unwrap().remove("sg_event_id");
+
+    for _ in 0..2 {
+        app.post_sendgrid_events(&json!([delivered]))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+
+    assert_eq!(recorded_events(&app).await, ["delivered"]);
+}
+
+#[tokio::test]
+async fn batches_larger_than_the_default_payload_limit_are_accepted() {
+    let app = TestApp::spawn().await;
+    let events: Vec<_> = (0..3000)
+        .map(|i| event("delivered", &i.to_string()))
+        .collect();
+    let events = json!(events);
+
+    assert!(serde_json::to_vec(&events).unwrap().len() > 256 * 1024);
+
+    let response = app.post_sendgrid_events(&events).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(recorded_events(&app).await.len(), 3000);
+}
+
+#[tokio::test]
+async fn hard_bounces_mark_the_subscriber_as_bounced() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    let mut bounce = event("bounce", "1");
+    bounce["type"] = "bounce".into();
+    bounce["reason"] = "550 5.1.1 The email account does not exist.".into();
+
+    app.post_sendgrid_events(&json!([bounce]))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(subscriber_status(&app).await, "bounced");
+
+    let audit_event =
+        sqlx::query!("SELECT details FROM audit_events WHERE action = 'subscriber.bounced'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+
+    assert_eq!(
+        audit_event.details["reason"],
+        "550 5.1.1 The email account does not exist."
+    );
+}
+
+#[tokio::test]
+async fn blocked_messages_do_not_mark_the_subscriber_as_bounced() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    let mut bounce = event("bounce", "1");
+    bounce["type"] = "blocked".into();
+
+    app.post_sendgrid_events(&json!([bounce]))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(recorded_events(&app).await, ["bounce"]);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn spam_reports_mark_the_subscriber_as_complained() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    let mut bounce = event("bounce", "2");
+    bounce["type"] = "bounce".into();
+
+    app.post_sendgrid_events(&json!([event("spamreport", "1"), bounce]))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(subscriber_status(&app).await, "complained");
+}
+
+#[tokio::test]
+async fn bounced_and_complained_subscribers_do_not_receive_newsletters() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    app.post_sendgrid_events(&json!([event("spamreport", "1")]))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn unsigned_or_tampered_events_are_rejected() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    let body = serde_json::to_vec(&json!([event("spamreport", "1")])).unwrap();
+
+    let unsigned = app
+        .post_sendgrid_events_with_signature(body.clone(), "1675000000", "")
+        .await;
+
+    assert_eq!(unsigned.status().as_u16(), 401);
+
+    let signature_of_another_batch = app.sign_sendgrid_events("1675000000", b"[]");
+    let tampered = app
+        .post_sendgrid_events_with_signature(body, "1675000000", &signature_of_another_batch)
+        .await;
+
+    assert_eq!(tampered.status().as_u16(), 401);
+    assert!(recorded_events(&app).await.is_empty());
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn batches_signed_long_ago_are_rejected_as_replays() {
+    let app = TestApp::spawn().await;
+    create_confirmed_subscriber(&app).await;
+
+    let body = serde_json::to_vec(&json!([event("spamreport", "1")])).unwrap();
+    let timestamp = (chrono::Utc::now() - chrono::Duration::minutes(10))
+        .timestamp()
+        .to_string();
+    let signature = app.sign_sendgrid_events(&timestamp, &body);
+
+    let response = app
+        .post_sendgrid_events_with_signature(body, &timestamp, &signature)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(recorded_events(&app).await.is_empty());
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn malformed_batches_are_rejected() {
+    let app = TestApp::spawn().await;
+
+    let response = app.post_sendgrid_events(&json!({ "not": "a list" })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}